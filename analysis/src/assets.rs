@@ -0,0 +1,259 @@
+//! Loading the images the renderers draw with, so that they can be used without going
+//! through replayshark.
+
+use image::imageops::FilterType;
+use image::{
+    DynamicImage, GenericImageView, ImageFormat, ImageResult, Pixel, Rgb, RgbImage, RgbaImage,
+};
+use std::path::{Path, PathBuf};
+
+/// Directory the minimap images are extracted to, one subdirectory per map
+pub const MAP_DIRECTORY: &str = "versions/0.10.3";
+
+/// Width and height in pixels of the minimap images in the game files
+const MINIMAP_SIZE: u32 = 760;
+
+/// How much [MapBackground::Darkened] dims the map by
+pub const DARKEN_FACTOR: f32 = 0.4;
+
+/// Colors used by [MapBackground::Outlines]
+const OUTLINE_BACKGROUND: Rgb<u8> = Rgb([16, 20, 28]);
+const OUTLINE_COLOR: Rgb<u8> = Rgb([150, 160, 170]);
+
+/// How the map is drawn behind everything else
+#[derive(Debug, Clone, Default)]
+pub enum MapBackground {
+    /// The minimap as it's shown in game
+    #[default]
+    Composited,
+    /// The minimap dimmed by [DARKEN_FACTOR], so that overlays stand out against it
+    Darkened,
+    /// A flat dark background with only the outlines of the islands
+    Outlines,
+    /// An image from disk, stretched to cover the map
+    Custom(PathBuf),
+}
+
+/// Width of each map in kilometers, by its `mapName` in the replay metadata
+const MAP_WIDTHS_KM: &[(&str, u32)] = &[
+    ("spaces/34_OC_islands", 24),
+    ("spaces/33_new_tierra", 24),
+    ("spaces/01_solomon_islands", 30),
+    ("spaces/10_NE_big_race", 30),
+    ("spaces/04_Archipelago", 30),
+    ("spaces/05_Ring", 36),
+    ("spaces/08_NE_passage", 36),
+    ("spaces/13_OC_new_dawn", 36),
+    ("spaces/17_NA_fault_line", 42),
+    ("spaces/41_Conquest", 42),
+    ("spaces/46_Estuary", 42),
+    ("spaces/42_Neighbors", 42),
+    ("spaces/50_Gold_harbor", 42),
+    ("spaces/20_NE_two_brothers", 42),
+    ("spaces/16_OC_bees_to_honey", 48),
+    ("spaces/22_tierra_del_fuego", 48),
+    ("spaces/15_NE_north", 48),
+    ("spaces/35_NE_north_winter", 48),
+    ("spaces/53_Shoreside", 42),
+    ("spaces/23_Shards", 42),
+    ("spaces/19_OC_prey", 42),
+    ("spaces/52_Britain", 42),
+    ("spaces/40_Okinawa", 42),
+    ("spaces/18_NE_ice_islands", 42),
+    ("spaces/14_Atlantic", 42),
+    ("spaces/38_Canada", 48),
+    ("spaces/37_Ridge", 48),
+    ("spaces/44_Path_warrior", 48),
+    ("spaces/25_sea_hope", 48),
+    ("spaces/45_Zigzag", 48),
+    ("spaces/47_Sleeping_Giant", 48),
+    ("spaces/51_Greece", 42),
+    ("spaces/28_naval_mission", 42),
+    ("spaces/00_CO_ocean", 36),
+];
+
+/// Width of `map_name` in kilometers, if it's a known map
+pub fn map_width_km(map_name: &str) -> Option<u32> {
+    MAP_WIDTHS_KM
+        .iter()
+        .find(|(name, _width)| *name == map_name)
+        .map(|(_name, width)| *width)
+}
+
+/// Half of the width of `map_name` in world units, which is how far the map extends
+/// from its center in each direction. For example 600 for New Dawn (36x36km) and 700
+/// for Fault Line (42x42km).
+pub fn map_half_width(map_name: &str) -> Option<f64> {
+    map_width_km(map_name).map(|width| (width * 50 / 3) as f64)
+}
+
+/// Directory holding the minimap images for `map_name`
+pub fn map_directory(map_name: &str) -> PathBuf {
+    Path::new(MAP_DIRECTORY).join(map_name)
+}
+
+fn load_png(path: &Path) -> ImageResult<DynamicImage> {
+    let file = std::fs::File::open(path)?;
+    image::load(std::io::BufReader::new(file), ImageFormat::Png)
+}
+
+/// Loads the minimap for `map_name`, with the islands drawn over the water, resized to
+/// `size` pixels square
+pub fn load_map_image(map_name: &str, size: u32) -> ImageResult<DynamicImage> {
+    let directory = map_directory(map_name);
+    let minimap = load_png(&directory.join("minimap.png"))?;
+    let minimap_background = load_png(&directory.join("minimap_water.png"))?;
+
+    let mut image = RgbImage::new(MINIMAP_SIZE, MINIMAP_SIZE);
+    for x in 0..MINIMAP_SIZE {
+        for y in 0..MINIMAP_SIZE {
+            let mut bg = minimap_background.get_pixel(x, y);
+            let fg = minimap.get_pixel(x, y);
+            bg.blend(&fg);
+            image.put_pixel(x, y, bg.to_rgb());
+        }
+    }
+    let image = DynamicImage::ImageRgb8(image);
+    Ok(image.resize_exact(size, size, FilterType::Lanczos3))
+}
+
+/// Where [load_map_image_cached] keeps the composited image for `map_name`
+fn cached_map_path(cache: &Path, build: u32, map_name: &str, size: u32) -> PathBuf {
+    cache
+        .join(build.to_string())
+        .join(format!("{}_{}.png", map_name.replace('/', "_"), size))
+}
+
+/// Like [load_map_image], but keeps the composited image in the `cache` directory so
+/// later renders of the same map skip loading and compositing the minimap. Images are
+/// kept per game `build`, so maps changed by a patch aren't drawn from a stale cache.
+pub fn load_map_image_cached(
+    cache: &Path,
+    build: u32,
+    map_name: &str,
+    size: u32,
+) -> ImageResult<DynamicImage> {
+    let path = cached_map_path(cache, build, map_name, size);
+    if let Ok(image) = load_png(&path) {
+        return Ok(image);
+    }
+
+    let image = load_map_image(map_name, size)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    image.save_with_format(&path, ImageFormat::Png)?;
+    Ok(image)
+}
+
+/// Scales the brightness of every pixel in `image` by `factor`
+pub fn darken(image: &DynamicImage, factor: f32) -> DynamicImage {
+    let mut image = image.to_rgb8();
+    for pixel in image.pixels_mut() {
+        *pixel = pixel.map(|channel| (channel as f32 * factor) as u8);
+    }
+    DynamicImage::ImageRgb8(image)
+}
+
+/// Loads the outlines of the islands on `map_name`, drawn on a flat dark background
+/// `size` pixels square
+pub fn load_island_outlines(map_name: &str, size: u32) -> ImageResult<DynamicImage> {
+    let minimap = load_png(&map_directory(map_name).join("minimap.png"))?;
+    // Scale the islands up before outlining them so the outline stays a pixel wide
+    let minimap = minimap.resize_exact(size, size, FilterType::Triangle);
+    Ok(DynamicImage::ImageRgb8(island_outlines(
+        &minimap.to_rgba8(),
+    )))
+}
+
+/// Outlines the opaque parts of `minimap`, which are the islands
+fn island_outlines(minimap: &RgbaImage) -> RgbImage {
+    let (width, height) = minimap.dimensions();
+    let is_land = |x: u32, y: u32| minimap.get_pixel(x, y)[3] > 127;
+    RgbImage::from_fn(width, height, |x, y| {
+        if !is_land(x, y) {
+            return OUTLINE_BACKGROUND;
+        }
+        // Islands running off the edge of the map aren't outlined along the edge
+        let neighbors = [
+            x.checked_sub(1).map(|x| (x, y)),
+            Some((x + 1, y)).filter(|(x, _y)| *x < width),
+            y.checked_sub(1).map(|y| (x, y)),
+            Some((x, y + 1)).filter(|(_x, y)| *y < height),
+        ];
+        let is_shore = neighbors.iter().flatten().any(|(x, y)| !is_land(*x, *y));
+        if is_shore {
+            OUTLINE_COLOR
+        } else {
+            OUTLINE_BACKGROUND
+        }
+    })
+}
+
+/// Loads a custom map background from `path`, stretched to `size` pixels square
+pub fn load_custom_background(path: &Path, size: u32) -> ImageResult<DynamicImage> {
+    let image = image::open(path)?;
+    Ok(image.resize_exact(size, size, FilterType::Lanczos3))
+}
+
+/// Loads an icon and scales it to fit within `size` pixels, keeping its aspect ratio
+pub fn load_icon(path: &Path, size: u32) -> ImageResult<DynamicImage> {
+    let image = image::open(path)?;
+    Ok(image.resize(size, size, FilterType::Lanczos3))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_map_half_width() {
+        assert_eq!(map_half_width("spaces/13_OC_new_dawn"), Some(600.0));
+        assert_eq!(map_half_width("spaces/17_NA_fault_line"), Some(700.0));
+        assert_eq!(map_half_width("spaces/99_unknown"), None);
+    }
+
+    #[test]
+    fn test_map_image_loaded_from_cache() {
+        let cache = std::env::temp_dir().join(format!("map-cache-{}", std::process::id()));
+        let path = cached_map_path(&cache, 1234, "spaces/99_unknown", 4);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        RgbImage::from_pixel(4, 4, image::Rgb([1, 2, 3]))
+            .save(&path)
+            .unwrap();
+
+        // The map isn't in MAP_DIRECTORY, so this only succeeds if the cache is used
+        let image = load_map_image_cached(&cache, 1234, "spaces/99_unknown", 4);
+        let other_build = load_map_image_cached(&cache, 1235, "spaces/99_unknown", 4);
+        std::fs::remove_dir_all(&cache).unwrap();
+
+        assert_eq!(image.unwrap().get_pixel(0, 0), image::Rgba([1, 2, 3, 255]));
+        assert!(other_build.is_err());
+    }
+
+    #[test]
+    fn test_island_outlines() {
+        // A 3x3 island in the middle of a 5x5 map
+        let minimap = RgbaImage::from_fn(5, 5, |x, y| {
+            let alpha = if (1..4).contains(&x) && (1..4).contains(&y) {
+                255
+            } else {
+                0
+            };
+            image::Rgba([0, 0, 0, alpha])
+        });
+        let outlines = island_outlines(&minimap);
+
+        assert_eq!(*outlines.get_pixel(0, 0), OUTLINE_BACKGROUND);
+        assert_eq!(*outlines.get_pixel(1, 1), OUTLINE_COLOR);
+        assert_eq!(*outlines.get_pixel(2, 1), OUTLINE_COLOR);
+        assert_eq!(*outlines.get_pixel(2, 2), OUTLINE_BACKGROUND);
+    }
+
+    #[test]
+    fn test_darken() {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([200, 100, 0])));
+        let darkened = darken(&image, 0.5).to_rgb8();
+        assert_eq!(*darkened.get_pixel(0, 0), Rgb([100, 50, 0]));
+    }
+}
@@ -1,6 +1,3 @@
-use image::GenericImageView;
-use image::Pixel;
-use image::{imageops::FilterType, ImageFormat, RgbImage};
 use plotters::prelude::*;
 use std::collections::HashMap;
 use wows_replays::analyzer::decoder::{DecodedPacket, DecodedPacketPayload};
@@ -21,8 +18,8 @@ impl DamageTrailsBuilder {
     }
 }
 
-impl AnalyzerBuilder for DamageTrailsBuilder {
-    fn build(&self, meta: &ReplayMeta) -> Box<dyn Analyzer> {
+impl AnalyzerMutBuilder for DamageTrailsBuilder {
+    fn build(&self, meta: &ReplayMeta) -> Box<dyn AnalyzerMut> {
         Box::new(DamageMonitor {
             version: Version::from_client_exe(&meta.clientVersionFromExe),
             username: meta.playerName.clone(),
@@ -77,56 +74,19 @@ impl AnalyzerMut for DamageMonitor {
         // Blit the background into the image
         {
             println!("Map name = {}", self.meta.as_ref().unwrap().mapName);
-            let minimap = image::load(
-                std::io::BufReader::new(
-                    std::fs::File::open(&format!(
-                        "versions/0.10.3/{}/minimap.png",
-                        self.meta.as_ref().unwrap().mapName
-                    ))
-                    .unwrap(),
-                ),
-                ImageFormat::Png,
-            )
-            .unwrap();
-            let minimap_background = image::load(
-                std::io::BufReader::new(
-                    std::fs::File::open(&format!(
-                        "versions/0.10.3/{}/minimap_water.png",
-                        self.meta.as_ref().unwrap().mapName
-                    ))
-                    .unwrap(),
-                ),
-                ImageFormat::Png,
-            )
-            .unwrap();
+            let image =
+                crate::assets::load_map_image(&self.meta.as_ref().unwrap().mapName, 2048).unwrap();
 
             println!("Minimap load time = {:?}", start.elapsed());
             let start = std::time::Instant::now();
 
-            let mut image = RgbImage::new(760, 760);
-            for x in 0..760 {
-                for y in 0..760 {
-                    let bg = minimap_background.get_pixel(x, y);
-                    let fg = minimap.get_pixel(x, y);
-                    let mut bg = bg.clone();
-                    bg.blend(&fg);
-                    image.put_pixel(x, y, bg.to_rgb());
-                }
-            }
-
-            println!("Minimap 760px fill time = {:?}", start.elapsed());
-            let start = std::time::Instant::now();
-
-            let image = image::DynamicImage::ImageRgb8(image);
-            let image = image.resize_exact(2048, 2048, FilterType::Lanczos3);
-
             let mut ctx = ChartBuilder::on(&root)
                 .x_label_area_size(0)
                 .y_label_area_size(0)
                 .build_cartesian_2d(0.0..1.0, 0.0..1.0)
                 .unwrap();
 
-            let elem: BitMapElement<_> = ((0.0, 1.0), image).into();
+            let elem = crate::trails::bitmap_element((0.0, 1.0), &image);
             ctx.draw_series(std::iter::once(elem)).unwrap();
 
             println!("Resize time = {:?}", start.elapsed());
@@ -136,53 +96,9 @@ impl AnalyzerMut for DamageMonitor {
 
         // Render the actual trails
 
-        let mut map_widths: HashMap<String, u32> = HashMap::new();
-        map_widths.insert("spaces/34_OC_islands".to_string(), 24);
-        map_widths.insert("spaces/33_new_tierra".to_string(), 24);
-        map_widths.insert("spaces/01_solomon_islands".to_string(), 30);
-        map_widths.insert("spaces/10_NE_big_race".to_string(), 30);
-        map_widths.insert("spaces/04_Archipelago".to_string(), 30);
-        map_widths.insert("spaces/05_Ring".to_string(), 36);
-        map_widths.insert("spaces/08_NE_passage".to_string(), 36);
-        map_widths.insert("spaces/13_OC_new_dawn".to_string(), 36);
-        map_widths.insert("spaces/17_NA_fault_line".to_string(), 42);
-        map_widths.insert("spaces/41_Conquest".to_string(), 42);
-        map_widths.insert("spaces/46_Estuary".to_string(), 42);
-        map_widths.insert("spaces/42_Neighbors".to_string(), 42);
-        map_widths.insert("spaces/50_Gold_harbor".to_string(), 42);
-        map_widths.insert("spaces/20_NE_two_brothers".to_string(), 42);
-        map_widths.insert("spaces/16_OC_bees_to_honey".to_string(), 48);
-        map_widths.insert("spaces/22_tierra_del_fuego".to_string(), 48);
-        map_widths.insert("spaces/15_NE_north".to_string(), 48);
-        map_widths.insert("spaces/35_NE_north_winter".to_string(), 48);
-        map_widths.insert("spaces/53_Shoreside".to_string(), 42);
-        map_widths.insert("spaces/23_Shards".to_string(), 42);
-        map_widths.insert("spaces/19_OC_prey".to_string(), 42);
-        map_widths.insert("spaces/52_Britain".to_string(), 42);
-        map_widths.insert("spaces/40_Okinawa".to_string(), 42);
-        map_widths.insert("spaces/18_NE_ice_islands".to_string(), 42);
-        map_widths.insert("spaces/14_Atlantic".to_string(), 42);
-        map_widths.insert("spaces/38_Canada".to_string(), 48);
-        map_widths.insert("spaces/37_Ridge".to_string(), 48);
-        map_widths.insert("spaces/44_Path_warrior".to_string(), 48);
-        map_widths.insert("spaces/25_sea_hope".to_string(), 48);
-        map_widths.insert("spaces/45_Zigzag".to_string(), 48);
-        map_widths.insert("spaces/47_Sleeping_Giant".to_string(), 48);
-        map_widths.insert("spaces/51_Greece".to_string(), 42);
-        map_widths.insert("spaces/28_naval_mission".to_string(), 42);
-        map_widths.insert("spaces/00_CO_ocean".to_string(), 36);
-
-        // 600 for New Dawn (36x36km)
-        // 700 for Fault Line (42x42km)
-        let scale = map_widths
-            .get(&self.meta.as_ref().unwrap().mapName)
-            .expect(&format!(
-                "Could not find size of map {}!",
-                self.meta.as_ref().unwrap().mapName
-            ))
-            * 50
-            / 3;
-        let scale = scale as f64;
+        let map_name = &self.meta.as_ref().unwrap().mapName;
+        let scale = crate::assets::map_half_width(map_name)
+            .unwrap_or_else(|| panic!("Could not find size of map {}!", map_name));
         let mut scatter_ctx = ChartBuilder::on(&root)
             .x_label_area_size(0)
             .y_label_area_size(0)
@@ -221,18 +137,15 @@ impl AnalyzerMut for DamageMonitor {
     }
 
     fn process_mut(&mut self, packet: &Packet<'_, '_>) {
-        let time = packet.clock;
-        let minutes = (time / 60.0).floor() as i32;
-        let seconds = (time - minutes as f32 * 60.0).floor() as i32;
-        let time = format!("{:02}:{:02}", minutes, seconds);
+        let time = packet.game_clock().to_mmss();
 
         let decoded = DecodedPacket::from(&self.version, false, packet);
         match &decoded.payload {
             DecodedPacketPayload::OnArenaStateReceived { players, .. } => {
                 for player in players.iter() {
                     if player.username == self.username {
-                        self.shipid = Some(player.shipid as u32);
-                        self.avatarid = Some(player.avatarid as u32);
+                        self.shipid = Some(player.entity_id as u32);
+                        self.avatarid = Some(player.avatar_id as u32);
                         break;
                     }
                 }
@@ -1,3 +1,6 @@
+#[cfg(feature = "graphics")]
+pub mod assets;
+
 #[cfg(feature = "graphics")]
 pub mod damage_trails;
 
@@ -1,182 +1,838 @@
+use crate::assets::{self, MapBackground};
 use image::GenericImageView;
-use image::Pixel;
-use image::{imageops::FilterType, ImageFormat, RgbImage};
+use plotters::coord::types::RangedCoordf64;
+use plotters::coord::Shift;
 use plotters::prelude::*;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use wows_replays::analyzer::battle_controller::{
+    BattlePhase, ConsumableState, ConsumableTimeline, Relation, Teams, TimelineSample,
+};
+use wows_replays::analyzer::decoder::{
+    Consumable, DeathCause, DecodedPacket, DecodedPacketPayload, VoiceLine,
+};
 use wows_replays::analyzer::*;
+use wows_replays::annotations::{AnnotationKind, Annotations};
+use wows_replays::clock::GameClock;
+use wows_replays::grid::GridSquare;
 use wows_replays::packet2::{Packet, PacketType};
+use wows_replays::ui_support::{IconStyle, IconVariant, ShipClass, ShipIcon, Silhouettes};
 use wows_replays::ReplayMeta;
 
+/// Color of label outlines, shadows and backgrounds
+const LABEL_BACKDROP: RGBColor = RGBColor(20, 20, 20);
+/// Padding in pixels around a label's text inside its [LabelStyle::Pill]
+const LABEL_PADDING: i32 = 4;
+/// Smallest gap in pixels kept between labels, enough to fit both their pills
+const LABEL_SPACING: i32 = 2 * LABEL_PADDING;
+/// How many rows up or down a label may be moved to keep it clear of other labels
+const MAX_LABEL_SHIFT: i32 = 6;
+
+/// Size in pixels that ship icons are drawn at
+const ICON_SIZE: u32 = 48;
+
+/// Size in pixels of the team health graph
+const GRAPH_WIDTH: u32 = 480;
+const GRAPH_HEIGHT: u32 = 200;
+
+/// Font size of the kill feed and chat
+const HUD_FONT_SIZE: u32 = 20;
+/// How many of the most recent kills or chat messages are listed
+const HUD_MAX_LINES: usize = 20;
+
+/// Size in pixels of the rendered image
+const IMAGE_SIZE: u32 = 2048;
+
+/// World units in a kilometer, matching the scale maps are drawn at
+const WORLD_UNITS_PER_KM: f64 = 100.0 / 3.0;
+
+/// Distance in kilometers a ship is assumed to spot torpedoes from. Torpedoes' actual
+/// detectability varies from about 1 to 2.5km.
+const TORPEDO_DETECTION_KM: f64 = 1.5;
+
+/// Whose information a trail render with [RenderOptions::fog_of_war] shows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Perspective {
+    /// The recording player's team
+    #[default]
+    Ally,
+    /// The teams opposing the recording player, e.g. to review what they knew
+    Enemy,
+}
+
+/// What is drawn on the trails image besides where each ship went
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    pub icon_style: IconStyle,
+    /// Directory of class icons named after their
+    /// [wows_replays::ui_support::IconKey]s, e.g. `destroyer_enemy.png`. Ships are only
+    /// marked with an icon at their final position if this is set.
+    pub class_icons: Option<PathBuf>,
+    /// Used with [IconStyle::Silhouette]
+    pub silhouettes: Silhouettes,
+    /// The class of each ship in the battle, by GameParams ID
+    pub ship_classes: HashMap<u32, ShipClass>,
+    /// Leave a "ghost" where enemy ships went undetected, labelled with how long they
+    /// stayed hidden
+    pub ghosts: bool,
+    /// Detection range of the recording player's ship in kilometers, drawn around where
+    /// the player's ship was last
+    pub view_range: Option<f32>,
+    /// Draw the tracks of torpedoes, from where they were launched to where they stopped
+    pub torpedoes: bool,
+    /// Only draw enemy torpedoes from where the [RenderOptions::perspective] team would
+    /// have spotted them, rather than from where they were launched. No spotting information is sent
+    /// for torpedoes, so they're assumed to be spotted once they come within
+    /// [TORPEDO_DETECTION_KM] of one of the team's ships.
+    pub spotted_torpedoes_only: bool,
+    /// Only draw what the [RenderOptions::perspective] team could see: enemy trails
+    /// while the enemy was spotted, no icons for enemies which ended the battle
+    /// undetected, and enemy torpedoes from where they were spotted, as with
+    /// [RenderOptions::spotted_torpedoes_only]
+    pub fog_of_war: bool,
+    pub perspective: Perspective,
+    /// Also write a JSON file of where each ship was last drawn on the image, so that a
+    /// viewer can show tooltips over the ships. See [ShipPosition].
+    pub positions_sidecar: Option<PathBuf>,
+    /// Directory to keep composited map backgrounds in between renders, see
+    /// [assets::load_map_image_cached]
+    pub asset_cache: Option<PathBuf>,
+    pub background: MapBackground,
+    /// Draw quick commands, such as map pings and "Requesting support", with who sent
+    /// them and a line from their ship to any place they pinged. With
+    /// [RenderOptions::fog_of_war], only those the viewing team could have seen are drawn.
+    pub pings: bool,
+    pub label_style: LabelStyle,
+    /// Print how long each step, and encoding the image, took to draw
+    pub profile: bool,
+    /// Draw everything but the map onto a transparent image, so the render can be laid
+    /// over other footage
+    pub transparent: bool,
+    /// Which layers to draw, bottom first. Layers left out aren't drawn. All layers are
+    /// drawn in [Layer::DEFAULT_ORDER] if this isn't set.
+    pub layers: Option<Vec<Layer>>,
+    /// List the ships sunk, who sank them and how, in the top right corner
+    pub kill_feed: bool,
+    /// List the chat messages in the top left corner. With [RenderOptions::fog_of_war],
+    /// opponents' team chat is left out.
+    pub chat: bool,
+    /// Draw the HUD, which is [Layer::KillFeed], [Layer::Chat] and [Layer::TeamGraph],
+    /// onto its own transparent image at this path instead of onto the render, so it can
+    /// be placed separately over other footage
+    pub hud_output: Option<PathBuf>,
+}
+
+/// Something drawn on the trails image. Layers are drawn in the order of
+/// [RenderOptions::layers], each over the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Layer {
+    /// The map
+    Background,
+    /// Where each ship went
+    Trails,
+    /// See [RenderOptions::view_range]
+    ViewRange,
+    /// See [RenderOptions::ghosts]
+    Ghosts,
+    /// See [RenderOptions::torpedoes]
+    Torpedoes,
+    /// See [RenderOptions::pings]
+    Pings,
+    /// Ship icons, see [RenderOptions::class_icons]
+    Ships,
+    /// See [TrailsBuilder::with_consumables]
+    Consumables,
+    /// See [TrailsBuilder::with_phases]
+    Phases,
+    /// See [TrailsBuilder::with_annotations]
+    Annotations,
+    /// The text of every other layer. Labels are placed together so they don't overlap,
+    /// so the labels of layers ordered after this one are drawn last, over everything.
+    Labels,
+    /// See [RenderOptions::kill_feed]
+    KillFeed,
+    /// See [RenderOptions::chat]
+    Chat,
+    /// See [TrailsBuilder::with_team_graph]
+    TeamGraph,
+}
+
+impl Layer {
+    /// The order layers are drawn in unless [RenderOptions::layers] is set
+    pub const DEFAULT_ORDER: [Layer; 14] = [
+        Layer::Background,
+        Layer::Trails,
+        Layer::ViewRange,
+        Layer::Ghosts,
+        Layer::Torpedoes,
+        Layer::Pings,
+        Layer::Ships,
+        Layer::Consumables,
+        Layer::Phases,
+        Layer::Annotations,
+        Layer::Labels,
+        Layer::KillFeed,
+        Layer::Chat,
+        Layer::TeamGraph,
+    ];
+
+    /// Whether the layer is part of the HUD rather than drawn over the map, see
+    /// [RenderOptions::hud_output]
+    pub fn is_hud(&self) -> bool {
+        matches!(self, Layer::KillFeed | Layer::Chat | Layer::TeamGraph)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layer::Background => "background",
+            Layer::Trails => "trails",
+            Layer::ViewRange => "view-range",
+            Layer::Ghosts => "ghosts",
+            Layer::Torpedoes => "torpedoes",
+            Layer::Pings => "pings",
+            Layer::Ships => "ships",
+            Layer::Consumables => "consumables",
+            Layer::Phases => "phases",
+            Layer::Annotations => "annotations",
+            Layer::Labels => "labels",
+            Layer::KillFeed => "kill-feed",
+            Layer::Chat => "chat",
+            Layer::TeamGraph => "team-graph",
+        }
+    }
+}
+
+impl std::str::FromStr for Layer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Layer::DEFAULT_ORDER
+            .iter()
+            .find(|layer| layer.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown layer {}", s))
+    }
+}
+
+/// How labels are set off from the map behind them so they stay readable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LabelStyle {
+    #[default]
+    Plain,
+    /// A dark outline around each letter
+    Outline,
+    /// A dark shadow below and to the right of the text
+    Shadow,
+    /// A dark rounded background behind the whole label
+    Pill,
+}
+
+/// Where a ship was last drawn on the trails image, in pixels from the top left corner
+#[derive(Debug, Clone, Serialize)]
+pub struct ShipPosition {
+    pub entity_id: u32,
+    pub username: Option<String>,
+    /// GameParams ID of the ship
+    pub ship_id: Option<u32>,
+    pub team_id: Option<u32>,
+    pub x: i32,
+    pub y: i32,
+    pub destroyed: bool,
+}
+
 pub struct TrailsBuilder {
     output: String,
+    consumables: Vec<ConsumableTimeline>,
+    phases: Vec<BattlePhase>,
+    annotations: Annotations,
+    timeline: Vec<TimelineSample>,
+    options: RenderOptions,
 }
 
 impl TrailsBuilder {
     pub fn new(output: &str) -> Self {
         Self {
             output: output.to_string(),
+            consumables: vec![],
+            phases: vec![],
+            annotations: Annotations::default(),
+            timeline: vec![],
+            options: RenderOptions::default(),
         }
     }
+
+    pub fn with_options(mut self, options: RenderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Marks where on their trails enemy ships got their radar or hydro back, from the
+    /// consumable timelines of the replay's battle report
+    pub fn with_consumables(mut self, consumables: Vec<ConsumableTimeline>) -> Self {
+        self.consumables = consumables;
+        self
+    }
+
+    /// Labels where the recording player was when each phase of the battle began
+    pub fn with_phases(mut self, phases: Vec<BattlePhase>) -> Self {
+        self.phases = phases;
+        self
+    }
+
+    /// Overlays a hand-written edit list of callouts, arrows and highlighted ships, each
+    /// placed where the ships were when it starts
+    pub fn with_annotations(mut self, annotations: Annotations) -> Self {
+        self.annotations = annotations;
+        self
+    }
+
+    /// Adds a graph of each team's health and score over the battle, from the timeline
+    /// of the replay's battle report
+    pub fn with_team_graph(mut self, timeline: Vec<TimelineSample>) -> Self {
+        self.timeline = timeline;
+        self
+    }
+}
+
+impl AnalyzerMutBuilder for TrailsBuilder {
+    fn build(&self, meta: &wows_replays::ReplayMeta) -> Box<dyn AnalyzerMut> {
+        Box::new(self.renderer(meta))
+    }
 }
 
-impl AnalyzerBuilder for TrailsBuilder {
-    fn build(&self, meta: &wows_replays::ReplayMeta) -> Box<dyn Analyzer> {
-        Box::new(TrailRenderer {
+impl TrailsBuilder {
+    fn renderer(&self, meta: &wows_replays::ReplayMeta) -> TrailRenderer {
+        TrailRenderer {
             trails: HashMap::new(),
             player_trail: vec![],
             output: self.output.clone(),
             meta: Some((*meta).clone()),
-        })
+            consumables: self.consumables.clone(),
+            phases: self.phases.clone(),
+            annotations: self.annotations.clone(),
+            timeline: self.timeline.clone(),
+            version: wows_replays::version::Version::from_client_exe(&meta.clientVersionFromExe),
+            options: self.options.clone(),
+            self_id: None,
+            teams: Teams::default(),
+            ship_ids: HashMap::new(),
+            usernames: HashMap::new(),
+            destroyed: HashSet::new(),
+            visibility: HashMap::new(),
+            torpedoes: vec![],
+            avatar_ships: HashMap::new(),
+            pings: vec![],
+            kills: vec![],
+            chat: vec![],
+        }
     }
 }
 
 struct TrailRenderer {
-    trails: HashMap<u32, Vec<(f32, f32)>>,
-    player_trail: Vec<(f32, f32)>,
+    /// Entity ID -> (clock, x, z)
+    trails: HashMap<u32, Vec<(f32, f32, f32)>>,
+    /// (clock, x, z)
+    player_trail: Vec<(f32, f32, f32)>,
     output: String,
     meta: Option<ReplayMeta>,
+    consumables: Vec<ConsumableTimeline>,
+    phases: Vec<BattlePhase>,
+    annotations: Annotations,
+    timeline: Vec<TimelineSample>,
+    version: wows_replays::version::Version,
+    options: RenderOptions,
+    /// Entity ID of the recording player's ship
+    self_id: Option<u32>,
+    teams: Teams,
+    /// Entity ID -> GameParams ID of the ship
+    ship_ids: HashMap<u32, u32>,
+    /// Entity ID -> player name
+    usernames: HashMap<u32, String>,
+    destroyed: HashSet<u32>,
+    /// Entity ID -> (clock, whether the ship was spotted) for each time it was spotted or
+    /// went undetected
+    visibility: HashMap<u32, Vec<(f32, bool)>>,
+    torpedoes: Vec<TorpedoTrack>,
+    /// Avatar ID -> entity ID of the player's ship
+    avatar_ships: HashMap<u32, u32>,
+    pings: Vec<Ping>,
+    kills: Vec<Kill>,
+    chat: Vec<ChatMessage>,
 }
 
-impl AnalyzerMut for TrailRenderer {
-    fn process_mut(&mut self, packet: &Packet<'_, '_>) {
-        match &packet.payload {
-            PacketType::Position(pos) => {
-                if !self.trails.contains_key(&pos.pid) {
-                    self.trails.insert(pos.pid, vec![]);
-                }
-                self.trails
-                    .get_mut(&pos.pid)
-                    .unwrap()
-                    .push((pos.position.x, pos.position.z));
+/// A ship sunk during the battle, as listed in the kill feed
+struct Kill {
+    clock: GameClock,
+    /// Entity ID of the ship which sank the victim
+    killer: u32,
+    /// Entity ID of the ship which was sunk
+    victim: u32,
+    cause: DeathCause,
+}
+
+/// A message sent in the battle's chat
+struct ChatMessage {
+    clock: GameClock,
+    /// Entity ID of the sender's ship
+    sender: u32,
+    /// Whether everyone in the battle could read the message, rather than only the
+    /// sender's team
+    is_global: bool,
+    text: String,
+}
+
+/// Text to draw over the map. Labels are collected while drawing everything else and
+/// placed together at the end, see [TrailRenderer::draw_labels].
+struct Label {
+    text: String,
+    /// Where the label's top left corner would be drawn if nothing was in its way
+    position: (f64, f64),
+    size: u32,
+    color: RGBAColor,
+}
+
+impl Label {
+    fn new<C: Color>(text: String, position: (f64, f64), size: u32, color: &C) -> Self {
+        Label {
+            text,
+            position,
+            size,
+            color: color.to_rgba(),
+        }
+    }
+}
+
+/// Bounds of a label in pixels
+#[derive(Debug, Clone, Copy)]
+struct LabelBox {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl LabelBox {
+    fn shifted(self, shift: i32) -> Self {
+        LabelBox {
+            y: self.y + shift,
+            ..self
+        }
+    }
+
+    fn overlaps(&self, other: &LabelBox) -> bool {
+        self.x < other.x + other.width + LABEL_SPACING
+            && other.x < self.x + self.width + LABEL_SPACING
+            && self.y < other.y + other.height + LABEL_SPACING
+            && other.y < self.y + self.height + LABEL_SPACING
+    }
+}
+
+/// How many pixels to move each label up or down so that none of them overlap. Labels
+/// are placed in order, each into the nearest free row above or below where it would
+/// have been drawn. A label with no free row within [MAX_LABEL_SHIFT] rows stays put.
+fn place_labels(boxes: &[LabelBox]) -> Vec<i32> {
+    let mut placed: Vec<LabelBox> = vec![];
+    boxes
+        .iter()
+        .map(|label| {
+            let step = label.height + LABEL_SPACING;
+            let shift = std::iter::once(0)
+                .chain((1..=MAX_LABEL_SHIFT).flat_map(|row| vec![-row * step, row * step]))
+                .find(|shift| {
+                    let moved = label.shifted(*shift);
+                    !placed.iter().any(|other| other.overlaps(&moved))
+                })
+                .unwrap_or(0);
+            placed.push(label.shifted(shift));
+            shift
+        })
+        .collect()
+}
+
+/// A quick command sent by a player
+struct Ping {
+    clock: GameClock,
+    /// Entity ID of the sender's ship
+    sender: u32,
+    is_global: bool,
+    message: VoiceLine,
+}
+
+/// A torpedo's run, from when the client was told about it to where it stopped
+struct TorpedoTrack {
+    /// Entity ID of the ship which launched the torpedo
+    owner: u32,
+    shot_id: u16,
+    /// (clock, x, z) of where the torpedo was launched
+    start: (f32, f32, f32),
+    /// (clock, x, z) of where the torpedo stopped, if it did before the replay ended
+    end: Option<(f32, f32, f32)>,
+}
+
+impl TrailRenderer {
+    /// Whether the ship is on the side the [RenderOptions::perspective] is drawn from
+    fn is_viewer(&self, entity_id: u32) -> bool {
+        matches!(
+            (self.options.perspective, self.teams.relation(entity_id)),
+            (Perspective::Ally, Relation::Own | Relation::Friendly)
+                | (Perspective::Enemy, Relation::Enemy)
+        )
+    }
+
+    /// Whether the ship is an enemy of the side the [RenderOptions::perspective] is drawn
+    /// from, and so could be undetected by it
+    fn is_opponent(&self, entity_id: u32) -> bool {
+        matches!(
+            (self.options.perspective, self.teams.relation(entity_id)),
+            (Perspective::Ally, Relation::Enemy)
+                | (Perspective::Enemy, Relation::Own | Relation::Friendly)
+        )
+    }
+
+    /// The color of the ship's team, see [Teams::color], or white if its team isn't known
+    fn team_color(&self, entity_id: u32) -> RGBColor {
+        match self.teams.team_of(entity_id) {
+            Some(team_id) => {
+                let (r, g, b) = self.teams.color(team_id);
+                RGBColor(r, g, b)
             }
-            PacketType::PlayerOrientation(pos) => {
-                self.player_trail.push((pos.position.x, pos.position.z));
+            None => WHITE,
+        }
+    }
+
+    /// Whether the ship should be drawn at `clock`. With
+    /// [RenderOptions::fog_of_war] opponents are only drawn while they're spotted, and
+    /// before they're first spotted they're assumed to be undetected.
+    fn is_visible(&self, entity_id: u32, clock: f32) -> bool {
+        if !self.options.fog_of_war || !self.is_opponent(entity_id) {
+            return true;
+        }
+        self.visibility
+            .get(&entity_id)
+            .and_then(|changes| {
+                changes
+                    .iter()
+                    .rev()
+                    .find(|(changed_at, _visible)| *changed_at <= clock)
+            })
+            .is_some_and(|(_changed_at, visible)| *visible)
+    }
+
+    /// Where the ship was at `clock`, or where it was last seen if it had disappeared by
+    /// then
+    fn position_at(&self, entity_id: u32, clock: GameClock) -> Option<(f64, f64)> {
+        let trail = self.trails.get(&entity_id)?;
+        trail
+            .iter()
+            .find(|(point_clock, _x, _y)| *point_clock >= clock.seconds())
+            .or_else(|| trail.last())
+            .map(|(_clock, x, y)| (*x as f64, *y as f64))
+    }
+
+    /// Where the recording player's ship was at `clock`
+    fn player_position_at(&self, clock: GameClock) -> Option<(f64, f64)> {
+        self.player_trail
+            .iter()
+            .find(|(point_clock, _x, _y)| *point_clock >= clock.seconds())
+            .or_else(|| self.player_trail.last())
+            .map(|(_clock, x, y)| (*x as f64, *y as f64))
+    }
+
+    /// Where the ships of the side the [RenderOptions::perspective] is drawn from were at
+    /// `clock`
+    fn viewer_positions_at(&self, clock: GameClock) -> Vec<(f64, f64)> {
+        let mut positions: Vec<_> = self
+            .trails
+            .keys()
+            .filter(|entity_id| self.is_viewer(**entity_id))
+            .filter_map(|entity_id| self.position_at(*entity_id, clock))
+            .collect();
+        // The player's own ship moves with orientation packets rather than positions
+        if self.options.perspective == Perspective::Ally {
+            positions.extend(self.player_position_at(clock));
+        }
+        positions
+    }
+
+    /// The first point along a torpedo's run, sampled every second, which is within
+    /// [TORPEDO_DETECTION_KM] of one of the ships of the side the
+    /// [RenderOptions::perspective] is drawn from
+    fn torpedo_spotted_at(
+        &self,
+        start: (f32, f32, f32),
+        end: (f32, f32, f32),
+    ) -> Option<(f64, f64)> {
+        let range = TORPEDO_DETECTION_KM * WORLD_UNITS_PER_KM;
+        let duration = (end.0 - start.0).max(0.0);
+        let samples = duration.ceil() as usize;
+        (0..=samples).find_map(|sample| {
+            let t = if duration > 0.0 {
+                (sample as f32 / duration).min(1.0)
+            } else {
+                1.0
+            };
+            let x = (start.1 + (end.1 - start.1) * t) as f64;
+            let y = (start.2 + (end.2 - start.2) * t) as f64;
+            let clock = GameClock(start.0 + (end.0 - start.0) * t);
+            self.viewer_positions_at(clock)
+                .iter()
+                .any(|(ship_x, ship_y)| {
+                    ((ship_x - x).powi(2) + (ship_y - y).powi(2)).sqrt() <= range
+                })
+                .then_some((x, y))
+        })
+    }
+
+    /// Loads the map in the style set by [RenderOptions::background]
+    fn load_background(&self, map_name: &str) -> image::ImageResult<image::DynamicImage> {
+        let composited = || match &self.options.asset_cache {
+            Some(cache) => {
+                assets::load_map_image_cached(cache, self.version.build, map_name, IMAGE_SIZE)
             }
-            _ => {}
+            None => assets::load_map_image(map_name, IMAGE_SIZE),
+        };
+        match &self.options.background {
+            MapBackground::Composited => composited(),
+            MapBackground::Darkened => {
+                composited().map(|image| assets::darken(&image, assets::DARKEN_FACTOR))
+            }
+            MapBackground::Outlines => assets::load_island_outlines(map_name, IMAGE_SIZE),
+            MapBackground::Custom(path) => assets::load_custom_background(path, IMAGE_SIZE),
         }
     }
 
-    fn finish(&mut self) {
-        // Setup the render context
-        let root = BitMapBackend::new(&self.output, (2048, 2048)).into_drawing_area();
-        root.fill(&BLACK).unwrap();
+    /// Draws the layers onto `root` in order, timing each into `timings`. Returns where
+    /// each ship ended up on the image, see [RenderOptions::positions_sidecar].
+    fn draw_layers<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        layers: &[Layer],
+        timings: &mut Vec<(&'static str, Duration)>,
+    ) -> Vec<ShipPosition> {
+        let map_name = &self.meta.as_ref().unwrap().mapName;
+        let scale = assets::map_half_width(map_name)
+            .unwrap_or_else(|| panic!("Could not find size of map {}!", map_name));
+        let mut scatter_ctx = ChartBuilder::on(root)
+            .x_label_area_size(0)
+            .y_label_area_size(0)
+            .build_cartesian_2d(-scale..scale, -scale..scale)
+            .unwrap();
+
+        let mut labels = vec![];
+        for layer in layers {
+            let start = Instant::now();
+            match layer {
+                Layer::Background => self.draw_background(root, map_name),
+                Layer::Trails => self.draw_trails(&mut scatter_ctx),
+                Layer::ViewRange => self.draw_view_range(&mut scatter_ctx, scale),
+                Layer::Ghosts => self.draw_ghosts(&mut scatter_ctx, &mut labels),
+                Layer::Torpedoes => self.draw_torpedoes(&mut scatter_ctx),
+                Layer::Pings => self.draw_pings(&mut scatter_ctx, scale, &mut labels),
+                Layer::Ships => self.draw_ship_icons(&mut scatter_ctx, scale),
+                Layer::Consumables => self.draw_consumable_warnings(&mut scatter_ctx, &mut labels),
+                Layer::Phases => self.draw_phase_markers(&mut scatter_ctx, &mut labels),
+                Layer::Annotations => self.draw_annotations(&mut scatter_ctx, &mut labels),
+                Layer::Labels => self.draw_labels(&mut scatter_ctx, &std::mem::take(&mut labels)),
+                Layer::KillFeed => self.draw_kill_feed(root),
+                Layer::Chat => self.draw_chat(root),
+                Layer::TeamGraph => self.draw_team_graph(root),
+            }
+            timings.push((layer.name(), start.elapsed()));
+        }
+        if layers.contains(&Layer::Labels) {
+            let start = Instant::now();
+            self.draw_labels(&mut scatter_ctx, &labels);
+            timings.push((Layer::Labels.name(), start.elapsed()));
+        }
+
+        self.ship_positions(&scatter_ctx)
+    }
 
-        // Blit the background into the image
+    /// Draws the layers onto a transparent PNG at `path`. The bitmap backend can't draw
+    /// transparency, so the layers are drawn once over black and once over white, and
+    /// each pixel's opacity is worked out from how much the two differ.
+    fn render_transparent(
+        &self,
+        path: &Path,
+        layers: &[Layer],
+        timings: &mut Vec<(&'static str, Duration)>,
+    ) -> Vec<ShipPosition> {
+        let size = (IMAGE_SIZE * IMAGE_SIZE * 3) as usize;
+        let mut on_black = vec![0; size];
+        let mut on_white = vec![255; size];
+        let positions = {
+            let root = BitMapBackend::with_buffer(&mut on_black, (IMAGE_SIZE, IMAGE_SIZE))
+                .into_drawing_area();
+            self.draw_layers(&root, layers, timings)
+        };
         {
-            let minimap = image::load(
-                std::io::BufReader::new(
-                    std::fs::File::open(&format!(
-                        "versions/0.10.3/{}/minimap.png",
-                        self.meta.as_ref().unwrap().mapName
-                    ))
-                    .unwrap(),
-                ),
-                ImageFormat::Png,
-            )
+            let root = BitMapBackend::with_buffer(&mut on_white, (IMAGE_SIZE, IMAGE_SIZE))
+                .into_drawing_area();
+            self.draw_layers(&root, layers, timings);
+        }
+
+        let start = Instant::now();
+        unblend(&on_black, &on_white, IMAGE_SIZE)
+            .save(path)
             .unwrap();
-            let minimap_background = image::load(
-                std::io::BufReader::new(
-                    std::fs::File::open(&format!(
-                        "versions/0.10.3/{}/minimap_water.png",
-                        self.meta.as_ref().unwrap().mapName
-                    ))
-                    .unwrap(),
-                ),
-                ImageFormat::Png,
-            )
+        timings.push(("encode", start.elapsed()));
+        positions
+    }
+
+    /// Draws the most recent kills in the top right corner, see [RenderOptions::kill_feed]
+    fn draw_kill_feed<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) {
+        if !self.options.kill_feed {
+            return;
+        }
+        let name = |entity_id: u32| {
+            self.usernames
+                .get(&entity_id)
+                .map(String::as_str)
+                .unwrap_or("?")
+                .to_string()
+        };
+        let lines: Vec<_> = self
+            .kills
+            .iter()
+            .map(|kill| {
+                let text = format!(
+                    "{} {} sank {} ({})",
+                    kill.clock,
+                    name(kill.killer),
+                    name(kill.victim),
+                    death_cause_text(kill.cause)
+                );
+                (text, self.team_color(kill.killer))
+            })
+            .collect();
+        draw_hud_box(root, &lines, true);
+    }
+
+    /// Draws the most recent chat messages in the top left corner, see
+    /// [RenderOptions::chat]
+    fn draw_chat<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) {
+        if !self.options.chat {
+            return;
+        }
+        let lines: Vec<_> = self
+            .chat
+            .iter()
+            .filter(|message| {
+                !self.options.fog_of_war || message.is_global || !self.is_opponent(message.sender)
+            })
+            .map(|message| {
+                let text = format!(
+                    "{} {}: {}",
+                    message.clock,
+                    self.usernames
+                        .get(&message.sender)
+                        .map(String::as_str)
+                        .unwrap_or("?"),
+                    message.text
+                );
+                (text, self.team_color(message.sender))
+            })
+            .collect();
+        draw_hud_box(root, &lines, false);
+    }
+
+    /// Draws each team's remaining health, as a fraction of its total, in the bottom left
+    /// corner. Scores are drawn fainter, relative to the highest score reached.
+    fn draw_team_graph<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>) {
+        let end = match self.timeline.last() {
+            Some(sample) => sample.timestamp().as_secs_f64(),
+            None => return,
+        };
+        let max_score = self
+            .timeline
+            .iter()
+            .flat_map(|sample| sample.teams())
+            .filter_map(|team| team.score())
+            .max()
+            .unwrap_or(0);
+        let team_ids: BTreeSet<u32> = self
+            .timeline
+            .iter()
+            .flat_map(|sample| sample.teams())
+            .map(|team| team.team_id())
+            .collect();
+
+        let area = root.clone().shrink(
+            (16, IMAGE_SIZE - GRAPH_HEIGHT - 16),
+            (GRAPH_WIDTH, GRAPH_HEIGHT),
+        );
+        area.fill(&BLACK.mix(0.6)).unwrap();
+        let mut ctx = ChartBuilder::on(&area)
+            .margin(8)
+            .x_label_area_size(0)
+            .y_label_area_size(0)
+            .build_cartesian_2d(0.0..end, 0.0..1.0)
             .unwrap();
 
-            let mut image = RgbImage::new(760, 760);
-            for x in 0..760 {
-                for y in 0..760 {
-                    let bg = minimap_background.get_pixel(x, y);
-                    let fg = minimap.get_pixel(x, y);
-                    let mut bg = bg.clone();
-                    bg.blend(&fg);
-                    image.put_pixel(x, y, bg.to_rgb());
-                }
-            }
-            let image = image::DynamicImage::ImageRgb8(image);
-            let image = image.resize_exact(2048, 2048, FilterType::Lanczos3);
+        for team_id in team_ids {
+            let (r, g, b) = self.teams.color(team_id);
+            let color = RGBColor(r, g, b);
+            let health = self.timeline.iter().filter_map(|sample| {
+                let team = sample.team(team_id)?;
+                (team.max_health() > 0.0).then(|| {
+                    (
+                        sample.timestamp().as_secs_f64(),
+                        (team.health() / team.max_health()) as f64,
+                    )
+                })
+            });
+            ctx.draw_series(LineSeries::new(health, color.stroke_width(3)))
+                .unwrap();
 
-            let mut ctx = ChartBuilder::on(&root)
-                .x_label_area_size(0)
-                .y_label_area_size(0)
-                .build_cartesian_2d(0.0..1.0, 0.0..1.0)
+            if max_score == 0 {
+                continue;
+            }
+            let score = self.timeline.iter().filter_map(|sample| {
+                let score = sample.team(team_id)?.score()?;
+                Some((
+                    sample.timestamp().as_secs_f64(),
+                    score as f64 / max_score as f64,
+                ))
+            });
+            ctx.draw_series(LineSeries::new(score, color.mix(0.5).stroke_width(1)))
                 .unwrap();
+        }
+    }
 
-            let elem: BitMapElement<_> = ((0.0, 1.0), image).into();
-            ctx.draw_series(std::iter::once(elem)).unwrap();
-        }
-
-        // Render the actual trails
-
-        let mut map_widths: HashMap<String, u32> = HashMap::new();
-        map_widths.insert("spaces/34_OC_islands".to_string(), 24);
-        map_widths.insert("spaces/33_new_tierra".to_string(), 24);
-        map_widths.insert("spaces/01_solomon_islands".to_string(), 30);
-        map_widths.insert("spaces/10_NE_big_race".to_string(), 30);
-        map_widths.insert("spaces/04_Archipelago".to_string(), 30);
-        map_widths.insert("spaces/05_Ring".to_string(), 36);
-        map_widths.insert("spaces/08_NE_passage".to_string(), 36);
-        map_widths.insert("spaces/13_OC_new_dawn".to_string(), 36);
-        map_widths.insert("spaces/17_NA_fault_line".to_string(), 42);
-        map_widths.insert("spaces/41_Conquest".to_string(), 42);
-        map_widths.insert("spaces/46_Estuary".to_string(), 42);
-        map_widths.insert("spaces/42_Neighbors".to_string(), 42);
-        map_widths.insert("spaces/50_Gold_harbor".to_string(), 42);
-        map_widths.insert("spaces/20_NE_two_brothers".to_string(), 42);
-        map_widths.insert("spaces/16_OC_bees_to_honey".to_string(), 48);
-        map_widths.insert("spaces/22_tierra_del_fuego".to_string(), 48);
-        map_widths.insert("spaces/15_NE_north".to_string(), 48);
-        map_widths.insert("spaces/35_NE_north_winter".to_string(), 48);
-        map_widths.insert("spaces/53_Shoreside".to_string(), 42);
-        map_widths.insert("spaces/23_Shards".to_string(), 42);
-        map_widths.insert("spaces/19_OC_prey".to_string(), 42);
-        map_widths.insert("spaces/52_Britain".to_string(), 42);
-        map_widths.insert("spaces/40_Okinawa".to_string(), 42);
-        map_widths.insert("spaces/18_NE_ice_islands".to_string(), 42);
-        map_widths.insert("spaces/14_Atlantic".to_string(), 42);
-        map_widths.insert("spaces/38_Canada".to_string(), 48);
-        map_widths.insert("spaces/37_Ridge".to_string(), 48);
-        map_widths.insert("spaces/44_Path_warrior".to_string(), 48);
-        map_widths.insert("spaces/25_sea_hope".to_string(), 48);
-        map_widths.insert("spaces/45_Zigzag".to_string(), 48);
-        map_widths.insert("spaces/47_Sleeping_Giant".to_string(), 48);
-        map_widths.insert("spaces/51_Greece".to_string(), 42);
-        map_widths.insert("spaces/28_naval_mission".to_string(), 42);
-        map_widths.insert("spaces/00_CO_ocean".to_string(), 36);
-
-        // 600 for New Dawn (36x36km)
-        // 700 for Fault Line (42x42km)
-        let scale = map_widths
-            .get(&self.meta.as_ref().unwrap().mapName)
-            .expect(&format!(
-                "Could not find size of map {}!",
-                self.meta.as_ref().unwrap().mapName
-            ))
-            * 50
-            / 3;
-        let scale = scale as f64;
-        let mut scatter_ctx = ChartBuilder::on(&root)
+    /// Draws the map in the [RenderOptions::background] style
+    fn draw_background<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>, map_name: &str) {
+        let image = self.load_background(map_name).unwrap();
+
+        let mut ctx = ChartBuilder::on(root)
             .x_label_area_size(0)
             .y_label_area_size(0)
-            .build_cartesian_2d(-scale..scale, -scale..scale)
+            .build_cartesian_2d(0.0..1.0, 0.0..1.0)
             .unwrap();
 
+        let elem = bitmap_element((0.0, 1.0), &image);
+        ctx.draw_series(std::iter::once(elem)).unwrap();
+    }
+
+    /// Draws where each ship went, in its team's color
+    fn draw_trails<DB: DrawingBackend>(
+        &self,
+        scatter_ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+    ) {
         let colors = [BLUE, CYAN, GREEN, MAGENTA, RED, WHITE, YELLOW];
         let mut min_x = 0.;
         let mut max_x = 0.;
-        for (i, (_k, v)) in self.trails.iter().enumerate() {
+        for (i, (k, v)) in self.trails.iter().enumerate() {
             //println!("{}", v.len());
             let series_minx = v
                 .iter()
-                .map(|(x, _y)| x)
+                .map(|(_clock, x, _y)| x)
                 .min_by(|a, b| a.partial_cmp(b).unwrap())
                 .unwrap();
             let series_maxx = v
                 .iter()
-                .map(|(x, _y)| x)
+                .map(|(_clock, x, _y)| x)
                 .max_by(|a, b| a.partial_cmp(b).unwrap())
                 .unwrap();
             if *series_minx < min_x {
@@ -185,20 +841,1129 @@ impl AnalyzerMut for TrailRenderer {
             if *series_maxx > max_x {
                 max_x = *series_maxx;
             }
+            // Ships are drawn in their team's color, so that battles with any number of
+            // teams can be told apart
+            let color = match self.teams.team_of(*k) {
+                Some(team_id) => {
+                    let (r, g, b) = self.teams.color(team_id);
+                    RGBColor(r, g, b)
+                }
+                None => colors[i % colors.len()],
+            };
             scatter_ctx
-                .draw_series(v.iter().map(|(x, y)| {
-                    Circle::new((*x as f64, *y as f64), 1, colors[i % colors.len()].filled())
-                }))
+                .draw_series(
+                    v.iter()
+                        .filter(|(clock, _x, _y)| self.is_visible(*k, *clock))
+                        .map(|(_clock, x, y)| {
+                            Circle::new((*x as f64, *y as f64), 1, color.filled())
+                        }),
+                )
                 .unwrap();
         }
 
         // Add the trail for the player
+        let self_id = self.self_id;
         scatter_ctx
             .draw_series(
                 self.player_trail
                     .iter()
-                    .map(|(x, y)| Circle::new((*x as f64, *y as f64), 2, WHITE.filled())),
+                    .filter(|(clock, _x, _y)| match self_id {
+                        Some(self_id) => self.is_visible(self_id, *clock),
+                        None => true,
+                    })
+                    .map(|(_clock, x, y)| Circle::new((*x as f64, *y as f64), 2, WHITE.filled())),
             )
             .unwrap();
     }
+
+    /// Places the labels so that they don't overlap and draws them. Labels which had to
+    /// be moved are joined to where they would have been drawn with a leader line.
+    fn draw_labels<DB: DrawingBackend>(
+        &self,
+        ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+        labels: &[Label],
+    ) {
+        let boxes: Vec<_> = labels
+            .iter()
+            .map(|label| {
+                let (x, y) = ctx.backend_coord(&label.position);
+                let (width, height) = ctx
+                    .plotting_area()
+                    .estimate_text_size(
+                        &label.text,
+                        &("sans-serif", label.size).into_font().color(&label.color),
+                    )
+                    .unwrap_or((0, label.size));
+                LabelBox {
+                    x,
+                    y,
+                    width: width as i32,
+                    height: height as i32,
+                }
+            })
+            .collect();
+        for ((label, label_box), shift) in labels.iter().zip(&boxes).zip(place_labels(&boxes)) {
+            if shift != 0 {
+                // Lead to the nearest edge of the moved label
+                let end = if shift < 0 {
+                    shift + label_box.height
+                } else {
+                    shift
+                };
+                ctx.draw_series(std::iter::once(
+                    EmptyElement::at(label.position)
+                        + PathElement::new(vec![(0, 0), (0, end)], label.color.stroke_width(1)),
+                ))
+                .unwrap();
+            }
+            self.draw_label(ctx, label, shift);
+        }
+    }
+
+    /// Draws the label `shift` pixels below its position, set off from the map in the
+    /// [RenderOptions::label_style]
+    fn draw_label<DB: DrawingBackend>(
+        &self,
+        ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+        label: &Label,
+        shift: i32,
+    ) {
+        let font = ("sans-serif", label.size).into_font();
+        let offsets: &[(i32, i32)] = match self.options.label_style {
+            LabelStyle::Plain | LabelStyle::Pill => &[],
+            LabelStyle::Shadow => &[(2, 2)],
+            LabelStyle::Outline => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+        };
+        if self.options.label_style == LabelStyle::Pill {
+            let (width, height) = ctx
+                .plotting_area()
+                .estimate_text_size(&label.text, &font.color(&label.color))
+                .unwrap_or((0, label.size));
+            let (width, height) = (width as i32, height as i32);
+            let radius = (height / 2 + LABEL_PADDING) as u32;
+            let middle = shift + height / 2;
+            let pill = EmptyElement::at(label.position)
+                + Rectangle::new(
+                    [
+                        (0, shift - LABEL_PADDING),
+                        (width, shift + height + LABEL_PADDING),
+                    ],
+                    LABEL_BACKDROP.filled(),
+                )
+                + Circle::new((0, middle), radius, LABEL_BACKDROP.filled())
+                + Circle::new((width, middle), radius, LABEL_BACKDROP.filled());
+            ctx.draw_series(std::iter::once(pill)).unwrap();
+        }
+        for (dx, dy) in offsets {
+            ctx.draw_series(std::iter::once(
+                EmptyElement::at(label.position)
+                    + Text::new(
+                        label.text.clone(),
+                        (*dx, shift + dy),
+                        font.color(&LABEL_BACKDROP),
+                    ),
+            ))
+            .unwrap();
+        }
+        ctx.draw_series(std::iter::once(
+            EmptyElement::at(label.position)
+                + Text::new(label.text.clone(), (0, shift), font.color(&label.color)),
+        ))
+        .unwrap();
+    }
+
+    /// Draws each ship's icon where it was last seen. `scale` is the map's half-width in
+    /// world units.
+    fn draw_ship_icons<DB: DrawingBackend>(
+        &self,
+        ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+        scale: f64,
+    ) {
+        let class_icons = match &self.options.class_icons {
+            Some(class_icons) => class_icons,
+            None => return,
+        };
+        let mut icons: HashMap<ShipIcon, Option<image::DynamicImage>> = HashMap::new();
+        // Icons are positioned by their top left corner
+        let offset = ICON_SIZE as f64 / 2.0 / Self::pixels_per_unit(scale);
+        for (entity_id, trail) in &self.trails {
+            let (ship_id, (clock, x, y)) = match (self.ship_ids.get(entity_id), trail.last()) {
+                (Some(ship_id), Some(position)) => (*ship_id, position),
+                _ => continue,
+            };
+            if !self.is_visible(*entity_id, *clock) {
+                continue;
+            }
+            let class = match self.options.ship_classes.get(&ship_id) {
+                Some(class) => *class,
+                None => continue,
+            };
+            let variant = IconVariant::from_relation(
+                self.teams.relation(*entity_id),
+                !self.destroyed.contains(entity_id),
+            );
+            let icon =
+                self.options
+                    .silhouettes
+                    .icon(ship_id, class, variant, self.options.icon_style);
+            let image = icons.entry(icon).or_insert_with(|| {
+                let path = match icon {
+                    ShipIcon::Silhouette(ship_id) => {
+                        self.options.silhouettes.path(ship_id)?.to_path_buf()
+                    }
+                    ShipIcon::Class(key) => class_icons.join(format!("{}.png", key)),
+                };
+                assets::load_icon(&path, ICON_SIZE).ok()
+            });
+            if let Some(image) = image {
+                let elem = bitmap_element((*x as f64 - offset, *y as f64 + offset), image);
+                ctx.draw_series(std::iter::once(elem)).unwrap();
+            }
+        }
+    }
+
+    /// Pixels per world unit, where `scale` is the map's half-width in world units
+    fn pixels_per_unit(scale: f64) -> f64 {
+        IMAGE_SIZE as f64 / (2.0 * scale)
+    }
+
+    /// Marks each spot where an enemy ship went undetected, with how long it stayed hidden
+    /// or `?` if it was never seen again
+    fn draw_ghosts<DB: DrawingBackend>(
+        &self,
+        ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+        labels: &mut Vec<Label>,
+    ) {
+        if !self.options.ghosts {
+            return;
+        }
+        for (entity_id, changes) in &self.visibility {
+            if !self.is_opponent(*entity_id) {
+                continue;
+            }
+            let (trail, team_id) =
+                match (self.trails.get(entity_id), self.teams.team_of(*entity_id)) {
+                    (Some(trail), Some(team_id)) => (trail, team_id),
+                    _ => continue,
+                };
+            let (r, g, b) = self.teams.color(team_id);
+            let color = RGBColor(r, g, b).mix(0.5);
+            for (idx, (hidden_at, visible)) in changes.iter().enumerate() {
+                if *visible {
+                    continue;
+                }
+                let last_seen = trail
+                    .iter()
+                    .rev()
+                    .find(|(clock, _x, _y)| clock <= hidden_at);
+                let position = match last_seen {
+                    Some((_clock, x, y)) => (*x as f64, *y as f64),
+                    None => continue,
+                };
+                let label = match changes[idx + 1..].iter().find(|(_clock, visible)| *visible) {
+                    Some((seen_at, _visible)) => format!("{:.0}s", seen_at - hidden_at),
+                    None => "?".to_string(),
+                };
+                ctx.draw_series(std::iter::once(Circle::new(
+                    position,
+                    8,
+                    color.stroke_width(2),
+                )))
+                .unwrap();
+                labels.push(Label::new(label, position, 20, &color));
+            }
+        }
+    }
+
+    fn draw_view_range<DB: DrawingBackend>(
+        &self,
+        ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+        scale: f64,
+    ) {
+        let (range, (_clock, x, y)) = match (self.options.view_range, self.player_trail.last()) {
+            (Some(range), Some(position)) => (range, position),
+            _ => return,
+        };
+        let radius = range as f64 * WORLD_UNITS_PER_KM * Self::pixels_per_unit(scale);
+        ctx.draw_series(std::iter::once(Circle::new(
+            (*x as f64, *y as f64),
+            radius as i32,
+            WHITE.mix(0.6).stroke_width(2),
+        )))
+        .unwrap();
+    }
+
+    /// Rings the spots where enemy ships' radar and hydro came off cooldown, since a
+    /// ship which has been there may be able to spot nearby ships again
+    fn draw_consumable_warnings<DB: DrawingBackend>(
+        &self,
+        ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+        labels: &mut Vec<Label>,
+    ) {
+        for timeline in &self.consumables {
+            let (color, label) = match timeline.consumable() {
+                Consumable::Radar => (YELLOW, "R"),
+                Consumable::HydroacousticSearch => (CYAN, "H"),
+                _ => continue,
+            };
+            if !self.is_opponent(timeline.entity_id()) {
+                continue;
+            }
+            let trail = match self.trails.get(&timeline.entity_id()) {
+                Some(trail) if !trail.is_empty() => trail,
+                _ => continue,
+            };
+
+            // The consumable is ready at the start of the battle, which isn't worth a
+            // warning
+            let ready_at = timeline
+                .windows()
+                .iter()
+                .filter(|window| window.state() == ConsumableState::Ready)
+                .map(|window| window.start())
+                .filter(|start| *start > Duration::ZERO);
+            for ready_at in ready_at {
+                let (clock, x, y) = trail
+                    .iter()
+                    .find(|(clock, _x, _y)| Duration::from_secs_f32(*clock) >= ready_at)
+                    .unwrap_or_else(|| trail.last().unwrap());
+                if !self.is_visible(timeline.entity_id(), *clock) {
+                    continue;
+                }
+                let position = (*x as f64, *y as f64);
+                ctx.draw_series(std::iter::once(Circle::new(
+                    position,
+                    12,
+                    color.stroke_width(3),
+                )))
+                .unwrap();
+                labels.push(Label::new(label.to_string(), position, 24, &color));
+            }
+        }
+    }
+
+    /// Chapter markers along the recording player's trail, one per phase of the battle
+    fn draw_phase_markers<DB: DrawingBackend>(
+        &self,
+        ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+        labels: &mut Vec<Label>,
+    ) {
+        for phase in &self.phases {
+            let (_clock, x, y) = match self
+                .player_trail
+                .iter()
+                .find(|(clock, _x, _y)| Duration::from_secs_f32(*clock) >= phase.start())
+                .or_else(|| self.player_trail.last())
+            {
+                Some(point) => point,
+                None => return,
+            };
+            let position = (*x as f64, *y as f64);
+            ctx.draw_series(std::iter::once(Cross::new(
+                position,
+                10,
+                WHITE.stroke_width(3),
+            )))
+            .unwrap();
+            labels.push(Label::new(phase.kind().to_string(), position, 28, &WHITE));
+        }
+    }
+
+    /// Draws each quick command where it was sent from, or where it pointed to with a line
+    /// from the sender's ship, in the sender's team's color. `scale` is the map's
+    /// half-width in world units.
+    fn draw_pings<DB: DrawingBackend>(
+        &self,
+        ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+        scale: f64,
+        labels: &mut Vec<Label>,
+    ) {
+        if !self.options.pings {
+            return;
+        }
+        for ping in &self.pings {
+            if self.options.fog_of_war && !ping.is_global && self.is_opponent(ping.sender) {
+                continue;
+            }
+            let from = if Some(ping.sender) == self.self_id {
+                self.player_position_at(ping.clock)
+            } else {
+                self.position_at(ping.sender, ping.clock)
+            };
+            let target = match ping.message {
+                VoiceLine::MapPointAttention(x, y) => Some((x as f64, y as f64)),
+                VoiceLine::AttentionToSquare(row, column) => GridSquare::from_indices(row, column)
+                    .map(|square| square.center_world(2.0 * scale as f32))
+                    .map(|(x, z)| (x as f64, z as f64)),
+                _ => None,
+            };
+            let color = self.team_color(ping.sender);
+            let label = format!(
+                "{} {}: {}",
+                ping.clock,
+                self.usernames
+                    .get(&ping.sender)
+                    .map(String::as_str)
+                    .unwrap_or("?"),
+                voice_line_text(&ping.message)
+            );
+            let position = match (from, target) {
+                (Some(from), Some(target)) => {
+                    ctx.draw_series(std::iter::once(PathElement::new(
+                        vec![from, target],
+                        color.stroke_width(2),
+                    )))
+                    .unwrap();
+                    target
+                }
+                (_, Some(target)) => target,
+                (Some(from), None) => from,
+                (None, None) => continue,
+            };
+            if target.is_some() {
+                ctx.draw_series(std::iter::once(Circle::new(
+                    position,
+                    12,
+                    color.stroke_width(2),
+                )))
+                .unwrap();
+            }
+            labels.push(Label::new(label, position, 20, &color));
+        }
+    }
+
+    /// Draws each torpedo's run as a line in its team's color, ending in a dot where it
+    /// stopped. Torpedoes which were still running when the replay ended aren't drawn.
+    fn draw_torpedoes<DB: DrawingBackend>(
+        &self,
+        ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+    ) {
+        if !self.options.torpedoes {
+            return;
+        }
+        for torpedo in &self.torpedoes {
+            let end = match torpedo.end {
+                Some(end) => end,
+                None => continue,
+            };
+            let from = if (self.options.spotted_torpedoes_only || self.options.fog_of_war)
+                && self.is_opponent(torpedo.owner)
+            {
+                match self.torpedo_spotted_at(torpedo.start, end) {
+                    Some(spotted_at) => spotted_at,
+                    None => continue,
+                }
+            } else {
+                (torpedo.start.1 as f64, torpedo.start.2 as f64)
+            };
+            let to = (end.1 as f64, end.2 as f64);
+            let color = self.team_color(torpedo.owner);
+            ctx.draw_series(std::iter::once(PathElement::new(
+                vec![from, to],
+                color.mix(0.7).stroke_width(1),
+            )))
+            .unwrap();
+            ctx.draw_series(std::iter::once(Circle::new(to, 2, color.filled())))
+                .unwrap();
+        }
+    }
+
+    /// Where each ship visible at its last position is on the image
+    fn ship_positions<DB: DrawingBackend>(
+        &self,
+        ctx: &ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+    ) -> Vec<ShipPosition> {
+        let mut positions: Vec<_> = self
+            .trails
+            .iter()
+            .filter_map(|(entity_id, trail)| {
+                let (clock, x, y) = trail.last()?;
+                if !self.is_visible(*entity_id, *clock) {
+                    return None;
+                }
+                let (x, y) = ctx.backend_coord(&(*x as f64, *y as f64));
+                Some(ShipPosition {
+                    entity_id: *entity_id,
+                    username: self.usernames.get(entity_id).cloned(),
+                    ship_id: self.ship_ids.get(entity_id).copied(),
+                    team_id: self.teams.team_of(*entity_id),
+                    x,
+                    y,
+                    destroyed: self.destroyed.contains(entity_id),
+                })
+            })
+            .collect();
+        positions.sort_by_key(|position| position.entity_id);
+        positions
+    }
+
+    fn draw_annotations<DB: DrawingBackend>(
+        &self,
+        ctx: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+        labels: &mut Vec<Label>,
+    ) {
+        for annotation in self.annotations.iter() {
+            match &annotation.kind {
+                AnnotationKind::Callout {
+                    text,
+                    entity_id,
+                    position,
+                } => {
+                    let position = match (entity_id, position) {
+                        (Some(entity_id), _) => self.position_at(*entity_id, annotation.start),
+                        (None, Some((x, y))) => Some((*x as f64, *y as f64)),
+                        (None, None) => None,
+                    };
+                    if let Some(position) = position {
+                        labels.push(Label::new(
+                            format!("{} {}", annotation.start, text),
+                            position,
+                            32,
+                            &WHITE,
+                        ));
+                    }
+                }
+                AnnotationKind::Arrow { from, to } => {
+                    let from = (from.0 as f64, from.1 as f64);
+                    let to = (to.0 as f64, to.1 as f64);
+                    ctx.draw_series(std::iter::once(PathElement::new(
+                        vec![from, to],
+                        WHITE.stroke_width(4),
+                    )))
+                    .unwrap();
+                    // The arrowhead's sides are a tenth of the arrow's length
+                    let (dx, dy) = ((from.0 - to.0) / 10.0, (from.1 - to.1) / 10.0);
+                    let (sin, cos) = std::f64::consts::FRAC_PI_6.sin_cos();
+                    let head = vec![
+                        (to.0 + dx * cos - dy * sin, to.1 + dx * sin + dy * cos),
+                        to,
+                        (to.0 + dx * cos + dy * sin, to.1 - dx * sin + dy * cos),
+                    ];
+                    ctx.draw_series(std::iter::once(PathElement::new(
+                        head,
+                        WHITE.stroke_width(4),
+                    )))
+                    .unwrap();
+                }
+                AnnotationKind::Highlight { entity_id } => {
+                    let trail = match self.trails.get(entity_id) {
+                        Some(trail) => trail,
+                        None => continue,
+                    };
+                    // Retrace the part of the ship's trail while it's highlighted
+                    let highlighted: Vec<_> = trail
+                        .iter()
+                        .filter(|(clock, _x, _y)| annotation.is_active_at(GameClock(*clock)))
+                        .map(|(_clock, x, y)| (*x as f64, *y as f64))
+                        .collect();
+                    ctx.draw_series(std::iter::once(PathElement::new(
+                        highlighted,
+                        YELLOW.stroke_width(5),
+                    )))
+                    .unwrap();
+                    if let Some(position) = self.position_at(*entity_id, annotation.start) {
+                        ctx.draw_series(std::iter::once(Circle::new(
+                            position,
+                            20,
+                            YELLOW.stroke_width(3),
+                        )))
+                        .unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws the last [HUD_MAX_LINES] of `lines` one under another on a dark box in the top
+/// right corner of `root`, or the top left if `right` is false
+fn draw_hud_box<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    lines: &[(String, RGBColor)],
+    right: bool,
+) {
+    let lines = &lines[lines.len().saturating_sub(HUD_MAX_LINES)..];
+    if lines.is_empty() {
+        return;
+    }
+    let font = ("sans-serif", HUD_FONT_SIZE).into_font();
+    let width = lines
+        .iter()
+        .filter_map(|(text, _color)| root.estimate_text_size(text, &font.color(&WHITE)).ok())
+        .map(|(width, _height)| width as i32)
+        .max()
+        .unwrap_or(0);
+    let line_height = HUD_FONT_SIZE as i32 + LABEL_PADDING;
+    let margin = 16;
+    let x = if right {
+        IMAGE_SIZE as i32 - margin - width - 2 * LABEL_PADDING
+    } else {
+        margin
+    };
+    root.draw(&Rectangle::new(
+        [
+            (x, margin),
+            (
+                x + width + 2 * LABEL_PADDING,
+                margin + line_height * lines.len() as i32 + LABEL_PADDING,
+            ),
+        ],
+        BLACK.mix(0.6).filled(),
+    ))
+    .unwrap();
+    for (i, (text, color)) in lines.iter().enumerate() {
+        let y = margin + LABEL_PADDING + line_height * i as i32;
+        root.draw(&Text::new(
+            text.as_str(),
+            (x + LABEL_PADDING, y),
+            font.color(color),
+        ))
+        .unwrap();
+    }
+}
+
+/// How a ship was sunk, as shown in the kill feed
+fn death_cause_text(cause: DeathCause) -> String {
+    match cause {
+        DeathCause::Secondaries => "secondaries".to_string(),
+        DeathCause::Artillery => "artillery".to_string(),
+        DeathCause::Fire => "fire".to_string(),
+        DeathCause::Flooding => "flooding".to_string(),
+        DeathCause::Torpedo => "torpedo".to_string(),
+        DeathCause::DiveBomber => "dive bomber".to_string(),
+        DeathCause::AerialRocket => "rockets".to_string(),
+        DeathCause::AerialTorpedo => "aerial torpedo".to_string(),
+        DeathCause::Detonation => "detonation".to_string(),
+        DeathCause::Ramming => "ramming".to_string(),
+        DeathCause::DepthCharge => "depth charges".to_string(),
+        DeathCause::SkipBombs => "skip bombs".to_string(),
+        DeathCause::Unknown(cause) => format!("unknown cause {}", cause),
+    }
+}
+
+/// Recovers a transparent image from RGB buffers of the same drawing over black and
+/// over white. Where nothing was drawn the two differ by 255 and the pixel is fully
+/// transparent, and where something opaque was drawn they're the same.
+fn unblend(on_black: &[u8], on_white: &[u8], size: u32) -> image::RgbaImage {
+    image::RgbaImage::from_fn(size, size, |x, y| {
+        let idx = ((y * size + x) * 3) as usize;
+        let black = &on_black[idx..idx + 3];
+        let white = &on_white[idx..idx + 3];
+        let difference: u32 = black
+            .iter()
+            .zip(white)
+            .map(|(black, white)| white.saturating_sub(*black) as u32)
+            .sum();
+        let alpha = 255 - difference / 3;
+        if alpha == 0 {
+            return image::Rgba([0, 0, 0, 0]);
+        }
+        // Over black, each channel was scaled down by the opacity
+        let channel = |idx: usize| (black[idx] as u32 * 255 / alpha).min(255) as u8;
+        image::Rgba([channel(0), channel(1), channel(2), alpha as u8])
+    })
+}
+
+/// A table of how long each step of a render took, slowest first. Steps which happen
+/// more than once, such as labels drawn in two passes, are added together.
+fn profile_summary(timings: &[(&'static str, Duration)]) -> String {
+    let mut totals: Vec<(&'static str, Duration)> = vec![];
+    for (step, elapsed) in timings {
+        match totals.iter_mut().find(|(name, _elapsed)| name == step) {
+            Some((_name, total)) => *total += *elapsed,
+            None => totals.push((step, *elapsed)),
+        }
+    }
+    totals.sort_by_key(|(_step, elapsed)| std::cmp::Reverse(*elapsed));
+
+    let total: Duration = totals.iter().map(|(_step, elapsed)| *elapsed).sum();
+    let mut summary = String::from("Render profile:\n");
+    for (step, elapsed) in &totals {
+        let share = if total.is_zero() {
+            0.0
+        } else {
+            elapsed.as_secs_f64() / total.as_secs_f64() * 100.0
+        };
+        summary += &format!("  {:<12} {:>10.1?} {:>5.1}%\n", step, elapsed, share);
+    }
+    summary += &format!("  {:<12} {:>10.1?}\n", "total", total);
+    summary
+}
+
+/// What a quick command says, as shown in the in-game chat
+fn voice_line_text(message: &VoiceLine) -> String {
+    match message {
+        VoiceLine::IntelRequired => "Intel required!".to_string(),
+        VoiceLine::FairWinds => "Fair winds!".to_string(),
+        VoiceLine::Wilco => "Wilco!".to_string(),
+        VoiceLine::Negative => "Negative!".to_string(),
+        VoiceLine::WellDone => "Well done!".to_string(),
+        VoiceLine::Curses => "Curses!".to_string(),
+        VoiceLine::UsingRadar => "Using radar!".to_string(),
+        VoiceLine::UsingHydroSearch => "Using hydroacoustic search!".to_string(),
+        VoiceLine::DefendTheBase => "Defend the base!".to_string(),
+        VoiceLine::SetSmokeScreen => "Set smoke screen!".to_string(),
+        VoiceLine::FollowMe => "Follow me!".to_string(),
+        VoiceLine::MapPointAttention(_, _) => "Attention to the point!".to_string(),
+        VoiceLine::UsingSubmarineLocator => "Using submarine locator!".to_string(),
+        VoiceLine::ProvideAntiAircraft => "Provide anti-aircraft support!".to_string(),
+        VoiceLine::RequestingSupport(_) => "Requesting support!".to_string(),
+        VoiceLine::Retreat(_) => "Retreat!".to_string(),
+        VoiceLine::AttentionToSquare(row, column) => {
+            match GridSquare::from_indices(*row, *column) {
+                Some(square) => format!("Attention to square {}!", square),
+                None => "Attention to square!".to_string(),
+            }
+        }
+        VoiceLine::QuickTactic(_, _) => "Quick tactic".to_string(),
+    }
+}
+
+/// Wraps an image so it can be drawn with its top left corner at `pos`. The image is
+/// copied into a buffer since plotters and this crate depend on different versions of
+/// `image`.
+pub(crate) fn bitmap_element<'a, Coord>(
+    pos: Coord,
+    image: &image::DynamicImage,
+) -> BitMapElement<'a, Coord> {
+    let (width, height) = image.dimensions();
+    BitMapElement::with_owned_buffer(pos, (width, height), image.to_rgb8().into_raw())
+        .expect("RGB buffer matches the image dimensions")
+}
+
+impl AnalyzerMut for TrailRenderer {
+    fn process_mut(&mut self, packet: &Packet<'_, '_>) {
+        match &packet.payload {
+            PacketType::Position(pos) => {
+                if !self.trails.contains_key(&pos.pid) {
+                    self.trails.insert(pos.pid, vec![]);
+                }
+                self.trails.get_mut(&pos.pid).unwrap().push((
+                    packet.clock,
+                    pos.position.x,
+                    pos.position.z,
+                ));
+            }
+            PacketType::PlayerOrientation(pos) => {
+                self.player_trail
+                    .push((packet.clock, pos.position.x, pos.position.z));
+            }
+            _ => {}
+        }
+
+        let decoded = DecodedPacket::from(&self.version, false, packet);
+        match decoded.payload {
+            DecodedPacketPayload::ShipDestroyed {
+                killer,
+                victim,
+                cause,
+            } => {
+                self.destroyed.insert(victim as u32);
+                self.kills.push(Kill {
+                    clock: GameClock(packet.clock),
+                    killer: killer as u32,
+                    victim: victim as u32,
+                    cause,
+                });
+            }
+            DecodedPacketPayload::Chat {
+                sender_id,
+                audience,
+                message,
+                ..
+            } => {
+                if let Some(sender) = self.avatar_ships.get(&(sender_id as u32)) {
+                    self.chat.push(ChatMessage {
+                        clock: GameClock(packet.clock),
+                        sender: *sender,
+                        is_global: audience == "battle_common",
+                        text: message.to_string(),
+                    });
+                }
+            }
+            DecodedPacketPayload::Torpedoes(salvos) => {
+                for salvo in salvos {
+                    for torpedo in salvo.torpedoes {
+                        self.torpedoes.push(TorpedoTrack {
+                            owner: salvo.owner_id as u32,
+                            shot_id: torpedo.shot_id,
+                            start: (packet.clock, torpedo.origin.x, torpedo.origin.z),
+                            end: None,
+                        });
+                    }
+                }
+            }
+            DecodedPacketPayload::ShotKills(packs) => {
+                for pack in packs {
+                    let owner = pack.owner_id as u32;
+                    for kill in pack.kills {
+                        let torpedo = self.torpedoes.iter_mut().find(|torpedo| {
+                            torpedo.end.is_none()
+                                && torpedo.owner == owner
+                                && torpedo.shot_id == kill.shot_id
+                        });
+                        if let Some(torpedo) = torpedo {
+                            torpedo.end = Some((packet.clock, kill.position.x, kill.position.z));
+                        }
+                    }
+                }
+            }
+            DecodedPacketPayload::EntityProperty(prop) if prop.property == "visibilityFlags" => {
+                if let Some(flags) = prop.value.uint_32_ref() {
+                    let changes = self.visibility.entry(prop.entity_id).or_default();
+                    let visible = *flags != 0;
+                    if changes.last().map(|(_clock, was_visible)| *was_visible) != Some(visible) {
+                        changes.push((packet.clock, visible));
+                    }
+                }
+            }
+            DecodedPacketPayload::VoiceLine {
+                sender_id,
+                is_global,
+                message,
+            } => {
+                if let Some(sender) = self.avatar_ships.get(&(sender_id as u32)) {
+                    self.pings.push(Ping {
+                        clock: GameClock(packet.clock),
+                        sender: *sender,
+                        is_global,
+                        message,
+                    });
+                }
+            }
+            DecodedPacketPayload::OnArenaStateReceived { players, .. } => {
+                self.avatar_ships.extend(
+                    players
+                        .iter()
+                        .map(|player| (player.avatar_id as u32, player.entity_id as u32)),
+                );
+                let vehicles = &self.meta.as_ref().unwrap().vehicles;
+                self.ship_ids.extend(players.iter().filter_map(|player| {
+                    let vehicle = vehicles
+                        .iter()
+                        .find(|vehicle| vehicle.id == player.meta_ship_id)?;
+                    Some((player.entity_id as u32, vehicle.shipId as u32))
+                }));
+                self.usernames.extend(
+                    players
+                        .iter()
+                        .map(|player| (player.entity_id as u32, player.username.clone())),
+                );
+                let player_name = &self.meta.as_ref().unwrap().playerName;
+                self.self_id = players
+                    .iter()
+                    .find(|player| &player.username == player_name)
+                    .map(|player| player.entity_id as u32);
+                self.teams = Teams::from_members(
+                    self.self_id,
+                    players
+                        .iter()
+                        .map(|player| (player.entity_id as u32, player.team_id as u32)),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self) {
+        let layers = self
+            .options
+            .layers
+            .clone()
+            .unwrap_or_else(|| Layer::DEFAULT_ORDER.to_vec());
+        let mut timings = vec![];
+        let layers = match &self.options.hud_output {
+            Some(hud_output) => {
+                let (hud, layers): (Vec<_>, Vec<_>) =
+                    layers.into_iter().partition(|layer| layer.is_hud());
+                self.render_transparent(hud_output, &hud, &mut timings);
+                layers
+            }
+            None => layers,
+        };
+
+        let positions = if self.options.transparent {
+            // The map would cover everything behind it
+            let layers: Vec<_> = layers
+                .into_iter()
+                .filter(|layer| *layer != Layer::Background)
+                .collect();
+            self.render_transparent(self.output.as_ref(), &layers, &mut timings)
+        } else {
+            let root =
+                BitMapBackend::new(&self.output, (IMAGE_SIZE, IMAGE_SIZE)).into_drawing_area();
+            root.fill(&BLACK).unwrap();
+            let positions = self.draw_layers(&root, &layers, &mut timings);
+            let start = Instant::now();
+            root.present().unwrap();
+            timings.push(("encode", start.elapsed()));
+            positions
+        };
+
+        if let Some(path) = &self.options.positions_sidecar {
+            let file = std::fs::File::create(path).unwrap();
+            serde_json::to_writer_pretty(file, &positions).unwrap();
+        }
+
+        if self.options.profile {
+            print!("{}", profile_summary(&timings));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use wows_replays::ui_support::IconVariant;
+
+    #[test]
+    fn test_fog_of_war() {
+        let meta = wows_replays::testkit::minimal_meta("0,11,7,0");
+        let mut renderer = TrailsBuilder::new("unused.png").renderer(&meta);
+        renderer.self_id = Some(1);
+        renderer.teams = Teams::from_members(Some(1), vec![(1, 0), (2, 1)]);
+        renderer
+            .visibility
+            .insert(2, vec![(10.0, true), (20.0, false)]);
+
+        // Everything is drawn without fog of war
+        assert!(renderer.is_visible(2, 5.0));
+
+        renderer.options.fog_of_war = true;
+        assert!(!renderer.is_visible(2, 5.0));
+        assert!(renderer.is_visible(2, 15.0));
+        assert!(!renderer.is_visible(2, 25.0));
+        assert!(renderer.is_visible(1, 25.0));
+
+        // From the enemy's perspective, it's the recording player's team that's hidden
+        renderer.options.perspective = Perspective::Enemy;
+        renderer.visibility.insert(1, vec![(30.0, true)]);
+        assert!(renderer.is_visible(2, 5.0));
+        assert!(!renderer.is_visible(1, 25.0));
+        assert!(renderer.is_visible(1, 35.0));
+    }
+
+    #[test]
+    fn test_torpedo_spotted_at() {
+        let meta = wows_replays::testkit::minimal_meta("0,11,7,0");
+        let mut renderer = TrailsBuilder::new("unused.png").renderer(&meta);
+        renderer.self_id = Some(1);
+        renderer.teams = Teams::from_members(Some(1), vec![(1, 0), (2, 1)]);
+        renderer.trails.insert(1, vec![(0.0, 0.0, 0.0)]);
+
+        // Heading straight for the player's ship at 10 units a second
+        let (x, y) = renderer
+            .torpedo_spotted_at((0.0, 1000.0, 0.0), (100.0, 0.0, 0.0))
+            .unwrap();
+        let range = TORPEDO_DETECTION_KM * WORLD_UNITS_PER_KM;
+        assert!(x <= range + 0.01 && x > range - 10.0, "spotted at {}", x);
+        assert_eq!(y, 0.0);
+
+        // Running away from the team
+        assert_eq!(
+            renderer.torpedo_spotted_at((0.0, 1000.0, 0.0), (100.0, 2000.0, 0.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_label_pill() {
+        let meta = wows_replays::testkit::minimal_meta("0,11,7,0");
+        let mut buffer = vec![0; (IMAGE_SIZE * IMAGE_SIZE * 3) as usize];
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, (IMAGE_SIZE, IMAGE_SIZE))
+                .into_drawing_area();
+            let mut ctx = ChartBuilder::on(&root)
+                .build_cartesian_2d(-1000.0..1000.0, -1000.0..1000.0)
+                .unwrap();
+            let mut renderer = TrailsBuilder::new("unused.png")
+                .with_options(RenderOptions {
+                    label_style: LabelStyle::Pill,
+                    ..Default::default()
+                })
+                .renderer(&meta);
+            let label = Label::new("label".to_string(), (0.0, 0.0), 20, &WHITE);
+            renderer.draw_label(&mut ctx, &label, 0);
+            renderer.options.label_style = LabelStyle::Plain;
+            let label = Label::new("label".to_string(), (500.0, 0.0), 20, &WHITE);
+            renderer.draw_label(&mut ctx, &label, 0);
+        }
+        let pixel = |x: u32, y: u32| {
+            let idx = ((y * IMAGE_SIZE + x) * 3) as usize;
+            &buffer[idx..idx + 3]
+        };
+
+        // Just left of where the text starts is inside the pill's rounded end
+        let (x, y) = (1023 - LABEL_PADDING as u32, 1024 + 8);
+        assert_eq!(pixel(x, y), &[20, 20, 20]);
+        assert_eq!(pixel(x + 512, y), &[0, 0, 0]);
+    }
+
+    #[test]
+    fn test_parse_layers() {
+        for layer in Layer::DEFAULT_ORDER.iter() {
+            assert_eq!(layer.name().parse::<Layer>(), Ok(*layer));
+        }
+        assert!("minimap".parse::<Layer>().is_err());
+    }
+
+    #[test]
+    fn test_place_labels() {
+        let label = LabelBox {
+            x: 100,
+            y: 100,
+            width: 50,
+            height: 20,
+        };
+        let apart = LabelBox { x: 500, ..label };
+        let step = label.height + LABEL_SPACING;
+        // The second label moves up a row, the third down a row, and the fourth is clear
+        assert_eq!(
+            place_labels(&[label, label, label, apart]),
+            vec![0, -step, step, 0]
+        );
+    }
+
+    #[test]
+    fn test_voice_line_text() {
+        assert_eq!(
+            voice_line_text(&VoiceLine::AttentionToSquare(5, 1)),
+            "Attention to square F2!"
+        );
+        assert_eq!(
+            voice_line_text(&VoiceLine::RequestingSupport(None)),
+            "Requesting support!"
+        );
+    }
+
+    #[test]
+    fn test_unblend() {
+        // Opaque red, nothing, and white at half opacity
+        let on_black = [255, 0, 0, 0, 0, 0, 128, 128, 128, 0, 0, 0];
+        let on_white = [255, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255];
+        let image = unblend(&on_black, &on_white, 2);
+        assert_eq!(*image.get_pixel(0, 0), image::Rgba([255, 0, 0, 255]));
+        assert_eq!(*image.get_pixel(1, 0), image::Rgba([0, 0, 0, 0]));
+        assert_eq!(*image.get_pixel(0, 1), image::Rgba([255, 255, 255, 128]));
+    }
+
+    #[test]
+    fn test_profile_summary() {
+        let summary = profile_summary(&[
+            ("pings", Duration::from_millis(10)),
+            ("background", Duration::from_millis(60)),
+            ("pings", Duration::from_millis(30)),
+        ]);
+        let lines: Vec<_> = summary
+            .lines()
+            .map(str::split_whitespace)
+            .map(Iterator::collect::<Vec<_>>)
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                vec!["Render", "profile:"],
+                vec!["background", "60.0ms", "60.0%"],
+                vec!["pings", "40.0ms", "40.0%"],
+                vec!["total", "100.0ms"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ship_positions() {
+        let meta = wows_replays::testkit::minimal_meta("0,11,7,0");
+        let mut renderer = TrailsBuilder::new("unused.png").renderer(&meta);
+        renderer.self_id = Some(1);
+        renderer.teams = Teams::from_members(Some(1), vec![(1, 0), (2, 1)]);
+        renderer.usernames.insert(1, "player".to_string());
+        renderer.trails.insert(1, vec![(10.0, 0.0, 0.0)]);
+        renderer.trails.insert(2, vec![(10.0, 500.0, 500.0)]);
+        renderer.destroyed.insert(2);
+
+        let mut buffer = vec![0; (IMAGE_SIZE * IMAGE_SIZE * 3) as usize];
+        let root =
+            BitMapBackend::with_buffer(&mut buffer, (IMAGE_SIZE, IMAGE_SIZE)).into_drawing_area();
+        let ctx = ChartBuilder::on(&root)
+            .build_cartesian_2d(-1000.0..1000.0, -1000.0..1000.0)
+            .unwrap();
+
+        let positions: Vec<_> = renderer
+            .ship_positions(&ctx)
+            .into_iter()
+            .map(|position| {
+                (
+                    position.entity_id,
+                    position.username,
+                    position.team_id,
+                    position.x,
+                    position.y,
+                    position.destroyed,
+                )
+            })
+            .collect();
+        // plotters maps the x range onto pixels 0..=2047, so the centre lands a pixel left
+        assert_eq!(
+            positions,
+            vec![
+                (1, Some("player".to_string()), Some(0), 1023, 1024, false),
+                (2, None, Some(1), 1535, 512, true),
+            ]
+        );
+    }
+    #[test]
+    fn test_ship_icons_drawn_from_buffer() {
+        let class_icons = std::env::temp_dir().join(format!("trail-icons-{}", std::process::id()));
+        std::fs::create_dir_all(&class_icons).unwrap();
+
+        let options = RenderOptions {
+            class_icons: Some(class_icons.clone()),
+            ship_classes: std::iter::once((42, ShipClass::Destroyer)).collect(),
+            ..RenderOptions::default()
+        };
+        let meta = wows_replays::testkit::minimal_meta("0,11,7,0");
+        let mut renderer = TrailsBuilder::new("unused.png")
+            .with_options(options)
+            .renderer(&meta);
+        renderer.trails.insert(1, vec![(10.0, 0.0, 0.0)]);
+        renderer.ship_ids.insert(1, 42);
+
+        let variant = IconVariant::from_relation(renderer.teams.relation(1), true);
+        image::RgbImage::from_pixel(16, 16, image::Rgb([255, 0, 0]))
+            .save(class_icons.join(format!("{}.png", ShipClass::Destroyer.icon(variant))))
+            .unwrap();
+
+        let mut buffer = vec![0; (IMAGE_SIZE * IMAGE_SIZE * 3) as usize];
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, (IMAGE_SIZE, IMAGE_SIZE))
+                .into_drawing_area();
+            let scale = 1000.0;
+            let mut ctx = ChartBuilder::on(&root)
+                .x_label_area_size(0)
+                .y_label_area_size(0)
+                .build_cartesian_2d(-scale..scale, -scale..scale)
+                .unwrap();
+            renderer.draw_ship_icons(&mut ctx, scale);
+            root.present().unwrap();
+        }
+        std::fs::remove_dir_all(&class_icons).unwrap();
+
+        // The icon is centered on the ship's last position, in the middle of the image
+        let pixel = |x: u32, y: u32| {
+            let offset = ((y * IMAGE_SIZE + x) * 3) as usize;
+            &buffer[offset..offset + 3]
+        };
+        let center = IMAGE_SIZE / 2;
+        assert_eq!(pixel(center, center), &[255, 0, 0]);
+        assert_eq!(pixel(center + ICON_SIZE, center), &[0, 0, 0]);
+    }
 }
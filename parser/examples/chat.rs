@@ -0,0 +1,15 @@
+//! Prints the chat log of a replay.
+//!
+//! ```text
+//! cargo run --example chat -- path/to/replay.wowsreplay
+//! ```
+
+use wows_replays::analyzer::chat::ChatLoggerBuilder;
+
+fn main() -> Result<(), wows_replays::ErrorKind> {
+    let replay = std::env::args()
+        .nth(1)
+        .expect("usage: chat <replay.wowsreplay>");
+
+    wows_replays::analyzer::parse_with(std::path::Path::new(&replay), &ChatLoggerBuilder::new())
+}
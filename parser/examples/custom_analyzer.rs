@@ -0,0 +1,57 @@
+//! A minimal custom analyzer which counts the packets of each type in a replay.
+//!
+//! Analyzers are created per replay by an [AnalyzerMutBuilder], which gets to see the
+//! replay's metadata first (e.g. to find the game version needed to decode packets).
+//! The analyzer then receives every packet in order, and `finish` once all of the
+//! packets have been processed.
+//!
+//! ```text
+//! cargo run --example custom_analyzer -- path/to/replay.wowsreplay
+//! ```
+
+use std::collections::HashMap;
+
+use wows_replays::analyzer::decoder::DecodedPacket;
+use wows_replays::analyzer::{AnalyzerMut, AnalyzerMutBuilder};
+use wows_replays::packet2::Packet;
+use wows_replays::version::Version;
+
+struct PacketCounterBuilder;
+
+impl AnalyzerMutBuilder for PacketCounterBuilder {
+    fn build(&self, meta: &wows_replays::ReplayMeta) -> Box<dyn AnalyzerMut> {
+        Box::new(PacketCounter {
+            version: Version::from_client_exe(&meta.clientVersionFromExe),
+            counts: HashMap::new(),
+        })
+    }
+}
+
+struct PacketCounter {
+    version: Version,
+    counts: HashMap<String, usize>,
+}
+
+impl AnalyzerMut for PacketCounter {
+    fn process_mut(&mut self, packet: &Packet<'_, '_>) {
+        let decoded = DecodedPacket::from(&self.version, false, packet);
+        let kind = format!("{:?}", decoded.payload.kind());
+        *self.counts.entry(kind).or_default() += 1;
+    }
+
+    fn finish(&mut self) {
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1));
+        for (kind, count) in counts {
+            println!("{:>8} {}", count, kind);
+        }
+    }
+}
+
+fn main() -> Result<(), wows_replays::ErrorKind> {
+    let replay = std::env::args()
+        .nth(1)
+        .expect("usage: custom_analyzer <replay.wowsreplay>");
+
+    wows_replays::analyzer::parse_with(std::path::Path::new(&replay), &PacketCounterBuilder)
+}
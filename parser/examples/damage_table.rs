@@ -0,0 +1,73 @@
+//! Prints a table of the damage dealt by every player in a replay.
+//!
+//! ```text
+//! cargo run --example damage_table -- path/to/replay.wowsreplay
+//! ```
+
+use std::collections::HashMap;
+
+use wows_replays::analyzer::decoder::{DecodedPacket, DecodedPacketPayload};
+use wows_replays::analyzer::{AnalyzerMut, AnalyzerMutBuilder};
+use wows_replays::packet2::Packet;
+use wows_replays::version::Version;
+
+struct DamageTableBuilder;
+
+impl AnalyzerMutBuilder for DamageTableBuilder {
+    fn build(&self, meta: &wows_replays::ReplayMeta) -> Box<dyn AnalyzerMut> {
+        Box::new(DamageTable {
+            version: Version::from_client_exe(&meta.clientVersionFromExe),
+            names: HashMap::new(),
+            damage: HashMap::new(),
+        })
+    }
+}
+
+struct DamageTable {
+    version: Version,
+    /// Maps ship entity IDs to the name of the player controlling them
+    names: HashMap<i64, String>,
+    /// Damage dealt by each ship entity ID
+    damage: HashMap<i64, f32>,
+}
+
+impl AnalyzerMut for DamageTable {
+    fn process_mut(&mut self, packet: &Packet<'_, '_>) {
+        let decoded = DecodedPacket::from(&self.version, false, packet);
+        match decoded.payload {
+            DecodedPacketPayload::OnArenaStateReceived { players, .. } => {
+                for player in players {
+                    self.names.insert(player.entity_id, player.username);
+                }
+            }
+            DecodedPacketPayload::DamageReceived { aggressors, .. } => {
+                for damage in aggressors {
+                    *self.damage.entry(damage.aggressor as i64).or_default() += damage.damage;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self) {
+        let mut rows: Vec<_> = self
+            .names
+            .iter()
+            .map(|(id, name)| (name, self.damage.get(id).cloned().unwrap_or_default()))
+            .collect();
+        rows.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        println!("{:<24} {:>10}", "Player", "Damage");
+        for (name, damage) in rows {
+            println!("{:<24} {:>10.0}", name, damage);
+        }
+    }
+}
+
+fn main() -> Result<(), wows_replays::ErrorKind> {
+    let replay = std::env::args()
+        .nth(1)
+        .expect("usage: damage_table <replay.wowsreplay>");
+
+    wows_replays::analyzer::parse_with(std::path::Path::new(&replay), &DamageTableBuilder)
+}
@@ -0,0 +1,55 @@
+//! Dumps every minimap update in a replay as CSV, one row per ship per update. This is
+//! the data the in-game minimap is drawn from.
+//!
+//! ```text
+//! cargo run --example minimap_frames -- path/to/replay.wowsreplay > minimap.csv
+//! ```
+
+use wows_replays::analyzer::decoder::{DecodedPacket, DecodedPacketPayload};
+use wows_replays::analyzer::{AnalyzerMut, AnalyzerMutBuilder};
+use wows_replays::packet2::Packet;
+use wows_replays::version::Version;
+
+struct MinimapDumperBuilder;
+
+impl AnalyzerMutBuilder for MinimapDumperBuilder {
+    fn build(&self, meta: &wows_replays::ReplayMeta) -> Box<dyn AnalyzerMut> {
+        println!("clock,entity_id,x,y,heading,disappearing");
+        Box::new(MinimapDumper {
+            version: Version::from_client_exe(&meta.clientVersionFromExe),
+        })
+    }
+}
+
+struct MinimapDumper {
+    version: Version,
+}
+
+impl AnalyzerMut for MinimapDumper {
+    fn process_mut(&mut self, packet: &Packet<'_, '_>) {
+        let decoded = DecodedPacket::from(&self.version, false, packet);
+        if let DecodedPacketPayload::MinimapUpdate { updates, .. } = decoded.payload {
+            for update in updates {
+                println!(
+                    "{},{},{},{},{},{}",
+                    decoded.clock,
+                    update.entity_id,
+                    update.x,
+                    update.y,
+                    update.heading,
+                    update.disappearing
+                );
+            }
+        }
+    }
+
+    fn finish(&mut self) {}
+}
+
+fn main() -> Result<(), wows_replays::ErrorKind> {
+    let replay = std::env::args()
+        .nth(1)
+        .expect("usage: minimap_frames <replay.wowsreplay>");
+
+    wows_replays::analyzer::parse_with(std::path::Path::new(&replay), &MinimapDumperBuilder)
+}
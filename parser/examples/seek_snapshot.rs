@@ -0,0 +1,53 @@
+//! Prints the position of every ship at a given point in a replay.
+//!
+//! The replay is parsed once into a [ReplaySession], which keeps a snapshot of the battle
+//! every few seconds of game time. Seeking starts from the snapshot before the requested
+//! time and moves the ships forward from there, so a viewer can jump back and forth
+//! without parsing the replay again.
+//!
+//! ```text
+//! cargo run --example seek_snapshot -- path/to/GameParams.json path/to/replay.wowsreplay 300
+//! ```
+//!
+//! [ReplaySession]: wows_replays::analyzer::battle_controller::ReplaySession
+
+use std::path::Path;
+
+use wows_replays::analyzer::battle_controller::KEYFRAME_INTERVAL;
+use wows_replays::clock::GameClock;
+use wows_replays::resource_loader::GameDataSource;
+
+fn main() -> Result<(), wows_replays::ErrorKind> {
+    let mut args = std::env::args().skip(1);
+    let usage = "usage: seek_snapshot <GameParams.json> <replay.wowsreplay> <seconds>";
+    let game_params = args.next().expect(usage);
+    let replay = args.next().expect(usage);
+    let clock = GameClock(args.next().expect(usage).parse().expect(usage));
+
+    let game_data = GameDataSource::load(Path::new(&game_params), None)?;
+    let session =
+        wows_replays::open_replay_session(Path::new(&replay), &game_data, KEYFRAME_INTERVAL)?;
+    if clock > session.end() {
+        println!("Replay ended before {:.1}s", clock.seconds());
+        return Ok(());
+    }
+
+    let snapshot = match session.seek_to(clock) {
+        Some(snapshot) => snapshot,
+        None => {
+            println!("Nothing was recorded before {:.1}s", clock.seconds());
+            return Ok(());
+        }
+    };
+    println!("Snapshot at {:.1}s:", snapshot.clock);
+    for ship in snapshot.ships.iter() {
+        if let Some(position) = &ship.position {
+            println!(
+                "{:>10} {:>10.1} {:>10.1} {:>10.1}",
+                ship.entity_id, position.x, position.y, position.z
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! Fuzzes the packet parser on decrypted packet data, using the entity definitions of a
+//! fixed game version.
+//!
+//! To seed the corpus with real packets, dump the decrypted packet data of a replay
+//! (`ReplayFile::packet_data`) to a file in `corpus/packets`.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use wows_replays::rpc::entitydefs::EntitySpec;
+use wows_replays::version::{EmbeddedDataFiles, Version};
+
+thread_local! {
+    static SPECS: &'static [EntitySpec] = {
+        let datafiles = EmbeddedDataFiles::new(
+            std::path::PathBuf::from("versions"),
+            Version::from_client_exe("0,11,7,0"),
+        )
+        .unwrap();
+        Box::leak(wows_replays::parse_scripts(&datafiles).unwrap().into_boxed_slice())
+    };
+}
+
+fuzz_target!(|data: &[u8]| {
+    SPECS.with(|specs| {
+        let mut parser = wows_replays::packet2::Parser::new(specs);
+        let mut i = data;
+        while !i.is_empty() {
+            match parser.parse_packet_bytes(i) {
+                Ok((remaining, _packet)) => i = remaining,
+                Err(_) => break,
+            }
+        }
+    });
+});
@@ -0,0 +1,13 @@
+//! Fuzzes the replay container: header, metadata, decryption and decompression.
+//!
+//! Real replays make a good seed corpus:
+//!
+//! ```text
+//! cargo fuzz run replay_file corpus/replay_file ../../test/replays
+//! ```
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = wows_replays::ReplayFile::from_bytes(data);
+});
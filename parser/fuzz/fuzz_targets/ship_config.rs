@@ -0,0 +1,11 @@
+//! Fuzzes the ship config blob parser across the supported layouts.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use wows_replays::analyzer::battle_controller::ShipConfig;
+use wows_replays::version::Version;
+
+fuzz_target!(|data: &[u8]| {
+    for version in ["0,11,7,0", "13,2,0,0"] {
+        let _ = ShipConfig::from_bytes(data, Version::from_client_exe(version));
+    }
+});
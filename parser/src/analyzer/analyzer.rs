@@ -40,8 +40,33 @@ impl AnalyzerAdapter {
 
 impl crate::packet2::PacketProcessorMut for AnalyzerAdapter {
     fn process_mut(&mut self, packet: crate::packet2::Packet<'_, '_>) {
+        #[cfg(feature = "packet-tracing")]
+        let _span = tracing::trace_span!("analyze").entered();
         for a in self.analyzers.iter_mut() {
             a.process_mut(&packet);
         }
     }
 }
+
+/// Reads the replay at `replay` and runs its packets through the analyzer built by
+/// `builder`, loading the entity definitions for the replay's game version.
+pub fn parse_with<B: AnalyzerMutBuilder>(
+    replay: &std::path::Path,
+    builder: &B,
+) -> Result<(), crate::ErrorKind> {
+    let _span = tracing::info_span!("parse_with", replay = %replay.display()).entered();
+    let replay_file = crate::ReplayFile::from_file(replay)?;
+    let datafiles = crate::version::EmbeddedDataFiles::new(
+        std::path::PathBuf::from("versions"),
+        crate::version::Version::from_client_exe(&replay_file.meta.clientVersionFromExe),
+    )?;
+    let specs = crate::parse_scripts(&datafiles)?;
+
+    let analyzer = builder.build(&replay_file.meta);
+    let mut adapter = AnalyzerAdapter::new(vec![analyzer]);
+    let mut parser = crate::packet2::Parser::new(&specs);
+    parser.parse_packets_mut(&replay_file.packet_data, &mut adapter)?;
+    adapter.finish();
+
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{TeamSnapshot, TimelineSample};
+
+/// How a mode awards points. The defaults follow Domination in Random Battles, with the
+/// points for destroying a ship averaged over the ship classes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScoringRules {
+    /// Score at which a team wins
+    pub win_score: u32,
+    /// Points each held capture point earns every `hold_period`
+    pub hold_reward: u32,
+    pub hold_period: Duration,
+    /// Points a team gains for destroying an enemy ship
+    pub kill_reward: u32,
+    /// Points a team loses when one of its ships is destroyed
+    pub kill_penalty: u32,
+}
+
+impl Default for ScoringRules {
+    fn default() -> Self {
+        ScoringRules {
+            win_score: 1000,
+            hold_reward: 3,
+            hold_period: Duration::from_secs(5),
+            kill_reward: 30,
+            kill_penalty: 40,
+        }
+    }
+}
+
+impl ScoringRules {
+    /// Points per second the team is expected to earn from its capture points and lose or
+    /// gain from ships expected to be destroyed by damage over time, e.g. fires
+    pub fn points_per_second(&self, team: &TeamSnapshot, enemies: &[&TeamSnapshot]) -> f32 {
+        let holding =
+            team.control_points as f32 * self.hold_reward as f32 / self.hold_period.as_secs_f32();
+        // An enemy ship sinking may have been caused by any of its enemies, which is
+        // only one team outside of modes like Battle Royale
+        let kills: f32 = enemies
+            .iter()
+            .map(|enemy| expected_losses(enemy) / enemies.len() as f32)
+            .sum();
+        holding + kills * self.kill_reward as f32 - expected_losses(team) * self.kill_penalty as f32
+    }
+}
+
+/// Ships per second the team is expected to lose to damage over time, assuming it's
+/// spread evenly over its ships
+fn expected_losses(team: &TeamSnapshot) -> f32 {
+    if team.ships_alive == 0 || team.health <= 0.0 {
+        return 0.0;
+    }
+    team.damage_over_time / (team.health / team.ships_alive as f32)
+}
+
+/// How one team is doing at a point in the battle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamProjection {
+    team_id: u32,
+    health_share: f32,
+    points_per_second: f32,
+    time_to_win: Option<Duration>,
+}
+
+impl TeamProjection {
+    pub fn team_id(&self) -> u32 {
+        self.team_id
+    }
+
+    /// The team's share of the health left in the battle, from 0 to 1
+    pub fn health_share(&self) -> f32 {
+        self.health_share
+    }
+
+    /// See [ScoringRules::points_per_second]
+    pub fn points_per_second(&self) -> f32 {
+        self.points_per_second
+    }
+
+    /// How long until the team reaches the winning score at its current rate. `None` if
+    /// it isn't gaining points or the mode has no scores.
+    pub fn time_to_win(&self) -> Option<Duration> {
+        self.time_to_win
+    }
+}
+
+/// Which team is ahead at a point in the battle, and how soon each team would win on
+/// points if nothing changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Advantage {
+    timestamp: Duration,
+    teams: Vec<TeamProjection>,
+}
+
+impl Advantage {
+    pub fn compute(sample: &TimelineSample, rules: &ScoringRules) -> Self {
+        let total_health: f32 = sample.teams.iter().map(|team| team.health).sum();
+        let teams = sample
+            .teams
+            .iter()
+            .map(|team| {
+                let enemies: Vec<_> = sample
+                    .teams
+                    .iter()
+                    .filter(|enemy| enemy.team_id != team.team_id)
+                    .collect();
+                let points_per_second = rules.points_per_second(team, &enemies);
+                let time_to_win = team.score.and_then(|score| {
+                    let remaining = rules.win_score.saturating_sub(score) as f32;
+                    if remaining == 0.0 {
+                        Some(Duration::ZERO)
+                    } else if points_per_second > 0.0 {
+                        Some(Duration::from_secs_f32(remaining / points_per_second))
+                    } else {
+                        None
+                    }
+                });
+                TeamProjection {
+                    team_id: team.team_id,
+                    health_share: if total_health > 0.0 {
+                        team.health / total_health
+                    } else {
+                        0.0
+                    },
+                    points_per_second,
+                    time_to_win,
+                }
+            })
+            .collect();
+
+        Advantage {
+            timestamp: sample.timestamp,
+            teams,
+        }
+    }
+
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// Every team, ordered by team id
+    pub fn teams(&self) -> &[TeamProjection] {
+        self.teams.as_ref()
+    }
+
+    pub fn team(&self, team_id: u32) -> Option<&TeamProjection> {
+        self.teams.iter().find(|team| team.team_id == team_id)
+    }
+
+    /// The team which would win first on points or, if no team is gaining points, the
+    /// team with the most health left
+    pub fn leader(&self) -> Option<u32> {
+        self.teams
+            .iter()
+            .filter_map(|team| Some((team.team_id, team.time_to_win?)))
+            .min_by_key(|(_team_id, time_to_win)| *time_to_win)
+            .map(|(team_id, _time_to_win)| team_id)
+            .or_else(|| {
+                self.teams
+                    .iter()
+                    .max_by(|a, b| a.health_share.total_cmp(&b.health_share))
+                    .map(|team| team.team_id)
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn team(team_id: u32, score: u32, control_points: usize, fires: usize) -> TeamSnapshot {
+        TeamSnapshot {
+            team_id,
+            health: 200000.0,
+            max_health: 400000.0,
+            score: Some(score),
+            ships_alive: 4,
+            control_points,
+            fires,
+            damage_over_time: fires as f32 * 150.0,
+        }
+    }
+
+    #[test]
+    fn test_advantage() {
+        let rules = ScoringRules::default();
+
+        // Holding two of three caps while behind on points
+        let sample = TimelineSample {
+            timestamp: Duration::from_secs(600),
+            teams: vec![team(0, 700, 2, 0), team(1, 800, 1, 0)],
+            cyclone: false,
+        };
+        let advantage = Advantage::compute(&sample, &rules);
+        let team_0 = advantage.team(0).unwrap();
+        assert!((team_0.points_per_second() - 1.2).abs() < 0.001);
+        assert_eq!(team_0.time_to_win().unwrap().as_secs_f32().round(), 250.0);
+        assert_eq!(
+            advantage
+                .team(1)
+                .unwrap()
+                .time_to_win()
+                .unwrap()
+                .as_secs_f32()
+                .round(),
+            333.0
+        );
+        assert_eq!(advantage.team(0).unwrap().health_share(), 0.5);
+        assert_eq!(advantage.leader(), Some(0));
+
+        // The same caps, but team 0 is burning down
+        let sample = TimelineSample {
+            timestamp: Duration::from_secs(600),
+            teams: vec![team(0, 700, 2, 4), team(1, 800, 1, 0)],
+            cyclone: false,
+        };
+        let advantage = Advantage::compute(&sample, &rules);
+        // 600 damage per second against ships with 50000 health left each
+        let losses = 600.0 / 50000.0;
+        let team_0 = advantage.team(0).unwrap();
+        assert!((team_0.points_per_second() - (1.2 - losses * 40.0)).abs() < 0.001);
+        let team_1 = advantage.team(1).unwrap();
+        assert!((team_1.points_per_second() - (0.6 + losses * 30.0)).abs() < 0.001);
+        assert_eq!(advantage.leader(), Some(1));
+
+        // Modes without scores fall back to the health left
+        let mut sample = sample;
+        sample.teams[0].score = None;
+        sample.teams[1].score = None;
+        sample.teams[1].health = 100000.0;
+        assert_eq!(Advantage::compute(&sample, &rules).leader(), Some(0));
+    }
+}
@@ -0,0 +1,187 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    packet2::{PropertyUpdatePacket, Vec3},
+    rpc::typedefs::ArgValue,
+};
+
+/// The border of the playable area, from the `BattleLogic` entity's `mapBorder`. The
+/// border's shape is defined by its GameParams entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapBorder {
+    params_id: u32,
+    position: Vec3,
+}
+
+impl MapBorder {
+    pub fn params_id(&self) -> u32 {
+        self.params_id
+    }
+
+    /// The world position the border is centered on
+    pub fn position(&self) -> &Vec3 {
+        &self.position
+    }
+}
+
+/// The map border being set, moved or removed, e.g. when it shrinks in modes with a
+/// shrinking playable area
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapBorderChange {
+    pub(crate) timestamp: Duration,
+    pub(crate) border: Option<MapBorder>,
+}
+
+impl MapBorderChange {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// The new border, or `None` if the whole map became playable
+    pub fn border(&self) -> Option<&MapBorder> {
+        self.border.as_ref()
+    }
+}
+
+/// The battle's `BattleLogic` entity, which holds match-wide state such as team scores
+/// and capture points. Its properties are deeply nested and change between game
+/// versions, so they're kept as JSON rather than being decoded into structs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BattleLogic {
+    id: u32,
+    properties: BTreeMap<String, serde_json::Value>,
+}
+
+impl BattleLogic {
+    pub(crate) fn new(id: u32) -> Self {
+        BattleLogic {
+            id,
+            properties: BTreeMap::new(),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Every property by name, e.g. `state` and `timeLeft`
+    pub fn properties(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.properties
+    }
+
+    pub fn property(&self, name: &str) -> Option<&serde_json::Value> {
+        self.properties.get(name)
+    }
+
+    /// The team's score, from `state.missions.teamsScore`. `None` in modes without
+    /// scores.
+    pub fn team_score(&self, team_id: u32) -> Option<u32> {
+        self.property("state")?
+            .pointer("/missions/teamsScore")?
+            .as_array()?
+            .iter()
+            .find(|score| score["teamId"].as_i64() == Some(team_id as i64))
+            .and_then(|score| score["score"].as_u64())
+            .map(|score| score as u32)
+    }
+
+    /// Number of capture points currently owned by the team
+    pub fn control_points_held(&self, team_id: u32) -> usize {
+        self.property("state")
+            .and_then(|state| state["controlPoints"].as_array())
+            .map(|points| {
+                points
+                    .iter()
+                    .filter(|point| point["teamId"].as_i64() == Some(team_id as i64))
+                    .count()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The battle's `WEATHER_STATE`, from `state.weather`. See [super::WeatherState]
+    /// for the decoded form.
+    pub fn weather(&self) -> Option<&serde_json::Value> {
+        self.property("state")?.get("weather")
+    }
+
+    /// The current map border, or `None` if the whole map is playable
+    pub fn map_border(&self) -> Option<MapBorder> {
+        let border = self.property("mapBorder")?;
+        let position = border["position"].as_array()?;
+        let coord = |idx: usize| position.get(idx)?.as_f64().map(|coord| coord as f32);
+        Some(MapBorder {
+            params_id: border["paramsId"].as_u64()? as u32,
+            position: Vec3 {
+                x: coord(0)?,
+                y: coord(1)?,
+                z: coord(2)?,
+            },
+        })
+    }
+
+    pub(crate) fn set_property(&mut self, name: &str, value: &ArgValue<'_>) {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.properties.insert(name.to_string(), value);
+            }
+            Err(e) => debug!("failed to convert BattleLogic property {}: {}", name, e),
+        }
+    }
+
+    pub(crate) fn apply_update(&mut self, update: &PropertyUpdatePacket<'_>) {
+        let property = match self.properties.get_mut(update.property) {
+            Some(property) => property,
+            None => {
+                debug!("update of unknown BattleLogic property {}", update.property);
+                return;
+            }
+        };
+        if let Err(e) = update.update_cmd.apply(property) {
+            debug!(
+                "failed to update BattleLogic property {}: {:?}",
+                update.property, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scores_and_control_points() {
+        let mut logic = BattleLogic::new(1);
+        logic.properties.insert(
+            "state".to_string(),
+            serde_json::json!({
+                "controlPoints": [{"teamId": 0}, {"teamId": 1}, {"teamId": 0}, {"teamId": -1}],
+                "missions": {"teamsScore": [{"teamId": 0, "score": 300}, {"teamId": 1, "score": 450}]},
+            }),
+        );
+
+        assert_eq!(logic.team_score(0), Some(300));
+        assert_eq!(logic.team_score(1), Some(450));
+        assert_eq!(logic.team_score(2), None);
+        assert_eq!(logic.control_points_held(0), 2);
+        assert_eq!(logic.control_points_held(1), 1);
+    }
+
+    #[test]
+    fn test_map_border() {
+        let mut logic = BattleLogic::new(1);
+        logic.set_property("mapBorder", &ArgValue::NullableFixedDict(None));
+        assert_eq!(logic.map_border(), None);
+
+        let mut border = std::collections::HashMap::new();
+        border.insert("paramsId", ArgValue::Uint32(42));
+        border.insert("position", ArgValue::Vector3((1.0, 2.0, 3.0)));
+        logic.set_property("mapBorder", &ArgValue::NullableFixedDict(Some(border)));
+        let border = logic.map_border().unwrap();
+        assert_eq!(border.params_id(), 42);
+        assert_eq!(border.position().z, 3.0);
+    }
+}
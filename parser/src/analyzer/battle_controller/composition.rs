@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use crate::game_params::Species;
+
+/// Ship classes in the order they're conventionally listed, with their abbreviations
+const CLASS_ABBREVIATIONS: [(Species, &str); 5] = [
+    (Species::AirCarrier, "CV"),
+    (Species::Destroyer, "DD"),
+    (Species::Cruiser, "CA"),
+    (Species::Battleship, "BB"),
+    (Species::Submarine, "SS"),
+];
+
+/// The number of ships of each class and tier on a team
+#[derive(Debug, Clone, Default)]
+pub struct TeamComposition {
+    pub(crate) team_id: u32,
+    pub(crate) by_class: BTreeMap<Species, usize>,
+    pub(crate) by_tier: BTreeMap<u32, usize>,
+    pub(crate) by_class_and_tier: BTreeMap<(Species, u32), usize>,
+}
+
+impl TeamComposition {
+    pub fn team_id(&self) -> u32 {
+        self.team_id
+    }
+
+    /// Total number of ships on the team
+    pub fn ships(&self) -> usize {
+        self.by_class.values().sum()
+    }
+
+    /// Number of ships of the class, e.g. [Species::Destroyer]
+    pub fn class_count(&self, species: &Species) -> usize {
+        self.by_class.get(species).copied().unwrap_or_default()
+    }
+
+    pub fn tier_count(&self, tier: u32) -> usize {
+        self.by_tier.get(&tier).copied().unwrap_or_default()
+    }
+
+    pub fn by_class(&self) -> &BTreeMap<Species, usize> {
+        &self.by_class
+    }
+
+    pub fn by_tier(&self) -> &BTreeMap<u32, usize> {
+        &self.by_tier
+    }
+
+    pub fn by_class_and_tier(&self) -> &BTreeMap<(Species, u32), usize> {
+        &self.by_class_and_tier
+    }
+
+    /// A short description of the team's classes, e.g. "1 CV, 4 DD, 3 CA, 4 BB"
+    pub fn summary(&self) -> String {
+        CLASS_ABBREVIATIONS
+            .iter()
+            .filter_map(|(species, abbreviation)| match self.class_count(species) {
+                0 => None,
+                count => Some(format!("{} {}", count, abbreviation)),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub(crate) fn add_ship(&mut self, species: Species, tier: u32) {
+        *self.by_tier.entry(tier).or_default() += 1;
+        *self
+            .by_class_and_tier
+            .entry((species.clone(), tier))
+            .or_default() += 1;
+        *self.by_class.entry(species).or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut team = TeamComposition::default();
+        for species in [
+            Species::Battleship,
+            Species::Destroyer,
+            Species::AirCarrier,
+            Species::Destroyer,
+        ] {
+            team.add_ship(species, 10);
+        }
+        team.add_ship(Species::Cruiser, 8);
+
+        assert_eq!(team.ships(), 5);
+        assert_eq!(team.tier_count(10), 4);
+        assert_eq!(team.by_class_and_tier()[&(Species::Destroyer, 10)], 2);
+        assert_eq!(team.summary(), "1 CV, 2 DD, 1 CA, 1 BB");
+    }
+}
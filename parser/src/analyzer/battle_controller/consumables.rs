@@ -0,0 +1,256 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{analyzer::decoder::Consumable, build::ConsumableSpec};
+
+/// A ship using one of its consumables
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConsumableActivation {
+    pub(crate) timestamp: Duration,
+    pub(crate) entity_id: u32,
+    pub(crate) consumable: Consumable,
+    pub(crate) duration: f32,
+}
+
+impl ConsumableActivation {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+
+    pub fn consumable(&self) -> Consumable {
+        self.consumable
+    }
+
+    /// Seconds the consumable stays active, as reported by the server. This includes any
+    /// modifiers from the ship's build.
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConsumableState {
+    /// The consumable can be used
+    Ready,
+    Active,
+    Reloading,
+    /// All charges have been used
+    Depleted,
+}
+
+/// A span of the battle during which a consumable stayed in the same state
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConsumableWindow {
+    state: ConsumableState,
+    start: Duration,
+    end: Option<Duration>,
+    charges_left: Option<usize>,
+}
+
+impl ConsumableWindow {
+    pub fn state(&self) -> ConsumableState {
+        self.state
+    }
+
+    pub fn start(&self) -> Duration {
+        self.start
+    }
+
+    /// `None` if the state lasted until the end of the battle
+    pub fn end(&self) -> Option<Duration> {
+        self.end
+    }
+
+    /// Charges remaining during the window, `None` if unlimited
+    pub fn charges_left(&self) -> Option<usize> {
+        self.charges_left
+    }
+
+    fn contains(&self, timestamp: Duration) -> bool {
+        self.start <= timestamp && self.end.map_or(true, |end| timestamp < end)
+    }
+}
+
+/// When one of a ship's consumables was available over the course of the battle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsumableTimeline {
+    entity_id: u32,
+    spec: ConsumableSpec,
+    windows: Vec<ConsumableWindow>,
+}
+
+impl ConsumableTimeline {
+    /// Builds the timeline from the consumable's activations, which must be in order. The
+    /// consumable is ready from the start of the battle.
+    pub fn new(entity_id: u32, spec: ConsumableSpec, activations: &[ConsumableActivation]) -> Self {
+        let mut windows: Vec<ConsumableWindow> = vec![];
+        let mut ready_at = Some(Duration::ZERO);
+        let mut charges_left = spec.charges;
+        for activation in activations {
+            let start = activation.timestamp;
+            // The reload time doesn't account for every modifier, so the consumable may be
+            // used again before it's expected to be ready
+            if let Some(previous) = windows.last_mut() {
+                if previous.end.map_or(true, |end| end > start) {
+                    previous.end = Some(start);
+                }
+            }
+            if let Some(ready_at) = ready_at.filter(|ready_at| *ready_at < start) {
+                windows.push(ConsumableWindow {
+                    state: ConsumableState::Ready,
+                    start: ready_at,
+                    end: Some(start),
+                    charges_left,
+                });
+            }
+
+            charges_left = charges_left.map(|charges| charges.saturating_sub(1));
+            let duration = if activation.duration > 0.0 {
+                activation.duration
+            } else {
+                spec.work_time
+            };
+            let active_end = start + Duration::from_secs_f32(duration);
+            windows.push(ConsumableWindow {
+                state: ConsumableState::Active,
+                start,
+                end: Some(active_end),
+                charges_left,
+            });
+
+            if charges_left == Some(0) {
+                windows.push(ConsumableWindow {
+                    state: ConsumableState::Depleted,
+                    start: active_end,
+                    end: None,
+                    charges_left,
+                });
+                ready_at = None;
+            } else {
+                let reloaded = active_end + Duration::from_secs_f32(spec.reload_time);
+                windows.push(ConsumableWindow {
+                    state: ConsumableState::Reloading,
+                    start: active_end,
+                    end: Some(reloaded),
+                    charges_left,
+                });
+                ready_at = Some(reloaded);
+            }
+        }
+        if let Some(ready_at) = ready_at {
+            windows.push(ConsumableWindow {
+                state: ConsumableState::Ready,
+                start: ready_at,
+                end: None,
+                charges_left,
+            });
+        }
+
+        ConsumableTimeline {
+            entity_id,
+            spec,
+            windows,
+        }
+    }
+
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+
+    pub fn consumable(&self) -> Consumable {
+        self.spec.consumable
+    }
+
+    /// The consumable's charges and timings as equipped on the ship
+    pub fn spec(&self) -> &ConsumableSpec {
+        &self.spec
+    }
+
+    /// The consumable's states in order, covering the whole battle
+    pub fn windows(&self) -> &[ConsumableWindow] {
+        self.windows.as_ref()
+    }
+
+    /// The consumable's state at the given time
+    pub fn window_at(&self, timestamp: Duration) -> Option<&ConsumableWindow> {
+        self.windows
+            .iter()
+            .rev()
+            .find(|window| window.contains(timestamp))
+    }
+
+    /// How long until the consumable can be used at the given time, zero if it's ready.
+    /// `None` if it has no charges left.
+    pub fn ready_in(&self, timestamp: Duration) -> Option<Duration> {
+        self.windows
+            .iter()
+            .filter(|window| window.end.map_or(true, |end| end > timestamp))
+            .find(|window| {
+                matches!(
+                    window.state,
+                    ConsumableState::Ready | ConsumableState::Depleted
+                )
+            })
+            .filter(|window| window.state == ConsumableState::Ready)
+            .map(|window| window.start.saturating_sub(timestamp))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_consumable_timeline() {
+        let spec = ConsumableSpec {
+            consumable: Consumable::Radar,
+            charges: Some(2),
+            work_time: 25.0,
+            reload_time: 120.0,
+            title_id: "RLSSearch".to_string(),
+            description_id: "RLSSearch".to_string(),
+        };
+        let activation = |secs| ConsumableActivation {
+            timestamp: Duration::from_secs(secs),
+            entity_id: 7,
+            consumable: Consumable::Radar,
+            duration: 20.0,
+        };
+        let timeline = ConsumableTimeline::new(7, spec, &[activation(100), activation(300)]);
+
+        let states: Vec<_> = timeline
+            .windows()
+            .iter()
+            .map(|window| (window.state(), window.start().as_secs()))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                (ConsumableState::Ready, 0),
+                (ConsumableState::Active, 100),
+                (ConsumableState::Reloading, 120),
+                (ConsumableState::Ready, 240),
+                (ConsumableState::Active, 300),
+                (ConsumableState::Depleted, 320),
+            ]
+        );
+
+        assert_eq!(
+            timeline.ready_in(Duration::from_secs(50)),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            timeline.ready_in(Duration::from_secs(220)),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(timeline.ready_in(Duration::from_secs(310)), None);
+        let window = timeline.window_at(Duration::from_secs(130)).unwrap();
+        assert_eq!(window.state(), ConsumableState::Reloading);
+        assert_eq!(window.charges_left(), Some(1));
+    }
+}
@@ -1,7 +1,7 @@
 use std::{
     borrow::Borrow,
     cell::{Ref, RefCell},
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::File,
     str::FromStr,
     sync::atomic::AtomicUsize,
@@ -14,32 +14,59 @@ use pickled::{HashableValue, Value};
 use serde::{Deserialize, Serialize};
 use strum::ParseError;
 use strum_macros::EnumString;
-use tracing::{debug, event, span, trace, warn, Level};
+use tracing::{debug, span, trace, warn, Level};
 use variantly::Variantly;
 
-static TIME_UNTIL_GAME_START: Duration = Duration::from_secs(30);
+use super::{
+    evaluate_skills, Advantage, BattleControllerState, BattleLogic, BattleOutcome, BattlePhase,
+    ConsumableActivation, ConsumableTimeline, DeathContext, EngagementStats,
+    ExpectedValuesProvider, IdResolver, MapBorderChange, MatchEventCounts, ObserverData,
+    PersonalRating, PlanesShotDown, PropertyHistory, ScoringRules, SkillEfficacy, TeamComposition,
+    TeamSnapshot, Teams, TimelineSample, TorpedoLaunch, TorpedoOutcome, TorpedoStats,
+    TorpedoWarning, TrackedProperty, WeatherEvent, WeatherState, DEATH_CONTEXT_WINDOW,
+    DEFAULT_TIMELINE_BUCKET, FIRE_DAMAGE_RATE, TORPEDO_HIT_RADIUS,
+};
 
 use crate::{
     analyzer::{
         analyzer::AnalyzerMut,
         decoder::{
-            ChatMessageExtra, DamageReceived, DeathCause, DecodedPacket, DecodedPacketPayloadKind,
-            DecoderBuilder, OnArenaStateReceivedPlayer,
+            ArtillerySalvo, ChatMessageExtra, Consumable, DamageReceived, DeathCause,
+            DecodedPacket, DecodedPacketPayloadKind, DecoderBuilder, OnArenaStateReceivedPlayer,
+            PlaneDeath, PlayerKind, Ribbon, ShotKills, TorpedoSalvo,
         },
         Analyzer,
     },
-    game_params::{CrewSkill, GameParamProvider, Param, ParamType, Vehicle},
+    build::{BuildResolver, Survivability},
+    clock::GameClock,
+    game_params::{CrewSkill, GameParamProvider, Param, ParamType, Species, Vehicle},
+    grid::GridSquare,
+    nested_property_path::UpdateAction,
     packet2::{
         EntityCreatePacket, EntityMethodPacket, EntityPropertyPacket, Packet, PacketProcessor,
-        PacketProcessorMut, PacketType, PacketTypeKind,
+        PacketProcessorMut, PacketType, PacketTypeKind, PropertyUpdatePacket, Vec3,
     },
+    parsing_failure,
     resource_loader::{self, ResourceLoader},
     rpc::{entitydefs::EntitySpec, typedefs::ArgValue},
     version::Version,
     IResult, Rc, ReplayMeta,
 };
 
-#[derive(Debug, Default, Clone)]
+/// Extra time, in seconds, after a salvo's shells are expected to land during which hit
+/// ribbons are still attributed to it. Ribbons arrive slightly after the shells land.
+const SALVO_RIBBON_GRACE_PERIOD: f32 = 1.0;
+
+/// Most timeline samples taken for a single packet. The clock only jumps further than
+/// this in corrupt replays.
+const MAX_TIMELINE_SAMPLES_PER_PACKET: usize = 3600;
+
+/// How long, in seconds, before and after a rejoin damage may be sent again. Damage
+/// received within this long before the rejoin is ignored if the server resends it within
+/// this long after.
+const REJOIN_RESEND_WINDOW: f32 = 30.0;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ShipConfig {
     abilities: Vec<u32>,
     hull: u32,
@@ -49,6 +76,12 @@ pub struct ShipConfig {
 }
 
 impl ShipConfig {
+    /// Parses the ship config blob sent in a vehicle's `shipConfig` property
+    pub fn from_bytes(blob: &[u8], version: Version) -> Result<ShipConfig, crate::ErrorKind> {
+        let (_, config) = parse_ship_config(blob, version)?;
+        Ok(config)
+    }
+
     pub fn signals(&self) -> &[u32] {
         self.signals.as_ref()
     }
@@ -70,7 +103,7 @@ impl ShipConfig {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Skills {
     aircraft_carrier: Vec<u8>,
     battleship: Vec<u8>,
@@ -104,6 +137,21 @@ impl Skills {
     pub fn aircraft_carrier(&self) -> &[u8] {
         self.aircraft_carrier.as_ref()
     }
+
+    /// The skills learned for ships of `species`, or `None` if `species` has no skill
+    /// slot
+    pub fn for_species(&self, species: &Species) -> Option<&[u8]> {
+        let skills = match species {
+            Species::AirCarrier => &self.aircraft_carrier,
+            Species::Battleship => &self.battleship,
+            Species::Cruiser => &self.cruiser,
+            Species::Destroyer => &self.destroyer,
+            Species::Auxiliary => &self.auxiliary,
+            Species::Submarine => &self.submarine,
+            _ => return None,
+        };
+        Some(skills.as_slice())
+    }
 }
 
 #[derive(Debug, Default)]
@@ -127,6 +175,12 @@ impl ShipLoadout {
 pub struct Player {
     name: String,
     clan: String,
+    clan_id: i64,
+    clan_color: u32,
+    division_id: i64,
+    division_number: Option<u32>,
+    is_division_mate: bool,
+    kind: PlayerKind,
     realm: String,
     db_id: i64,
     relation: u32,
@@ -137,33 +191,54 @@ pub struct Player {
     max_health: u32,
     is_abuser: bool,
     is_hidden: bool,
+    has_metadata: bool,
     vehicle: Rc<Param>,
 }
 
 impl Player {
+    /// Builds a player from the arena state. Players missing from the replay's metadata
+    /// (e.g. in some operations and training replays) are built from the arena state
+    /// alone, using `fallback_relation` as their relation. Returns `None` if the player's
+    /// ship can't be found.
     fn from_arena_player<G: ResourceLoader>(
         player: &OnArenaStateReceivedPlayer,
-        metadata_player: &MetadataPlayer,
+        metadata_player: Option<&MetadataPlayer>,
+        fallback_relation: u32,
         resources: &G,
-    ) -> Player {
+    ) -> Option<Player> {
         let OnArenaStateReceivedPlayer {
             username,
             clan,
+            clan_id,
+            clan_color,
+            prebattle_id,
             realm,
             db_id,
             avatar_id: avatarid,
             meta_ship_id: shipid,
+            ship_params_id,
             entity_id,
             team_id: teamid,
             max_health: health,
             raw,
             is_abuser,
             is_hidden,
+            is_bot: _,
         } = player;
 
-        Player {
+        let vehicle_id = metadata_player
+            .map(|metadata_player| metadata_player.vehicle.id())
+            .unwrap_or(*ship_params_id as u32);
+
+        Some(Player {
             name: username.clone(),
             clan: clan.clone(),
+            clan_id: *clan_id,
+            clan_color: *clan_color as u32,
+            division_id: *prebattle_id,
+            division_number: None,
+            is_division_mate: false,
+            kind: player.kind(),
             realm: realm.clone(),
             db_id: *db_id,
             avatar_id: *avatarid as u32,
@@ -171,13 +246,14 @@ impl Player {
             entity_id: *entity_id as u32,
             team_id: *teamid as u32,
             max_health: *health as u32,
-            vehicle: resources
-                .game_param_by_id(metadata_player.vehicle.id())
-                .expect("could not find vehicle"),
-            relation: metadata_player.relation,
+            vehicle: resources.game_param_by_id(vehicle_id)?,
+            relation: metadata_player
+                .map(|metadata_player| metadata_player.relation)
+                .unwrap_or(fallback_relation),
             is_abuser: *is_abuser,
             is_hidden: *is_hidden,
-        }
+            has_metadata: metadata_player.is_some(),
+        })
     }
 
     pub fn name(&self) -> &str {
@@ -188,6 +264,42 @@ impl Player {
         self.clan.as_ref()
     }
 
+    /// The clan's DB ID, or 0 if the player is not in a clan
+    pub fn clan_id(&self) -> i64 {
+        self.clan_id
+    }
+
+    /// Color of the clan tag as 0xRRGGBB
+    pub fn clan_color(&self) -> u32 {
+        self.clan_color
+    }
+
+    /// Color of the clan tag as (red, green, blue)
+    pub fn clan_color_rgb(&self) -> (u8, u8, u8) {
+        (
+            (self.clan_color >> 16) as u8,
+            (self.clan_color >> 8) as u8,
+            self.clan_color as u8,
+        )
+    }
+
+    /// ID of the player's division, or 0 if they are not in a division
+    pub fn division_id(&self) -> i64 {
+        self.division_id
+    }
+
+    /// The player's division number within their team, starting at 1, if they are
+    /// in a division
+    pub fn division_number(&self) -> Option<u32> {
+        self.division_number
+    }
+
+    /// Whether the player is in the same division as the recording player. This is
+    /// false for the recording player themselves.
+    pub fn is_division_mate(&self) -> bool {
+        self.is_division_mate
+    }
+
     pub fn relation(&self) -> u32 {
         self.relation
     }
@@ -220,6 +332,11 @@ impl Player {
         self.realm.as_ref()
     }
 
+    /// Whether this ship is controlled by a human or the AI
+    pub fn kind(&self) -> PlayerKind {
+        self.kind
+    }
+
     pub fn db_id(&self) -> i64 {
         self.db_id
     }
@@ -228,6 +345,12 @@ impl Player {
         self.is_abuser
     }
 
+    /// Whether the player was present in the replay's metadata. Players without metadata
+    /// have their relation guessed from their team.
+    pub fn has_metadata(&self) -> bool {
+        self.has_metadata
+    }
+
     pub fn is_hidden(&self) -> bool {
         self.is_hidden
     }
@@ -284,8 +407,16 @@ pub enum EntityType {
     SmokeScreen,
 }
 
+/// Everything [BattleController] learned about a battle. Reports can be serialized and
+/// deserialized again without losing any data, so that they can be cached rather than
+/// re-parsing the replay.
+///
+/// Parsing the same replay always produces the same report, down to the order of every
+/// collection, so serialized reports can be diffed. Per-entity collections are ordered by
+/// entity ID and events by time.
+#[derive(Serialize, Deserialize)]
 pub struct BattleReport {
-    self_entity: Rc<VehicleEntity>,
+    self_entity: Option<Rc<VehicleEntity>>,
     version: Version,
     map_name: String,
     game_mode: String,
@@ -293,17 +424,120 @@ pub struct BattleReport {
     match_group: String,
     player_entities: Vec<Rc<VehicleEntity>>,
     game_chat: Vec<GameMessage>,
+    salvo_outcomes: Vec<SalvoOutcome>,
+    skill_efficacy: Vec<SkillEfficacy>,
+    captures: usize,
+    battle_results: Option<String>,
+    winning_team: Option<i8>,
+    observer_data: Option<ObserverData>,
+    property_history: Option<PropertyHistory>,
+    death_contexts: Vec<DeathContext>,
+    rejoins: Vec<Rejoin>,
+    timeline: Vec<TimelineSample>,
+    weather_events: Vec<WeatherEvent>,
+    map_borders: Vec<MapBorderChange>,
+    torpedo_launches: Vec<TorpedoLaunch>,
+    torpedo_stats: Vec<TorpedoStats>,
+    torpedo_warnings: Vec<TorpedoWarning>,
+    planes_shot_down: Vec<PlanesShotDown>,
+    engagement_stats: Vec<EngagementStats>,
+    survivability: BTreeMap<u32, Survivability>,
+    consumable_activations: Vec<ConsumableActivation>,
+    consumables: Vec<ConsumableTimeline>,
+    damage_timeline: Vec<DamageEvent>,
+    ribbons: BTreeMap<u32, Vec<(GameClock, Ribbon)>>,
 }
 
 impl BattleReport {
-    pub fn self_entity(&self) -> Rc<VehicleEntity> {
+    /// The recording player's vehicle. `None` for observer replays, which have no
+    /// recording player.
+    pub fn self_entity(&self) -> Option<Rc<VehicleEntity>> {
         self.self_entity.clone()
     }
 
+    /// Every player's vehicle, ordered by entity ID
     pub fn player_entities(&self) -> &[Rc<VehicleEntity>] {
         self.player_entities.as_ref()
     }
 
+    /// Which player's ship is on which team
+    pub fn teams(&self) -> Teams {
+        Teams::new(
+            self.player_entities
+                .iter()
+                .filter_map(|entity| entity.player()),
+        )
+    }
+
+    /// The team which won, or `None` if the battle was a draw or the replay doesn't say.
+    /// Older game versions don't include the winner in replays.
+    pub fn winning_team(&self) -> Option<u32> {
+        self.winning_team
+            .filter(|team_id| *team_id >= 0)
+            .map(|team_id| team_id as u32)
+    }
+
+    /// How the battle ended for the team, or `None` if the replay doesn't say
+    pub fn outcome_for_team(&self, team_id: u32) -> Option<BattleOutcome> {
+        match self.winning_team? {
+            // The game reports a draw as the winner being team -1
+            winner if winner < 0 => Some(BattleOutcome::Draw),
+            winner if winner as u32 == team_id => Some(BattleOutcome::Win),
+            _ => Some(BattleOutcome::Loss),
+        }
+    }
+
+    /// How the battle ended for the recording player's team. `None` for observer replays.
+    pub fn outcome(&self) -> Option<BattleOutcome> {
+        self.outcome_for_team(self.teams().self_team()?)
+    }
+
+    /// Resolves any form of player ID, e.g. an avatar ID from a chat message, to the player
+    pub fn id_resolver(&self) -> IdResolver {
+        IdResolver::new(
+            self.player_entities
+                .iter()
+                .filter_map(|entity| entity.player()),
+        )
+    }
+
+    /// The number of ships of each class and tier on every team, ordered by team id
+    pub fn composition(&self) -> Vec<TeamComposition> {
+        let mut teams: BTreeMap<u32, TeamComposition> = BTreeMap::new();
+        for player in self
+            .player_entities
+            .iter()
+            .filter_map(|entity| entity.player())
+        {
+            let vehicle = player.vehicle();
+            let (species, tier) = match (
+                vehicle.species(),
+                vehicle.data().vehicle_ref().map(|data| data.level()),
+            ) {
+                (Some(species), Some(tier)) => (species, tier),
+                _ => continue,
+            };
+            teams
+                .entry(player.team_id())
+                .or_insert_with(|| TeamComposition {
+                    team_id: player.team_id(),
+                    ..Default::default()
+                })
+                .add_ship(species, tier);
+        }
+        teams.into_values().collect()
+    }
+
+    /// Player entities controlled by humans, for stats which should not include bots
+    pub fn human_player_entities(&self) -> impl Iterator<Item = &Rc<VehicleEntity>> {
+        self.player_entities.iter().filter(|entity| {
+            entity
+                .player()
+                .map(|player| player.kind().is_human())
+                .unwrap_or(false)
+        })
+    }
+
     pub fn game_chat(&self) -> &[GameMessage] {
         self.game_chat.as_ref()
     }
@@ -327,13 +561,374 @@ impl BattleReport {
     pub fn game_type(&self) -> &str {
         self.game_type.as_ref()
     }
+
+    /// Outcomes of each main battery salvo fired by the recording player, in the order
+    /// they were fired.
+    pub fn salvo_outcomes(&self) -> &[SalvoOutcome] {
+        self.salvo_outcomes.as_ref()
+    }
+
+    /// The recording player's captain skills, cross-referenced with the in-match events
+    /// they influence. Empty if the player's captain could not be resolved.
+    pub fn skill_efficacy(&self) -> &[SkillEfficacy] {
+        self.skill_efficacy.as_ref()
+    }
+
+    /// Number of capture points the recording player captured
+    pub fn captures(&self) -> usize {
+        self.captures
+    }
+
+    /// The raw post-battle results JSON, if the replay was recorded until the results
+    /// screen
+    pub fn battle_results(&self) -> Option<&str> {
+        self.battle_results.as_deref()
+    }
+
+    /// Camera and stream data only present in observer replays
+    pub fn observer_data(&self) -> Option<&ObserverData> {
+        self.observer_data.as_ref()
+    }
+
+    /// History of vehicle property changes, if enabled with
+    /// [BattleController::track_property_history]
+    pub fn property_history(&self) -> Option<&PropertyHistory> {
+        self.property_history.as_ref()
+    }
+
+    /// The circumstances of every ship's death, in the order they died
+    pub fn death_contexts(&self) -> &[DeathContext] {
+        self.death_contexts.as_ref()
+    }
+
+    /// Times the recording player's client reconnected during the battle. Data around
+    /// these points may be incomplete.
+    pub fn rejoins(&self) -> &[Rejoin] {
+        self.rejoins.as_ref()
+    }
+
+    /// Describes how `victim` died for a kill feed, e.g. "Player sunk by Yamato (main
+    /// battery)". Ship names are localized with `resources` when possible. `None` if the
+    /// ship didn't die.
+    pub fn describe_death<G: ResourceLoader>(
+        &self,
+        victim: &VehicleEntity,
+        resources: &G,
+    ) -> Option<String> {
+        let death = victim.death_info()?;
+        let victim_name = victim.player().map(|player| player.name()).unwrap_or("?");
+        let killer_ship = self
+            .player_entities
+            .iter()
+            .find(|entity| entity.id() == death.killer())
+            .and_then(|entity| entity.player())
+            .map(|player| {
+                resources
+                    .localized_name_from_param(player.vehicle())
+                    .unwrap_or_else(|| player.vehicle().name())
+                    .to_string()
+            });
+        Some(match killer_ship {
+            Some(ship) if death.killer() != victim.id() => format!(
+                "{} sunk by {} ({})",
+                victim_name,
+                ship,
+                death.cause().description()
+            ),
+            _ => format!("{} sunk ({})", victim_name, death.cause().description()),
+        })
+    }
+
+    /// The state of each team sampled at regular intervals, see
+    /// [BattleController::set_timeline_bucket]
+    pub fn timeline(&self) -> &[TimelineSample] {
+        self.timeline.as_ref()
+    }
+
+    /// The battle split into phases by the pace of the fighting, in order. See
+    /// [BattlePhase::segment].
+    pub fn phases(&self) -> Vec<BattlePhase> {
+        BattlePhase::segment(&self.timeline)
+    }
+
+    /// Which team was ahead at each sample of the [BattleReport::timeline], and how soon
+    /// each team would have won on points
+    pub fn advantage(&self, rules: &ScoringRules) -> Vec<Advantage> {
+        self.timeline
+            .iter()
+            .map(|sample| Advantage::compute(sample, rules))
+            .collect()
+    }
+
+    /// Every change of the weather, in order
+    pub fn weather_events(&self) -> &[WeatherEvent] {
+        self.weather_events.as_ref()
+    }
+
+    /// Every change of the map border, in order. Empty if the whole map was playable
+    /// for the entire battle.
+    pub fn map_borders(&self) -> &[MapBorderChange] {
+        self.map_borders.as_ref()
+    }
+
+    /// Every torpedo salvo launched or dropped during the battle, in order
+    pub fn torpedo_launches(&self) -> &[TorpedoLaunch] {
+        self.torpedo_launches.as_ref()
+    }
+
+    /// Enemy torpedoes heading for the recording player's ship, in the order they were
+    /// spotted
+    pub fn torpedo_warnings(&self) -> &[TorpedoWarning] {
+        self.torpedo_warnings.as_ref()
+    }
+
+    /// The player whose ship or aircraft launched the torpedoes
+    pub fn torpedo_owner(&self, launch: &TorpedoLaunch) -> Option<&Rc<VehicleEntity>> {
+        self.player_entities
+            .iter()
+            .find(|entity| entity.id() == launch.owner())
+    }
+
+    /// Torpedo statistics of every player who launched torpedoes or sunk a ship with
+    /// them, ordered by entity id
+    pub fn torpedo_stats(&self) -> &[TorpedoStats] {
+        self.torpedo_stats.as_ref()
+    }
+
+    pub fn torpedo_stats_for(&self, entity_id: u32) -> Option<&TorpedoStats> {
+        self.torpedo_stats
+            .iter()
+            .find(|stats| stats.entity_id() == entity_id)
+    }
+
+    /// Every time aircraft were shot down by a ship, in order
+    pub fn planes_shot_down(&self) -> &[PlanesShotDown] {
+        self.planes_shot_down.as_ref()
+    }
+
+    /// Number of aircraft the ship had shot down by `timestamp`
+    pub fn planes_shot_down_by(&self, entity_id: u32, timestamp: Duration) -> usize {
+        self.planes_shot_down
+            .iter()
+            .filter(|kill| kill.killer() == entity_id && kill.timestamp() <= timestamp)
+            .map(|kill| kill.count())
+            .sum()
+    }
+
+    /// Secondary battery and anti-aircraft statistics of every player who shot down
+    /// aircraft or sunk a ship with secondaries, ordered by entity id
+    pub fn engagement_stats(&self) -> &[EngagementStats] {
+        self.engagement_stats.as_ref()
+    }
+
+    pub fn engagement_stats_for(&self, entity_id: u32) -> Option<&EngagementStats> {
+        self.engagement_stats
+            .iter()
+            .find(|stats| stats.entity_id() == entity_id)
+    }
+
+    /// Health, torpedo protection and heals of every player's ship resolved from the
+    /// GameParams, by entity id. Ships whose hull isn't in the GameParams are missing.
+    pub fn survivability(&self) -> &BTreeMap<u32, Survivability> {
+        &self.survivability
+    }
+
+    pub fn survivability_of(&self, entity_id: u32) -> Option<&Survivability> {
+        self.survivability.get(&entity_id)
+    }
+
+    /// Every consumable used by every ship, in order
+    pub fn consumable_activations(&self) -> &[ConsumableActivation] {
+        self.consumable_activations.as_ref()
+    }
+
+    /// Every hit of damage dealt by every ship, in order
+    pub fn damage_timeline(&self) -> &[DamageEvent] {
+        self.damage_timeline.as_ref()
+    }
+
+    /// The ribbons each ship earned, in order, by entity id. Only the recording player's
+    /// client is told about ribbons, so this has at most the recording player's ship.
+    pub fn ribbons(&self) -> &BTreeMap<u32, Vec<(GameClock, Ribbon)>> {
+        &self.ribbons
+    }
+
+    /// How many of `ribbon` the ship with id `entity_id` earned
+    pub fn ribbon_count(&self, entity_id: u32, ribbon: Ribbon) -> usize {
+        self.ribbons.get(&entity_id).map_or(0, |ribbons| {
+            ribbons
+                .iter()
+                .filter(|(_, earned)| *earned == ribbon)
+                .count()
+        })
+    }
+
+    /// When each consumable equipped on each player's ship was ready, active or reloading,
+    /// ordered by entity id. Ships not in the GameParams are missing.
+    pub fn consumables(&self) -> &[ConsumableTimeline] {
+        self.consumables.as_ref()
+    }
+
+    pub fn consumable_timeline(
+        &self,
+        entity_id: u32,
+        consumable: Consumable,
+    ) -> Option<&ConsumableTimeline> {
+        self.consumables.iter().find(|timeline| {
+            timeline.entity_id() == entity_id && timeline.consumable() == consumable
+        })
+    }
+
+    /// Rates every player's performance against their ship's expected values, ordered by
+    /// entity id. Players whose ship has no expected values are left out.
+    pub fn personal_ratings(&self, provider: &dyn ExpectedValuesProvider) -> Vec<PersonalRating> {
+        self.player_entities
+            .iter()
+            .filter_map(|vehicle| {
+                let player = vehicle.player()?;
+                let expected = provider.expected_values(player.vehicle().id())?;
+                let won = self
+                    .outcome_for_team(player.team_id())
+                    .map(|outcome| outcome == BattleOutcome::Win);
+                Some(PersonalRating::compute(
+                    vehicle.id(),
+                    &expected,
+                    vehicle.damage(),
+                    vehicle.kills().len(),
+                    won,
+                ))
+            })
+            .collect()
+    }
 }
 
 type Id = u32;
 
-struct DamageEvent {
-    amount: f32,
+/// The result of a main battery salvo fired by the recording player, built by matching
+/// the hit ribbons the player received against the salvos they had in flight.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SalvoOutcome {
+    timestamp: Duration,
+    salvo_id: i32,
+    shells_fired: usize,
+    flight_time: f32,
+    penetrations: usize,
+    over_penetrations: usize,
+    non_penetrations: usize,
+    ricochets: usize,
+    citadels: usize,
+}
+
+impl SalvoOutcome {
+    fn from_salvo(timestamp: Duration, salvo: &ArtillerySalvo) -> Self {
+        SalvoOutcome {
+            timestamp,
+            salvo_id: salvo.salvo_id,
+            shells_fired: salvo.shots.len(),
+            flight_time: salvo
+                .shots
+                .iter()
+                .fold(0.0, |max, shot| f32::max(max, shot.server_time_left)),
+            ..Default::default()
+        }
+    }
+
+    /// Returns true if a ribbon received at `timestamp` could belong to this salvo
+    fn is_landing_at(&self, timestamp: Duration) -> bool {
+        let landed_by = self.timestamp.as_secs_f32() + self.flight_time + SALVO_RIBBON_GRACE_PERIOD;
+        timestamp >= self.timestamp && timestamp.as_secs_f32() <= landed_by
+    }
+
+    /// Returns true if some shells of this salvo have not yet been matched to a ribbon
+    fn has_unaccounted_shells(&self) -> bool {
+        self.hits() + self.ricochets < self.shells_fired
+    }
+
+    /// Records a ribbon against this salvo. Returns false if the ribbon is not an
+    /// artillery hit ribbon.
+    fn record_ribbon(&mut self, ribbon: Ribbon) -> bool {
+        match ribbon {
+            Ribbon::Penetration => self.penetrations += 1,
+            Ribbon::OverPenetration => self.over_penetrations += 1,
+            Ribbon::NonPenetration => self.non_penetrations += 1,
+            Ribbon::Ricochet => self.ricochets += 1,
+            Ribbon::Citadel => self.citadels += 1,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Time the salvo was fired at
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    pub fn salvo_id(&self) -> i32 {
+        self.salvo_id
+    }
+
+    pub fn shells_fired(&self) -> usize {
+        self.shells_fired
+    }
+
+    /// Time, in seconds, the longest-flying shell of this salvo took to land
+    pub fn flight_time(&self) -> f32 {
+        self.flight_time
+    }
+
+    /// Number of shells which hit a ship. Ricochets are not counted as hits.
+    pub fn hits(&self) -> usize {
+        self.penetrations + self.over_penetrations + self.non_penetrations + self.citadels
+    }
+
+    pub fn penetrations(&self) -> usize {
+        self.penetrations
+    }
+
+    pub fn over_penetrations(&self) -> usize {
+        self.over_penetrations
+    }
+
+    pub fn non_penetrations(&self) -> usize {
+        self.non_penetrations
+    }
+
+    pub fn ricochets(&self) -> usize {
+        self.ricochets
+    }
+
+    pub fn citadels(&self) -> usize {
+        self.citadels
+    }
+}
+
+/// A ship taking damage from another, as reported to the victim's clients
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DamageEvent {
+    timestamp: Duration,
+    aggressor: Id,
     victim: Id,
+    amount: f32,
+}
+
+impl DamageEvent {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// Entity ID of the ship which dealt the damage
+    pub fn aggressor(&self) -> Id {
+        self.aggressor
+    }
+
+    /// Entity ID of the ship which took the damage
+    pub fn victim(&self) -> Id {
+        self.victim
+    }
+
+    pub fn amount(&self) -> f32 {
+        self.amount
+    }
 }
 
 pub struct BattleController<'res, 'replay, G> {
@@ -344,10 +939,48 @@ pub struct BattleController<'res, 'replay, G> {
     entities_by_id: HashMap<Id, Entity>,
     method_callbacks: HashMap<(ParamType, String), fn(&PacketType<'_, '_>)>,
     property_callbacks: HashMap<(ParamType, String), fn(&ArgValue<'_>)>,
-    damage_dealt: HashMap<u32, Vec<DamageEvent>>,
+    damage_timeline: Vec<DamageEvent>,
     frags: HashMap<u32, Vec<Death>>,
+    smoke_fire_events: HashMap<Id, Vec<SmokeFireEvent>>,
+    /// Last known normalized minimap position of each ship
+    minimap_positions: HashMap<Id, (f32, f32)>,
+    /// Last known world position of each ship
+    world_positions: HashMap<Id, Vec3>,
+    /// GameParams ID of the last main battery shell each ship fired
+    last_shell_params: HashMap<Id, u32>,
     event_handler: Option<Rc<dyn EventHandler>>,
     game_chat: Vec<GameMessage>,
+    salvo_outcomes: Vec<SalvoOutcome>,
+    /// Ribbons received and consumables used by the recording player
+    self_events: MatchEventCounts,
+    /// Ribbons earned by each ship, which is only ever the recording player's
+    ribbons: HashMap<Id, Vec<(GameClock, Ribbon)>>,
+    battle_results: Option<String>,
+    winning_team: Option<i8>,
+    /// Only tracked for observer replays
+    observer_data: Option<ObserverData>,
+    /// Only tracked when enabled with [BattleController::track_property_history]
+    property_history: Option<PropertyHistory>,
+    /// Clock of the last processed packet
+    clock: GameClock,
+    base_player_created: bool,
+    rejoins: Vec<Rejoin>,
+    /// Damage that may be resent after the latest rejoin
+    rejoin_resend: Option<RejoinResend>,
+    /// Entity IDs ships had before a rejoin, mapped to their new IDs
+    entity_remap: HashMap<Id, Id>,
+    timeline: Vec<TimelineSample>,
+    timeline_bucket: Duration,
+    next_timeline_sample: Duration,
+    /// Entity ID of the recording player's avatar
+    avatar_id: Option<Id>,
+    weather: WeatherState,
+    weather_events: Vec<WeatherEvent>,
+    map_borders: Vec<MapBorderChange>,
+    torpedo_launches: Vec<TorpedoLaunch>,
+    torpedo_warnings: Vec<TorpedoWarning>,
+    planes_shot_down: Vec<PlanesShotDown>,
+    consumable_activations: Vec<ConsumableActivation>,
     version: Version,
 }
 
@@ -382,8 +1015,39 @@ where
             event_handler: None,
             game_chat: Default::default(),
             version: crate::version::Version::from_client_exe(&game_meta.clientVersionFromExe),
-            damage_dealt: Default::default(),
+            damage_timeline: Vec::new(),
             frags: Default::default(),
+            smoke_fire_events: Default::default(),
+            minimap_positions: Default::default(),
+            world_positions: Default::default(),
+            last_shell_params: Default::default(),
+            salvo_outcomes: Default::default(),
+            self_events: Default::default(),
+            ribbons: HashMap::new(),
+            battle_results: None,
+            winning_team: None,
+            observer_data: if game_meta.is_observer() {
+                Some(ObserverData::default())
+            } else {
+                None
+            },
+            property_history: None,
+            clock: GameClock::default(),
+            base_player_created: false,
+            rejoins: Vec::new(),
+            rejoin_resend: None,
+            entity_remap: HashMap::new(),
+            timeline: Vec::new(),
+            timeline_bucket: DEFAULT_TIMELINE_BUCKET,
+            next_timeline_sample: Duration::ZERO,
+            avatar_id: None,
+            weather: WeatherState::default(),
+            weather_events: Vec::new(),
+            map_borders: Vec::new(),
+            torpedo_launches: Vec::new(),
+            torpedo_warnings: Vec::new(),
+            planes_shot_down: Vec::new(),
+            consumable_activations: Vec::new(),
         }
     }
 
@@ -391,10 +1055,32 @@ where
         self.event_handler = Some(event_handler);
     }
 
+    /// Records a history of changes to the given vehicle properties, instead of only
+    /// their latest values. Should be called before any packets are processed.
+    pub fn track_property_history(&mut self, properties: &[TrackedProperty]) {
+        self.property_history = Some(PropertyHistory::new(properties));
+    }
+
+    /// The property history recorded so far, if enabled
+    pub fn property_history(&self) -> Option<&PropertyHistory> {
+        self.property_history.as_ref()
+    }
+
+    /// Sets how often the report's timeline is sampled, every second by default. A zero
+    /// duration disables the timeline.
+    pub fn set_timeline_bucket(&mut self, bucket: Duration) {
+        self.timeline_bucket = bucket;
+    }
+
     pub fn players(&self) -> &[SharedPlayer] {
         self.metadata_players.as_ref()
     }
 
+    /// Resolves any form of player ID to the player, for the players seen so far
+    pub fn id_resolver(&self) -> IdResolver {
+        IdResolver::new(self.player_entities.values())
+    }
+
     pub fn game_mode(&self) -> String {
         let id = format!("IDS_SCENARIO_{}", self.game_meta.scenario.to_uppercase());
         self.game_resources
@@ -492,6 +1178,19 @@ where
 
         match entity_type {
             EntityType::Vehicle => {
+                // Entities are created again after the client reconnects. Keep the
+                // existing entity so that data associated with it isn't lost.
+                if let Some(vehicle) = self
+                    .entities_by_id
+                    .get(&packet.entity_id)
+                    .and_then(|entity| entity.vehicle_ref())
+                {
+                    RefCell::borrow_mut(vehicle)
+                        .props
+                        .update_from_args(&packet.props, self.version);
+                    return;
+                }
+
                 let mut props = VehicleProps::default();
                 props.update_from_args(&packet.props, self.version.clone());
 
@@ -516,14 +1215,44 @@ where
                     captain,
                     damage: 0.0,
                     death_info: None,
+                    kills: Vec::new(),
+                    smoke_fire_events: Vec::new(),
                 }));
 
                 self.entities_by_id
                     .insert(packet.entity_id, Entity::Vehicle(vehicle.clone()));
             }
-            EntityType::BattleLogic => debug!("BattleLogic create"),
+            EntityType::BattleLogic => {
+                debug!("BattleLogic create");
+                let mut logic = BattleLogic::new(packet.entity_id);
+                for (name, value) in &packet.props {
+                    logic.set_property(name, value);
+                }
+
+                self.entities_by_id.insert(
+                    packet.entity_id,
+                    Entity::BattleLogic(Rc::new(RefCell::new(logic))),
+                );
+                self.update_weather();
+                self.update_map_border();
+            }
             EntityType::InteractiveZone => debug!("InteractiveZone create"),
-            EntityType::SmokeScreen => debug!("SmokeScreen create"),
+            EntityType::SmokeScreen => {
+                debug!("SmokeScreen create");
+                let mut smoke = SmokeScreen {
+                    id: packet.entity_id,
+                    radius: 0.0,
+                    points: Vec::new(),
+                };
+                for (name, value) in &packet.props {
+                    smoke.update_by_name(name, value);
+                }
+
+                self.entities_by_id.insert(
+                    packet.entity_id,
+                    Entity::SmokeScreen(Rc::new(RefCell::new(smoke))),
+                );
+            }
             EntityType::BattleEntity => debug!("BattleEntity create"),
             EntityType::Building => debug!("Building create"),
         }
@@ -533,115 +1262,772 @@ where
         self.game_chat.as_slice()
     }
 
-    pub fn build_report(mut self) -> BattleReport {
-        for (aggressor, damage_events) in &self.damage_dealt {
-            if let Some(aggressor_player) = self.entities_by_id.get_mut(&aggressor) {
-                let vehicle = aggressor_player
-                    .vehicle_ref()
-                    .expect("aggressor has no vehicle?");
+    /// Entity ID of the recording player's vehicle, if the arena state has been received
+    fn self_entity_id(&self) -> Option<Id> {
+        self.player_entities
+            .values()
+            .find(|player| player.relation == 0)
+            .map(|player| player.entity_id)
+    }
 
-                let mut vehicle = vehicle.borrow_mut();
-                vehicle.damage += damage_events.iter().fold(0.0, |mut accum, event| {
-                    accum += event.amount;
-                    accum
-                });
-            } else {
-                // panic!("unknown aggressor {:?}?", *aggressor);
+    fn handle_artillery_shots(&mut self, timestamp: Duration, salvos: &[ArtillerySalvo]) {
+        let self_id = self.self_entity_id();
+
+        for salvo in salvos {
+            self.check_smoke_fire(timestamp, salvo);
+            self.last_shell_params
+                .insert(salvo.owner_id as Id, salvo.params_id);
+
+            if Some(salvo.owner_id as u32) == self_id {
+                self.salvo_outcomes
+                    .push(SalvoOutcome::from_salvo(timestamp, salvo));
             }
         }
+    }
 
-        self.entities_by_id.values().for_each(|entity| {
-            if let Some(vehicle) = entity.vehicle_ref() {
-                let mut vehicle = vehicle.borrow_mut();
+    fn handle_torpedoes(&mut self, timestamp: Duration, salvos: &[TorpedoSalvo]) {
+        for salvo in salvos {
+            let owner = salvo.owner_id as Id;
+            // Carriers don't carry torpedo tubes, so their torpedoes are always dropped
+            // by aircraft
+            let aerial = self
+                .player_entities
+                .get(&owner)
+                .is_some_and(|player| player.vehicle.species() == Some(Species::AirCarrier));
+            self.torpedo_launches.push(TorpedoLaunch {
+                timestamp,
+                owner,
+                params_id: salvo.params_id,
+                count: salvo.torpedoes.len(),
+                aerial,
+            });
+        }
 
-                if let Some(death) = self
-                    .frags
-                    .values()
-                    .find_map(|deaths| deaths.iter().find(|death| death.victim == vehicle.id))
-                {
-                    vehicle.death_info = Some(DeathInfo {
-                        time_lived: death.timestamp - TIME_UNTIL_GAME_START,
-                        killer: death.killer,
-                        cause: death.cause,
-                    })
+        // The client is told about torpedoes as they're launched or spotted, which is
+        // when the player is warned about those heading their way
+        let (self_id, position) = match self
+            .self_entity_id()
+            .and_then(|id| Some((id, self.world_positions.get(&id)?.clone())))
+        {
+            Some(self_position) => self_position,
+            None => return,
+        };
+        let teams = Teams::new(self.player_entities.values());
+        for salvo in salvos {
+            if !teams.is_enemy(self_id, salvo.owner_id as Id) {
+                continue;
+            }
+            for torpedo in &salvo.torpedoes {
+                if TorpedoWarning::is_threat(&torpedo.origin, &torpedo.direction, &position) {
+                    self.torpedo_warnings.push(TorpedoWarning {
+                        owner: salvo.owner_id as Id,
+                        params_id: salvo.params_id,
+                        shot_id: torpedo.shot_id,
+                        spotted_at: timestamp,
+                        ended_at: None,
+                        outcome: TorpedoOutcome::Unknown,
+                    });
                 }
             }
-        });
+        }
+    }
 
-        let player_entity_ids: Vec<_> = self.player_entities.keys().cloned().collect();
-        let player_entities: Vec<Rc<VehicleEntity>> = self
-            .entities_by_id
-            .iter()
-            .filter_map(|(entity_id, entity)| {
-                if player_entity_ids.contains(entity_id) {
-                    let vehicle: VehicleEntity = RefCell::borrow(entity.vehicle_ref()?).clone();
-                    Some(Rc::new(vehicle))
-                } else {
-                    None
+    /// Resolves the warned about torpedoes which stopped, as hits if they stopped at the
+    /// recording player's ship
+    fn handle_shot_kills(&mut self, timestamp: Duration, packs: &[ShotKills]) {
+        let position = match self
+            .self_entity_id()
+            .and_then(|id| self.world_positions.get(&id))
+        {
+            Some(position) => position.clone(),
+            None => return,
+        };
+        for pack in packs {
+            for kill in &pack.kills {
+                let warning = self.torpedo_warnings.iter_mut().find(|warning| {
+                    warning.ended_at.is_none()
+                        && warning.owner == pack.owner_id as Id
+                        && warning.shot_id == kill.shot_id
+                });
+                if let Some(warning) = warning {
+                    let (dx, dz) = (kill.position.x - position.x, kill.position.z - position.z);
+                    warning.ended_at = Some(timestamp);
+                    warning.outcome = if (dx * dx + dz * dz).sqrt() <= TORPEDO_HIT_RADIUS {
+                        TorpedoOutcome::Hit
+                    } else {
+                        TorpedoOutcome::Dodged
+                    };
                 }
-            })
-            .collect();
+            }
+        }
+    }
 
-        BattleReport {
-            self_entity: player_entities
-                .iter()
-                .find(|entity| entity.player.as_ref().unwrap().relation == 0)
-                .cloned()
-                .expect("could not find self_player"),
-            version: Version::from_client_exe(self.game_version()),
-            match_group: self.match_group().to_owned(),
-            map_name: self.map_name(),
-            game_mode: self.game_mode(),
-            game_type: self.game_type(),
-            player_entities,
-            game_chat: self.game_chat,
+    fn handle_plane_death(&mut self, timestamp: Duration, death: &PlaneDeath) {
+        // Aircraft which crash for other reasons, e.g. running out of fuel, have no killer
+        if death.killer_id <= 0 {
+            return;
         }
+        self.planes_shot_down.push(PlanesShotDown {
+            timestamp,
+            squadron_id: death.squadron_id,
+            killer: death.killer_id as Id,
+            count: death.planes.len(),
+        });
     }
-}
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
-pub enum ChatChannel {
-    Division,
-    Global,
-    Team,
-}
+    /// Records a [SmokeFireEvent] if the ship which fired `salvo` was inside of a smoke screen
+    fn check_smoke_fire(&mut self, timestamp: Duration, salvo: &ArtillerySalvo) {
+        // The origin of a shell is the gun which fired it, which is close enough to the
+        // position of the ship for our purposes
+        let position = match salvo.shots.first() {
+            Some(shot) => &shot.origin,
+            None => return,
+        };
 
-fn parse_ship_config<'a>(blob: &'a [u8], version: Version) -> IResult<&'a [u8], ShipConfig> {
+        let smoke = self.entities_by_id.values().find_map(|entity| {
+            let smoke = RefCell::borrow(entity.smoke_screen_ref()?);
+            if smoke.contains(position.x, position.z) {
+                Some(smoke.id)
+            } else {
+                None
+            }
+        });
+
+        if let Some(smoke_id) = smoke {
+            trace!("ship {} fired from smoke {}", salvo.owner_id, smoke_id);
+            self.smoke_fire_events
+                .entry(salvo.owner_id as Id)
+                .or_default()
+                .push(SmokeFireEvent {
+                    timestamp,
+                    smoke_id,
+                    position: position.clone(),
+                });
+        }
+    }
+
+    fn handle_property_update(&mut self, update: &PropertyUpdatePacket<'_>) {
+        let entity = match self.entities_by_id.get(&(update.entity_id as u32)) {
+            Some(entity) => entity,
+            None => return,
+        };
+
+        if let Some(smoke) = entity.smoke_screen_ref() {
+            if update.property == "points" && update.update_cmd.levels.is_empty() {
+                RefCell::borrow_mut(smoke).update_points(&update.update_cmd.action);
+            }
+        }
+        if let Some(logic) = entity.battle_logic_ref() {
+            RefCell::borrow_mut(logic).apply_update(update);
+            match update.property {
+                "state" => self.update_weather(),
+                "mapBorder" => self.update_map_border(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Records any changes of the weather in the `BattleLogic` state
+    fn update_weather(&mut self) {
+        let weather = match self
+            .entities_by_id
+            .values()
+            .find_map(|entity| entity.battle_logic_ref())
+            .and_then(|logic| RefCell::borrow(logic).weather().cloned())
+        {
+            Some(weather) => weather,
+            None => return,
+        };
+        let timestamp = Duration::from(self.clock);
+        for change in self.weather.update_from_json(&weather) {
+            debug!("weather changed: {:?}", change);
+            self.weather_events.push(WeatherEvent { timestamp, change });
+        }
+    }
+
+    /// Records a change of the `BattleLogic` map border
+    fn update_map_border(&mut self) {
+        let border = match self
+            .entities_by_id
+            .values()
+            .find_map(|entity| entity.battle_logic_ref())
+        {
+            Some(logic) => RefCell::borrow(logic).map_border(),
+            None => return,
+        };
+        // Battles without a border never record one
+        let previous = self.map_borders.last().and_then(|change| change.border());
+        if previous == border.as_ref() {
+            return;
+        }
+        debug!("map border changed: {:?}", border);
+        self.map_borders.push(MapBorderChange {
+            timestamp: Duration::from(self.clock),
+            border,
+        });
+    }
+
+    /// Records a change of the view distance from the avatar's `weatherParams`
+    fn update_visibility(&mut self, params: &ArgValue<'_>) {
+        let params = match serde_json::to_value(params) {
+            Ok(params) => params,
+            Err(e) => {
+                debug!("failed to convert weatherParams: {}", e);
+                return;
+            }
+        };
+        if let Some(change) = self.weather.update_visibility(&params) {
+            debug!("weather changed: {:?}", change);
+            self.weather_events.push(WeatherEvent {
+                timestamp: Duration::from(self.clock),
+                change,
+            });
+        }
+    }
+
+    /// Samples the timeline at every bucket boundary up to `timestamp`
+    fn record_timeline(&mut self, timestamp: Duration) {
+        if self.timeline_bucket.is_zero() {
+            return;
+        }
+        for _ in 0..MAX_TIMELINE_SAMPLES_PER_PACKET {
+            if self.next_timeline_sample > timestamp {
+                return;
+            }
+            let sample = self.timeline_sample(self.next_timeline_sample);
+            self.timeline.push(sample);
+            self.next_timeline_sample = self
+                .next_timeline_sample
+                .saturating_add(self.timeline_bucket);
+        }
+        // Skip the rest of the gap rather than filling it with samples
+        debug!("clock jumped to {:?}, skipping timeline samples", timestamp);
+        self.next_timeline_sample = timestamp.saturating_add(self.timeline_bucket);
+    }
+
+    fn timeline_sample(&self, timestamp: Duration) -> TimelineSample {
+        let logic = self
+            .entities_by_id
+            .values()
+            .find_map(|entity| entity.battle_logic_ref())
+            .map(|logic| RefCell::borrow(logic));
+
+        let teams = self
+            .teams()
+            .team_ids()
+            .map(|team_id| {
+                let mut team = TeamSnapshot {
+                    team_id,
+                    health: 0.0,
+                    max_health: 0.0,
+                    score: logic.as_ref().and_then(|logic| logic.team_score(team_id)),
+                    ships_alive: 0,
+                    control_points: logic
+                        .as_ref()
+                        .map(|logic| logic.control_points_held(team_id))
+                        .unwrap_or_default(),
+                    fires: 0,
+                    damage_over_time: 0.0,
+                };
+                for player in self
+                    .player_entities
+                    .values()
+                    .filter(|player| player.team_id == team_id)
+                {
+                    team.max_health += player.max_health as f32;
+                    let vehicle = match self
+                        .entities_by_id
+                        .get(&player.entity_id)
+                        .and_then(|entity| entity.vehicle_ref())
+                    {
+                        Some(vehicle) => RefCell::borrow(vehicle),
+                        // Ships which haven't been created yet are at full health
+                        None => {
+                            team.health += player.max_health as f32;
+                            team.ships_alive += 1;
+                            continue;
+                        }
+                    };
+                    if vehicle.props.is_alive {
+                        // Each set bit is a burning section of the ship
+                        let fires = vehicle.props.burning_flags.count_ones() as usize;
+                        team.health += vehicle.props.health;
+                        team.ships_alive += 1;
+                        team.fires += fires;
+                        team.damage_over_time +=
+                            fires as f32 * FIRE_DAMAGE_RATE * player.max_health as f32;
+                    }
+                }
+                team
+            })
+            .collect();
+
+        TimelineSample {
+            timestamp,
+            teams,
+            cyclone: self.weather.is_cyclone(),
+        }
+    }
+
+    fn handle_position(&mut self, timestamp: Duration, entity_id: Id, position: &Vec3) {
+        self.world_positions.insert(entity_id, position.clone());
+        if let Some(history) = self.property_history.as_mut() {
+            history.record_position(entity_id, timestamp, position);
+        }
+    }
+
+    /// Records the tracked properties of a vehicle, or only `property` if given
+    fn record_property_history(
+        &mut self,
+        timestamp: Duration,
+        entity_id: Id,
+        property: Option<TrackedProperty>,
+    ) {
+        let history = match self.property_history.as_mut() {
+            Some(history) => history,
+            None => return,
+        };
+        if let Some(vehicle) = self
+            .entities_by_id
+            .get(&entity_id)
+            .and_then(|entity| entity.vehicle_ref())
+        {
+            history.record(
+                entity_id,
+                timestamp,
+                &RefCell::borrow(vehicle).props,
+                property,
+            );
+        }
+    }
+
+    fn handle_ribbon(&mut self, timestamp: Duration, ribbon: Ribbon) {
+        *self.self_events.ribbons.entry(ribbon).or_default() += 1;
+        match self.self_entity_id() {
+            Some(self_id) => self
+                .ribbons
+                .entry(self_id)
+                .or_default()
+                .push((GameClock(timestamp.as_secs_f32()), ribbon)),
+            None => trace!("ribbon {:?} before the recording player is known", ribbon),
+        }
+
+        // Earlier salvos land first, so attribute the ribbon to the oldest salvo which
+        // could still be landing and has shells left to account for.
+        if let Some(salvo) = self
+            .salvo_outcomes
+            .iter_mut()
+            .find(|salvo| salvo.is_landing_at(timestamp) && salvo.has_unaccounted_shells())
+        {
+            if !salvo.record_ribbon(ribbon) {
+                trace!("ribbon {:?} is not an artillery hit", ribbon);
+            }
+        }
+    }
+
+    fn handle_consumable(
+        &mut self,
+        timestamp: Duration,
+        entity: Id,
+        consumable: Consumable,
+        duration: f32,
+    ) {
+        if Some(entity) == self.self_entity_id() {
+            *self.self_events.consumables.entry(consumable).or_default() += 1;
+        }
+        self.consumable_activations.push(ConsumableActivation {
+            timestamp,
+            entity_id: entity,
+            consumable,
+            duration,
+        });
+    }
+
+    pub fn build_report(mut self) -> BattleReport {
+        for event in &self.damage_timeline {
+            if let Some(aggressor_player) = self.entities_by_id.get_mut(&event.aggressor) {
+                let vehicle = aggressor_player
+                    .vehicle_ref()
+                    .expect("aggressor has no vehicle?");
+
+                vehicle.borrow_mut().damage += event.amount;
+            } else {
+                // panic!("unknown aggressor {:?}?", event.aggressor);
+            }
+        }
+
+        self.entities_by_id.values().for_each(|entity| {
+            if let Some(vehicle) = entity.vehicle_ref() {
+                let mut vehicle = vehicle.borrow_mut();
+
+                if let Some(events) = self.smoke_fire_events.get(&vehicle.id) {
+                    vehicle.smoke_fire_events = events.clone();
+                }
+
+                if let Some(death) = self
+                    .frags
+                    .values()
+                    .find_map(|deaths| deaths.iter().find(|death| death.victim == vehicle.id))
+                {
+                    vehicle.death_info = Some(DeathInfo {
+                        // A death timestamped before the battle started, e.g. from a
+                        // corrupt clock, counts as no time lived
+                        time_lived: GameClock::from(death.timestamp)
+                            .battle_time()
+                            .unwrap_or_default(),
+                        killer: death.killer,
+                        cause: death.cause,
+                        grid_square: death.grid_square,
+                        weapon_params_id: death.weapon_params_id,
+                    })
+                }
+
+                if let Some(kills) = self.frags.get(&vehicle.id) {
+                    vehicle.kills = kills
+                        .iter()
+                        .map(|death| KillInfo {
+                            timestamp: death.timestamp,
+                            victim: death.victim,
+                            cause: death.cause,
+                            grid_square: death.grid_square,
+                            weapon_params_id: death.weapon_params_id,
+                        })
+                        .collect();
+                }
+            }
+        });
+
+        let player_entity_ids: Vec<_> = self.player_entities.keys().cloned().collect();
+        let mut player_entities: Vec<Rc<VehicleEntity>> = self
+            .entities_by_id
+            .iter()
+            .filter_map(|(entity_id, entity)| {
+                if player_entity_ids.contains(entity_id) {
+                    let vehicle: VehicleEntity = RefCell::borrow(entity.vehicle_ref()?).clone();
+                    Some(Rc::new(vehicle))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        player_entities.sort_by_key(|entity| entity.id());
+
+        let self_entity = player_entities
+            .iter()
+            .find(|entity| entity.player.as_ref().unwrap().relation == 0)
+            .cloned();
+        if self_entity.is_none() && self.observer_data.is_none() {
+            warn!("could not find the recording player in a non-observer replay");
+        }
+
+        let death_contexts = self.death_contexts();
+        let torpedo_stats = self.torpedo_stats(self_entity.as_deref());
+        let engagement_stats = self.engagement_stats(self_entity.as_deref());
+        let resolver = BuildResolver::new(self.game_resources);
+        let survivability = player_entities
+            .iter()
+            .filter_map(|vehicle| {
+                Some((
+                    vehicle.id(),
+                    resolver.resolve_vehicle_survivability(vehicle)?,
+                ))
+            })
+            .collect();
+        let activations = &self.consumable_activations;
+        let consumables = player_entities
+            .iter()
+            .filter_map(|vehicle| {
+                Some((vehicle.id(), resolver.resolve_vehicle_consumables(vehicle)?))
+            })
+            .flat_map(|(entity_id, specs)| {
+                specs.into_iter().map(move |spec| {
+                    let used: Vec<_> = activations
+                        .iter()
+                        .filter(|activation| {
+                            activation.entity_id == entity_id
+                                && activation.consumable == spec.consumable
+                        })
+                        .copied()
+                        .collect();
+                    ConsumableTimeline::new(entity_id, spec, &used)
+                })
+            })
+            .collect();
+
+        self.self_events.salvos = self.salvo_outcomes.len();
+        let skill_efficacy = self
+            .self_entity_skills(self_entity.as_deref())
+            .unwrap_or_default();
+
+        BattleReport {
+            self_entity,
+            version: Version::from_client_exe(self.game_version()),
+            match_group: self.match_group().to_owned(),
+            map_name: self.map_name(),
+            game_mode: self.game_mode(),
+            game_type: self.game_type(),
+            player_entities,
+            game_chat: self.game_chat,
+            salvo_outcomes: self.salvo_outcomes,
+            skill_efficacy,
+            captures: self
+                .self_events
+                .ribbons
+                .get(&Ribbon::Captured)
+                .cloned()
+                .unwrap_or_default(),
+            battle_results: self.battle_results,
+            winning_team: self.winning_team,
+            observer_data: self.observer_data,
+            property_history: self.property_history,
+            death_contexts,
+            rejoins: self.rejoins,
+            timeline: self.timeline,
+            weather_events: self.weather_events,
+            map_borders: self.map_borders,
+            torpedo_launches: self.torpedo_launches,
+            torpedo_stats,
+            torpedo_warnings: self.torpedo_warnings,
+            planes_shot_down: self.planes_shot_down,
+            engagement_stats,
+            survivability,
+            consumable_activations: self.consumable_activations,
+            consumables,
+            damage_timeline: self.damage_timeline,
+            ribbons: self.ribbons.into_iter().collect(),
+        }
+    }
+
+    fn torpedo_stats(&self, self_entity: Option<&VehicleEntity>) -> Vec<TorpedoStats> {
+        fn entry(stats: &mut HashMap<u32, TorpedoStats>, entity_id: u32) -> &mut TorpedoStats {
+            stats.entry(entity_id).or_insert_with(|| TorpedoStats {
+                entity_id,
+                ..Default::default()
+            })
+        }
+
+        let mut stats = HashMap::new();
+        for launch in &self.torpedo_launches {
+            entry(&mut stats, launch.owner).record_launch(launch);
+        }
+        for (killer, deaths) in &self.frags {
+            for death in deaths.iter().filter(|death| {
+                matches!(death.cause, DeathCause::Torpedo | DeathCause::AerialTorpedo)
+            }) {
+                entry(&mut stats, *killer).record_kill(death.cause);
+            }
+        }
+        if let Some(self_entity) = self_entity {
+            let ribbons = |ribbon| {
+                self.self_events
+                    .ribbons
+                    .get(&ribbon)
+                    .copied()
+                    .unwrap_or_default()
+            };
+            let stats = entry(&mut stats, self_entity.id);
+            stats.hits = Some(ribbons(Ribbon::TorpedoHit));
+            stats.floods = Some(ribbons(Ribbon::Flooding));
+        }
+
+        let mut stats: Vec<TorpedoStats> = stats.into_values().collect();
+        stats.sort_by_key(|stats| stats.entity_id);
+        stats
+    }
+
+    fn engagement_stats(&self, self_entity: Option<&VehicleEntity>) -> Vec<EngagementStats> {
+        fn entry(
+            stats: &mut HashMap<u32, EngagementStats>,
+            entity_id: u32,
+        ) -> &mut EngagementStats {
+            stats.entry(entity_id).or_insert_with(|| EngagementStats {
+                entity_id,
+                ..Default::default()
+            })
+        }
+
+        let mut stats = HashMap::new();
+        for kill in &self.planes_shot_down {
+            entry(&mut stats, kill.killer).planes_shot_down += kill.count;
+        }
+        for (killer, deaths) in &self.frags {
+            let kills = deaths
+                .iter()
+                .filter(|death| death.cause == DeathCause::Secondaries)
+                .count();
+            if kills > 0 {
+                entry(&mut stats, *killer).secondary_kills += kills;
+            }
+        }
+        if let Some(self_entity) = self_entity {
+            entry(&mut stats, self_entity.id).secondary_hits = Some(
+                self.self_events
+                    .ribbons
+                    .get(&Ribbon::SecondaryHit)
+                    .copied()
+                    .unwrap_or_default(),
+            );
+        }
+
+        let mut stats: Vec<EngagementStats> = stats.into_values().collect();
+        stats.sort_by_key(|stats| stats.entity_id);
+        stats
+    }
+
+    fn death_contexts(&self) -> Vec<DeathContext> {
+        let mut contexts: Vec<DeathContext> = self
+            .frags
+            .values()
+            .flatten()
+            .map(|death| {
+                let window_start = death.timestamp.saturating_sub(DEATH_CONTEXT_WINDOW);
+                let victim_health = self
+                    .property_history
+                    .as_ref()
+                    .map(|history| {
+                        history.changes_between(
+                            death.victim,
+                            TrackedProperty::Health,
+                            window_start,
+                            death.timestamp,
+                        )
+                    })
+                    .unwrap_or_default();
+
+                let mut context = DeathContext {
+                    timestamp: death.timestamp,
+                    victim: death.victim,
+                    killer: death.killer,
+                    cause: death.cause,
+                    killer_position: death.killer_position.clone(),
+                    victim_position: death.victim_position.clone(),
+                    victim_health,
+                    damage_sources: Vec::new(),
+                };
+                for event in &self.damage_timeline {
+                    if event.victim == death.victim
+                        && event.timestamp >= window_start
+                        && event.timestamp <= death.timestamp
+                    {
+                        context.add_damage(event.aggressor, event.amount);
+                    }
+                }
+                context.damage_sources.sort_by(|a, b| {
+                    b.damage()
+                        .total_cmp(&a.damage())
+                        .then(a.aggressor().cmp(&b.aggressor()))
+                });
+                context
+            })
+            .collect();
+        contexts.sort_by_key(|context| (context.timestamp, context.victim));
+        contexts
+    }
+
+    fn self_entity_skills(
+        &self,
+        self_entity: Option<&VehicleEntity>,
+    ) -> Option<Vec<SkillEfficacy>> {
+        let self_entity = self_entity?;
+        let species = self_entity.player.as_ref()?.vehicle.species()?;
+        let skills = self_entity.commander_skills()?;
+        Some(evaluate_skills(&skills, &species, &self.self_events))
+    }
+}
+
+/// Numbers the divisions of each team in the order their first member appears, and
+/// flags the recording player's division mates.
+fn assign_divisions(players: &mut [Player]) {
+    let self_division = players
+        .iter()
+        .find(|player| player.relation == 0)
+        .map(|player| player.division_id)
+        .filter(|division_id| *division_id != 0);
+
+    let mut division_numbers: HashMap<(u32, i64), u32> = HashMap::new();
+    for player in players.iter() {
+        if player.division_id == 0 {
+            continue;
+        }
+        let team_divisions = division_numbers
+            .keys()
+            .filter(|(team_id, _)| *team_id == player.team_id)
+            .count() as u32;
+        division_numbers
+            .entry((player.team_id, player.division_id))
+            .or_insert(team_divisions + 1);
+    }
+
+    for player in players.iter_mut() {
+        player.division_number = division_numbers
+            .get(&(player.team_id, player.division_id))
+            .cloned();
+        player.is_division_mate = player.relation != 0
+            && self_division.is_some()
+            && self_division == Some(player.division_id);
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum ChatChannel {
+    Division,
+    Global,
+    Team,
+}
+
+/// Parses a u32 count followed by that many items of `item_size` bytes each. Counts which
+/// couldn't possibly fit in the remaining input are rejected up front, so that corrupt
+/// data can't request huge allocations.
+fn counted<'a, O, F>(
+    i: &'a [u8],
+    what: &str,
+    item_size: usize,
+    item: F,
+) -> IResult<&'a [u8], Vec<O>>
+where
+    F: FnMut(&'a [u8]) -> IResult<&'a [u8], O>,
+{
+    let (i, n) = le_u32(i)?;
+    if (n as usize).saturating_mul(item_size) > i.len() {
+        return Err(parsing_failure(format!(
+            "ship config claims {} {} but only {} bytes remain",
+            n,
+            what,
+            i.len()
+        )));
+    }
+    count(item, n as usize)(i)
+}
+
+fn parse_ship_config<'a>(blob: &'a [u8], version: Version) -> IResult<&'a [u8], ShipConfig> {
     let i = blob;
     let (i, _unk) = le_u32(i)?;
 
     let (i, ship_params_id) = le_u32(i)?;
     let (i, _unk2) = le_u32(i)?;
 
-    let (i, unit_count) = le_u32(i)?;
-    let (i, units) = count(le_u32, unit_count as usize)(i)?;
+    let (i, units) = counted(i, "units", 4, le_u32)?;
+    if units.is_empty() {
+        return Err(parsing_failure("ship config has no units".to_string()));
+    }
 
-    let i = if version.is_at_least(&Version {
-        major: 13,
-        minor: 2,
-        patch: 0,
-        build: 0,
-    }) {
+    let i = if version.capabilities().ship_config_extra_unit_field {
         let (i, _unk) = le_u32(i)?;
         i
     } else {
         i
     };
 
-    let (i, modernization_count) = le_u32(i)?;
-    let (i, modernization) = count(le_u32, modernization_count as usize)(i)?;
+    let (i, modernization) = counted(i, "modernizations", 4, le_u32)?;
 
-    let (i, signal_count) = le_u32(i)?;
-    let (i, signals) = count(le_u32, signal_count as usize)(i)?;
+    let (i, signals) = counted(i, "signals", 4, le_u32)?;
 
     let (i, _supply_state) = le_u32(i)?;
 
-    let (i, camo_info_count) = le_u32(i)?;
     // First item in pair is camo_info, second is camo_scheme
-    let (i, _camo) = count(pair(le_u32, le_u32), camo_info_count as usize)(i)?;
+    let (i, _camo) = counted(i, "camouflages", 8, pair(le_u32, le_u32))?;
 
-    let (i, abilities_count) = le_u32(i)?;
-    let (i, abilities) = count(le_u32, abilities_count as usize)(i)?;
+    let (i, abilities) = counted(i, "abilities", 4, le_u32)?;
 
     Ok((
         i,
@@ -663,13 +2049,13 @@ pub struct GameMessage {
     pub message: String,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct AAAura {
     id: u32,
     enabled: bool,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct VehicleState {
     /// TODO
     buffs: Option<()>,
@@ -678,7 +2064,7 @@ pub struct VehicleState {
     battery: Option<()>,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct CrewModifiersCompactParams {
     params_id: u32,
     is_in_adaption: bool,
@@ -811,13 +2197,18 @@ impl UpdateFromReplayArgs for CrewModifiersCompactParams {
 
         if args.contains_key(LEARNED_SKILLS_KEY) {
             let learned_skills = arg_value_to_type!(args, LEARNED_SKILLS_KEY, &[()]);
+            // Older versions have fewer species, so missing slots are left empty
             let skills_from_idx = |idx: usize| -> Vec<u8> {
-                learned_skills[idx]
-                    .array_ref()
-                    .unwrap()
-                    .iter()
-                    .map(|idx| *(*idx).uint_8_ref().unwrap())
-                    .collect()
+                learned_skills
+                    .get(idx)
+                    .and_then(|skills| skills.array_ref())
+                    .map(|skills| {
+                        skills
+                            .iter()
+                            .filter_map(|skill| skill.uint_8_ref().copied())
+                            .collect()
+                    })
+                    .unwrap_or_default()
             };
 
             let skills = Skills {
@@ -834,7 +2225,7 @@ impl UpdateFromReplayArgs for CrewModifiersCompactParams {
     }
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct VehicleProps {
     ignore_map_borders: bool,
     air_defense_dispersion_radius: f32,
@@ -1312,11 +2703,10 @@ impl UpdateFromReplayArgs for VehicleProps {
         // TODO: sounds
 
         if args.contains_key(SHIP_CONFIG_KEY) {
-            let (_remainder, ship_config) =
-                parse_ship_config(arg_value_to_type!(args, SHIP_CONFIG_KEY, &[u8]), version)
-                    .expect("failed to parse ship config");
-
-            self.ship_config = ship_config;
+            match parse_ship_config(arg_value_to_type!(args, SHIP_CONFIG_KEY, &[u8]), version) {
+                Ok((_remainder, ship_config)) => self.ship_config = ship_config,
+                Err(e) => warn!(error = ?e, "failed to parse ship config"),
+            }
         }
 
         set_arg_value!(self.wave_local_pos, args, WAVE_LOCAL_POS_KEY, u16);
@@ -1356,11 +2746,101 @@ impl UpdateFromReplayArgs for VehicleProps {
     }
 }
 
-#[derive(Debug, Clone)]
+/// The recording player's client reconnecting to the battle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rejoin {
+    timestamp: Duration,
+    clock_jump: f32,
+}
+
+impl Rejoin {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// Seconds the clock moved between the last packet before the rejoin and the rejoin.
+    /// Negative if the clock went backwards.
+    pub fn clock_jump(&self) -> f32 {
+        self.clock_jump
+    }
+}
+
+/// Damage received shortly before a rejoin, which the server may send again afterwards
+struct RejoinResend {
+    /// How many times each `(aggressor, victim, amount)` was received before the rejoin
+    pending: HashMap<(Id, Id, u32), usize>,
+    /// Resent damage is only expected until this time
+    deadline: Duration,
+}
+
+impl RejoinResend {
+    fn new(damage_timeline: &[DamageEvent], previous_clock: GameClock, rejoined: Duration) -> Self {
+        let window = Duration::from_secs_f32(REJOIN_RESEND_WINDOW);
+        let since = Duration::try_from_secs_f32(previous_clock.seconds())
+            .unwrap_or_default()
+            .saturating_sub(window);
+        let mut pending = HashMap::new();
+        for event in damage_timeline
+            .iter()
+            .rev()
+            .take_while(|event| event.timestamp >= since)
+        {
+            *pending.entry(Self::key(event)).or_insert(0) += 1;
+        }
+
+        RejoinResend {
+            pending,
+            deadline: rejoined + window,
+        }
+    }
+
+    fn key(event: &DamageEvent) -> (Id, Id, u32) {
+        (event.aggressor, event.victim, event.amount.to_bits())
+    }
+
+    /// Updates the pending damage for ships that were given new entity IDs
+    fn remap(&mut self, entity_remap: &HashMap<Id, Id>) {
+        let remap = |id: Id| entity_remap.get(&id).copied().unwrap_or(id);
+        self.pending = self.pending.drain().fold(
+            HashMap::new(),
+            |mut pending, ((aggressor, victim, amount), count)| {
+                *pending
+                    .entry((remap(aggressor), remap(victim), amount))
+                    .or_insert(0) += count;
+                pending
+            },
+        );
+    }
+
+    /// Whether all of `events` were received before the rejoin and not yet resent,
+    /// regardless of their timestamps. Each event received before the rejoin can only
+    /// match once, so identical hits after the resend are still recorded.
+    fn is_resend(&mut self, events: &[DamageEvent]) -> bool {
+        let mut needed: HashMap<_, usize> = HashMap::new();
+        for event in events {
+            *needed.entry(Self::key(event)).or_insert(0) += 1;
+        }
+        let matches = needed
+            .iter()
+            .all(|(key, count)| matches!(self.pending.get(key), Some(pending) if pending >= count));
+        if matches {
+            for (key, count) in needed {
+                if let Some(pending) = self.pending.get_mut(&key) {
+                    *pending -= count;
+                }
+            }
+        }
+        matches
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeathInfo {
     time_lived: Duration,
     killer: u32,
     cause: DeathCause,
+    grid_square: Option<GridSquare>,
+    weapon_params_id: Option<u32>,
 }
 
 impl DeathInfo {
@@ -1375,9 +2855,61 @@ impl DeathInfo {
     pub fn cause(&self) -> DeathCause {
         self.cause
     }
+
+    /// Grid square the ship was last seen in on the minimap before it died
+    pub fn grid_square(&self) -> Option<GridSquare> {
+        self.grid_square
+    }
+
+    /// GameParams ID of the projectile which killed the ship, if known. Only main
+    /// battery kills can currently be linked to the shell which was fired.
+    pub fn weapon_params_id(&self) -> Option<u32> {
+        self.weapon_params_id
+    }
+
+    /// The GameParam of the projectile which killed the ship, see
+    /// [Self::weapon_params_id]
+    pub fn weapon<G: ResourceLoader>(&self, resources: &G) -> Option<Rc<Param>> {
+        resources.game_param_by_id(self.weapon_params_id?)
+    }
 }
 
-#[derive(Debug, Clone)]
+/// A ship destroyed by a player
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KillInfo {
+    timestamp: Duration,
+    victim: u32,
+    cause: DeathCause,
+    grid_square: Option<GridSquare>,
+    weapon_params_id: Option<u32>,
+}
+
+impl KillInfo {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    pub fn victim(&self) -> u32 {
+        self.victim
+    }
+
+    pub fn cause(&self) -> DeathCause {
+        self.cause
+    }
+
+    /// Grid square the victim was last seen in on the minimap before it died
+    pub fn grid_square(&self) -> Option<GridSquare> {
+        self.grid_square
+    }
+
+    /// GameParams ID of the projectile which killed the victim, see
+    /// [DeathInfo::weapon_params_id]
+    pub fn weapon_params_id(&self) -> Option<u32> {
+        self.weapon_params_id
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VehicleEntity {
     id: u32,
     player: Option<Rc<Player>>,
@@ -1386,6 +2918,8 @@ pub struct VehicleEntity {
     captain: Option<Rc<Param>>,
     damage: f32,
     death_info: Option<DeathInfo>,
+    kills: Vec<KillInfo>,
+    smoke_fire_events: Vec<SmokeFireEvent>,
 }
 
 impl VehicleEntity {
@@ -1401,30 +2935,19 @@ impl VehicleEntity {
         &self.props
     }
 
+    /// When the ship was last spotted or went undetected
+    pub fn visibility_changed_at(&self) -> GameClock {
+        GameClock(self.visibility_changed_at)
+    }
+
     pub fn commander_id(&self) -> Id {
         self.props.crew_modifiers_compact_params.params_id
     }
 
+    /// The captain's learned skills for this ship's species. `None` if the player or
+    /// captain aren't known, or the ship's species has no skills.
     pub fn commander_skills(&self) -> Option<Vec<&CrewSkill>> {
-        let vehicle_species = self
-            .player
-            .as_ref()
-            .expect("player has not yet loaded")
-            .vehicle
-            .species()
-            .expect("vehicle species not set");
-
-        let skills = &self.props.crew_modifiers_compact_params.learned_skills;
-        let skills_for_species = match vehicle_species {
-            crate::game_params::Species::AirCarrier => skills.aircraft_carrier.as_slice(),
-            crate::game_params::Species::Battleship => skills.battleship.as_slice(),
-            crate::game_params::Species::Cruiser => skills.cruiser.as_slice(),
-            crate::game_params::Species::Destroyer => skills.destroyer.as_slice(),
-            crate::game_params::Species::Submarine => skills.submarine.as_slice(),
-            other => {
-                panic!("Unexpected vehicle species: {:?}", other);
-            }
-        };
+        let skills_for_species = self.skills_for_species()?;
 
         let captain = self
             .captain()?
@@ -1434,36 +2957,30 @@ impl VehicleEntity {
 
         let skills = skills_for_species
             .iter()
-            .map(|skill_type| {
-                captain
-                    .skill_by_type(*skill_type as u32)
-                    .expect("could not get skill type")
+            .filter_map(|skill_type| {
+                let skill = captain.skill_by_type(*skill_type as u32);
+                if skill.is_none() {
+                    warn!("unknown skill type {}", skill_type);
+                }
+                skill
             })
             .collect();
 
         Some(skills)
     }
 
+    /// The raw skill IDs learned for this ship's species. Empty if the player isn't known
+    /// or the ship's species has no skills.
     pub fn commander_skills_raw(&self) -> &[u8] {
-        let vehicle_species = self
-            .player
-            .as_ref()
-            .expect("player has not yet loaded")
-            .vehicle
-            .species()
-            .expect("vehicle species not set");
-
-        let skills = &self.props.crew_modifiers_compact_params.learned_skills;
-        match vehicle_species {
-            crate::game_params::Species::AirCarrier => skills.aircraft_carrier.as_slice(),
-            crate::game_params::Species::Battleship => skills.battleship.as_slice(),
-            crate::game_params::Species::Cruiser => skills.cruiser.as_slice(),
-            crate::game_params::Species::Destroyer => skills.destroyer.as_slice(),
-            crate::game_params::Species::Submarine => skills.submarine.as_slice(),
-            other => {
-                panic!("Unexpected vehicle species: {:?}", other);
-            }
-        }
+        self.skills_for_species().unwrap_or_default()
+    }
+
+    fn skills_for_species(&self) -> Option<&[u8]> {
+        let species = self.player.as_ref()?.vehicle.species()?;
+        self.props
+            .crew_modifiers_compact_params
+            .learned_skills
+            .for_species(&species)
     }
 
     pub fn captain(&self) -> Option<&Param> {
@@ -1477,11 +2994,133 @@ impl VehicleEntity {
     pub fn death_info(&self) -> Option<&DeathInfo> {
         self.death_info.as_ref()
     }
+
+    /// Ships destroyed by this ship, in the order they were destroyed
+    pub fn kills(&self) -> &[KillInfo] {
+        self.kills.as_ref()
+    }
+
+    /// Every time this ship fired its main battery from inside of a smoke screen
+    pub fn smoke_fire_events(&self) -> &[SmokeFireEvent] {
+        self.smoke_fire_events.as_ref()
+    }
+}
+
+/// Indicates that a ship fired its main battery while inside of a smoke screen. Firing
+/// from smoke increases the ship's detection range for a short period of time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmokeFireEvent {
+    timestamp: Duration,
+    smoke_id: Id,
+    position: Vec3,
+}
+
+impl SmokeFireEvent {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// Entity ID of the smoke screen the ship was in
+    pub fn smoke_id(&self) -> Id {
+        self.smoke_id
+    }
+
+    /// World position of the ship when it fired
+    pub fn position(&self) -> &Vec3 {
+        &self.position
+    }
+}
+
+/// A smoke screen laid by a ship or consumable. The smoke covers a circle of `radius`
+/// around each of its points.
+#[derive(Debug, Clone)]
+pub struct SmokeScreen {
+    id: Id,
+    radius: f32,
+    /// World (x, z) coordinates of each puff of smoke
+    points: Vec<(f32, f32)>,
+}
+
+impl SmokeScreen {
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn points(&self) -> &[(f32, f32)] {
+        self.points.as_ref()
+    }
+
+    /// Returns true if the given world (x, z) coordinate is covered by this smoke screen
+    pub fn contains(&self, x: f32, z: f32) -> bool {
+        let radius_squared = self.radius * self.radius;
+        self.points.iter().any(|(px, pz)| {
+            let dx = px - x;
+            let dz = pz - z;
+            dx * dx + dz * dz <= radius_squared
+        })
+    }
+
+    fn update_by_name(&mut self, name: &str, value: &ArgValue<'_>) {
+        match name {
+            "radius" => {
+                if let ArgValue::Float32(radius) = value {
+                    self.radius = *radius;
+                }
+            }
+            "points" => {
+                if let ArgValue::Array(points) = value {
+                    self.points = points.iter().filter_map(smoke_point).collect();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn update_points(&mut self, action: &UpdateAction<'_>) {
+        match action {
+            UpdateAction::SetRange {
+                start,
+                stop,
+                values,
+            } => {
+                let start = (*start).min(self.points.len());
+                let stop = (*stop).clamp(start, self.points.len());
+                self.points
+                    .splice(start..stop, values.iter().filter_map(smoke_point));
+            }
+            UpdateAction::SetElement { index, value } => {
+                if let (Some(point), Some(new_point)) =
+                    (self.points.get_mut(*index), smoke_point(value))
+                {
+                    *point = new_point;
+                }
+            }
+            UpdateAction::RemoveRange { start, stop } => {
+                let start = (*start).min(self.points.len());
+                let stop = (*stop).clamp(start, self.points.len());
+                self.points.drain(start..stop);
+            }
+            UpdateAction::SetKey { .. } => {}
+        }
+    }
+}
+
+fn smoke_point(value: &ArgValue<'_>) -> Option<(f32, f32)> {
+    match value {
+        ArgValue::Vector2(point) => Some(*point),
+        _ => None,
+    }
 }
 
 #[derive(Debug, Variantly)]
 pub enum Entity {
     Vehicle(Rc<RefCell<VehicleEntity>>),
+    SmokeScreen(Rc<RefCell<SmokeScreen>>),
+    BattleLogic(Rc<RefCell<BattleLogic>>),
 }
 
 impl Entity {
@@ -1490,6 +3129,7 @@ impl Entity {
             Entity::Vehicle(vehicle) => {
                 RefCell::borrow_mut(&*vehicle).player = Some(arena_player);
             }
+            Entity::SmokeScreen(_) | Entity::BattleLogic(_) => {}
         }
     }
 }
@@ -1500,6 +3140,11 @@ struct Death {
     killer: u32,
     victim: u32,
     cause: DeathCause,
+    grid_square: Option<GridSquare>,
+    weapon_params_id: Option<u32>,
+    /// Last known positions at the time of death
+    killer_position: Option<Vec3>,
+    victim_position: Option<Vec3>,
 }
 
 impl<'res, 'replay, G> AnalyzerMut for BattleController<'res, 'replay, G>
@@ -1510,6 +3155,17 @@ where
         let span = span!(Level::TRACE, "packet processing");
         let _enter = span.enter();
 
+        // Corrupt replays may have negative, NaN or enormous clocks, which can't be placed
+        // on the battle's timeline
+        let timestamp = match Duration::try_from_secs_f32(packet.clock) {
+            Ok(timestamp) => timestamp,
+            Err(e) => {
+                debug!("dropping packet with clock {}: {}", packet.clock, e);
+                return;
+            }
+        };
+        let previous_clock = std::mem::replace(&mut self.clock, GameClock(packet.clock));
+        self.record_timeline(timestamp);
         let decoded = DecodedPacket::from(&self.version, false, packet);
         let payload_kind = decoded.payload.kind();
         match decoded.payload {
@@ -1529,14 +3185,22 @@ where
             } => {
                 trace!("HANDLE VOICE LINE");
             }
-            crate::analyzer::decoder::DecodedPacketPayload::Ribbon(_ribbon) => {
-                trace!("HANDLE RIBBON")
+            crate::analyzer::decoder::DecodedPacketPayload::Ribbon(ribbon) => {
+                self.handle_ribbon(timestamp, ribbon);
             }
-            crate::analyzer::decoder::DecodedPacketPayload::Position(_pos) => {
-                trace!("HANDLE POSITION")
+            crate::analyzer::decoder::DecodedPacketPayload::Position(pos) => {
+                trace!("HANDLE POSITION");
+                self.handle_position(timestamp, pos.pid, &pos.position);
             }
-            crate::analyzer::decoder::DecodedPacketPayload::PlayerOrientation(_orientation) => {
-                trace!("PLAYER ORIENTATION")
+            crate::analyzer::decoder::DecodedPacketPayload::PlayerOrientation(orientation) => {
+                trace!("PLAYER ORIENTATION");
+                // The recording player's own ship is only positioned by these packets
+                if orientation.parent_id == 0 {
+                    self.handle_position(timestamp, orientation.pid, &orientation.position);
+                }
+                if let Some(observer_data) = self.observer_data.as_mut() {
+                    observer_data.record_camera_target(timestamp, orientation.parent_id);
+                }
             }
             crate::analyzer::decoder::DecodedPacketPayload::DamageStat(_damage) => {
                 trace!("DAMAGE STAT")
@@ -1546,11 +3210,34 @@ where
                 victim,
                 cause,
             } => {
+                // Deaths may be sent again after the client reconnects
+                let already_dead = self
+                    .frags
+                    .values()
+                    .flatten()
+                    .any(|death| death.victim == victim as u32);
+                if already_dead {
+                    return;
+                }
+
+                let grid_square = self
+                    .minimap_positions
+                    .get(&(victim as u32))
+                    .and_then(|(x, y)| GridSquare::from_normalized(*x, *y));
                 self.frags.entry(killer as u32).or_default().push(Death {
-                    timestamp: Duration::from_secs_f32(packet.clock),
+                    timestamp,
                     killer: killer as u32,
                     victim: victim as u32,
                     cause,
+                    grid_square,
+                    weapon_params_id: match cause {
+                        DeathCause::Artillery => {
+                            self.last_shell_params.get(&(killer as u32)).copied()
+                        }
+                        _ => None,
+                    },
+                    killer_position: self.world_positions.get(&(killer as u32)).cloned(),
+                    victim_position: self.world_positions.get(&(victim as u32)).cloned(),
                 });
             }
             crate::analyzer::decoder::DecodedPacketPayload::EntityMethod(method) => {
@@ -1560,16 +3247,54 @@ where
                 if let Some(entity) = self.entities_by_id.get(&prop.entity_id) {
                     if let Some(vehicle) = entity.vehicle_ref() {
                         let mut vehicle = RefCell::borrow_mut(&vehicle);
+                        let was_visible = vehicle.props.visibility_flags != 0;
                         vehicle.props.update_by_name(
                             prop.property,
                             &prop.value,
                             self.version.clone(),
                         );
+                        if (vehicle.props.visibility_flags != 0) != was_visible {
+                            vehicle.visibility_changed_at = packet.clock;
+                        }
                     }
+                    if let Some(smoke) = entity.smoke_screen_ref() {
+                        RefCell::borrow_mut(smoke).update_by_name(prop.property, &prop.value);
+                    }
+                    if let Some(logic) = entity.battle_logic_ref() {
+                        RefCell::borrow_mut(logic).set_property(prop.property, &prop.value);
+                        match prop.property {
+                            "state" => self.update_weather(),
+                            "mapBorder" => self.update_map_border(),
+                            _ => {}
+                        }
+                    }
+                }
+                if Some(prop.entity_id) == self.avatar_id && prop.property == "weatherParams" {
+                    self.update_visibility(&prop.value);
+                }
+                if let Some(property) = TrackedProperty::from_property_name(prop.property) {
+                    self.record_property_history(timestamp, prop.entity_id, Some(property));
                 }
             }
             crate::analyzer::decoder::DecodedPacketPayload::BasePlayerCreate(base) => {
                 trace!("BASE PLAYER CREATE");
+                // The player's avatar is created once per connection, so seeing it again
+                // means the client reconnected
+                if self.base_player_created {
+                    let rejoin = Rejoin {
+                        timestamp,
+                        clock_jump: packet.clock - previous_clock.seconds(),
+                    };
+                    debug!("client rejoined: {:?}", rejoin);
+                    self.rejoins.push(rejoin);
+                    self.rejoin_resend = Some(RejoinResend::new(
+                        &self.damage_timeline,
+                        previous_clock,
+                        timestamp,
+                    ));
+                }
+                self.base_player_created = true;
+                self.avatar_id = Some(base.entity_id);
             }
             crate::analyzer::decoder::DecodedPacketPayload::CellPlayerCreate(cell) => {
                 // let metadata_player = self
@@ -1586,15 +3311,33 @@ where
                 // self.player_entities
                 //     .insert(battle_player.entity_id, Rc::new(battle_player));
                 trace!("CELL PLAYER CREATE");
+                if let Some(params) = cell.props.get("weatherParams") {
+                    self.update_visibility(params);
+                }
             }
             crate::analyzer::decoder::DecodedPacketPayload::EntityEnter(e) => {
                 trace!("ENTITY ENTER")
             }
-            crate::analyzer::decoder::DecodedPacketPayload::EntityLeave(_leave) => {
-                trace!("ENTITY LEAVE")
+            crate::analyzer::decoder::DecodedPacketPayload::EntityControl(control) => {
+                trace!("ENTITY CONTROL");
+                if let Some(observer_data) = self.observer_data.as_mut() {
+                    observer_data.record_control_change(
+                        timestamp,
+                        control.entity_id,
+                        control.is_controlled,
+                    );
+                }
+            }
+            crate::analyzer::decoder::DecodedPacketPayload::EntityLeave(leave) => {
+                trace!("ENTITY LEAVE");
+                // Smoke screens leave once they've dissipated
+                if let Some(Entity::SmokeScreen(_)) = self.entities_by_id.get(&leave.entity_id) {
+                    self.entities_by_id.remove(&leave.entity_id);
+                }
             }
             crate::analyzer::decoder::DecodedPacketPayload::EntityCreate(entity_create) => {
                 self.handle_entity_create(entity_create);
+                self.record_property_history(timestamp, entity_create.entity_id, None);
             }
             crate::analyzer::decoder::DecodedPacketPayload::OnArenaStateReceived {
                 arg0,
@@ -1602,17 +3345,80 @@ where
                 arg2,
                 players,
             } => {
-                for player in &players {
-                    let metadata_player = self
-                        .metadata_players
+                let metadata_player = |player: &OnArenaStateReceivedPlayer| {
+                    self.metadata_players
                         .iter()
                         .find(|meta_player| meta_player.id == player.meta_ship_id as u32)
-                        .expect("could not map arena player to metadata player");
-                    let battle_player = Rc::new(Player::from_arena_player(
-                        player,
-                        metadata_player.as_ref(),
-                        self.game_resources,
-                    ));
+                };
+                // Players missing from the metadata are assumed to be allies if they're on
+                // the same team as a player known to be an ally
+                let ally_team = players.iter().find_map(|player| {
+                    metadata_player(player)
+                        .filter(|meta_player| meta_player.relation <= 1)
+                        .map(|_| player.team_id)
+                });
+
+                let mut battle_players: Vec<Player> = players
+                    .iter()
+                    .filter_map(|player| {
+                        let metadata_player = metadata_player(player);
+                        if metadata_player.is_none() {
+                            warn!(
+                                "arena player {} is missing from the replay metadata",
+                                player.username
+                            );
+                        }
+                        let fallback_relation = if Some(player.team_id) == ally_team {
+                            1
+                        } else {
+                            2
+                        };
+                        let battle_player = Player::from_arena_player(
+                            player,
+                            metadata_player.map(|player| player.as_ref()),
+                            fallback_relation,
+                            self.game_resources,
+                        );
+                        if battle_player.is_none() {
+                            warn!(
+                                "could not find the vehicle of arena player {}",
+                                player.username
+                            );
+                        }
+                        battle_player
+                    })
+                    .collect();
+                assign_divisions(&mut battle_players);
+
+                for battle_player in battle_players {
+                    let battle_player = Rc::new(battle_player);
+
+                    // A ship may be recreated under a new entity ID after a rejoin. Bots
+                    // may share a db_id, so the name and team have to match as well.
+                    let previous_id = self
+                        .player_entities
+                        .values()
+                        .find(|player| {
+                            player.db_id == battle_player.db_id
+                                && player.name == battle_player.name
+                                && player.team_id == battle_player.team_id
+                        })
+                        .map(|player| player.entity_id)
+                        .filter(|id| *id != battle_player.entity_id);
+                    if let Some(previous_id) = previous_id {
+                        debug!("entity {} is now {}", previous_id, battle_player.entity_id);
+                        self.player_entities.remove(&previous_id);
+                        for id in self.entity_remap.values_mut() {
+                            if *id == previous_id {
+                                *id = battle_player.entity_id;
+                            }
+                        }
+                        self.entity_remap
+                            .insert(previous_id, battle_player.entity_id);
+                        if let Some(resend) = self.rejoin_resend.as_mut() {
+                            resend.remap(&self.entity_remap);
+                        }
+                    }
 
                     self.player_entities
                         .insert(battle_player.entity_id, battle_player.clone());
@@ -1627,34 +3433,55 @@ where
                 victim,
                 aggressors,
             } => {
-                for damage in aggressors {
-                    self.damage_dealt
-                        .entry(damage.aggressor as u32)
-                        .or_default()
-                        .push(DamageEvent {
-                            amount: damage.damage,
-                            victim,
-                        });
+                let events: Vec<DamageEvent> = aggressors
+                    .iter()
+                    .map(|damage| DamageEvent {
+                        timestamp,
+                        aggressor: damage.aggressor as u32,
+                        victim,
+                        amount: damage.damage,
+                    })
+                    .collect();
+
+                // Damage may be sent again after the client reconnects
+                if let Some(resend) = self.rejoin_resend.as_mut() {
+                    if timestamp > resend.deadline {
+                        self.rejoin_resend = None;
+                    } else if resend.is_resend(&events) {
+                        return;
+                    }
                 }
+
+                self.damage_timeline.extend(events);
             }
             crate::analyzer::decoder::DecodedPacketPayload::MinimapUpdate { updates, arg1 } => {
-                trace!("MINIMAP UPDATE")
+                trace!("MINIMAP UPDATE");
+                for update in &updates {
+                    if !update.disappearing {
+                        self.minimap_positions
+                            .insert(update.entity_id as u32, (update.x, update.y));
+                    }
+                }
             }
             crate::analyzer::decoder::DecodedPacketPayload::PropertyUpdate(update) => {
                 if let Some(entity) = self.entities_by_id.get(&(update.entity_id as u32)) {
                     debug!("PROPERTY UPDATE: {:#?}", update);
                 }
+                self.handle_property_update(update);
             }
             crate::analyzer::decoder::DecodedPacketPayload::BattleEnd {
                 winning_team,
                 unknown,
-            } => trace!("BATTLE END"),
+            } => {
+                trace!("BATTLE END");
+                self.winning_team = winning_team;
+            }
             crate::analyzer::decoder::DecodedPacketPayload::Consumable {
                 entity,
                 consumable,
                 duration,
             } => {
-                trace!("CONSUMABLE");
+                self.handle_consumable(timestamp, entity, consumable, duration);
             }
             crate::analyzer::decoder::DecodedPacketPayload::CruiseState { state, value } => {
                 trace!("CRUISE STATE")
@@ -1662,17 +3489,43 @@ where
             crate::analyzer::decoder::DecodedPacketPayload::Map(_) => trace!("MAP"),
             crate::analyzer::decoder::DecodedPacketPayload::Version(_) => trace!("VERSION"),
             crate::analyzer::decoder::DecodedPacketPayload::Camera(_) => trace!("CAMERA"),
-            crate::analyzer::decoder::DecodedPacketPayload::CameraMode(_) => {
-                trace!("CAMERA MODE")
+            crate::analyzer::decoder::DecodedPacketPayload::CameraMode(mode) => {
+                trace!("CAMERA MODE");
+                if let Some(observer_data) = self.observer_data.as_mut() {
+                    observer_data.record_camera_mode(timestamp, mode);
+                }
             }
             crate::analyzer::decoder::DecodedPacketPayload::CameraFreeLook(_) => {
                 trace!("CAMERA FREE LOOK")
             }
-            crate::analyzer::decoder::DecodedPacketPayload::Unknown(_) => trace!("UNKNOWN"),
-            crate::analyzer::decoder::DecodedPacketPayload::Invalid(_) => trace!("INVALID"),
+            crate::analyzer::decoder::DecodedPacketPayload::Unknown(_)
+            | crate::analyzer::decoder::DecodedPacketPayload::Invalid(_) => {
+                trace!("UNKNOWN OR INVALID");
+                if let Some(observer_data) = self.observer_data.as_mut() {
+                    observer_data.record_unrecognized(packet.packet_type);
+                }
+            }
+            crate::analyzer::decoder::DecodedPacketPayload::CustomMethod(custom) => {
+                trace!("CUSTOM DECODE OF {}", custom.method)
+            }
+            crate::analyzer::decoder::DecodedPacketPayload::PartialDecode(partial) => {
+                trace!("PARTIAL DECODE OF {}", partial.method)
+            }
             crate::analyzer::decoder::DecodedPacketPayload::Audit(_) => trace!("AUDIT"),
-            crate::analyzer::decoder::DecodedPacketPayload::BattleResults(_json) => {
-                trace!("BATTLE RESULTS")
+            crate::analyzer::decoder::DecodedPacketPayload::BattleResults(json) => {
+                self.battle_results = Some(json.to_string());
+            }
+            crate::analyzer::decoder::DecodedPacketPayload::ArtilleryShots(salvos) => {
+                self.handle_artillery_shots(timestamp, &salvos);
+            }
+            crate::analyzer::decoder::DecodedPacketPayload::Torpedoes(salvos) => {
+                self.handle_torpedoes(timestamp, &salvos);
+            }
+            crate::analyzer::decoder::DecodedPacketPayload::ShotKills(packs) => {
+                self.handle_shot_kills(timestamp, &packs);
+            }
+            crate::analyzer::decoder::DecodedPacketPayload::PlaneDeath(death) => {
+                self.handle_plane_death(timestamp, &death);
             }
         }
     }
@@ -1680,6 +3533,43 @@ where
     fn finish(&mut self) {}
 }
 
+impl<'res, 'replay, G> BattleControllerState for BattleController<'res, 'replay, G>
+where
+    G: ResourceLoader,
+{
+    fn clock(&self) -> GameClock {
+        self.clock
+    }
+
+    fn player_entities(&self) -> &HashMap<u32, Rc<Player>> {
+        &self.player_entities
+    }
+
+    fn entities_by_id(&self) -> &HashMap<u32, Entity> {
+        &self.entities_by_id
+    }
+
+    fn positions(&self) -> &HashMap<u32, Vec3> {
+        &self.world_positions
+    }
+
+    fn property_history(&self) -> Option<&PropertyHistory> {
+        self.property_history.as_ref()
+    }
+
+    fn weather(&self) -> &WeatherState {
+        &self.weather
+    }
+
+    fn damage_timeline(&self) -> &[DamageEvent] {
+        &self.damage_timeline
+    }
+
+    fn ribbons(&self) -> &HashMap<u32, Vec<(GameClock, Ribbon)>> {
+        &self.ribbons
+    }
+}
+
 impl<'res, 'replay, G> PacketProcessorMut for BattleController<'res, 'replay, G>
 where
     G: ResourceLoader,
@@ -1688,3 +3578,631 @@ where
         AnalyzerMut::process_mut(self, &packet);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analyzer::battle_controller::PlayerId;
+
+    struct TestResources;
+
+    impl ResourceLoader for TestResources {
+        fn localized_name_from_param(&self, param: &Param) -> Option<&str> {
+            (param.index() == "PASB001").then_some("Iowa")
+        }
+
+        fn localized_name_from_id(&self, _id: &str) -> Option<String> {
+            None
+        }
+
+        fn game_param_by_id(&self, _id: u32) -> Option<Rc<Param>> {
+            None
+        }
+
+        fn entity_specs(&self) -> &[EntitySpec] {
+            &[]
+        }
+    }
+
+    fn config_bytes(units: &[u32], abilities_count: u32) -> Vec<u8> {
+        let mut words = vec![0, 1, 0, units.len() as u32];
+        words.extend_from_slice(units);
+        // modernization, signals, supply state, camouflage, abilities
+        words.extend_from_slice(&[0, 0, 0, 0, abilities_count]);
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_ship_config_sanity_checks() {
+        let version = Version::from_client_exe("0,11,7,0");
+
+        let config = ShipConfig::from_bytes(&config_bytes(&[5, 6], 0), version).unwrap();
+        assert_eq!(config.hull(), 5);
+        assert_eq!(config.units(), &[5, 6]);
+
+        assert!(ShipConfig::from_bytes(&config_bytes(&[], 0), version).is_err());
+        assert!(ShipConfig::from_bytes(&config_bytes(&[5], u32::MAX), version).is_err());
+    }
+
+    #[test]
+    fn test_skills_for_species() {
+        let skills = Skills {
+            auxiliary: vec![1, 2],
+            ..Default::default()
+        };
+        assert_eq!(skills.for_species(&Species::Auxiliary), Some(&[1, 2][..]));
+        assert_eq!(skills.for_species(&Species::Destroyer), Some(&[][..]));
+        assert_eq!(skills.for_species(&Species::Airship), None);
+    }
+
+    fn test_vehicle(id: u32, name: &str, death_info: Option<DeathInfo>) -> Rc<VehicleEntity> {
+        let vehicle = crate::game_params::ParamBuilder::default()
+            .id(1)
+            .index("PASB001".to_string())
+            .name("PASB001_Battleship".to_string())
+            .species(Some(Species::Battleship))
+            .nation("USA".to_string())
+            .data(crate::game_params::ParamData::Unit)
+            .build()
+            .unwrap();
+        let player = Player {
+            name: name.to_string(),
+            clan: "CLAN".to_string(),
+            clan_id: 2,
+            clan_color: 0xffffff,
+            division_id: 0,
+            division_number: None,
+            is_division_mate: false,
+            kind: PlayerKind::Human,
+            realm: "NA".to_string(),
+            db_id: 3,
+            relation: 0,
+            avatar_id: 4,
+            ship_id: 5,
+            entity_id: id,
+            team_id: 0,
+            max_health: 50000,
+            is_abuser: false,
+            is_hidden: false,
+            has_metadata: true,
+            vehicle: Rc::new(vehicle),
+        };
+        Rc::new(VehicleEntity {
+            id,
+            player: Some(Rc::new(player)),
+            visibility_changed_at: 0.0,
+            props: VehicleProps::default(),
+            captain: None,
+            damage: 1234.0,
+            death_info,
+            kills: vec![KillInfo {
+                timestamp: Duration::from_secs(200),
+                victim: 8,
+                cause: DeathCause::Torpedo,
+                grid_square: None,
+                weapon_params_id: None,
+            }],
+            smoke_fire_events: vec![],
+        })
+    }
+
+    fn test_report(player_entities: Vec<Rc<VehicleEntity>>) -> BattleReport {
+        let mut property_history = PropertyHistory::new(&[TrackedProperty::Health]);
+        property_history.record(6, Duration::from_secs(1), player_entities[0].props(), None);
+        BattleReport {
+            self_entity: player_entities.first().cloned(),
+            version: Version::from_client_exe("0,11,7,0"),
+            map_name: "spaces/16_OC_bees_to_honey".to_string(),
+            game_mode: "Domination".to_string(),
+            game_type: "RandomBattle".to_string(),
+            match_group: "pvp".to_string(),
+            player_entities,
+            game_chat: vec![GameMessage {
+                sender_relation: 0,
+                sender_name: "player".to_string(),
+                channel: ChatChannel::Global,
+                message: "gl hf".to_string(),
+            }],
+            salvo_outcomes: vec![SalvoOutcome::default()],
+            skill_efficacy: vec![],
+            captures: 1,
+            battle_results: Some("{}".to_string()),
+            winning_team: Some(0),
+            observer_data: Some(ObserverData::default()),
+            property_history: Some(property_history),
+            death_contexts: vec![],
+            rejoins: vec![Rejoin {
+                timestamp: Duration::from_secs(60),
+                clock_jump: 2.5,
+            }],
+            timeline: vec![],
+            weather_events: vec![],
+            map_borders: vec![],
+            torpedo_launches: vec![],
+            torpedo_stats: vec![],
+            torpedo_warnings: vec![],
+            planes_shot_down: vec![],
+            engagement_stats: vec![],
+            survivability: BTreeMap::new(),
+            consumable_activations: Vec::new(),
+            consumables: Vec::new(),
+            damage_timeline: vec![DamageEvent {
+                timestamp: Duration::from_secs(90),
+                aggressor: 6,
+                victim: 7,
+                amount: 1200.0,
+            }],
+            ribbons: BTreeMap::from([(
+                6,
+                vec![
+                    (GameClock(90.0), Ribbon::Citadel),
+                    (GameClock(95.0), Ribbon::SetFire),
+                ],
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_report_round_trip() {
+        let death_info = DeathInfo {
+            time_lived: Duration::from_secs(300),
+            killer: 7,
+            cause: DeathCause::Artillery,
+            grid_square: None,
+            weapon_params_id: Some(9),
+        };
+        let report = test_report(vec![test_vehicle(6, "player", Some(death_info))]);
+
+        let serialized = serde_json::to_string(&report).unwrap();
+        let restored: BattleReport = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(serde_json::to_string(&restored).unwrap(), serialized);
+
+        let entity = &restored.player_entities()[0];
+        assert_eq!(entity.player().unwrap().name(), "player");
+        assert_eq!(entity.kills()[0].victim(), 8);
+        assert_eq!(restored.self_entity().unwrap().id(), 6);
+        assert_eq!(restored.damage_timeline()[0].aggressor(), 6);
+        assert_eq!(restored.ribbon_count(6, Ribbon::Citadel), 1);
+    }
+
+    #[test]
+    fn test_describe_death() {
+        let death_info = |killer| DeathInfo {
+            time_lived: Duration::from_secs(300),
+            killer,
+            cause: DeathCause::Artillery,
+            grid_square: None,
+            weapon_params_id: None,
+        };
+        let report = test_report(vec![
+            test_vehicle(6, "victim", Some(death_info(7))),
+            test_vehicle(7, "killer", None),
+            test_vehicle(8, "flooded", Some(death_info(8))),
+        ]);
+        let entities = report.player_entities();
+
+        assert_eq!(
+            report.describe_death(&entities[0], &TestResources),
+            Some("victim sunk by Iowa (main battery)".to_string())
+        );
+        assert_eq!(report.describe_death(&entities[1], &TestResources), None);
+        assert_eq!(
+            report.describe_death(&entities[2], &TestResources),
+            Some("flooded sunk (main battery)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_clocks() {
+        let meta = crate::testkit::minimal_meta("0,11,7,0");
+        let mut controller = BattleController::new(&meta, &TestResources);
+        let origin = Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        let position = |clock| Packet {
+            packet_size: 0,
+            packet_type: 0xA,
+            clock,
+            payload: PacketType::Position(crate::packet2::PositionPacket {
+                pid: 1,
+                position: origin.clone(),
+                position_error: origin.clone(),
+                rotation: crate::packet2::Rot3 {
+                    roll: 0.0,
+                    pitch: 0.0,
+                    yaw: 0.0,
+                },
+                is_error: false,
+            }),
+            raw: &[],
+        };
+
+        for clock in &[-1.0, f32::NAN, f32::INFINITY, f32::MAX, 2.5] {
+            AnalyzerMut::process_mut(&mut controller, &position(*clock));
+        }
+        assert_eq!(controller.clock, GameClock(2.5));
+        assert_eq!(controller.timeline.len(), 3);
+
+        // A clock far in the future is kept, but doesn't fill the gap with samples
+        AnalyzerMut::process_mut(&mut controller, &position(1e12));
+        assert_eq!(controller.clock, GameClock(1e12));
+        assert_eq!(
+            controller.timeline.len(),
+            3 + MAX_TIMELINE_SAMPLES_PER_PACKET
+        );
+    }
+
+    #[test]
+    fn test_damage_replayed_after_rejoin() {
+        use crate::packet2::{BasePlayerCreatePacket, EntityMethodPacket};
+        use crate::rpc::typedefs::ArgValue;
+
+        let meta = crate::testkit::minimal_meta("0,11,7,0");
+        let mut controller = BattleController::new(&meta, &TestResources);
+        let base_player_create = |clock| Packet {
+            packet_size: 0,
+            packet_type: 0x0,
+            clock,
+            payload: PacketType::BasePlayerCreate(BasePlayerCreatePacket {
+                entity_id: 1,
+                entity_type: "Avatar",
+                props: HashMap::new(),
+            }),
+            raw: &[],
+        };
+        let damage = |clock, amount| {
+            let mut dict = HashMap::new();
+            dict.insert("vehicleID", ArgValue::Int32(7));
+            dict.insert("damage", ArgValue::Float32(amount));
+            Packet {
+                packet_size: 0,
+                packet_type: 0x8,
+                clock,
+                payload: PacketType::EntityMethod(EntityMethodPacket {
+                    entity_id: 6,
+                    method: "receiveDamagesOnShip",
+                    args: vec![ArgValue::Array(vec![ArgValue::FixedDict(dict)])],
+                }),
+                raw: &[],
+            }
+        };
+
+        AnalyzerMut::process_mut(&mut controller, &base_player_create(1.0));
+        AnalyzerMut::process_mut(&mut controller, &damage(20.0, 1500.0));
+        AnalyzerMut::process_mut(&mut controller, &damage(20.0, 1500.0));
+        AnalyzerMut::process_mut(&mut controller, &damage(21.0, 800.0));
+        assert_eq!(controller.damage_timeline.len(), 3);
+
+        // The client reconnects and the server sends the damage again, at the new clock
+        AnalyzerMut::process_mut(&mut controller, &base_player_create(30.0));
+        AnalyzerMut::process_mut(&mut controller, &damage(30.0, 1500.0));
+        AnalyzerMut::process_mut(&mut controller, &damage(30.0, 1500.0));
+        assert_eq!(controller.rejoins.len(), 1);
+        assert_eq!(controller.damage_timeline.len(), 3);
+
+        // Identical hits are only resent as many times as they were received
+        AnalyzerMut::process_mut(&mut controller, &damage(31.0, 1500.0));
+        assert_eq!(controller.damage_timeline.len(), 4);
+
+        // Anything after the resend window is new damage
+        AnalyzerMut::process_mut(&mut controller, &damage(90.0, 800.0));
+        assert_eq!(controller.damage_timeline.len(), 5);
+        assert!(controller.rejoin_resend.is_none());
+    }
+
+    #[test]
+    fn test_rejoin_resend_remap() {
+        let event = |aggressor, victim| DamageEvent {
+            timestamp: Duration::from_secs(20),
+            aggressor,
+            victim,
+            amount: 1500.0,
+        };
+        let mut resend =
+            RejoinResend::new(&[event(7, 6)], GameClock(25.0), Duration::from_secs(30));
+
+        // The victim was recreated as entity 16 after the rejoin
+        let mut entity_remap = HashMap::new();
+        entity_remap.insert(6, 16);
+        resend.remap(&entity_remap);
+        assert!(!resend.is_resend(&[event(7, 6)]));
+        assert!(resend.is_resend(&[event(7, 16)]));
+        assert!(!resend.is_resend(&[event(7, 16)]));
+    }
+
+    #[test]
+    fn test_outcome() {
+        let mut report = test_report(vec![test_vehicle(6, "Player", None)]);
+        assert_eq!(report.winning_team(), Some(0));
+        assert_eq!(report.outcome(), Some(BattleOutcome::Win));
+        assert_eq!(report.outcome_for_team(2), Some(BattleOutcome::Loss));
+
+        report.winning_team = Some(-1);
+        assert_eq!(report.winning_team(), None);
+        assert_eq!(report.outcome(), Some(BattleOutcome::Draw));
+    }
+
+    #[test]
+    fn test_id_resolver() {
+        let report = test_report(vec![test_vehicle(6, "Player", None)]);
+        let resolver = report.id_resolver();
+        for id in [
+            PlayerId::Entity(6),
+            PlayerId::Avatar(4),
+            PlayerId::MetaShip(5),
+            PlayerId::Account(3),
+        ] {
+            assert_eq!(resolver.player(id).unwrap().name(), "Player");
+        }
+        assert_eq!(resolver.entity_id(PlayerId::Avatar(4)), Some(6));
+        assert_eq!(resolver.avatar_id(PlayerId::Entity(6)), Some(4));
+        assert!(resolver.player(PlayerId::Entity(4)).is_none());
+    }
+
+    #[test]
+    fn test_planes_shot_down_by() {
+        let mut report = test_report(vec![test_vehicle(6, "player", None)]);
+        let kill = |secs, killer, count| PlanesShotDown {
+            timestamp: Duration::from_secs(secs),
+            squadron_id: 1,
+            killer,
+            count,
+        };
+        report.planes_shot_down = vec![kill(100, 6, 2), kill(200, 7, 1), kill(300, 6, 3)];
+
+        assert_eq!(report.planes_shot_down_by(6, Duration::from_secs(50)), 0);
+        assert_eq!(report.planes_shot_down_by(6, Duration::from_secs(200)), 2);
+        assert_eq!(report.planes_shot_down_by(6, Duration::from_secs(300)), 5);
+    }
+
+    #[test]
+    fn test_salvo_ribbons() {
+        let meta = crate::testkit::minimal_meta("0,11,7,0");
+        let mut controller = BattleController::new(&meta, &TestResources);
+        let salvo = |secs, shells| SalvoOutcome {
+            timestamp: Duration::from_secs(secs),
+            shells_fired: shells,
+            flight_time: 5.0,
+            ..Default::default()
+        };
+        controller.salvo_outcomes = vec![salvo(10, 1), salvo(12, 3)];
+        let ribbon = |controller: &mut BattleController<_>, secs: f32, ribbon| {
+            controller.handle_ribbon(Duration::from_secs_f32(secs), ribbon)
+        };
+
+        // Before either salvo was fired
+        ribbon(&mut controller, 9.5, Ribbon::Citadel);
+        // The oldest salvo still landing gets the ribbon, the next once it's accounted for
+        ribbon(&mut controller, 14.0, Ribbon::Penetration);
+        ribbon(&mut controller, 14.0, Ribbon::Ricochet);
+        // Right at the end of the grace period
+        ribbon(&mut controller, 18.0, Ribbon::Citadel);
+        // After the grace period
+        ribbon(&mut controller, 18.5, Ribbon::Penetration);
+
+        let outcomes = &controller.salvo_outcomes;
+        assert_eq!(outcomes[0].penetrations(), 1);
+        assert_eq!(outcomes[0].citadels(), 0);
+        assert_eq!(outcomes[1].ricochets(), 1);
+        assert_eq!(outcomes[1].citadels(), 1);
+        assert_eq!(outcomes[1].penetrations(), 0);
+        assert!(outcomes[1].has_unaccounted_shells());
+    }
+
+    #[test]
+    fn test_death_contexts() {
+        let meta = crate::testkit::minimal_meta("0,11,7,0");
+        let mut controller = BattleController::new(&meta, &TestResources);
+        controller.track_property_history(&[TrackedProperty::Health]);
+        let history = controller.property_history.as_mut().unwrap();
+        for (secs, health) in [(10, 30000.0), (80, 12000.0), (95, 4000.0)] {
+            history.push(
+                7,
+                TrackedProperty::Health,
+                Duration::from_secs(secs),
+                health,
+            );
+        }
+        let damage = |secs, aggressor, victim, amount| DamageEvent {
+            timestamp: Duration::from_secs(secs),
+            aggressor,
+            victim,
+            amount,
+        };
+        controller.damage_timeline = vec![
+            // Before the window
+            damage(60, 8, 7, 5000.0),
+            damage(70, 8, 7, 1000.0),
+            damage(80, 6, 7, 18000.0),
+            damage(90, 8, 7, 2000.0),
+            damage(90, 7, 6, 3000.0),
+            damage(100, 6, 7, 4000.0),
+            // After the death
+            damage(101, 9, 7, 1000.0),
+        ];
+        controller.frags.insert(
+            6,
+            vec![Death {
+                timestamp: Duration::from_secs(100),
+                killer: 6,
+                victim: 7,
+                cause: DeathCause::Artillery,
+                grid_square: None,
+                weapon_params_id: None,
+                killer_position: None,
+                victim_position: None,
+            }],
+        );
+
+        let contexts = controller.death_contexts();
+        assert_eq!(contexts.len(), 1);
+        let context = &contexts[0];
+        assert_eq!(context.victim(), 7);
+        assert_eq!(context.killer(), 6);
+        let sources: Vec<(u32, f32)> = context
+            .damage_sources()
+            .iter()
+            .map(|source| (source.aggressor(), source.damage()))
+            .collect();
+        assert_eq!(sources, vec![(6, 22000.0), (8, 3000.0)]);
+        let health: Vec<(u64, f64)> = context
+            .victim_health()
+            .iter()
+            .map(|change| (change.timestamp().as_secs(), change.value()))
+            .collect();
+        assert_eq!(health, vec![(70, 30000.0), (80, 12000.0), (95, 4000.0)]);
+    }
+
+    #[test]
+    fn test_damage_timeline() {
+        use crate::packet2::EntityMethodPacket;
+        use crate::rpc::typedefs::ArgValue;
+
+        let meta = crate::testkit::minimal_meta("0,11,7,0");
+        let mut controller = BattleController::new(&meta, &TestResources);
+        let damage = |clock, aggressors: &[(i32, f32)]| {
+            let aggressors = aggressors
+                .iter()
+                .map(|(aggressor, amount)| {
+                    let mut dict = HashMap::new();
+                    dict.insert("vehicleID", ArgValue::Int32(*aggressor));
+                    dict.insert("damage", ArgValue::Float32(*amount));
+                    ArgValue::FixedDict(dict)
+                })
+                .collect();
+            Packet {
+                packet_size: 0,
+                packet_type: 0x8,
+                clock,
+                payload: PacketType::EntityMethod(EntityMethodPacket {
+                    entity_id: 6,
+                    method: "receiveDamagesOnShip",
+                    args: vec![ArgValue::Array(aggressors)],
+                }),
+                raw: &[],
+            }
+        };
+
+        AnalyzerMut::process_mut(&mut controller, &damage(20.0, &[(7, 1500.0), (8, 300.0)]));
+        AnalyzerMut::process_mut(&mut controller, &damage(70.0, &[(7, 1000.0)]));
+        AnalyzerMut::process_mut(&mut controller, &damage(130.0, &[(7, 200.0)]));
+
+        let timeline: Vec<(u64, u32, u32, f32)> = controller
+            .damage_timeline
+            .iter()
+            .map(|event| {
+                (
+                    event.timestamp().as_secs(),
+                    event.aggressor(),
+                    event.victim(),
+                    event.amount(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            timeline,
+            vec![
+                (20, 7, 6, 1500.0),
+                (20, 8, 6, 300.0),
+                (70, 7, 6, 1000.0),
+                (130, 7, 6, 200.0),
+            ]
+        );
+        assert_eq!(controller.damage_per_minute(7), vec![1500.0, 1000.0, 200.0]);
+        assert_eq!(controller.damage_per_minute(8), vec![300.0]);
+        assert!(controller.damage_per_minute(6).is_empty());
+    }
+
+    #[test]
+    fn test_firing_from_smoke() {
+        let meta = crate::testkit::minimal_meta("0,11,7,0");
+        let mut controller = BattleController::new(&meta, &TestResources);
+        controller.entities_by_id.insert(
+            20,
+            Entity::SmokeScreen(Rc::new(RefCell::new(SmokeScreen {
+                id: 20,
+                radius: 50.0,
+                points: vec![(100.0, 100.0), (180.0, 100.0)],
+            }))),
+        );
+        let salvo = |x, z| ArtillerySalvo {
+            params_id: 1,
+            owner_id: 6,
+            salvo_id: 1,
+            shots: vec![crate::analyzer::decoder::ArtilleryShot {
+                origin: Vec3 { x, y: 0.0, z },
+                target: Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                pitch: 0.0,
+                speed: 800.0,
+                shot_id: 1,
+                gun_barrel_id: 0,
+                server_time_left: 5.0,
+                shooter_height: 0.0,
+                hit_distance: 0.0,
+            }],
+        };
+
+        // Inside the second puff, right at the edge of the first, and outside both
+        for (secs, x, z) in [(10, 200.0, 110.0), (11, 100.0, 150.0), (12, 100.0, 151.0)] {
+            controller.handle_artillery_shots(Duration::from_secs(secs), &[salvo(x, z)]);
+        }
+
+        // The smoke dissipates
+        let leave = Packet {
+            packet_size: 0,
+            packet_type: 0x4,
+            clock: 13.0,
+            payload: PacketType::EntityLeave(crate::packet2::EntityLeavePacket { entity_id: 20 }),
+            raw: &[],
+        };
+        AnalyzerMut::process_mut(&mut controller, &leave);
+        controller.handle_artillery_shots(Duration::from_secs(14), &[salvo(100.0, 100.0)]);
+
+        let events: Vec<(u64, Id)> = controller.smoke_fire_events[&6]
+            .iter()
+            .map(|event| (event.timestamp.as_secs(), event.smoke_id))
+            .collect();
+        assert_eq!(events, vec![(10, 20), (11, 20)]);
+    }
+
+    #[test]
+    fn test_timeline_sampling() {
+        let meta = crate::testkit::minimal_meta("0,11,7,0");
+        let sample_times = |bucket, clocks: &[f32]| {
+            let mut controller = BattleController::new(&meta, &TestResources);
+            controller.set_timeline_bucket(bucket);
+            for clock in clocks {
+                controller.record_timeline(Duration::from_secs_f32(*clock));
+            }
+            controller
+                .timeline
+                .iter()
+                .map(|sample| sample.timestamp().as_secs_f32())
+                .collect::<Vec<_>>()
+        };
+
+        // A sample is due once the clock reaches it
+        assert_eq!(
+            sample_times(Duration::from_secs(1), &[0.5, 0.9, 1.0, 2.5]),
+            vec![0.0, 1.0, 2.0]
+        );
+        assert_eq!(
+            sample_times(Duration::from_secs(5), &[4.9, 12.0]),
+            vec![0.0, 5.0, 10.0]
+        );
+        assert!(sample_times(Duration::ZERO, &[0.0, 10.0]).is_empty());
+
+        // A gap of more than MAX_TIMELINE_SAMPLES_PER_PACKET buckets is skipped, and
+        // sampling resumes a bucket after the jump
+        let jump = MAX_TIMELINE_SAMPLES_PER_PACKET as f32 + 100.0;
+        let times = sample_times(Duration::from_secs(1), &[jump, jump + 1.0]);
+        assert_eq!(times.len(), MAX_TIMELINE_SAMPLES_PER_PACKET + 1);
+        assert_eq!(times.last(), Some(&(jump + 1.0)));
+    }
+}
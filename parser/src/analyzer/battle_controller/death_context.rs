@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::PropertyChange;
+use crate::{analyzer::decoder::DeathCause, packet2::Vec3};
+
+/// How far before a death its [DeathContext] looks
+pub const DEATH_CONTEXT_WINDOW: Duration = Duration::from_secs(30);
+
+/// Total damage an aggressor dealt to a ship in the window before its death
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DamageSource {
+    aggressor: u32,
+    damage: f32,
+}
+
+impl DamageSource {
+    pub fn aggressor(&self) -> u32 {
+        self.aggressor
+    }
+
+    pub fn damage(&self) -> f32 {
+        self.damage
+    }
+}
+
+/// The circumstances of a ship's death, e.g. for a kill cam which zooms in on each death
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeathContext {
+    pub(crate) timestamp: Duration,
+    pub(crate) victim: u32,
+    pub(crate) killer: u32,
+    pub(crate) cause: DeathCause,
+    pub(crate) killer_position: Option<Vec3>,
+    pub(crate) victim_position: Option<Vec3>,
+    pub(crate) victim_health: Vec<PropertyChange>,
+    pub(crate) damage_sources: Vec<DamageSource>,
+}
+
+impl DeathContext {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    pub fn victim(&self) -> u32 {
+        self.victim
+    }
+
+    pub fn killer(&self) -> u32 {
+        self.killer
+    }
+
+    pub fn cause(&self) -> DeathCause {
+        self.cause
+    }
+
+    /// The killer's last known world position, which may be stale if the killer wasn't
+    /// spotted
+    pub fn killer_position(&self) -> Option<&Vec3> {
+        self.killer_position.as_ref()
+    }
+
+    /// The victim's last known world position
+    pub fn victim_position(&self) -> Option<&Vec3> {
+        self.victim_position.as_ref()
+    }
+
+    /// Distance between the killer and victim, in world units
+    pub fn distance(&self) -> Option<f32> {
+        let (a, b) = (
+            self.killer_position.as_ref()?,
+            self.victim_position.as_ref()?,
+        );
+        Some(((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt())
+    }
+
+    /// Changes of the victim's health in the [DEATH_CONTEXT_WINDOW] before its death,
+    /// starting with the health it had at the start of the window. Only available when
+    /// health history is tracked with [super::BattleController::track_property_history].
+    pub fn victim_health(&self) -> &[PropertyChange] {
+        self.victim_health.as_ref()
+    }
+
+    /// Damage dealt to the victim in the [DEATH_CONTEXT_WINDOW] before its death, most
+    /// damage first
+    pub fn damage_sources(&self) -> &[DamageSource] {
+        self.damage_sources.as_ref()
+    }
+
+    pub(crate) fn add_damage(&mut self, aggressor: u32, damage: f32) {
+        match self
+            .damage_sources
+            .iter_mut()
+            .find(|source| source.aggressor == aggressor)
+        {
+            Some(source) => source.damage += damage,
+            None => self.damage_sources.push(DamageSource { aggressor, damage }),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Aircraft shot down by a ship
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanesShotDown {
+    pub(crate) timestamp: Duration,
+    pub(crate) squadron_id: i64,
+    pub(crate) killer: u32,
+    pub(crate) count: usize,
+}
+
+impl PlanesShotDown {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    pub fn squadron_id(&self) -> i64 {
+        self.squadron_id
+    }
+
+    /// Entity ID of the ship which shot the aircraft down
+    pub fn killer(&self) -> u32 {
+        self.killer
+    }
+
+    /// Number of aircraft shot down at once
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// Secondary battery and anti-aircraft statistics of a single player. Damage dealt isn't
+/// attributed to weapons in the replay, so only hits and kills are available.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EngagementStats {
+    pub(crate) entity_id: u32,
+    pub(crate) planes_shot_down: usize,
+    pub(crate) secondary_kills: usize,
+    pub(crate) secondary_hits: Option<usize>,
+}
+
+impl EngagementStats {
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+
+    pub fn planes_shot_down(&self) -> usize {
+        self.planes_shot_down
+    }
+
+    /// Ships sunk by secondary batteries
+    pub fn secondary_kills(&self) -> usize {
+        self.secondary_kills
+    }
+
+    /// Secondary battery hits, from the player's ribbons. Only known for the recording
+    /// player.
+    pub fn secondary_hits(&self) -> Option<usize> {
+        self.secondary_hits
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use super::Player;
+use crate::Rc;
+
+/// A player's ID, in any of the forms the game refers to players by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlayerId {
+    /// ID of the player's `Vehicle` entity, used by most packets
+    Entity(u32),
+    /// ID of the player's `Avatar` entity, used e.g. by chat messages
+    Avatar(u32),
+    /// The player's `id` in the replay metadata's vehicle list
+    MetaShip(u32),
+    /// The player's account (DB) ID. Bots don't have one.
+    Account(i64),
+}
+
+/// Maps every form of [PlayerId] to the player it belongs to
+#[derive(Debug, Clone, Default)]
+pub struct IdResolver {
+    players: HashMap<PlayerId, Rc<Player>>,
+}
+
+impl IdResolver {
+    pub fn new<'a, I: IntoIterator<Item = &'a Rc<Player>>>(players: I) -> Self {
+        let mut resolver = IdResolver::default();
+        for player in players {
+            let mut ids = vec![
+                PlayerId::Entity(player.entity_id()),
+                PlayerId::Avatar(player.avatar_id()),
+                PlayerId::MetaShip(player.ship_id()),
+            ];
+            if player.db_id() > 0 {
+                ids.push(PlayerId::Account(player.db_id()));
+            }
+            for id in ids {
+                resolver.players.insert(id, player.clone());
+            }
+        }
+        resolver
+    }
+
+    pub fn player(&self, id: PlayerId) -> Option<&Rc<Player>> {
+        self.players.get(&id)
+    }
+
+    /// The ID of the player's `Vehicle` entity
+    pub fn entity_id(&self, id: PlayerId) -> Option<u32> {
+        self.player(id).map(|player| player.entity_id())
+    }
+
+    /// The ID of the player's `Avatar` entity
+    pub fn avatar_id(&self, id: PlayerId) -> Option<u32> {
+        self.player(id).map(|player| player.avatar_id())
+    }
+
+    pub fn meta_ship_id(&self, id: PlayerId) -> Option<u32> {
+        self.player(id).map(|player| player.ship_id())
+    }
+
+    pub fn account_id(&self, id: PlayerId) -> Option<i64> {
+        self.player(id)
+            .map(|player| player.db_id())
+            .filter(|db_id| *db_id > 0)
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use super::{BattleReport, VehicleEntity};
+
+/// A part of a ship's build which is compared against the corpus on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum BuildComponent {
+    Hull,
+    Modules,
+    Upgrades,
+    Signals,
+    Consumables,
+    CaptainSkills,
+}
+
+const COMPONENTS: [BuildComponent; 6] = [
+    BuildComponent::Hull,
+    BuildComponent::Modules,
+    BuildComponent::Upgrades,
+    BuildComponent::Signals,
+    BuildComponent::Consumables,
+    BuildComponent::CaptainSkills,
+];
+
+/// A ship's loadout as GameParams IDs, with each component sorted so that builds which
+/// only differ in slot order compare equal. Captain skills are the raw skill types.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct ShipBuild {
+    components: BTreeMap<BuildComponent, Vec<u32>>,
+}
+
+impl ShipBuild {
+    pub fn from_vehicle(vehicle: &VehicleEntity) -> Self {
+        let config = vehicle.props().ship_config();
+        let mut build = ShipBuild::default();
+        build.set(BuildComponent::Hull, &[config.hull()]);
+        build.set(BuildComponent::Modules, config.units());
+        build.set(BuildComponent::Upgrades, config.modernization());
+        build.set(BuildComponent::Signals, config.signals());
+        build.set(BuildComponent::Consumables, config.abilities());
+        let skills: Vec<u32> = vehicle
+            .commander_skills_raw()
+            .iter()
+            .map(|skill| *skill as u32)
+            .collect();
+        build.set(BuildComponent::CaptainSkills, &skills);
+        build
+    }
+
+    /// Sets a component's IDs. Empty slots (ID 0) are dropped.
+    pub fn set(&mut self, component: BuildComponent, ids: &[u32]) {
+        let mut ids: Vec<u32> = ids.iter().copied().filter(|id| *id != 0).collect();
+        ids.sort_unstable();
+        self.components.insert(component, ids);
+    }
+
+    pub fn component(&self, component: BuildComponent) -> &[u32] {
+        self.components
+            .get(&component)
+            .map(|ids| ids.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// How a component of a player's build differs from the most common one for the ship
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildDeviation {
+    component: BuildComponent,
+    /// IDs in the common build which the player doesn't have
+    missing: Vec<u32>,
+    /// IDs the player has which aren't in the common build
+    extra: Vec<u32>,
+    /// Share of the ship's builds in the corpus using the common component
+    popularity: f32,
+}
+
+impl BuildDeviation {
+    pub fn component(&self) -> BuildComponent {
+        self.component
+    }
+
+    pub fn missing(&self) -> &[u32] {
+        self.missing.as_ref()
+    }
+
+    pub fn extra(&self) -> &[u32] {
+        self.extra.as_ref()
+    }
+
+    pub fn popularity(&self) -> f32 {
+        self.popularity
+    }
+}
+
+/// A player's build annotated with its deviations from the corpus
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildAnnotation {
+    entity_id: u32,
+    /// GameParams ID of the ship
+    ship_id: u32,
+    build: ShipBuild,
+    /// Number of builds of the ship in the corpus. Deviations are less meaningful when
+    /// this is small.
+    samples: usize,
+    deviations: Vec<BuildDeviation>,
+}
+
+impl BuildAnnotation {
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+
+    pub fn ship_id(&self) -> u32 {
+        self.ship_id
+    }
+
+    pub fn build(&self) -> &ShipBuild {
+        &self.build
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn deviations(&self) -> &[BuildDeviation] {
+        self.deviations.as_ref()
+    }
+}
+
+/// Collects the builds of every ship seen across a set of replays, to find each ship's
+/// "meta" build. Each component is compared on its own, as whole builds rarely match
+/// exactly.
+#[derive(Debug, Clone, Default)]
+pub struct BuildCorpus {
+    /// Ship GameParams ID -> component -> component IDs -> times seen
+    ships: BTreeMap<u32, BTreeMap<BuildComponent, BTreeMap<Vec<u32>, usize>>>,
+    samples: BTreeMap<u32, usize>,
+}
+
+impl BuildCorpus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the builds of every player in the battle
+    pub fn add_report(&mut self, report: &BattleReport) {
+        for vehicle in report.player_entities() {
+            if let Some(player) = vehicle.player() {
+                self.add_build(player.vehicle().id(), &ShipBuild::from_vehicle(vehicle));
+            }
+        }
+    }
+
+    pub fn add_build(&mut self, ship_id: u32, build: &ShipBuild) {
+        let ship = self.ships.entry(ship_id).or_default();
+        for component in COMPONENTS.iter() {
+            *ship
+                .entry(*component)
+                .or_default()
+                .entry(build.component(*component).to_vec())
+                .or_default() += 1;
+        }
+        *self.samples.entry(ship_id).or_default() += 1;
+    }
+
+    /// Number of builds of the ship in the corpus
+    pub fn samples(&self, ship_id: u32) -> usize {
+        self.samples.get(&ship_id).copied().unwrap_or_default()
+    }
+
+    /// The most common build of the ship, or `None` if it isn't in the corpus. Ties go to
+    /// the lowest IDs so the result is stable.
+    pub fn meta_build(&self, ship_id: u32) -> Option<ShipBuild> {
+        let ship = self.ships.get(&ship_id)?;
+        let mut build = ShipBuild::default();
+        for (component, variants) in ship {
+            if let Some((ids, _count)) = most_common(variants) {
+                build.components.insert(*component, ids.clone());
+            }
+        }
+        Some(build)
+    }
+
+    /// How the build differs from the ship's most common build. Empty if the ship isn't in
+    /// the corpus.
+    pub fn deviations(&self, ship_id: u32, build: &ShipBuild) -> Vec<BuildDeviation> {
+        let (ship, samples) = match (self.ships.get(&ship_id), self.samples.get(&ship_id)) {
+            (Some(ship), Some(samples)) => (ship, *samples),
+            _ => return vec![],
+        };
+
+        let mut deviations = vec![];
+        for (component, variants) in ship {
+            let (common, count) = match most_common(variants) {
+                Some(common) => common,
+                None => continue,
+            };
+            let actual = build.component(*component);
+            if actual == common.as_slice() {
+                continue;
+            }
+            deviations.push(BuildDeviation {
+                component: *component,
+                missing: common
+                    .iter()
+                    .filter(|id| !actual.contains(id))
+                    .copied()
+                    .collect(),
+                extra: actual
+                    .iter()
+                    .filter(|id| !common.contains(id))
+                    .copied()
+                    .collect(),
+                popularity: count as f32 / samples as f32,
+            });
+        }
+        deviations
+    }
+
+    /// Annotates every player's build in the battle with its deviations from the corpus
+    pub fn annotate(&self, report: &BattleReport) -> Vec<BuildAnnotation> {
+        report
+            .player_entities()
+            .iter()
+            .filter_map(|vehicle| {
+                let ship_id = vehicle.player()?.vehicle().id();
+                let build = ShipBuild::from_vehicle(vehicle);
+                Some(BuildAnnotation {
+                    entity_id: vehicle.id(),
+                    ship_id,
+                    deviations: self.deviations(ship_id, &build),
+                    build,
+                    samples: self.samples(ship_id),
+                })
+            })
+            .collect()
+    }
+}
+
+fn most_common(variants: &BTreeMap<Vec<u32>, usize>) -> Option<(&Vec<u32>, usize)> {
+    variants
+        .iter()
+        // max_by_key returns the last maximum, so iterate in reverse to prefer the first
+        .rev()
+        .max_by_key(|(_ids, count)| **count)
+        .map(|(ids, count)| (ids, *count))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn build(upgrades: &[u32], signals: &[u32]) -> ShipBuild {
+        let mut build = ShipBuild::default();
+        build.set(BuildComponent::Hull, &[1]);
+        build.set(BuildComponent::Upgrades, upgrades);
+        build.set(BuildComponent::Signals, signals);
+        build
+    }
+
+    #[test]
+    fn test_deviations() {
+        let mut corpus = BuildCorpus::new();
+        corpus.add_build(10, &build(&[2, 1], &[5]));
+        corpus.add_build(10, &build(&[1, 2], &[]));
+        corpus.add_build(10, &build(&[1, 3], &[5]));
+
+        let meta = corpus.meta_build(10).unwrap();
+        assert_eq!(meta.component(BuildComponent::Upgrades), &[1, 2]);
+        assert_eq!(meta.component(BuildComponent::Signals), &[5]);
+        assert_eq!(corpus.samples(10), 3);
+
+        // Slot order and empty slots don't matter
+        assert!(corpus.deviations(10, &build(&[0, 2, 1], &[5])).is_empty());
+
+        let deviations = corpus.deviations(10, &build(&[1, 3], &[5]));
+        assert_eq!(deviations.len(), 1);
+        assert_eq!(deviations[0].component(), BuildComponent::Upgrades);
+        assert_eq!(deviations[0].missing(), &[2]);
+        assert_eq!(deviations[0].extra(), &[3]);
+        assert!((deviations[0].popularity() - 2.0 / 3.0).abs() < 0.001);
+
+        assert!(corpus.deviations(11, &build(&[], &[])).is_empty());
+    }
+}
@@ -1,7 +1,51 @@
+mod advantage;
+mod battle_logic;
+mod composition;
+mod consumables;
 mod controller;
+mod death_context;
+mod engagement_stats;
+mod id_resolver;
+mod meta_build;
 mod observer;
+mod observer_data;
+mod personal_rating;
+mod phases;
 pub mod player;
+mod property_history;
+mod results_diff;
+mod session;
 pub mod ship;
+mod skill_efficacy;
+mod snapshot;
+mod state;
+mod teams;
+mod timeline;
+mod torpedo_stats;
+mod torpedo_warnings;
+mod weather;
 
+pub use advantage::*;
+pub use battle_logic::*;
+pub use composition::*;
+pub use consumables::*;
 pub use controller::*;
+pub use death_context::*;
+pub use engagement_stats::*;
+pub use id_resolver::*;
+pub use meta_build::*;
 pub use observer::*;
+pub use observer_data::*;
+pub use personal_rating::*;
+pub use phases::*;
+pub use property_history::*;
+pub use results_diff::*;
+pub use session::*;
+pub use skill_efficacy::*;
+pub use snapshot::*;
+pub use state::*;
+pub use teams::*;
+pub use timeline::*;
+pub use torpedo_stats::*;
+pub use torpedo_warnings::*;
+pub use weather::*;
@@ -0,0 +1,134 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::decoder::CameraMode;
+
+/// An entity the observer's camera was attached to, from the given time onwards
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraTarget {
+    timestamp: Duration,
+    entity_id: u32,
+}
+
+impl CameraTarget {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+}
+
+/// The observer's client gaining or losing control of an entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControlChange {
+    timestamp: Duration,
+    entity_id: u32,
+    is_controlled: bool,
+}
+
+impl ControlChange {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+
+    /// Whether control was gained rather than lost
+    pub fn is_controlled(&self) -> bool {
+        self.is_controlled
+    }
+}
+
+/// Data only present in observer (spectator) replays, such as those recorded by tournament
+/// observers. These replays have no recording player, and instead follow whichever ship
+/// the observer is watching.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ObserverData {
+    camera_targets: Vec<CameraTarget>,
+    camera_modes: Vec<(Duration, CameraMode)>,
+    control_changes: Vec<ControlChange>,
+    unrecognized_packets: BTreeMap<u32, usize>,
+}
+
+impl ObserverData {
+    /// The entities the observer watched, in order. A new target is only recorded when
+    /// the camera switches to a different entity.
+    pub fn camera_targets(&self) -> &[CameraTarget] {
+        self.camera_targets.as_ref()
+    }
+
+    /// Every change of the observer's camera mode
+    pub fn camera_modes(&self) -> &[(Duration, CameraMode)] {
+        self.camera_modes.as_ref()
+    }
+
+    /// Every time the observer's client gained or lost control of an entity, which
+    /// happens as the observer switches between POVs
+    pub fn control_changes(&self) -> &[ControlChange] {
+        self.control_changes.as_ref()
+    }
+
+    /// Number of packets of each packet type which could not be decoded
+    pub fn unrecognized_packets(&self) -> &BTreeMap<u32, usize> {
+        &self.unrecognized_packets
+    }
+
+    pub(crate) fn record_camera_target(&mut self, timestamp: Duration, entity_id: u32) {
+        if entity_id == 0 {
+            return;
+        }
+        if self.camera_targets.last().map(|target| target.entity_id) != Some(entity_id) {
+            self.camera_targets.push(CameraTarget {
+                timestamp,
+                entity_id,
+            });
+        }
+    }
+
+    pub(crate) fn record_camera_mode(&mut self, timestamp: Duration, mode: CameraMode) {
+        self.camera_modes.push((timestamp, mode));
+    }
+
+    pub(crate) fn record_control_change(
+        &mut self,
+        timestamp: Duration,
+        entity_id: u32,
+        is_controlled: bool,
+    ) {
+        self.control_changes.push(ControlChange {
+            timestamp,
+            entity_id,
+            is_controlled,
+        });
+    }
+
+    pub(crate) fn record_unrecognized(&mut self, packet_type: u32) {
+        *self.unrecognized_packets.entry(packet_type).or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_camera_targets_deduplicated() {
+        let mut data = ObserverData::default();
+        data.record_camera_target(Duration::from_secs(1), 10);
+        data.record_camera_target(Duration::from_secs(2), 10);
+        data.record_camera_target(Duration::from_secs(3), 0);
+        data.record_camera_target(Duration::from_secs(4), 11);
+
+        let targets: Vec<_> = data
+            .camera_targets()
+            .iter()
+            .map(|target| (target.timestamp().as_secs(), target.entity_id()))
+            .collect();
+        assert_eq!(targets, vec![(1, 10), (4, 11)]);
+    }
+}
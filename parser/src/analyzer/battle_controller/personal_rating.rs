@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ErrorKind;
+
+/// The average performance of a ship across all players
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ExpectedValues {
+    pub average_damage_dealt: f32,
+    pub average_frags: f32,
+    /// In percent, e.g. 49.5
+    pub win_rate: f32,
+}
+
+/// Provides the expected values that players' performance is rated against. Implement
+/// this to use values from a source other than an [ExpectedValuesTable].
+pub trait ExpectedValuesProvider {
+    /// The expected values of the ship with the GameParams ID, or `None` if unknown
+    fn expected_values(&self, ship_id: u32) -> Option<ExpectedValues>;
+}
+
+/// Expected values by ship, as published by wows-numbers.com
+#[derive(Debug, Clone, Default)]
+pub struct ExpectedValuesTable {
+    ships: HashMap<u32, ExpectedValues>,
+}
+
+impl ExpectedValuesTable {
+    pub fn new(ships: HashMap<u32, ExpectedValues>) -> Self {
+        Self { ships }
+    }
+
+    /// Parses wows-numbers.com's expected values JSON, whose `data` maps ship IDs to their
+    /// values. Ships without enough data have an empty list instead and are skipped.
+    pub fn from_json(json: &str) -> Result<Self, ErrorKind> {
+        #[derive(Deserialize)]
+        struct File {
+            data: HashMap<String, serde_json::Value>,
+        }
+
+        let file: File = serde_json::from_str(json)?;
+        let mut ships = HashMap::new();
+        for (ship_id, entry) in file.data {
+            let ship_id = ship_id
+                .parse()
+                .map_err(|_| ErrorKind::ParsingFailure(format!("invalid ship ID {}", ship_id)))?;
+            if entry.is_object() {
+                ships.insert(ship_id, serde_json::from_value(entry)?);
+            }
+        }
+        Ok(Self { ships })
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self, ErrorKind> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| ErrorKind::ParsingFailure(format!("{:?}: {}", path, e)))?;
+        Self::from_json(&json)
+    }
+}
+
+impl ExpectedValuesProvider for ExpectedValuesTable {
+    fn expected_values(&self, ship_id: u32) -> Option<ExpectedValues> {
+        self.ships.get(&ship_id).copied()
+    }
+}
+
+/// A player's performance in the battle relative to their ship's expected values, using
+/// the wows-numbers.com personal rating (PR) formula
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersonalRating {
+    pub(crate) entity_id: u32,
+    pub(crate) damage_ratio: f32,
+    pub(crate) frags_ratio: f32,
+    pub(crate) wins_ratio: Option<f32>,
+    pub(crate) rating: f32,
+}
+
+impl PersonalRating {
+    /// Computes the rating of a single battle. `won` is `None` if the outcome is unknown,
+    /// in which case the win component is left out.
+    pub fn compute(
+        entity_id: u32,
+        expected: &ExpectedValues,
+        damage: f32,
+        frags: usize,
+        won: Option<bool>,
+    ) -> Self {
+        let damage_ratio = ratio(damage, expected.average_damage_dealt);
+        let frags_ratio = ratio(frags as f32, expected.average_frags);
+        let wins_ratio = won.map(|won| ratio(if won { 100.0 } else { 0.0 }, expected.win_rate));
+
+        let normalized =
+            |ratio: f32, threshold: f32| ((ratio - threshold) / (1.0 - threshold)).max(0.0);
+        let rating = 700.0 * normalized(damage_ratio, 0.4)
+            + 300.0 * normalized(frags_ratio, 0.1)
+            + wins_ratio.map_or(0.0, |wins_ratio| 150.0 * normalized(wins_ratio, 0.7));
+
+        PersonalRating {
+            entity_id,
+            damage_ratio,
+            frags_ratio,
+            wins_ratio,
+            rating,
+        }
+    }
+
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+
+    /// Damage dealt relative to the ship's average
+    pub fn damage_ratio(&self) -> f32 {
+        self.damage_ratio
+    }
+
+    /// Ships destroyed relative to the ship's average
+    pub fn frags_ratio(&self) -> f32 {
+        self.frags_ratio
+    }
+
+    /// The battle's result relative to the ship's win rate, if the result is known
+    pub fn wins_ratio(&self) -> Option<f32> {
+        self.wins_ratio
+    }
+
+    /// The PR of the battle. An average battle in an average ship scores around 1000.
+    pub fn rating(&self) -> f32 {
+        self.rating
+    }
+}
+
+fn ratio(actual: f32, expected: f32) -> f32 {
+    if expected > 0.0 {
+        actual / expected
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_personal_rating() {
+        let table = ExpectedValuesTable::from_json(
+            r#"{"time": 0, "data": {
+                "1": {"average_damage_dealt": 50000.0, "average_frags": 1.0, "win_rate": 50.0},
+                "2": []
+            }}"#,
+        )
+        .unwrap();
+        assert!(table.expected_values(2).is_none());
+        let expected = table.expected_values(1).unwrap();
+
+        let rating = PersonalRating::compute(7, &expected, 50000.0, 1, Some(true));
+        assert_eq!(rating.damage_ratio(), 1.0);
+        assert_eq!(rating.wins_ratio(), Some(2.0));
+        assert!((rating.rating() - 1650.0).abs() < 0.01);
+
+        // A loss only loses the win component
+        let rating = PersonalRating::compute(7, &expected, 50000.0, 1, Some(false));
+        assert!((rating.rating() - 1000.0).abs() < 0.01);
+        assert_eq!(
+            PersonalRating::compute(7, &expected, 0.0, 0, None).rating(),
+            0.0
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::TimelineSample;
+
+/// Span of the timeline the damage rate is averaged over when looking for phase changes
+pub const PHASE_DAMAGE_WINDOW: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BattlePhaseKind {
+    /// Ships moving into position before anyone has taken damage
+    Opening,
+    /// The first exchange of fire, until it dies down or a capture point changes hands
+    FirstEngagement,
+    MidGame,
+    /// A team has a third of its ships or fewer left
+    Endgame,
+}
+
+impl std::fmt::Display for BattlePhaseKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BattlePhaseKind::Opening => "Opening",
+            BattlePhaseKind::FirstEngagement => "First engagement",
+            BattlePhaseKind::MidGame => "Mid-game",
+            BattlePhaseKind::Endgame => "Endgame",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A part of the battle, from one change in its pace to the next
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BattlePhase {
+    pub(crate) kind: BattlePhaseKind,
+    pub(crate) start: Duration,
+    pub(crate) end: Duration,
+}
+
+impl BattlePhase {
+    /// Splits the battle into phases from the damage rate and capture points in its
+    /// timeline. Phases are in order, and ones the battle never reached are left out.
+    pub fn segment(timeline: &[TimelineSample]) -> Vec<BattlePhase> {
+        let (first, last) = match (timeline.first(), timeline.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return vec![],
+        };
+        let rates = damage_rates(timeline);
+        let mut starts = vec![(BattlePhaseKind::Opening, 0)];
+
+        if let Some(engagement) = rates.iter().position(|rate| *rate > 0.0) {
+            starts.push((BattlePhaseKind::FirstEngagement, engagement));
+
+            // The engagement ends when the damage rate falls to half of its peak, or a
+            // capture point changes hands
+            let mut peak: f32 = 0.0;
+            let mid_game = (engagement + 1..timeline.len()).find(|idx| {
+                peak = peak.max(rates[*idx]);
+                let captured = timeline[*idx].teams.iter().any(|team| {
+                    timeline[*idx - 1]
+                        .team(team.team_id)
+                        .is_some_and(|previous| previous.control_points != team.control_points)
+                });
+                rates[*idx] < peak / 2.0 || captured
+            });
+            if let Some(mid_game) = mid_game {
+                starts.push((BattlePhaseKind::MidGame, mid_game));
+            }
+
+            let after = starts
+                .last()
+                .map(|(_kind, idx)| *idx + 1)
+                .unwrap_or_default();
+            let endgame = (after..timeline.len()).find(|idx| {
+                timeline[*idx].teams.iter().any(|team| {
+                    first
+                        .team(team.team_id)
+                        .is_some_and(|initial| team.ships_alive * 3 <= initial.ships_alive)
+                })
+            });
+            if let Some(endgame) = endgame {
+                starts.push((BattlePhaseKind::Endgame, endgame));
+            }
+        }
+
+        starts
+            .iter()
+            .enumerate()
+            .map(|(i, (kind, idx))| BattlePhase {
+                kind: *kind,
+                start: timeline[*idx].timestamp,
+                end: starts
+                    .get(i + 1)
+                    .map(|(_kind, next)| timeline[*next].timestamp)
+                    .unwrap_or(last.timestamp),
+            })
+            .collect()
+    }
+
+    pub fn kind(&self) -> BattlePhaseKind {
+        self.kind
+    }
+
+    pub fn start(&self) -> Duration {
+        self.start
+    }
+
+    pub fn end(&self) -> Duration {
+        self.end
+    }
+}
+
+/// Damage per second dealt to all teams over the [PHASE_DAMAGE_WINDOW] ending at each
+/// sample, from how much health the teams lost
+fn damage_rates(timeline: &[TimelineSample]) -> Vec<f32> {
+    let total_health =
+        |sample: &TimelineSample| -> f32 { sample.teams.iter().map(|team| team.health).sum() };
+    let damage: Vec<f32> = timeline
+        .iter()
+        .enumerate()
+        .map(|(idx, sample)| match idx.checked_sub(1) {
+            // Heals can make up for damage, but never count as negative damage
+            Some(previous) => (total_health(&timeline[previous]) - total_health(sample)).max(0.0),
+            None => 0.0,
+        })
+        .collect();
+
+    timeline
+        .iter()
+        .enumerate()
+        .map(|(idx, sample)| {
+            let window_start = sample.timestamp.saturating_sub(PHASE_DAMAGE_WINDOW);
+            let in_window: f32 = timeline[..=idx]
+                .iter()
+                .zip(&damage)
+                .rev()
+                .take_while(|(sample, _damage)| sample.timestamp > window_start)
+                .map(|(_sample, damage)| *damage)
+                .sum();
+            in_window / PHASE_DAMAGE_WINDOW.as_secs_f32()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analyzer::battle_controller::TeamSnapshot;
+
+    fn sample(secs: u64, health: [f32; 2], ships_alive: [usize; 2]) -> TimelineSample {
+        TimelineSample {
+            timestamp: Duration::from_secs(secs),
+            teams: (0..2)
+                .map(|team_id| TeamSnapshot {
+                    team_id: team_id as u32,
+                    health: health[team_id],
+                    max_health: 100000.0,
+                    score: None,
+                    ships_alive: ships_alive[team_id],
+                    control_points: 0,
+                    fires: 0,
+                    damage_over_time: 0.0,
+                })
+                .collect(),
+            cyclone: false,
+        }
+    }
+
+    #[test]
+    fn test_segment() {
+        let mut timeline: Vec<_> = (0..=60)
+            .map(|secs| sample(secs * 5, [100000.0, 100000.0], [6, 6]))
+            .collect();
+        // Fighting starts at 100s and dies down after 150s
+        for (idx, sample) in timeline.iter_mut().enumerate().skip(20) {
+            let lost = (idx.min(30) - 19) as f32 * 2000.0;
+            sample.teams[0].health -= lost;
+            sample.teams[1].health -= lost;
+        }
+        // Team 1 is down to two ships from 250s
+        for sample in timeline.iter_mut().skip(50) {
+            sample.teams[1].ships_alive = 2;
+        }
+
+        let phases: Vec<_> = BattlePhase::segment(&timeline)
+            .iter()
+            .map(|phase| (phase.kind(), phase.start().as_secs(), phase.end().as_secs()))
+            .collect();
+        assert_eq!(
+            phases,
+            vec![
+                (BattlePhaseKind::Opening, 0, 100),
+                (BattlePhaseKind::FirstEngagement, 100, 170),
+                (BattlePhaseKind::MidGame, 170, 250),
+                (BattlePhaseKind::Endgame, 250, 300),
+            ]
+        );
+    }
+}
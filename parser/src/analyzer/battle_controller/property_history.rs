@@ -0,0 +1,322 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::VehicleProps;
+use crate::packet2::Vec3;
+
+/// A vehicle property whose changes can be recorded over time
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum TrackedProperty {
+    Health,
+    ServerSpeedRaw,
+    VisibilityFlags,
+    BurningFlags,
+}
+
+impl TrackedProperty {
+    pub const ALL: [TrackedProperty; 4] = [
+        TrackedProperty::Health,
+        TrackedProperty::ServerSpeedRaw,
+        TrackedProperty::VisibilityFlags,
+        TrackedProperty::BurningFlags,
+    ];
+
+    /// The name of the entity property in the game's entity definitions
+    pub fn property_name(&self) -> &'static str {
+        match self {
+            TrackedProperty::Health => "health",
+            TrackedProperty::ServerSpeedRaw => "serverSpeedRaw",
+            TrackedProperty::VisibilityFlags => "visibilityFlags",
+            TrackedProperty::BurningFlags => "burningFlags",
+        }
+    }
+
+    pub fn from_property_name(name: &str) -> Option<TrackedProperty> {
+        TrackedProperty::ALL
+            .iter()
+            .find(|property| property.property_name() == name)
+            .copied()
+    }
+
+    fn value(&self, props: &VehicleProps) -> f64 {
+        match self {
+            TrackedProperty::Health => props.health() as f64,
+            TrackedProperty::ServerSpeedRaw => props.server_speed_raw() as f64,
+            TrackedProperty::VisibilityFlags => props.visibility_flags() as f64,
+            TrackedProperty::BurningFlags => props.burning_flags() as f64,
+        }
+    }
+}
+
+/// A property taking on a new value
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PropertyChange {
+    timestamp: Duration,
+    value: f64,
+}
+
+impl PropertyChange {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// The new value. Flags are stored as their integer value, which is represented
+    /// exactly.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+/// A ship's world position at a point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionSample {
+    timestamp: Duration,
+    position: Vec3,
+}
+
+impl PositionSample {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    pub fn position(&self) -> &Vec3 {
+        &self.position
+    }
+}
+
+/// Timestamped changes of selected vehicle properties, for time-series analysis and
+/// scrubbing through a replay. Ship positions are always recorded along with them. Only
+/// recorded when enabled with [super::BattleController::track_property_history].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PropertyHistory {
+    tracked: BTreeSet<TrackedProperty>,
+    entities: BTreeMap<u32, BTreeMap<TrackedProperty, Vec<PropertyChange>>>,
+    positions: BTreeMap<u32, Vec<PositionSample>>,
+}
+
+impl PropertyHistory {
+    pub fn new(tracked: &[TrackedProperty]) -> Self {
+        PropertyHistory {
+            tracked: tracked.iter().copied().collect(),
+            entities: BTreeMap::new(),
+            positions: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_tracked(&self, property: TrackedProperty) -> bool {
+        self.tracked.contains(&property)
+    }
+
+    /// Entities which have any recorded history, in ascending order
+    pub fn entity_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.entities.keys().copied()
+    }
+
+    /// Every change of `property` on the entity, in order. The first change is the value
+    /// the entity was created with.
+    pub fn changes(&self, entity_id: u32, property: TrackedProperty) -> &[PropertyChange] {
+        self.entities
+            .get(&entity_id)
+            .and_then(|properties| properties.get(&property))
+            .map(|changes| changes.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The value `property` had at `timestamp`, or `None` if it wasn't known yet
+    pub fn value_at(
+        &self,
+        entity_id: u32,
+        property: TrackedProperty,
+        timestamp: Duration,
+    ) -> Option<f64> {
+        let changes = self.changes(entity_id, property);
+        let idx = changes.partition_point(|change| change.timestamp <= timestamp);
+        idx.checked_sub(1).map(|idx| changes[idx].value)
+    }
+
+    /// Changes of `property` between `start` and `end`, beginning with the value it had
+    /// at `start` if it was known by then
+    pub fn changes_between(
+        &self,
+        entity_id: u32,
+        property: TrackedProperty,
+        start: Duration,
+        end: Duration,
+    ) -> Vec<PropertyChange> {
+        let changes = self.changes(entity_id, property);
+        let first = changes.partition_point(|change| change.timestamp <= start);
+        let last = changes.partition_point(|change| change.timestamp <= end);
+        let initial = first.checked_sub(1).map(|idx| PropertyChange {
+            timestamp: start,
+            value: changes[idx].value,
+        });
+        initial
+            .into_iter()
+            .chain(changes[first..last.max(first)].iter().copied())
+            .collect()
+    }
+
+    /// The value of `property` at `timestamp`, linearly interpolated between the
+    /// surrounding changes. Holds the last value after the final change.
+    pub fn interpolated_value_at(
+        &self,
+        entity_id: u32,
+        property: TrackedProperty,
+        timestamp: Duration,
+    ) -> Option<f64> {
+        let changes = self.changes(entity_id, property);
+        let idx = changes.partition_point(|change| change.timestamp <= timestamp);
+        let prev = changes.get(idx.checked_sub(1)?)?;
+        match changes.get(idx) {
+            Some(next) => {
+                let t = lerp_factor(prev.timestamp, next.timestamp, timestamp) as f64;
+                Some(prev.value + (next.value - prev.value) * t)
+            }
+            None => Some(prev.value),
+        }
+    }
+
+    /// Every recorded position of the entity, in order
+    pub fn positions(&self, entity_id: u32) -> &[PositionSample] {
+        self.positions
+            .get(&entity_id)
+            .map(|positions| positions.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The entity's position at `timestamp`, linearly interpolated between the
+    /// surrounding samples. Holds the last position after the final sample.
+    pub fn position_at(&self, entity_id: u32, timestamp: Duration) -> Option<Vec3> {
+        let positions = self.positions(entity_id);
+        let idx = positions.partition_point(|sample| sample.timestamp <= timestamp);
+        let prev = positions.get(idx.checked_sub(1)?)?;
+        match positions.get(idx) {
+            Some(next) => {
+                let t = lerp_factor(prev.timestamp, next.timestamp, timestamp);
+                let (a, b) = (&prev.position, &next.position);
+                Some(Vec3 {
+                    x: a.x + (b.x - a.x) * t,
+                    y: a.y + (b.y - a.y) * t,
+                    z: a.z + (b.z - a.z) * t,
+                })
+            }
+            None => Some(prev.position.clone()),
+        }
+    }
+
+    pub(crate) fn record_position(&mut self, entity_id: u32, timestamp: Duration, position: &Vec3) {
+        self.positions
+            .entry(entity_id)
+            .or_default()
+            .push(PositionSample {
+                timestamp,
+                position: position.clone(),
+            });
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        entity_id: u32,
+        timestamp: Duration,
+        props: &VehicleProps,
+        property: Option<TrackedProperty>,
+    ) {
+        for tracked in TrackedProperty::ALL {
+            if !self.is_tracked(tracked) || property.is_some_and(|p| p != tracked) {
+                continue;
+            }
+            self.push(entity_id, tracked, timestamp, tracked.value(props));
+        }
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        entity_id: u32,
+        property: TrackedProperty,
+        timestamp: Duration,
+        value: f64,
+    ) {
+        let changes = self
+            .entities
+            .entry(entity_id)
+            .or_default()
+            .entry(property)
+            .or_default();
+        if changes.last().map(|change| change.value) != Some(value) {
+            changes.push(PropertyChange { timestamp, value });
+        }
+    }
+}
+
+/// How far `timestamp` is between `start` and `end`, from 0 to 1
+fn lerp_factor(start: Duration, end: Duration, timestamp: Duration) -> f32 {
+    let span = end.saturating_sub(start).as_secs_f32();
+    if span == 0.0 {
+        return 0.0;
+    }
+    (timestamp.saturating_sub(start).as_secs_f32() / span).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_value_at() {
+        let mut history = PropertyHistory::new(&[TrackedProperty::Health]);
+        history.push(1, TrackedProperty::Health, Duration::from_secs(10), 100.0);
+        history.push(1, TrackedProperty::Health, Duration::from_secs(20), 100.0);
+        history.push(1, TrackedProperty::Health, Duration::from_secs(30), 50.0);
+
+        assert_eq!(history.changes(1, TrackedProperty::Health).len(), 2);
+        let at = |secs| history.value_at(1, TrackedProperty::Health, Duration::from_secs(secs));
+        assert_eq!(at(5), None);
+        assert_eq!(at(25), Some(100.0));
+        assert_eq!(at(30), Some(50.0));
+        assert_eq!(
+            history.value_at(2, TrackedProperty::Health, Duration::from_secs(30)),
+            None
+        );
+
+        let window: Vec<_> = history
+            .changes_between(
+                1,
+                TrackedProperty::Health,
+                Duration::from_secs(15),
+                Duration::from_secs(40),
+            )
+            .iter()
+            .map(|change| (change.timestamp().as_secs(), change.value()))
+            .collect();
+        assert_eq!(window, vec![(15, 100.0), (30, 50.0)]);
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let mut history = PropertyHistory::new(&[TrackedProperty::Health]);
+        history.push(1, TrackedProperty::Health, Duration::from_secs(10), 100.0);
+        history.push(1, TrackedProperty::Health, Duration::from_secs(20), 50.0);
+        let at = |secs| {
+            history.interpolated_value_at(1, TrackedProperty::Health, Duration::from_secs(secs))
+        };
+        assert_eq!(at(5), None);
+        assert_eq!(at(15), Some(75.0));
+        assert_eq!(at(25), Some(50.0));
+
+        let point = |x| Vec3 { x, y: 0.0, z: 0.0 };
+        history.record_position(1, Duration::from_secs(0), &point(0.0));
+        history.record_position(1, Duration::from_secs(4), &point(8.0));
+        assert_eq!(
+            history.position_at(1, Duration::from_secs(1)).unwrap().x,
+            2.0
+        );
+        assert_eq!(
+            history.position_at(1, Duration::from_secs(9)).unwrap().x,
+            8.0
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ErrorKind;
+
+use super::BattleReport;
+
+/// Positions of the compared fields within each player's `playersPublicInfo` array of the
+/// post-battle results. The array layout changes between game versions, so it has to be
+/// provided by the caller (e.g. from the game's battle results constants).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResultsLayout {
+    pub damage: usize,
+    pub frags: usize,
+    /// Number of capture points the player captured. Only compared for the recording
+    /// player, since capture ribbons are only received for them.
+    pub captures: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResultsField {
+    Damage,
+    Frags,
+    Captures,
+}
+
+/// A value which the controller tracked differently than the battle results reported
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultsDiscrepancy {
+    pub player_name: String,
+    pub db_id: i64,
+    pub field: ResultsField,
+    pub tracked: f64,
+    pub reported: f64,
+}
+
+/// Tracked damage is summed from floats, so allow for some rounding
+const DAMAGE_TOLERANCE: f64 = 1.0;
+
+impl BattleReport {
+    /// Compares the damage, frags, and captures tracked while processing the replay with the
+    /// post-battle results. Returns `None` if the replay did not contain battle results.
+    pub fn diff_battle_results(
+        &self,
+        layout: &ResultsLayout,
+    ) -> Option<Result<Vec<ResultsDiscrepancy>, ErrorKind>> {
+        self.battle_results()
+            .map(|results| diff_results(self, results, layout))
+    }
+}
+
+fn diff_results(
+    report: &BattleReport,
+    results: &str,
+    layout: &ResultsLayout,
+) -> Result<Vec<ResultsDiscrepancy>, ErrorKind> {
+    let results: Value = serde_json::from_str(results)?;
+    let public_info = results
+        .get("playersPublicInfo")
+        .and_then(|info| info.as_object())
+        .ok_or_else(|| {
+            ErrorKind::ParsingFailure("battle results have no playersPublicInfo".to_string())
+        })?;
+
+    let self_id = report.self_entity().map(|entity| entity.id());
+    let mut discrepancies = Vec::new();
+    for vehicle in report.player_entities() {
+        let player = match vehicle.player() {
+            Some(player) => player,
+            None => continue,
+        };
+        let info = match public_info
+            .get(&player.db_id().to_string())
+            .and_then(|info| info.as_array())
+        {
+            Some(info) => info,
+            None => continue,
+        };
+        let reported = |idx: usize| -> Result<f64, ErrorKind> {
+            info.get(idx)
+                .and_then(|value| value.as_f64())
+                .ok_or_else(|| {
+                    ErrorKind::ParsingFailure(format!(
+                        "battle results for {} have no number at index {}",
+                        player.name(),
+                        idx
+                    ))
+                })
+        };
+
+        let mut compared = vec![
+            (ResultsField::Damage, vehicle.damage() as f64, layout.damage),
+            (
+                ResultsField::Frags,
+                vehicle.kills().len() as f64,
+                layout.frags,
+            ),
+        ];
+        if let (Some(captures), true) = (layout.captures, Some(vehicle.id()) == self_id) {
+            compared.push((ResultsField::Captures, report.captures() as f64, captures));
+        }
+
+        for (field, tracked, idx) in compared {
+            let reported = reported(idx)?;
+            let tolerance = if field == ResultsField::Damage {
+                DAMAGE_TOLERANCE
+            } else {
+                0.0
+            };
+            if (tracked - reported).abs() > tolerance {
+                discrepancies.push(ResultsDiscrepancy {
+                    player_name: player.name().to_string(),
+                    db_id: player.db_id(),
+                    field,
+                    tracked,
+                    reported,
+                });
+            }
+        }
+    }
+
+    Ok(discrepancies)
+}
@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+use super::{BattleReport, BattleSnapshot, PropertyHistory, TrackedProperty};
+use crate::clock::GameClock;
+
+/// How often [crate::open_replay_session] records a keyframe by default, in game time
+pub const KEYFRAME_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A parsed replay which can be scrubbed back and forth, e.g. by an interactive viewer,
+/// without parsing its packets again.
+///
+/// The battle's state is stored as a [BattleSnapshot] keyframe every few seconds of game
+/// time, and the ships' position, health and visibility between keyframes are taken from
+/// the [PropertyHistory] recorded while parsing. Everything else, such as the weather, is
+/// as of the keyframe before the seeked time.
+pub struct ReplaySession {
+    keyframes: Vec<BattleSnapshot>,
+    report: BattleReport,
+}
+
+impl ReplaySession {
+    pub(crate) fn new(keyframes: Vec<BattleSnapshot>, report: BattleReport) -> Self {
+        ReplaySession { keyframes, report }
+    }
+
+    /// The keyframes, in order
+    pub fn keyframes(&self) -> &[BattleSnapshot] {
+        &self.keyframes
+    }
+
+    /// The report of the whole replay, as returned by [crate::analyze_replay]
+    pub fn report(&self) -> &BattleReport {
+        &self.report
+    }
+
+    /// The clock of the last keyframe, which is no earlier than the last packet
+    pub fn end(&self) -> GameClock {
+        GameClock(self.keyframes.last().map_or(0.0, |keyframe| keyframe.clock))
+    }
+
+    /// The state of the battle at `clock`, or `None` if it's before the first keyframe
+    pub fn seek_to(&self, clock: GameClock) -> Option<BattleSnapshot> {
+        let idx = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.clock <= clock.seconds());
+        let keyframe = self.keyframes.get(idx.checked_sub(1)?)?;
+        Some(match self.report.property_history() {
+            Some(history) => advance_snapshot(keyframe, history, clock),
+            None => keyframe.clone(),
+        })
+    }
+}
+
+/// Brings the ships in `keyframe` forward to `clock` using `history`. Ships created
+/// after the keyframe aren't added.
+fn advance_snapshot(
+    keyframe: &BattleSnapshot,
+    history: &PropertyHistory,
+    clock: GameClock,
+) -> BattleSnapshot {
+    let timestamp = Duration::from(clock);
+    let mut snapshot = keyframe.clone();
+    snapshot.clock = clock.seconds();
+    for ship in snapshot.ships.iter_mut() {
+        if let Some(position) = history.position_at(ship.entity_id, timestamp) {
+            ship.position = Some(position);
+        }
+        if let Some(health) = history.value_at(ship.entity_id, TrackedProperty::Health, timestamp) {
+            ship.health = health as f32;
+            ship.alive = ship.alive && health > 0.0;
+        }
+
+        let visibility = history.changes(ship.entity_id, TrackedProperty::VisibilityFlags);
+        let idx = visibility.partition_point(|change| change.timestamp() <= timestamp);
+        if let Some(change) = idx.checked_sub(1).map(|idx| &visibility[idx]) {
+            ship.visible = change.value() != 0.0;
+            ship.undetected_since = (ship.alive && !ship.visible && ship.position.is_some())
+                .then(|| change.timestamp().as_secs_f32());
+        }
+    }
+
+    snapshot
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analyzer::battle_controller::{ShipSnapshot, WeatherState};
+    use crate::packet2::Vec3;
+
+    #[test]
+    fn test_advance_snapshot() {
+        let keyframe = BattleSnapshot {
+            clock: 30.0,
+            ships: vec![ShipSnapshot {
+                entity_id: 1,
+                player_name: None,
+                team_id: None,
+                position: Some(Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                }),
+                health: 1000.0,
+                max_health: Some(1000),
+                alive: true,
+                visible: true,
+                undetected_since: None,
+            }],
+            weather: WeatherState::default(),
+            map_border: None,
+            view_range: None,
+        };
+
+        let mut history = PropertyHistory::new(&TrackedProperty::ALL);
+        let secs = Duration::from_secs;
+        history.record_position(
+            1,
+            secs(30),
+            &Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        );
+        history.record_position(
+            1,
+            secs(40),
+            &Vec3 {
+                x: 10.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        );
+        history.push(1, TrackedProperty::Health, secs(0), 1000.0);
+        history.push(1, TrackedProperty::Health, secs(45), 0.0);
+        history.push(1, TrackedProperty::VisibilityFlags, secs(0), 1.0);
+        history.push(1, TrackedProperty::VisibilityFlags, secs(38), 0.0);
+
+        let snapshot = advance_snapshot(&keyframe, &history, GameClock(35.0));
+        let ship = &snapshot.ships[0];
+        assert_eq!(snapshot.clock, 35.0);
+        assert_eq!(ship.position.as_ref().unwrap().x, 5.0);
+        assert!(ship.alive && ship.visible);
+
+        let ship = &advance_snapshot(&keyframe, &history, GameClock(40.0)).ships[0];
+        assert!(!ship.visible);
+        assert_eq!(ship.undetected_since, Some(38.0));
+
+        let ship = &advance_snapshot(&keyframe, &history, GameClock(50.0)).ships[0];
+        assert!(!ship.alive);
+        assert_eq!(ship.health, 0.0);
+        assert_eq!(ship.undetected_since, None);
+    }
+}
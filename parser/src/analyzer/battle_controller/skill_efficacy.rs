@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analyzer::decoder::{Consumable, Ribbon},
+    game_params::{CrewSkill, CrewSkillModifier, Species},
+};
+
+/// An in-match statistic which a captain skill can plausibly influence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SkillEvidenceKind {
+    FiresSet,
+    FloodsCaused,
+    MainBatterySalvos,
+    SecondaryHits,
+    TorpedoHits,
+    PlanesShotDown,
+    ShipsSpotted,
+    ConsumableUsed(Consumable),
+}
+
+/// Maps fragments of GameParams modifier names (lowercased) to the statistic that modifier
+/// affects. Modifier names are stable across skill reworks, so matching on them is more
+/// robust than matching on the skill names themselves.
+const MODIFIER_EVIDENCE: &[(&str, SkillEvidenceKind)] = &[
+    ("burnprob", SkillEvidenceKind::FiresSet),
+    ("burnchance", SkillEvidenceKind::FiresSet),
+    ("floodprob", SkillEvidenceKind::FloodsCaused),
+    ("uwcoeffbonus", SkillEvidenceKind::FloodsCaused),
+    ("gmshotdelay", SkillEvidenceKind::MainBatterySalvos),
+    ("gmrotationspeed", SkillEvidenceKind::MainBatterySalvos),
+    ("gmmaxdist", SkillEvidenceKind::MainBatterySalvos),
+    ("gmidealradius", SkillEvidenceKind::MainBatterySalvos),
+    ("gsshotdelay", SkillEvidenceKind::SecondaryHits),
+    ("gsmaxdist", SkillEvidenceKind::SecondaryHits),
+    ("gsidealradius", SkillEvidenceKind::SecondaryHits),
+    ("torpedoreloadtime", SkillEvidenceKind::TorpedoHits),
+    ("torpedospeed", SkillEvidenceKind::TorpedoHits),
+    ("aaaura", SkillEvidenceKind::PlanesShotDown),
+    ("aabubble", SkillEvidenceKind::PlanesShotDown),
+    ("aasector", SkillEvidenceKind::PlanesShotDown),
+    ("visionxraytorpedodist", SkillEvidenceKind::ShipsSpotted),
+    (
+        "smokegenerator",
+        SkillEvidenceKind::ConsumableUsed(Consumable::Smoke),
+    ),
+    (
+        "regencrew",
+        SkillEvidenceKind::ConsumableUsed(Consumable::RepairParty),
+    ),
+    (
+        "crashcrew",
+        SkillEvidenceKind::ConsumableUsed(Consumable::DamageControl),
+    ),
+    (
+        "speedbooster",
+        SkillEvidenceKind::ConsumableUsed(Consumable::SpeedBoost),
+    ),
+    ("rls", SkillEvidenceKind::ConsumableUsed(Consumable::Radar)),
+    (
+        "sonar",
+        SkillEvidenceKind::ConsumableUsed(Consumable::HydroacousticSearch),
+    ),
+    (
+        "airdefensedisp",
+        SkillEvidenceKind::ConsumableUsed(Consumable::DefensiveAntiAircraft),
+    ),
+];
+
+/// How often a statistic influenced by a skill occurred during the match
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkillEvidence {
+    kind: SkillEvidenceKind,
+    count: usize,
+}
+
+impl SkillEvidence {
+    pub fn kind(&self) -> SkillEvidenceKind {
+        self.kind
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// Whether one of the recording player's captain skills demonstrably had an effect on the
+/// battle, based on the events it influences.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkillEfficacy {
+    skill_name: String,
+    evidence: Vec<SkillEvidence>,
+}
+
+impl SkillEfficacy {
+    /// The skill's internal GameParams name, e.g. `ArmamentExpert`
+    pub fn skill_name(&self) -> &str {
+        self.skill_name.as_ref()
+    }
+
+    /// Statistics the skill influences. Empty if none of the skill's effects are tracked.
+    pub fn evidence(&self) -> &[SkillEvidence] {
+        self.evidence.as_ref()
+    }
+
+    /// Whether any of the events this skill influences actually occurred
+    pub fn mattered(&self) -> bool {
+        self.evidence.iter().any(|evidence| evidence.count > 0)
+    }
+}
+
+/// Counts of the recording player's in-match events which skills are checked against
+#[derive(Debug, Default)]
+pub(crate) struct MatchEventCounts {
+    pub ribbons: HashMap<Ribbon, usize>,
+    pub consumables: HashMap<Consumable, usize>,
+    pub salvos: usize,
+}
+
+impl MatchEventCounts {
+    fn count(&self, kind: SkillEvidenceKind) -> usize {
+        let ribbons = |ribbon| self.ribbons.get(&ribbon).cloned().unwrap_or_default();
+        match kind {
+            SkillEvidenceKind::FiresSet => ribbons(Ribbon::SetFire),
+            SkillEvidenceKind::FloodsCaused => ribbons(Ribbon::Flooding),
+            SkillEvidenceKind::MainBatterySalvos => self.salvos,
+            SkillEvidenceKind::SecondaryHits => ribbons(Ribbon::SecondaryHit),
+            SkillEvidenceKind::TorpedoHits => ribbons(Ribbon::TorpedoHit),
+            SkillEvidenceKind::PlanesShotDown => ribbons(Ribbon::PlaneShotDown),
+            SkillEvidenceKind::ShipsSpotted => ribbons(Ribbon::Spotted),
+            SkillEvidenceKind::ConsumableUsed(consumable) => self
+                .consumables
+                .get(&consumable)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Returns the statistics a modifier influences, if it has any effect on `species`
+fn modifier_evidence(
+    modifier: &CrewSkillModifier,
+    species: &Species,
+) -> impl Iterator<Item = SkillEvidenceKind> {
+    // Multiplicative modifiers are neutral at 1.0 and additive ones at 0.0
+    let applies = modifier
+        .get_for_species(species)
+        .is_some_and(|value| value != 1.0 && value != 0.0);
+    let name = modifier.name().to_lowercase();
+
+    MODIFIER_EVIDENCE
+        .iter()
+        .filter(move |(fragment, _)| applies && name.contains(fragment))
+        .map(|(_, kind)| *kind)
+}
+
+pub(crate) fn evaluate_skills(
+    skills: &[&CrewSkill],
+    species: &Species,
+    counts: &MatchEventCounts,
+) -> Vec<SkillEfficacy> {
+    skills
+        .iter()
+        .map(|skill| {
+            // Triggered skills (e.g. Adrenaline Rush) keep their modifiers in the trigger
+            let modifiers = skill
+                .modifiers()
+                .into_iter()
+                .chain(skill.logic_trigger().modifiers())
+                .flatten();
+
+            let mut evidence: Vec<SkillEvidence> = Vec::new();
+            for kind in modifiers.flat_map(|modifier| modifier_evidence(modifier, species)) {
+                if evidence.iter().all(|existing| existing.kind != kind) {
+                    evidence.push(SkillEvidence {
+                        kind,
+                        count: counts.count(kind),
+                    });
+                }
+            }
+
+            SkillEfficacy {
+                skill_name: skill.name().to_owned(),
+                evidence,
+            }
+        })
+        .collect()
+}
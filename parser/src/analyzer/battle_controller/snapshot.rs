@@ -0,0 +1,98 @@
+use serde::Serialize;
+
+use super::{BattleControllerState, MapBorder, WeatherState};
+use crate::packet2::Vec3;
+
+/// A ship's state in a [BattleSnapshot]
+#[derive(Debug, Clone, Serialize)]
+pub struct ShipSnapshot {
+    pub entity_id: u32,
+    pub player_name: Option<String>,
+    pub team_id: Option<u32>,
+    /// Last known world position
+    pub position: Option<Vec3>,
+    pub health: f32,
+    pub max_health: Option<u32>,
+    pub alive: bool,
+    pub visible: bool,
+    /// Game clock when a living ship which isn't currently spotted was last seen, so
+    /// viewers can draw a fading "ghost" at its last known `position` with a timer
+    pub undetected_since: Option<f32>,
+}
+
+/// The ranges to draw around the recording player's ship, in kilometers, from its
+/// ship's stock stats
+#[derive(Debug, Clone, Serialize)]
+pub struct ViewRange {
+    pub entity_id: u32,
+    /// The distance at which the ship is detected by enemy ships
+    pub detection: Option<f32>,
+    pub main_battery: Option<f32>,
+}
+
+/// The state of the battle at a single point in time, for streaming to live viewers
+#[derive(Debug, Clone, Serialize)]
+pub struct BattleSnapshot {
+    /// Game clock, in seconds
+    pub clock: f32,
+    /// Every ship which has been created, ordered by entity id
+    pub ships: Vec<ShipSnapshot>,
+    pub weather: WeatherState,
+    pub map_border: Option<MapBorder>,
+    pub view_range: Option<ViewRange>,
+}
+
+impl BattleSnapshot {
+    pub fn from_state<S: BattleControllerState + ?Sized>(state: &S) -> Self {
+        let mut ships: Vec<ShipSnapshot> = state
+            .entities_by_id()
+            .values()
+            .filter_map(|entity| entity.vehicle_ref())
+            .map(|vehicle| {
+                let vehicle = vehicle.borrow();
+                let player = vehicle.player();
+                let alive = vehicle.props().is_alive();
+                let visible = vehicle.props().visibility_flags() != 0;
+                let position = state.positions().get(&vehicle.id()).cloned();
+                ShipSnapshot {
+                    entity_id: vehicle.id(),
+                    player_name: player.map(|player| player.name().to_string()),
+                    team_id: player.map(|player| player.team_id()),
+                    undetected_since: (alive && !visible && position.is_some())
+                        .then(|| vehicle.visibility_changed_at().seconds()),
+                    position,
+                    health: vehicle.props().health(),
+                    max_health: player.map(|player| player.max_health()),
+                    alive,
+                    visible,
+                }
+            })
+            .collect();
+        ships.sort_by_key(|ship| ship.entity_id);
+
+        let view_range = state
+            .player_entities()
+            .values()
+            .find(|player| player.relation() == 0)
+            .map(|player| {
+                let stats = player
+                    .vehicle()
+                    .data()
+                    .vehicle_ref()
+                    .and_then(|vehicle| vehicle.base_stats());
+                ViewRange {
+                    entity_id: player.entity_id(),
+                    detection: stats.and_then(|stats| stats.concealment_by_sea),
+                    main_battery: stats.and_then(|stats| stats.main_battery_range),
+                }
+            });
+
+        BattleSnapshot {
+            clock: state.clock().seconds(),
+            ships,
+            weather: state.weather().clone(),
+            map_border: state.map_border(),
+            view_range,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::{cell::RefCell, collections::HashMap, time::Duration};
+
+use serde::Serialize;
+
+use super::{
+    DamageEvent, Entity, MapBorder, Player, PropertyHistory, Teams, TrackedProperty, VehicleEntity,
+    WeatherState,
+};
+use crate::{analyzer::decoder::Ribbon, clock::GameClock, packet2::Vec3, Rc};
+
+/// A ship's state at an arbitrary point in time, see [BattleControllerState::state_at]
+#[derive(Debug, Clone, Serialize)]
+pub struct ShipState {
+    entity_id: u32,
+    position: Option<Vec3>,
+    health: Option<f32>,
+}
+
+impl ShipState {
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+
+    /// The ship's world position, or `None` if it hadn't been seen yet
+    pub fn position(&self) -> Option<&Vec3> {
+        self.position.as_ref()
+    }
+
+    /// The ship's health, or `None` if it wasn't known yet
+    pub fn health(&self) -> Option<f32> {
+        self.health
+    }
+}
+
+/// Read access to the state of a battle while its packets are being processed, for
+/// consumers such as renderers which inspect the state as they go
+pub trait BattleControllerState {
+    /// The clock of the last processed packet
+    fn clock(&self) -> GameClock;
+
+    /// Players by their vehicle's entity id
+    fn player_entities(&self) -> &HashMap<u32, Rc<Player>>;
+
+    fn entities_by_id(&self) -> &HashMap<u32, Entity>;
+
+    /// Last known world position of each ship, by entity id
+    fn positions(&self) -> &HashMap<u32, Vec3>;
+
+    /// The history recorded so far, if enabled with
+    /// [super::BattleController::track_property_history]
+    fn property_history(&self) -> Option<&PropertyHistory>;
+
+    /// The current weather
+    fn weather(&self) -> &WeatherState;
+
+    /// Every hit of damage dealt so far, in order
+    fn damage_timeline(&self) -> &[DamageEvent];
+
+    /// The ribbons earned so far by each ship, in order. Only the recording player's
+    /// client is told about ribbons, so this has at most the recording player's ship.
+    fn ribbons(&self) -> &HashMap<u32, Vec<(GameClock, Ribbon)>>;
+
+    /// Damage dealt by the ship with id `entity_id` in each minute of game time so far,
+    /// starting from the first minute of the game clock
+    fn damage_per_minute(&self, entity_id: u32) -> Vec<f32> {
+        let mut minutes = Vec::new();
+        for event in self
+            .damage_timeline()
+            .iter()
+            .filter(|event| event.aggressor() == entity_id)
+        {
+            let minute = (event.timestamp().as_secs() / 60) as usize;
+            if minutes.len() <= minute {
+                minutes.resize(minute + 1, 0.0);
+            }
+            minutes[minute] += event.amount();
+        }
+        minutes
+    }
+
+    /// The current map border, or `None` if the whole map is playable
+    fn map_border(&self) -> Option<MapBorder> {
+        self.entities_by_id()
+            .values()
+            .find_map(|entity| entity.battle_logic_ref())
+            .and_then(|logic| logic.borrow().map_border())
+    }
+
+    /// Every ship which hasn't been destroyed, ordered by entity id
+    fn alive_ships(&self) -> Vec<&Rc<RefCell<VehicleEntity>>> {
+        let mut ships: Vec<_> = self
+            .entities_by_id()
+            .values()
+            .filter_map(|entity| entity.vehicle_ref())
+            .filter(|vehicle| vehicle.borrow().props().is_alive())
+            .collect();
+        ships.sort_by_key(|vehicle| vehicle.borrow().id());
+        ships
+    }
+
+    /// The teams of the players seen so far
+    fn teams(&self) -> Teams {
+        Teams::new(self.player_entities().values())
+    }
+
+    /// The living ships on a different team than the ship with id `entity_id` which are
+    /// currently spotted, ordered by entity id. Empty if the ship's team isn't known.
+    fn visible_enemies_of(&self, entity_id: u32) -> Vec<&Rc<RefCell<VehicleEntity>>> {
+        let teams = self.teams();
+        self.alive_ships()
+            .into_iter()
+            .filter(|vehicle| {
+                let vehicle = vehicle.borrow();
+                vehicle.props().visibility_flags() != 0 && teams.is_enemy(entity_id, vehicle.id())
+            })
+            .collect()
+    }
+
+    fn player_by_name(&self, name: &str) -> Option<&Rc<Player>> {
+        self.player_entities()
+            .values()
+            .find(|player| player.name() == name)
+    }
+
+    /// The vehicle the player is controlling, once it has been created
+    fn entity_for_player(&self, player: &Player) -> Option<&Rc<RefCell<VehicleEntity>>> {
+        self.entities_by_id()
+            .get(&player.entity_id())
+            .and_then(|entity| entity.vehicle_ref())
+    }
+
+    /// The interpolated position and health of every ship at `clock`, ordered by entity
+    /// id. Only ships which have been created by the time of [Self::clock] are included.
+    ///
+    /// This requires property history to be enabled, and `clock` should not be later
+    /// than [Self::clock]. Without history every ship's position and health are `None`.
+    fn state_at(&self, clock: GameClock) -> Vec<ShipState> {
+        let timestamp = Duration::from(clock);
+        let history = self.property_history();
+
+        let mut states: Vec<ShipState> = self
+            .entities_by_id()
+            .iter()
+            .filter(|(_, entity)| entity.vehicle_ref().is_some())
+            .map(|(entity_id, _)| ShipState {
+                entity_id: *entity_id,
+                position: history.and_then(|history| history.position_at(*entity_id, timestamp)),
+                health: history
+                    .and_then(|history| {
+                        history.interpolated_value_at(
+                            *entity_id,
+                            TrackedProperty::Health,
+                            timestamp,
+                        )
+                    })
+                    .map(|health| health as f32),
+            })
+            .collect();
+        states.sort_by_key(|state| state.entity_id);
+        states
+    }
+}
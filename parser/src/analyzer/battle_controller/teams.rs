@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use super::Player;
+use crate::Rc;
+
+/// How one ship relates to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Relation {
+    /// The same ship
+    Own,
+    Friendly,
+    Enemy,
+    /// Either ship's team isn't known, e.g. because it isn't a player's ship, or there is no
+    /// recording player to relate to in an observer replay
+    Neutral,
+}
+
+/// How a battle ended for a team
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BattleOutcome {
+    Win,
+    /// Another team won
+    Loss,
+    Draw,
+}
+
+/// Colors to draw teams in, as RGB. See [Teams::color].
+pub const TEAM_COLORS: [(u8, u8, u8); 8] = [
+    (76, 232, 170),
+    (254, 77, 42),
+    (255, 199, 31),
+    (73, 153, 255),
+    (214, 92, 255),
+    (65, 223, 234),
+    (255, 140, 0),
+    (220, 220, 220),
+];
+
+/// Which ships are on which team. Any number of teams is supported: every team other than
+/// a ship's own is its enemy.
+#[derive(Debug, Clone, Default)]
+pub struct Teams {
+    self_entity: Option<u32>,
+    /// Entity IDs of each team's ships, in ascending order
+    members: BTreeMap<u32, Vec<u32>>,
+    team_of: HashMap<u32, u32>,
+}
+
+impl Teams {
+    pub fn new<'a, I: IntoIterator<Item = &'a Rc<Player>>>(players: I) -> Self {
+        let mut self_entity = None;
+        let mut members = Vec::new();
+        for player in players {
+            if player.relation() == 0 {
+                self_entity = Some(player.entity_id());
+            }
+            members.push((player.entity_id(), player.team_id()));
+        }
+        Self::from_members(self_entity, members)
+    }
+
+    /// Builds the teams from `(entity_id, team_id)` pairs, e.g. from the arena state when
+    /// the [Player]s aren't at hand
+    pub fn from_members<I: IntoIterator<Item = (u32, u32)>>(
+        self_entity: Option<u32>,
+        members: I,
+    ) -> Self {
+        let mut teams = Teams {
+            self_entity,
+            ..Default::default()
+        };
+        for (entity_id, team_id) in members {
+            teams.members.entry(team_id).or_default().push(entity_id);
+            teams.team_of.insert(entity_id, team_id);
+        }
+        for members in teams.members.values_mut() {
+            members.sort_unstable();
+        }
+        teams
+    }
+
+    /// Every team's ID, in ascending order
+    pub fn team_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.members.keys().copied()
+    }
+
+    pub fn team_count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// Entity IDs of the team's ships, in ascending order
+    pub fn members(&self, team_id: u32) -> &[u32] {
+        self.members
+            .get(&team_id)
+            .map(|members| members.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn team_of(&self, entity_id: u32) -> Option<u32> {
+        self.team_of.get(&entity_id).copied()
+    }
+
+    /// The recording player's team, or `None` in observer replays
+    pub fn self_team(&self) -> Option<u32> {
+        self.team_of(self.self_entity?)
+    }
+
+    /// How the ship `other` relates to the ship `entity_id`
+    pub fn relation_between(&self, entity_id: u32, other: u32) -> Relation {
+        match (self.team_of(entity_id), self.team_of(other)) {
+            _ if entity_id == other => Relation::Own,
+            (Some(team), Some(other_team)) if team == other_team => Relation::Friendly,
+            (Some(_), Some(_)) => Relation::Enemy,
+            _ => Relation::Neutral,
+        }
+    }
+
+    /// How the ship relates to the recording player
+    pub fn relation(&self, entity_id: u32) -> Relation {
+        match self.self_entity {
+            Some(self_entity) => self.relation_between(self_entity, entity_id),
+            None => Relation::Neutral,
+        }
+    }
+
+    /// The color to draw the team in, distinct for up to eight teams. The recording
+    /// player's team is green and the first enemy team red, so a regular battle has the
+    /// game's usual colors.
+    pub fn color(&self, team_id: u32) -> (u8, u8, u8) {
+        let self_team = self.self_team();
+        let idx = self_team
+            .into_iter()
+            .chain(self.team_ids().filter(|id| Some(*id) != self_team))
+            .position(|id| id == team_id)
+            .unwrap_or(self.team_count());
+        TEAM_COLORS[idx % TEAM_COLORS.len()]
+    }
+
+    /// Whether the ships are on the same team, including a ship with itself
+    pub fn is_friendly(&self, entity_id: u32, other: u32) -> bool {
+        matches!(
+            self.relation_between(entity_id, other),
+            Relation::Own | Relation::Friendly
+        )
+    }
+
+    pub fn is_enemy(&self, entity_id: u32, other: u32) -> bool {
+        self.relation_between(entity_id, other) == Relation::Enemy
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_relations() {
+        // Three teams, as in Battle Royale
+        let teams = Teams::from_members(Some(3), [(3, 0), (1, 0), (2, 1), (4, 2)]);
+        assert_eq!(teams.team_ids().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(teams.members(0), &[1, 3]);
+        assert_eq!(teams.self_team(), Some(0));
+
+        assert_eq!(teams.relation(3), Relation::Own);
+        assert_eq!(teams.relation(1), Relation::Friendly);
+        assert_eq!(teams.relation(4), Relation::Enemy);
+        assert_eq!(teams.relation(5), Relation::Neutral);
+        assert!(teams.is_enemy(2, 4));
+        assert!(teams.is_friendly(1, 3));
+        assert_eq!(teams.color(0), TEAM_COLORS[0]);
+        assert_eq!(teams.color(2), TEAM_COLORS[2]);
+
+        let observed = Teams::from_members(None, [(1, 0), (2, 1)]);
+        assert_eq!(observed.relation(1), Relation::Neutral);
+        assert!(observed.is_enemy(1, 2));
+    }
+}
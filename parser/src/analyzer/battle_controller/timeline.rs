@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Default spacing of the samples in [super::BattleReport::timeline]
+pub const DEFAULT_TIMELINE_BUCKET: Duration = Duration::from_secs(1);
+
+/// Fraction of a ship's maximum health each fire on it burns per second, before any
+/// fire damage reduction
+pub const FIRE_DAMAGE_RATE: f32 = 0.003;
+
+/// The state of one team at a point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamSnapshot {
+    pub(crate) team_id: u32,
+    pub(crate) health: f32,
+    pub(crate) max_health: f32,
+    pub(crate) score: Option<u32>,
+    pub(crate) ships_alive: usize,
+    pub(crate) control_points: usize,
+    pub(crate) fires: usize,
+    pub(crate) damage_over_time: f32,
+}
+
+impl TeamSnapshot {
+    pub fn team_id(&self) -> u32 {
+        self.team_id
+    }
+
+    /// Total health of the team's ships
+    pub fn health(&self) -> f32 {
+        self.health
+    }
+
+    /// Total maximum health of the team's ships, including destroyed ones
+    pub fn max_health(&self) -> f32 {
+        self.max_health
+    }
+
+    /// The team's score, or `None` in modes without scores
+    pub fn score(&self) -> Option<u32> {
+        self.score
+    }
+
+    pub fn ships_alive(&self) -> usize {
+        self.ships_alive
+    }
+
+    /// Number of capture points the team owned
+    pub fn control_points(&self) -> usize {
+        self.control_points
+    }
+
+    /// Number of fires burning on the team's ships
+    pub fn fires(&self) -> usize {
+        self.fires
+    }
+
+    /// Damage per second the team's ships are taking from fires, see [FIRE_DAMAGE_RATE]
+    pub fn damage_over_time(&self) -> f32 {
+        self.damage_over_time
+    }
+}
+
+/// The state of every team at the end of a timeline bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineSample {
+    pub(crate) timestamp: Duration,
+    pub(crate) teams: Vec<TeamSnapshot>,
+    pub(crate) cyclone: bool,
+}
+
+impl TimelineSample {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// Every team, ordered by team id
+    pub fn teams(&self) -> &[TeamSnapshot] {
+        self.teams.as_ref()
+    }
+
+    pub fn team(&self, team_id: u32) -> Option<&TeamSnapshot> {
+        self.teams.iter().find(|team| team.team_id == team_id)
+    }
+
+    /// Whether a cyclone was active, see [super::WeatherState::is_cyclone]
+    pub fn is_cyclone(&self) -> bool {
+        self.cyclone
+    }
+}
@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::decoder::DeathCause;
+
+/// A torpedo salvo, resolved to the ship which launched it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TorpedoLaunch {
+    pub(crate) timestamp: Duration,
+    pub(crate) owner: u32,
+    pub(crate) params_id: u32,
+    pub(crate) count: usize,
+    pub(crate) aerial: bool,
+}
+
+impl TorpedoLaunch {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    /// Entity ID of the ship which launched the torpedoes. For air-dropped torpedoes
+    /// this is the aircraft carrier the aircraft belong to.
+    pub fn owner(&self) -> u32 {
+        self.owner
+    }
+
+    /// GameParams ID of the torpedo
+    pub fn params_id(&self) -> u32 {
+        self.params_id
+    }
+
+    /// Number of torpedoes in the salvo
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Whether the torpedoes were dropped by aircraft rather than launched by a ship
+    pub fn is_aerial(&self) -> bool {
+        self.aerial
+    }
+}
+
+/// Torpedo statistics of a single player
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TorpedoStats {
+    pub(crate) entity_id: u32,
+    pub(crate) salvos: usize,
+    pub(crate) launched: usize,
+    pub(crate) aerial_launched: usize,
+    pub(crate) hits: Option<usize>,
+    pub(crate) floods: Option<usize>,
+    pub(crate) kills: usize,
+}
+
+impl TorpedoStats {
+    pub fn entity_id(&self) -> u32 {
+        self.entity_id
+    }
+
+    pub fn salvos(&self) -> usize {
+        self.salvos
+    }
+
+    /// Number of torpedoes launched, including those dropped by aircraft
+    pub fn launched(&self) -> usize {
+        self.launched
+    }
+
+    /// Number of torpedoes dropped by aircraft
+    pub fn aerial_launched(&self) -> usize {
+        self.aerial_launched
+    }
+
+    /// Torpedo hits, from the player's ribbons. Only known for the recording player.
+    pub fn hits(&self) -> Option<usize> {
+        self.hits
+    }
+
+    /// Floods caused, from the player's ribbons. Only known for the recording player.
+    pub fn floods(&self) -> Option<usize> {
+        self.floods
+    }
+
+    /// Ships sunk by torpedoes, whether launched by a ship or aircraft
+    pub fn kills(&self) -> usize {
+        self.kills
+    }
+
+    /// Fraction of launched torpedoes which hit, if hits are known
+    pub fn hit_rate(&self) -> Option<f32> {
+        if self.launched == 0 {
+            return None;
+        }
+        Some(self.hits? as f32 / self.launched as f32)
+    }
+
+    pub(crate) fn record_launch(&mut self, launch: &TorpedoLaunch) {
+        self.salvos += 1;
+        self.launched += launch.count;
+        if launch.aerial {
+            self.aerial_launched += launch.count;
+        }
+    }
+
+    pub(crate) fn record_kill(&mut self, cause: DeathCause) {
+        if matches!(cause, DeathCause::Torpedo | DeathCause::AerialTorpedo) {
+            self.kills += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let launch = |count, aerial| TorpedoLaunch {
+            timestamp: Duration::from_secs(60),
+            owner: 1,
+            params_id: 2,
+            count,
+            aerial,
+        };
+        let mut stats = TorpedoStats::default();
+        assert_eq!(stats.hit_rate(), None);
+
+        stats.record_launch(&launch(4, false));
+        stats.record_launch(&launch(2, true));
+        stats.record_kill(DeathCause::AerialTorpedo);
+        stats.record_kill(DeathCause::Artillery);
+        assert_eq!(stats.salvos(), 2);
+        assert_eq!(stats.launched(), 6);
+        assert_eq!(stats.aerial_launched(), 2);
+        assert_eq!(stats.kills(), 1);
+        assert_eq!(stats.hit_rate(), None);
+
+        stats.hits = Some(3);
+        assert_eq!(stats.hit_rate(), Some(0.5));
+    }
+}
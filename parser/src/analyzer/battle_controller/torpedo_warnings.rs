@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::packet2::Vec3;
+
+/// How close, in world units, an enemy torpedo's path must come to the recording
+/// player's ship when it's spotted to count as a threat. About 1.5 km.
+pub const TORPEDO_THREAT_RADIUS: f32 = 50.0;
+
+/// How close, in world units, to the recording player's ship a torpedo must stop to
+/// count as a hit rather than a dodge. About half the length of a large ship.
+pub const TORPEDO_HIT_RADIUS: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TorpedoOutcome {
+    Hit,
+    Dodged,
+    /// The torpedo was still running when the replay ended
+    Unknown,
+}
+
+/// An enemy torpedo heading for the recording player's ship, from when the player's
+/// client was first told about it until it hit or missed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TorpedoWarning {
+    pub(crate) owner: u32,
+    pub(crate) params_id: u32,
+    pub(crate) shot_id: u16,
+    pub(crate) spotted_at: Duration,
+    pub(crate) ended_at: Option<Duration>,
+    pub(crate) outcome: TorpedoOutcome,
+}
+
+impl TorpedoWarning {
+    /// Entity ID of the ship which launched the torpedo
+    pub fn owner(&self) -> u32 {
+        self.owner
+    }
+
+    /// GameParams ID of the torpedo
+    pub fn params_id(&self) -> u32 {
+        self.params_id
+    }
+
+    pub fn spotted_at(&self) -> Duration {
+        self.spotted_at
+    }
+
+    /// When the torpedo hit or stopped, if it did before the replay ended
+    pub fn ended_at(&self) -> Option<Duration> {
+        self.ended_at
+    }
+
+    pub fn outcome(&self) -> TorpedoOutcome {
+        self.outcome
+    }
+
+    /// How long the player had to react to the torpedo before it hit or stopped
+    pub fn warning_time(&self) -> Option<Duration> {
+        self.ended_at
+            .map(|ended_at| ended_at.saturating_sub(self.spotted_at))
+    }
+
+    /// Whether a torpedo launched from `origin` in `direction` passes within
+    /// [TORPEDO_THREAT_RADIUS] of `target`, ignoring height
+    pub(crate) fn is_threat(origin: &Vec3, direction: &Vec3, target: &Vec3) -> bool {
+        let (dx, dz) = (direction.x, direction.z);
+        let length = (dx * dx + dz * dz).sqrt();
+        if length == 0.0 {
+            return false;
+        }
+        let (tx, tz) = (target.x - origin.x, target.z - origin.z);
+        // Distance along the torpedo's path to the point closest to the target
+        let along = (tx * dx + tz * dz) / length;
+        if along < 0.0 {
+            return (tx * tx + tz * tz).sqrt() <= TORPEDO_THREAT_RADIUS;
+        }
+        let across = (tx * dz - tz * dx).abs() / length;
+        across <= TORPEDO_THREAT_RADIUS
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_threat() {
+        let vec3 = |x, z| Vec3 { x, y: 0.0, z };
+        let origin = vec3(0.0, 0.0);
+        let north = vec3(0.0, 1.0);
+        assert!(TorpedoWarning::is_threat(
+            &origin,
+            &north,
+            &vec3(10.0, 300.0)
+        ));
+        assert!(!TorpedoWarning::is_threat(
+            &origin,
+            &north,
+            &vec3(100.0, 300.0)
+        ));
+        // Torpedoes don't turn around
+        assert!(!TorpedoWarning::is_threat(
+            &origin,
+            &north,
+            &vec3(0.0, -300.0)
+        ));
+
+        let warning = TorpedoWarning {
+            owner: 1,
+            params_id: 2,
+            shot_id: 3,
+            spotted_at: Duration::from_secs(100),
+            ended_at: Some(Duration::from_secs(108)),
+            outcome: TorpedoOutcome::Dodged,
+        };
+        assert_eq!(warning.warning_time(), Some(Duration::from_secs(8)));
+    }
+}
@@ -0,0 +1,231 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// A weather zone covering part of the map, such as a squall
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalWeather {
+    name: String,
+    position: (f32, f32),
+    radius: f32,
+    params_id: u32,
+}
+
+impl LocalWeather {
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    /// The zone's center as world X/Z coordinates
+    pub fn position(&self) -> (f32, f32) {
+        self.position
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// GameParams ID of the zone's weather
+    pub fn params_id(&self) -> u32 {
+        self.params_id
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        let position = value["position"].as_array()?;
+        Some(LocalWeather {
+            name: json_string(&value["name"])?,
+            position: (
+                position.first()?.as_f64()? as f32,
+                position.get(1)?.as_f64()? as f32,
+            ),
+            radius: value["radius"].as_f64()? as f32,
+            params_id: value["paramsId"].as_u64()? as u32,
+        })
+    }
+}
+
+/// Strings in entity properties are serialized as their raw bytes
+fn json_string(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Array(bytes) => {
+            let bytes = bytes
+                .iter()
+                .map(|byte| byte.as_u64().map(|byte| byte as u8))
+                .collect::<Option<Vec<u8>>>()?;
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        _ => None,
+    }
+}
+
+/// The battle's current weather. The map-wide weather comes from the `BattleLogic`
+/// entity's `state.weather`, while the visibility limit comes from the recording player's
+/// avatar.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WeatherState {
+    main_weather: Option<u32>,
+    global_weather: Option<u32>,
+    local_weather: Vec<LocalWeather>,
+    max_visibility_distance: Option<f32>,
+}
+
+impl WeatherState {
+    /// GameParams ID of the weather the battle started with
+    pub fn main_weather(&self) -> Option<u32> {
+        self.main_weather
+    }
+
+    /// GameParams ID of the current map-wide weather
+    pub fn global_weather(&self) -> Option<u32> {
+        self.global_weather
+    }
+
+    pub fn local_weather(&self) -> &[LocalWeather] {
+        self.local_weather.as_ref()
+    }
+
+    /// The recording player's maximum visibility distance, in world units
+    pub fn max_visibility_distance(&self) -> Option<f32> {
+        self.max_visibility_distance
+    }
+
+    /// Whether a cyclone is active, i.e. the map-wide weather has changed from the
+    /// weather the battle started with
+    pub fn is_cyclone(&self) -> bool {
+        match (self.main_weather, self.global_weather) {
+            (Some(main), Some(global)) => global != 0 && global != main,
+            _ => false,
+        }
+    }
+
+    /// Updates the state from a `WEATHER_STATE` value, returning what changed
+    pub(crate) fn update_from_json(&mut self, weather: &serde_json::Value) -> Vec<WeatherChange> {
+        let was_cyclone = self.is_cyclone();
+        let previous_local = std::mem::take(&mut self.local_weather);
+
+        self.main_weather = weather["mainWeather"].as_u64().map(|id| id as u32);
+        self.global_weather = weather
+            .pointer("/globalWeather/param")
+            .and_then(|param| param.as_u64())
+            .map(|id| id as u32);
+        self.local_weather = weather["localWeather"]
+            .as_array()
+            .map(|zones| zones.iter().filter_map(LocalWeather::from_json).collect())
+            .unwrap_or_default();
+
+        let mut changes = Vec::new();
+        match (was_cyclone, self.is_cyclone()) {
+            (false, true) => changes.push(WeatherChange::CycloneStarted {
+                params_id: self.global_weather.unwrap_or_default(),
+            }),
+            (true, false) => changes.push(WeatherChange::CycloneEnded),
+            _ => {}
+        }
+        for zone in &self.local_weather {
+            if !previous_local.iter().any(|prev| prev.name == zone.name) {
+                changes.push(WeatherChange::LocalWeatherStarted(zone.clone()));
+            }
+        }
+        for zone in previous_local {
+            if !self.local_weather.iter().any(|cur| cur.name == zone.name) {
+                changes.push(WeatherChange::LocalWeatherEnded { name: zone.name });
+            }
+        }
+        changes
+    }
+
+    /// Updates the visibility limit from a `WEATHER_LOGIC_PARAMS` value
+    pub(crate) fn update_visibility(
+        &mut self,
+        params: &serde_json::Value,
+    ) -> Option<WeatherChange> {
+        let distance = params["maxVisibilityDistance"].as_f64()? as f32;
+        if self.max_visibility_distance == Some(distance) {
+            return None;
+        }
+        self.max_visibility_distance = Some(distance);
+        Some(WeatherChange::VisibilityChanged {
+            max_visibility_distance: distance,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WeatherChange {
+    CycloneStarted {
+        /// GameParams ID of the cyclone's weather
+        params_id: u32,
+    },
+    CycloneEnded,
+    LocalWeatherStarted(LocalWeather),
+    LocalWeatherEnded {
+        name: String,
+    },
+    /// The recording player's view distance changed, e.g. because a cyclone reduced it
+    VisibilityChanged {
+        max_visibility_distance: f32,
+    },
+}
+
+/// A change of the weather during the battle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeatherEvent {
+    pub(crate) timestamp: Duration,
+    pub(crate) change: WeatherChange,
+}
+
+impl WeatherEvent {
+    pub fn timestamp(&self) -> Duration {
+        self.timestamp
+    }
+
+    pub fn change(&self) -> &WeatherChange {
+        &self.change
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_weather_changes() {
+        let mut weather = WeatherState::default();
+        let calm = serde_json::json!({
+            "mainWeather": 100,
+            "globalWeather": {"param": 100, "item": null, "notification": null},
+            "localWeather": [],
+        });
+        assert!(weather.update_from_json(&calm).is_empty());
+        assert!(!weather.is_cyclone());
+
+        let storm = serde_json::json!({
+            "mainWeather": 100,
+            "globalWeather": {"param": 200, "item": null, "notification": null},
+            "localWeather": [
+                {"name": b"squall", "position": [10.0, -20.0], "radius": 50.0, "paramsId": 300},
+            ],
+        });
+        let changes = weather.update_from_json(&storm);
+        assert!(weather.is_cyclone());
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0], WeatherChange::CycloneStarted { params_id: 200 });
+        assert_eq!(weather.local_weather()[0].position(), (10.0, -20.0));
+
+        assert_eq!(
+            weather.update_from_json(&calm),
+            vec![
+                WeatherChange::CycloneEnded,
+                WeatherChange::LocalWeatherEnded {
+                    name: "squall".to_string()
+                }
+            ]
+        );
+
+        let params = serde_json::json!({"maxVisibilityDistance": 8000.0});
+        assert!(weather.update_visibility(&params).is_some());
+        assert!(weather.update_visibility(&params).is_none());
+        assert_eq!(weather.max_visibility_distance(), Some(8000.0));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::analyzer::battle_controller::BattleLogic;
+use crate::analyzer::decoder::{DecodedPacket, DecodedPacketPayload};
+use crate::packet2::Packet;
+use serde::Serialize;
+use std::io::Write;
+
+use super::analyzer::{AnalyzerMut, AnalyzerMutBuilder};
+
+/// One line of the dump: the entire state of the `BattleLogic` entity after a change
+#[derive(Debug, Serialize)]
+pub struct BattleLogicSnapshot<'a> {
+    /// Game clock, in seconds
+    pub clock: f32,
+    /// The property which changed, or `None` when the entity was created
+    pub property: Option<&'a str>,
+    pub logic: &'a BattleLogic,
+}
+
+/// Dumps the `BattleLogic` entity's full state as a JSON line every time it changes. The
+/// state holds the mode's missions, scoring, timers and more, most of which the
+/// [super::battle_controller::BattleController] doesn't interpret.
+pub struct BattleLogicDumpBuilder {
+    path: Option<String>,
+}
+
+impl BattleLogicDumpBuilder {
+    pub fn new(output: Option<&str>) -> Self {
+        Self {
+            path: output.map(|s| s.to_string()),
+        }
+    }
+}
+
+impl AnalyzerMutBuilder for BattleLogicDumpBuilder {
+    fn build(&self, meta: &crate::ReplayMeta) -> Box<dyn AnalyzerMut> {
+        let version = crate::version::Version::from_client_exe(&meta.clientVersionFromExe);
+        Box::new(BattleLogicDump {
+            output: self.path.as_ref().map(|path| {
+                Box::new(std::fs::File::create(path).unwrap()) as Box<dyn std::io::Write>
+            }),
+            logic: None,
+            version,
+        })
+    }
+}
+
+struct BattleLogicDump {
+    output: Option<Box<dyn std::io::Write>>,
+    logic: Option<BattleLogic>,
+    version: crate::version::Version,
+}
+
+impl BattleLogicDump {
+    fn write(&mut self, clock: f32, property: Option<&str>) {
+        let logic = match self.logic.as_ref() {
+            Some(logic) => logic,
+            None => return,
+        };
+        let line = serde_json::to_string(&BattleLogicSnapshot {
+            clock,
+            property,
+            logic,
+        })
+        .unwrap();
+        match self.output.as_mut() {
+            Some(f) => {
+                writeln!(f, "{}", line).unwrap();
+            }
+            None => {
+                println!("{}", line);
+            }
+        }
+    }
+}
+
+impl AnalyzerMut for BattleLogicDump {
+    fn finish(&mut self) {}
+
+    fn process_mut(&mut self, packet: &Packet<'_, '_>) {
+        let decoded = DecodedPacket::from(&self.version, false, packet);
+        match decoded.payload {
+            DecodedPacketPayload::EntityCreate(create) if create.entity_type == "BattleLogic" => {
+                let mut logic = BattleLogic::new(create.entity_id);
+                for (name, value) in &create.props {
+                    logic.set_property(name, value);
+                }
+                self.logic = Some(logic);
+                self.write(decoded.clock, None);
+            }
+            DecodedPacketPayload::EntityProperty(prop) => {
+                match self.logic.as_mut() {
+                    Some(logic) if logic.id() == prop.entity_id => {
+                        logic.set_property(prop.property, &prop.value)
+                    }
+                    _ => return,
+                }
+                self.write(decoded.clock, Some(prop.property));
+            }
+            DecodedPacketPayload::PropertyUpdate(update) => {
+                match self.logic.as_mut() {
+                    Some(logic) if logic.id() as i32 == update.entity_id => {
+                        logic.apply_update(update)
+                    }
+                    _ => return,
+                }
+                self.write(decoded.clock, Some(update.property));
+            }
+            _ => {}
+        }
+    }
+}
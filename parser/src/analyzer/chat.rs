@@ -43,7 +43,7 @@ impl AnalyzerMut for ChatLogger {
             } => {
                 println!(
                     "{}: {}: {} {}",
-                    decoded.clock,
+                    decoded.game_clock(),
                     self.usernames.get(&sender_id).unwrap(),
                     audience,
                     message
@@ -54,7 +54,7 @@ impl AnalyzerMut for ChatLogger {
             } => {
                 println!(
                     "{}: {}: voiceline {:#?}",
-                    decoded.clock,
+                    decoded.game_clock(),
                     self.usernames.get(&sender_id).unwrap(),
                     message
                 );
@@ -1,12 +1,12 @@
-use crate::analyzer::{Analyzer, AnalyzerBuilder};
 use crate::packet2::{Entity, EntityMethodPacket, Packet, PacketType};
+use crate::version::ArenaPlayerKeyMap;
 use crate::{unpack_rpc_args, ErrorKind, IResult};
 use kinded::Kinded;
 use modular_bitfield::prelude::*;
 use nom::number::complete::{le_f32, le_i32, le_u16, le_u32, le_u64, le_u8};
 use pickled::Value;
-use serde::Serialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
 use std::iter::FromIterator;
 
@@ -81,7 +81,7 @@ pub enum VoiceLine {
 }
 
 /// Enumerates the ribbons which appear in the top-right
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum Ribbon {
     PlaneShotDown,
     Incapacitation,
@@ -113,7 +113,7 @@ pub enum Ribbon {
     Unknown(i8),
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum DeathCause {
     Secondaries,
     Artillery,
@@ -130,6 +130,27 @@ pub enum DeathCause {
     Unknown(u32),
 }
 
+impl DeathCause {
+    /// A short description of the cause for kill feeds, e.g. "main battery"
+    pub fn description(&self) -> &'static str {
+        match self {
+            DeathCause::Secondaries => "secondary battery",
+            DeathCause::Artillery => "main battery",
+            DeathCause::Fire => "fire",
+            DeathCause::Flooding => "flooding",
+            DeathCause::Torpedo => "torpedoes",
+            DeathCause::DiveBomber => "dive bombers",
+            DeathCause::AerialRocket => "rockets",
+            DeathCause::AerialTorpedo => "torpedo bombers",
+            DeathCause::Detonation => "detonation",
+            DeathCause::Ramming => "ramming",
+            DeathCause::DepthCharge => "depth charges",
+            DeathCause::SkipBombs => "skip bombs",
+            DeathCause::Unknown(_) => "unknown",
+        }
+    }
+}
+
 /// Contains the information describing a player
 #[derive(Debug, Clone, Serialize)]
 pub struct OnArenaStateReceivedPlayer {
@@ -137,6 +158,12 @@ pub struct OnArenaStateReceivedPlayer {
     pub username: String,
     /// The player's clan
     pub clan: String,
+    /// The player's clan's DB ID, or 0 if they are not in a clan
+    pub clan_id: i64,
+    /// Color of the player's clan tag as 0xRRGGBB
+    pub clan_color: i64,
+    /// ID of the division the player is in, or 0 if they are not in a division
+    pub prebattle_id: i64,
     /// The player's DB ID (unique player ID)
     pub db_id: i64,
     /// The realm this player belongs to
@@ -145,6 +172,8 @@ pub struct OnArenaStateReceivedPlayer {
     pub avatar_id: i64,
     /// Their ship ID in the game
     pub meta_ship_id: i64,
+    /// GameParams ID of their ship
+    pub ship_params_id: i64,
     /// This player's entity created by a CreateEntity packet
     pub entity_id: i64,
     //playeravatarid: i64,
@@ -156,10 +185,53 @@ pub struct OnArenaStateReceivedPlayer {
     pub is_abuser: bool,
     /// Has hidden stats
     pub is_hidden: bool,
+    /// Set by the server for ships controlled by the AI
+    pub is_bot: bool,
 
     /// This is a raw dump (with the values converted to strings) of every key for the player.
     // TODO: Replace String with the actual pickle value (which is cleanly serializable)
-    pub raw: HashMap<i64, String>,
+    pub raw: BTreeMap<i64, String>,
+}
+
+/// Who is controlling a ship
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum PlayerKind {
+    Human,
+    /// An AI ship standing in for a player, e.g. in co-op battles
+    Bot,
+    /// An AI ship spawned by a scenario, e.g. the enemies in operations
+    ScenarioAI,
+}
+
+impl OnArenaStateReceivedPlayer {
+    /// Classifies who is controlling this player's ship
+    pub fn kind(&self) -> PlayerKind {
+        PlayerKind::classify(self.is_bot, &self.username, &self.realm, self.db_id)
+    }
+}
+
+impl PlayerKind {
+    /// Bots are flagged by the server, but older replays and some modes only mark them
+    /// by their name (co-op bots are named `:Name:`) or by not belonging to any account.
+    pub fn classify(is_bot: bool, username: &str, realm: &str, db_id: i64) -> PlayerKind {
+        // Scenario ships are named with a translation ID, e.g. `IDS_OP_22_SHIP_01`
+        let scenario_name = username.trim_matches(':').starts_with("IDS_");
+        if is_bot || username.starts_with(':') {
+            if scenario_name {
+                PlayerKind::ScenarioAI
+            } else {
+                PlayerKind::Bot
+            }
+        } else if scenario_name || (db_id <= 0 && realm.is_empty()) {
+            PlayerKind::ScenarioAI
+        } else {
+            PlayerKind::Human
+        }
+    }
+
+    pub fn is_human(&self) -> bool {
+        *self == PlayerKind::Human
+    }
 }
 
 /// Indicates that the given attacker has dealt damage
@@ -193,7 +265,7 @@ pub struct MinimapUpdate {
 }
 
 /// Enumerates usable consumables in-game
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum Consumable {
     DamageControl,
     SpottingAircraft,
@@ -212,7 +284,31 @@ pub enum Consumable {
     Unknown(i8),
 }
 
-#[derive(Debug, Clone, Copy, Serialize)]
+impl Consumable {
+    /// The consumable with the given GameParams consumable type, e.g. `rls` for Radar
+    pub fn from_consumable_type(consumable_type: &str) -> Option<Self> {
+        let consumable = match consumable_type {
+            "crashCrew" => Consumable::DamageControl,
+            "scout" => Consumable::SpottingAircraft,
+            "airDefenseDisp" => Consumable::DefensiveAntiAircraft,
+            "speedBoosters" => Consumable::SpeedBoost,
+            "regenCrew" => Consumable::RepairParty,
+            "fighter" => Consumable::CatapultFighter,
+            "artilleryBoosters" => Consumable::MainBatteryReloadBooster,
+            "torpedoReloader" => Consumable::TorpedoReloadBooster,
+            "smokeGenerator" => Consumable::Smoke,
+            "rls" => Consumable::Radar,
+            "sonar" => Consumable::HydroacousticSearch,
+            "hydrophone" => Consumable::Hydrophone,
+            "fastRudders" => Consumable::EnhancedRudders,
+            "subsEnergyFreeze" => Consumable::ReserveBattery,
+            _ => return None,
+        };
+        Some(consumable)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum CameraMode {
     OverheadMap,
     FollowingShells,
@@ -254,6 +350,86 @@ pub enum CruiseState {
     Unknown(u32),
 }
 
+/// A single shell fired as part of an artillery salvo
+#[derive(Debug, Clone, Serialize)]
+pub struct ArtilleryShot {
+    /// World position the shell was fired from
+    pub origin: crate::packet2::Vec3,
+    /// World position the shell is aimed at
+    pub target: crate::packet2::Vec3,
+    pub pitch: f32,
+    pub speed: f32,
+    /// Unique (per-owner) ID of this shell, referenced when the shell hits something
+    pub shot_id: u16,
+    pub gun_barrel_id: u16,
+    /// Time remaining, in seconds, until the shell reaches its target
+    pub server_time_left: f32,
+    pub shooter_height: f32,
+    pub hit_distance: f32,
+}
+
+/// A salvo of shells fired by one ship
+#[derive(Debug, Clone, Serialize)]
+pub struct ArtillerySalvo {
+    /// GameParams ID of the projectile
+    pub params_id: u32,
+    /// The ship ID (note: Not the avatar ID) of the ship which fired
+    pub owner_id: i32,
+    pub salvo_id: i32,
+    pub shots: Vec<ArtilleryShot>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Torpedo {
+    /// World position the torpedo was launched from
+    pub origin: crate::packet2::Vec3,
+    pub direction: crate::packet2::Vec3,
+    pub shot_id: u16,
+    pub armed: bool,
+}
+
+/// A salvo of torpedoes launched by a ship, or dropped by one of its aircraft
+#[derive(Debug, Clone, Serialize)]
+pub struct TorpedoSalvo {
+    /// GameParams ID of the projectile
+    pub params_id: u32,
+    /// The ship ID of the ship which launched the torpedoes, or of the aircraft carrier
+    /// whose aircraft dropped them
+    pub owner_id: i32,
+    pub salvo_id: i32,
+    pub torpedoes: Vec<Torpedo>,
+}
+
+/// A projectile which stopped, by hitting something or reaching the end of its range
+#[derive(Debug, Clone, Serialize)]
+pub struct ShotKill {
+    /// World position the projectile stopped at
+    pub position: crate::packet2::Vec3,
+    /// Matches the `shot_id` of the projectile when it was fired, e.g. [Torpedo::shot_id]
+    pub shot_id: u16,
+}
+
+/// Projectiles of one ship which stopped
+#[derive(Debug, Clone, Serialize)]
+pub struct ShotKills {
+    /// The ship ID of the ship which fired the projectiles
+    pub owner_id: i32,
+    pub hit_type: u8,
+    pub kills: Vec<ShotKill>,
+}
+
+/// Aircraft of a squadron being destroyed
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaneDeath {
+    /// ID of the squadron the aircraft belonged to
+    pub squadron_id: i64,
+    /// Indices of the destroyed aircraft within the squadron
+    pub planes: Vec<u8>,
+    pub reason: u8,
+    /// The ship ID of the ship which shot the aircraft down, or 0 if none did
+    pub killer_id: i64,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ChatMessageExtra {
     pre_battle_sign: i64,
@@ -318,6 +494,7 @@ pub enum DecodedPacketPayload<'replay, 'argtype, 'rawpacket> {
     EntityProperty(&'rawpacket crate::packet2::EntityPropertyPacket<'argtype>),
     BasePlayerCreate(&'rawpacket crate::packet2::BasePlayerCreatePacket<'argtype>),
     CellPlayerCreate(&'rawpacket crate::packet2::CellPlayerCreatePacket<'argtype>),
+    EntityControl(&'rawpacket crate::packet2::EntityControlPacket),
     EntityEnter(&'rawpacket crate::packet2::EntityEnterPacket),
     EntityLeave(&'rawpacket crate::packet2::EntityLeavePacket),
     EntityCreate(&'rawpacket crate::packet2::EntityCreatePacket<'argtype>),
@@ -328,7 +505,7 @@ pub enum DecodedPacketPayload<'replay, 'argtype, 'rawpacket> {
         /// Unknown
         arg1: i8,
         /// Unknown
-        arg2: HashMap<i64, Vec<Option<HashMap<String, String>>>>,
+        arg2: BTreeMap<i64, Vec<Option<BTreeMap<String, String>>>>,
         /// A list of the players in this game
         players: Vec<OnArenaStateReceivedPlayer>,
     },
@@ -425,11 +602,23 @@ pub enum DecodedPacketPayload<'replay, 'argtype, 'rawpacket> {
     Unknown(&'replay [u8]),
     /// This is a packet of known type, but which we were unable to parse
     Invalid(&'rawpacket crate::packet2::InvalidPacket<'replay>),
+    /// An entity method decoded by a decoder registered with
+    /// [crate::packet2::Parser::register_method_decoder]
+    CustomMethod(&'rawpacket crate::packet2::CustomMethodPacket<'replay, 'argtype>),
+    /// A known entity method whose arguments we were unable to parse, or which didn't have
+    /// the shape we expected
+    PartialDecode(crate::packet2::PartialDecodePacket<'replay, 'argtype>),
     /// If parsing with audits enabled, this indicates a packet that may be of special interest
     /// for whoever is reading the audits.
     Audit(String),
     /// End of battle results (free xp, damage details, etc.)
     BattleResults(&'replay str),
+    /// Sent when one or more ships fire their main battery
+    ArtilleryShots(Vec<ArtillerySalvo>),
+    Torpedoes(Vec<TorpedoSalvo>),
+    /// Sent when projectiles stop, e.g. a torpedo hitting a ship or running out of range
+    ShotKills(Vec<ShotKills>),
+    PlaneDeath(PlaneDeath),
     /*
     ArtilleryHit(ArtilleryHitPacket<'a>),
     */
@@ -503,6 +692,165 @@ fn try_convert_pickle_to_string(value: pickled::value::Value) -> pickled::value:
     }
 }
 
+fn vec3_from_arg(arg: &crate::rpc::typedefs::ArgValue) -> Result<crate::packet2::Vec3, String> {
+    match arg {
+        crate::rpc::typedefs::ArgValue::Vector3((x, y, z)) => Ok(crate::packet2::Vec3 {
+            x: *x,
+            y: *y,
+            z: *z,
+        }),
+        _ => Err(format!("expected a Vector3, got {:?}", arg)),
+    }
+}
+
+fn array_from_arg<'a, 'argtype>(
+    arg: &'a crate::rpc::typedefs::ArgValue<'argtype>,
+) -> Result<&'a [crate::rpc::typedefs::ArgValue<'argtype>], String> {
+    match arg {
+        crate::rpc::typedefs::ArgValue::Array(a) => Ok(a),
+        _ => Err(format!("expected an Array, got {:?}", arg)),
+    }
+}
+
+fn dict_from_arg<'a, 'argtype>(
+    arg: &'a crate::rpc::typedefs::ArgValue<'argtype>,
+) -> Result<&'a HashMap<&'argtype str, crate::rpc::typedefs::ArgValue<'argtype>>, String> {
+    match arg {
+        crate::rpc::typedefs::ArgValue::FixedDict(m) => Ok(m),
+        _ => Err(format!("expected a FixedDict, got {:?}", arg)),
+    }
+}
+
+/// Looks up `key` in a FixedDict argument
+fn dict_get<'a, 'argtype>(
+    dict: &'a HashMap<&'argtype str, crate::rpc::typedefs::ArgValue<'argtype>>,
+    key: &str,
+) -> Result<&'a crate::rpc::typedefs::ArgValue<'argtype>, String> {
+    dict.get(key).ok_or_else(|| format!("missing {}", key))
+}
+
+/// Looks up `key` in a FixedDict argument and converts it to `T`
+fn dict_value<'a, 'argtype, T>(
+    dict: &'a HashMap<&'argtype str, crate::rpc::typedefs::ArgValue<'argtype>>,
+    key: &str,
+) -> Result<T, String>
+where
+    &'a crate::rpc::typedefs::ArgValue<'argtype>: TryInto<T, Error = ()>,
+{
+    let value = dict_get(dict, key)?;
+    value
+        .try_into()
+        .map_err(|_| format!("unexpected type for {}: {:?}", key, value))
+}
+
+/// Converts the `index`th positional argument to `T`
+fn arg_value<'a, 'argtype, T>(
+    args: &'a [crate::rpc::typedefs::ArgValue<'argtype>],
+    index: usize,
+) -> Result<T, String>
+where
+    &'a crate::rpc::typedefs::ArgValue<'argtype>: TryInto<T, Error = ()>,
+{
+    let value = args
+        .get(index)
+        .ok_or_else(|| format!("missing argument {}", index))?;
+    value
+        .try_into()
+        .map_err(|_| format!("unexpected type for argument {}: {:?}", index, value))
+}
+
+/// Decodes the arguments of `receive_planeDeath`
+fn plane_death_from_args(args: &[crate::rpc::typedefs::ArgValue]) -> Result<PlaneDeath, String> {
+    Ok(PlaneDeath {
+        squadron_id: arg_value(args, 0)?,
+        planes: arg_value(args, 1)?,
+        reason: arg_value(args, 2)?,
+        killer_id: arg_value(args, 3)?,
+    })
+}
+
+/// Decodes the arguments of `receiveArtilleryShots`
+fn artillery_salvos_from_args(
+    args: &[crate::rpc::typedefs::ArgValue],
+) -> Result<Vec<ArtillerySalvo>, String> {
+    let mut salvos = vec![];
+    for salvo in array_from_arg(args.first().ok_or("missing salvos")?)? {
+        let salvo = dict_from_arg(salvo)?;
+        let mut shots = vec![];
+        for shot in array_from_arg(dict_get(salvo, "shots")?)? {
+            let shot = dict_from_arg(shot)?;
+            shots.push(ArtilleryShot {
+                origin: vec3_from_arg(dict_get(shot, "pos")?)?,
+                target: vec3_from_arg(dict_get(shot, "tarPos")?)?,
+                pitch: dict_value(shot, "pitch")?,
+                speed: dict_value(shot, "speed")?,
+                shot_id: dict_value(shot, "shotID")?,
+                gun_barrel_id: dict_value(shot, "gunBarrelID")?,
+                server_time_left: dict_value(shot, "serverTimeLeft")?,
+                shooter_height: dict_value(shot, "shooterHeight")?,
+                hit_distance: dict_value(shot, "hitDistance")?,
+            });
+        }
+        salvos.push(ArtillerySalvo {
+            params_id: dict_value(salvo, "paramsID")?,
+            owner_id: dict_value(salvo, "ownerID")?,
+            salvo_id: dict_value(salvo, "salvoID")?,
+            shots,
+        });
+    }
+    Ok(salvos)
+}
+
+/// Decodes the arguments of `receiveTorpedoes`
+fn torpedo_salvos_from_args(
+    args: &[crate::rpc::typedefs::ArgValue],
+) -> Result<Vec<TorpedoSalvo>, String> {
+    let mut salvos = vec![];
+    for salvo in array_from_arg(args.first().ok_or("missing salvos")?)? {
+        let salvo = dict_from_arg(salvo)?;
+        let mut torpedoes = vec![];
+        for torpedo in array_from_arg(dict_get(salvo, "torpedoes")?)? {
+            let torpedo = dict_from_arg(torpedo)?;
+            let armed: u8 = dict_value(torpedo, "armed")?;
+            torpedoes.push(Torpedo {
+                origin: vec3_from_arg(dict_get(torpedo, "pos")?)?,
+                direction: vec3_from_arg(dict_get(torpedo, "dir")?)?,
+                shot_id: dict_value(torpedo, "shotID")?,
+                armed: armed != 0,
+            });
+        }
+        salvos.push(TorpedoSalvo {
+            params_id: dict_value(salvo, "paramsID")?,
+            owner_id: dict_value(salvo, "ownerID")?,
+            salvo_id: dict_value(salvo, "salvoID")?,
+            torpedoes,
+        });
+    }
+    Ok(salvos)
+}
+
+/// Decodes the arguments of `receiveShotKills`
+fn shot_kills_from_args(args: &[crate::rpc::typedefs::ArgValue]) -> Result<Vec<ShotKills>, String> {
+    let mut packs = vec![];
+    for pack in array_from_arg(args.first().ok_or("missing kills")?)? {
+        let pack = dict_from_arg(pack)?;
+        let mut kills = vec![];
+        for kill in array_from_arg(dict_get(pack, "kills")?)? {
+            let kill = dict_from_arg(kill)?;
+            kills.push(ShotKill {
+                position: vec3_from_arg(dict_get(kill, "pos")?)?,
+                shot_id: dict_value(kill, "shotID")?,
+            });
+        }
+        packs.push(ShotKills {
+            owner_id: dict_value(pack, "ownerID")?,
+            hit_type: dict_value(pack, "hitType")?,
+            kills,
+        });
+    }
+    Ok(packs)
+}
+
 fn parse_receive_common_cmd_blob(blob: &[u8]) -> IResult<&[u8], (VoiceLine, bool)> {
     let i = blob;
     let (i, line) = le_u16(i)?;
@@ -683,6 +1031,7 @@ where
             }
             PacketType::BasePlayerCreate(b) => DecodedPacketPayload::BasePlayerCreate(b),
             PacketType::CellPlayerCreate(c) => DecodedPacketPayload::CellPlayerCreate(c),
+            PacketType::EntityControl(e) => DecodedPacketPayload::EntityControl(e),
             PacketType::EntityEnter(e) => DecodedPacketPayload::EntityEnter(e),
             PacketType::EntityLeave(e) => DecodedPacketPayload::EntityLeave(e),
             PacketType::EntityCreate(e) => DecodedPacketPayload::EntityCreate(e),
@@ -707,10 +1056,25 @@ where
                 }
             }
             PacketType::Invalid(u) => DecodedPacketPayload::Invalid(&u),
+            PacketType::CustomMethod(custom) => DecodedPacketPayload::CustomMethod(custom),
+            PacketType::PartialDecode(partial) => {
+                DecodedPacketPayload::PartialDecode(partial.clone())
+            }
             PacketType::BattleResults(results) => DecodedPacketPayload::BattleResults(results),
         }
     }
 
+    /// A known entity method whose arguments decoded, but didn't have the shape we
+    /// expected
+    fn partial_decode(packet: &EntityMethodPacket<'argtype>, error: String) -> Self {
+        DecodedPacketPayload::PartialDecode(crate::packet2::PartialDecodePacket {
+            entity_id: packet.entity_id,
+            method: packet.method,
+            raw_args: &[],
+            error,
+        })
+    }
+
     fn from_entity_method(
         version: &crate::version::Version,
         audit: bool,
@@ -814,61 +1178,60 @@ where
                 extra_data,
             }
         } else if *method == "receive_CommonCMD" {
-            let (sender_id, message, is_global) =
-                if version.is_at_least(&crate::version::Version::from_client_exe("0,12,8,0")) {
-                    let sender = *args[0]
-                        .int_32_ref()
-                        .expect("receive_CommonCMD: sender is not an i32");
-
-                    let blob = args[1]
-                        .blob_ref()
-                        .expect("receive_CommonCMD: second argument is not a blob");
+            let (sender_id, message, is_global) = if version.capabilities().common_cmd_blob {
+                let sender = *args[0]
+                    .int_32_ref()
+                    .expect("receive_CommonCMD: sender is not an i32");
 
-                    let (_reminader, (message_type, is_global)) =
-                        parse_receive_common_cmd_blob(blob.as_ref())
-                            .expect("receive_CommonCMD: failed to parse blob");
+                let blob = args[1]
+                    .blob_ref()
+                    .expect("receive_CommonCMD: second argument is not a blob");
 
-                    (sender, message_type, is_global)
-                } else {
-                    let (audience, sender_id, line, a, b) =
-                        unpack_rpc_args!(args, u8, i32, u8, u32, u64);
-                    let is_global = match audience {
-                        0 => false,
-                        1 => true,
-                        _ => {
-                            panic!(
-                                "Got unknown audience {} sender=0x{:x} line={} a={:x} b={:x}",
-                                audience, sender_id, line, a, b
-                            );
-                        }
-                    };
-                    let message = match line {
-                        1 => VoiceLine::AttentionToSquare(a, b as u32),
-                        2 => VoiceLine::QuickTactic(a as u16, b as u64),
-                        3 => VoiceLine::RequestingSupport(None),
-                        5 => VoiceLine::Wilco,
-                        6 => VoiceLine::Negative,
-                        7 => VoiceLine::WellDone, // TODO: Find the corresponding field
-                        8 => VoiceLine::FairWinds,
-                        9 => VoiceLine::Curses,
-                        10 => VoiceLine::DefendTheBase,
-                        11 => VoiceLine::ProvideAntiAircraft,
-                        12 => VoiceLine::Retreat(if b != 0 { Some(b as i32) } else { None }),
-                        13 => VoiceLine::IntelRequired,
-                        14 => VoiceLine::SetSmokeScreen,
-                        15 => VoiceLine::UsingRadar,
-                        16 => VoiceLine::UsingHydroSearch,
-                        17 => VoiceLine::FollowMe,
-                        18 => VoiceLine::MapPointAttention(a as f32, b as f32),
-                        19 => VoiceLine::UsingSubmarineLocator,
-                        _ => {
-                            panic!("Unknown voice line {} a={:x} b={:x}!", line, a, b);
-                        }
-                    };
+                let (_reminader, (message_type, is_global)) =
+                    parse_receive_common_cmd_blob(blob.as_ref())
+                        .expect("receive_CommonCMD: failed to parse blob");
 
-                    (sender_id, message, is_global)
+                (sender, message_type, is_global)
+            } else {
+                let (audience, sender_id, line, a, b) =
+                    unpack_rpc_args!(args, u8, i32, u8, u32, u64);
+                let is_global = match audience {
+                    0 => false,
+                    1 => true,
+                    _ => {
+                        panic!(
+                            "Got unknown audience {} sender=0x{:x} line={} a={:x} b={:x}",
+                            audience, sender_id, line, a, b
+                        );
+                    }
+                };
+                let message = match line {
+                    1 => VoiceLine::AttentionToSquare(a, b as u32),
+                    2 => VoiceLine::QuickTactic(a as u16, b as u64),
+                    3 => VoiceLine::RequestingSupport(None),
+                    5 => VoiceLine::Wilco,
+                    6 => VoiceLine::Negative,
+                    7 => VoiceLine::WellDone, // TODO: Find the corresponding field
+                    8 => VoiceLine::FairWinds,
+                    9 => VoiceLine::Curses,
+                    10 => VoiceLine::DefendTheBase,
+                    11 => VoiceLine::ProvideAntiAircraft,
+                    12 => VoiceLine::Retreat(if b != 0 { Some(b as i32) } else { None }),
+                    13 => VoiceLine::IntelRequired,
+                    14 => VoiceLine::SetSmokeScreen,
+                    15 => VoiceLine::UsingRadar,
+                    16 => VoiceLine::UsingHydroSearch,
+                    17 => VoiceLine::FollowMe,
+                    18 => VoiceLine::MapPointAttention(a as f32, b as f32),
+                    19 => VoiceLine::UsingSubmarineLocator,
+                    _ => {
+                        panic!("Unknown voice line {} a={:x} b={:x}!", line, a, b);
+                    }
                 };
 
+                (sender_id, message, is_global)
+            };
+
             // let (audience, sender_id, line, a, b) = unpack_rpc_args!(args, u8, i32, u8, u32, u64);
 
             DecodedPacketPayload::VoiceLine {
@@ -892,7 +1255,7 @@ where
                 pickled::value::Value::Dict(d) => d,
                 _ => panic!(),
             };
-            let mut arg2 = HashMap::new();
+            let mut arg2 = BTreeMap::new();
             for (k, v) in value.iter() {
                 let k = match k {
                     pickled::value::HashableValue::I64(i) => *i,
@@ -952,90 +1315,90 @@ where
                         }
                     }
 
-                    let keys: HashMap<&'static str, i64> = if version
-                        .is_at_least(&crate::version::Version::from_client_exe("0,12,8,0"))
-                    {
-                        let mut h = HashMap::new();
-                        h.insert("accountDBID", 0);
-                        h.insert("antiAbuseEnabled", 1);
-                        h.insert("avatarId", 2);
-                        h.insert("camouflageInfo", 3);
-                        h.insert("clanColor", 4);
-                        h.insert("clanID", 5);
-                        h.insert("clanTag", 6);
-                        h.insert("crewParams", 7);
-                        h.insert("dogTag", 8);
-                        h.insert("fragsCount", 9);
-                        h.insert("friendlyFireEnabled", 10);
-                        h.insert("id", 11);
-                        h.insert("invitationsEnabled", 12);
-                        h.insert("isAbuser", 13);
-                        h.insert("isAlive", 14);
-                        h.insert("isBot", 15);
-                        h.insert("isClientLoaded", 16);
-                        h.insert("isConnected", 17);
-                        h.insert("isHidden", 18);
-                        h.insert("isLeaver", 19);
-                        h.insert("isPreBattleOwner", 20);
-                        h.insert("isTShooter", 21);
-                        h.insert("keyTargetMarkers", 22);
-                        h.insert("killedBuildingsCount", 23);
-                        h.insert("maxHealth", 24);
-                        h.insert("name", 25);
-                        h.insert("playerMode", 26);
-                        h.insert("preBattleIdOnStart", 27);
-                        h.insert("preBattleSign", 28);
-                        h.insert("prebattleId", 29);
-                        h.insert("realm", 30);
-                        h.insert("shipComponents", 31);
-                        h.insert("shipConfigDump", 32);
-                        h.insert("shipId", 33);
-                        h.insert("shipParamsId", 34);
-                        h.insert("skinId", 35);
-                        h.insert("teamId", 36);
-                        h.insert("ttkStatus", 37);
-                        h
-                    } else if version
-                        .is_at_least(&crate::version::Version::from_client_exe("0,10,9,0"))
-                    {
-                        // 0.10.9 inserted things at 0x1 and 0x1F
-                        let mut h = HashMap::new();
-                        h.insert("avatarId", 0x2);
-                        h.insert("clanTag", 0x6);
-                        h.insert("maxHealth", 0x17);
-                        h.insert("name", 0x18);
-                        h.insert("shipId", 0x20);
-                        h.insert("shipParamsId", 0x21);
-                        h.insert("skinId", 0x22);
-                        h.insert("teamId", 0x23);
-                        h
-                    } else if version
-                        .is_at_least(&crate::version::Version::from_client_exe("0,10,7,0"))
-                    {
-                        // 0.10.7
-                        let mut h = HashMap::new();
-                        h.insert("avatarId", 0x1);
-                        h.insert("clanTag", 0x5);
-                        h.insert("maxHealth", 0x16);
-                        h.insert("name", 0x17);
-                        h.insert("shipId", 0x1e);
-                        h.insert("shipParamsId", 0x1f);
-                        h.insert("skinId", 0x20);
-                        h.insert("teamId", 0x21);
-                        h
-                    } else {
-                        // 0.10.6 and earlier
-                        let mut h = HashMap::new();
-                        h.insert("avatarId", 0x1);
-                        h.insert("clanTag", 0x5);
-                        h.insert("maxHealth", 0x15);
-                        h.insert("name", 0x16);
-                        h.insert("shipId", 0x1d);
-                        h.insert("shipParamsId", 0x1e);
-                        h.insert("skinId", 0x1f);
-                        h.insert("teamId", 0x20);
-                        h
-                    };
+                    let keys: HashMap<&'static str, i64> =
+                        match version.capabilities().arena_player_keys {
+                            ArenaPlayerKeyMap::V0_12_8 => {
+                                let mut h = HashMap::new();
+                                h.insert("accountDBID", 0);
+                                h.insert("antiAbuseEnabled", 1);
+                                h.insert("avatarId", 2);
+                                h.insert("camouflageInfo", 3);
+                                h.insert("clanColor", 4);
+                                h.insert("clanID", 5);
+                                h.insert("clanTag", 6);
+                                h.insert("crewParams", 7);
+                                h.insert("dogTag", 8);
+                                h.insert("fragsCount", 9);
+                                h.insert("friendlyFireEnabled", 10);
+                                h.insert("id", 11);
+                                h.insert("invitationsEnabled", 12);
+                                h.insert("isAbuser", 13);
+                                h.insert("isAlive", 14);
+                                h.insert("isBot", 15);
+                                h.insert("isClientLoaded", 16);
+                                h.insert("isConnected", 17);
+                                h.insert("isHidden", 18);
+                                h.insert("isLeaver", 19);
+                                h.insert("isPreBattleOwner", 20);
+                                h.insert("isTShooter", 21);
+                                h.insert("keyTargetMarkers", 22);
+                                h.insert("killedBuildingsCount", 23);
+                                h.insert("maxHealth", 24);
+                                h.insert("name", 25);
+                                h.insert("playerMode", 26);
+                                h.insert("preBattleIdOnStart", 27);
+                                h.insert("preBattleSign", 28);
+                                h.insert("prebattleId", 29);
+                                h.insert("realm", 30);
+                                h.insert("shipComponents", 31);
+                                h.insert("shipConfigDump", 32);
+                                h.insert("shipId", 33);
+                                h.insert("shipParamsId", 34);
+                                h.insert("skinId", 35);
+                                h.insert("teamId", 36);
+                                h.insert("ttkStatus", 37);
+                                h
+                            }
+                            ArenaPlayerKeyMap::V0_10_9 => {
+                                // 0.10.9 inserted things at 0x1 and 0x1F
+                                let mut h = HashMap::new();
+                                h.insert("avatarId", 0x2);
+                                h.insert("clanTag", 0x6);
+                                h.insert("maxHealth", 0x17);
+                                h.insert("name", 0x18);
+                                h.insert("shipId", 0x20);
+                                h.insert("shipParamsId", 0x21);
+                                h.insert("skinId", 0x22);
+                                h.insert("teamId", 0x23);
+                                h
+                            }
+                            ArenaPlayerKeyMap::V0_10_7 => {
+                                // 0.10.7
+                                let mut h = HashMap::new();
+                                h.insert("avatarId", 0x1);
+                                h.insert("clanTag", 0x5);
+                                h.insert("maxHealth", 0x16);
+                                h.insert("name", 0x17);
+                                h.insert("shipId", 0x1e);
+                                h.insert("shipParamsId", 0x1f);
+                                h.insert("skinId", 0x20);
+                                h.insert("teamId", 0x21);
+                                h
+                            }
+                            ArenaPlayerKeyMap::V0_10_0 => {
+                                // 0.10.6 and earlier
+                                let mut h = HashMap::new();
+                                h.insert("avatarId", 0x1);
+                                h.insert("clanTag", 0x5);
+                                h.insert("maxHealth", 0x15);
+                                h.insert("name", 0x16);
+                                h.insert("shipId", 0x1d);
+                                h.insert("shipParamsId", 0x1e);
+                                h.insert("skinId", 0x1f);
+                                h.insert("teamId", 0x20);
+                                h
+                            }
+                        };
 
                     /*
                     1: Player ID
@@ -1066,6 +1429,24 @@ where
                         .expect("clanTag is not a string")
                         .clone();
 
+                    let clan_id = *values
+                        .get(keys.get("clanID").unwrap())
+                        .unwrap()
+                        .i64_ref()
+                        .expect("clanID is not an i64");
+
+                    let clan_color = *values
+                        .get(keys.get("clanColor").unwrap())
+                        .unwrap()
+                        .i64_ref()
+                        .expect("clanColor is not an i64");
+
+                    let prebattle_id = *values
+                        .get(keys.get("prebattleId").unwrap())
+                        .unwrap()
+                        .i64_ref()
+                        .expect("prebattleId is not an i64");
+
                     let shipid = *values
                         .get(keys.get("shipId").unwrap())
                         .unwrap()
@@ -1076,7 +1457,7 @@ where
                         .unwrap()
                         .i64_ref()
                         .expect("shipId is not an i64");
-                    let playerid = *values
+                    let ship_params_id = *values
                         .get(keys.get("shipParamsId").unwrap())
                         .unwrap()
                         .i64_ref()
@@ -1132,7 +1513,14 @@ where
                         .cloned()
                         .expect("isHidden is not a bool");
 
-                    let mut raw = HashMap::new();
+                    let is_bot = values
+                        .get(keys.get("isBot").unwrap())
+                        .unwrap()
+                        .bool_ref()
+                        .cloned()
+                        .expect("isBot is not a bool");
+
+                    let mut raw = BTreeMap::new();
                     for (k, v) in values.iter() {
                         raw.insert(*k, format!("{:?}", v));
                     }
@@ -1140,15 +1528,20 @@ where
                     players_out.push(OnArenaStateReceivedPlayer {
                         username,
                         clan,
+                        clan_id,
+                        clan_color,
+                        prebattle_id,
                         realm,
                         db_id,
                         avatar_id: avatar,
                         meta_ship_id,
+                        ship_params_id,
                         entity_id: shipid,
                         team_id: team,
                         max_health: health,
                         is_abuser,
                         is_hidden,
+                        is_bot,
                         raw,
                     });
                 }
@@ -1216,6 +1609,11 @@ where
                 _ => panic!("foo"),
             }
             DecodedPacketPayload::DamageStat(stats)
+        } else if *method == "receive_planeDeath" {
+            match plane_death_from_args(args) {
+                Ok(death) => DecodedPacketPayload::PlaneDeath(death),
+                Err(error) => DecodedPacketPayload::partial_decode(packet, error),
+            }
         } else if *method == "receiveVehicleDeath" {
             let (victim, killer, cause) = unpack_rpc_args!(args, i32, i32, u32);
             let cause = match cause {
@@ -1311,6 +1709,21 @@ where
                 victim: *entity_id,
                 aggressors: v,
             }
+        } else if *method == "receiveArtilleryShots" {
+            match artillery_salvos_from_args(args) {
+                Ok(salvos) => DecodedPacketPayload::ArtilleryShots(salvos),
+                Err(error) => DecodedPacketPayload::partial_decode(packet, error),
+            }
+        } else if *method == "receiveTorpedoes" {
+            match torpedo_salvos_from_args(args) {
+                Ok(salvos) => DecodedPacketPayload::Torpedoes(salvos),
+                Err(error) => DecodedPacketPayload::partial_decode(packet, error),
+            }
+        } else if *method == "receiveShotKills" {
+            match shot_kills_from_args(args) {
+                Ok(packs) => DecodedPacketPayload::ShotKills(packs),
+                Err(error) => DecodedPacketPayload::partial_decode(packet, error),
+            }
         } else if *method == "onCheckGamePing" {
             let (ping,) = unpack_rpc_args!(args, u64);
             DecodedPacketPayload::CheckPing(ping)
@@ -1361,13 +1774,12 @@ where
                 arg1: args1,
             }
         } else if *method == "onBattleEnd" {
-            let (winning_team, unknown) =
-                if version.is_at_least(&crate::version::Version::from_client_exe("0,12,8,0")) {
-                    (None, None)
-                } else {
-                    let (winning_team, unknown) = unpack_rpc_args!(args, i8, u8);
-                    (Some(winning_team), Some(unknown))
-                };
+            let (winning_team, unknown) = if version.capabilities().battle_end_has_winner {
+                let (winning_team, unknown) = unpack_rpc_args!(args, i8, u8);
+                (Some(winning_team), Some(unknown))
+            } else {
+                (None, None)
+            };
             DecodedPacketPayload::BattleEnd {
                 winning_team,
                 unknown,
@@ -1419,6 +1831,12 @@ pub struct DecodedPacket<'replay, 'argtype, 'rawpacket> {
     pub payload: DecodedPacketPayload<'replay, 'argtype, 'rawpacket>,
 }
 
+impl<'replay, 'argtype, 'rawpacket> DecodedPacket<'replay, 'argtype, 'rawpacket> {
+    pub fn game_clock(&self) -> crate::clock::GameClock {
+        crate::clock::GameClock(self.clock)
+    }
+}
+
 impl<'replay, 'argtype, 'rawpacket> DecodedPacket<'replay, 'argtype, 'rawpacket>
 where
     'rawpacket: 'replay,
@@ -1429,6 +1847,8 @@ where
         audit: bool,
         packet: &'rawpacket Packet<'_, '_>,
     ) -> Self {
+        #[cfg(feature = "packet-tracing")]
+        let _span = tracing::trace_span!("decode").entered();
         let decoded = Self {
             clock: packet.clock,
             packet_type: packet.packet_type,
@@ -2,10 +2,18 @@ mod analyzer;
 pub mod chat;
 //pub mod damage_trails;
 pub mod battle_controller;
+pub mod battle_logic_dump;
 pub mod decoder;
 pub mod packet_dump;
+pub mod path_export;
+pub mod pipe;
+#[cfg(feature = "scripting")]
+pub mod scripting;
 pub mod summary;
 pub mod survey;
+pub mod target_selection;
 //pub mod trails;
 
-pub use analyzer::{Analyzer, AnalyzerAdapter, AnalyzerBuilder, AnalyzerMut, AnalyzerMutBuilder};
+pub use analyzer::{
+    parse_with, Analyzer, AnalyzerAdapter, AnalyzerBuilder, AnalyzerMut, AnalyzerMutBuilder,
+};
@@ -0,0 +1,143 @@
+use crate::analyzer::decoder::{DecodedPacket, DecodedPacketPayload};
+use crate::packet2::{Packet, Rot3, Vec3};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use super::analyzer::{AnalyzerMut, AnalyzerMutBuilder};
+
+/// Version of the exported file format. Bump this when making incompatible changes.
+const PATH_FORMAT_VERSION: u32 = 1;
+
+/// Minimum time, in seconds, between two keyframes of the same ship.
+const MIN_KEYFRAME_INTERVAL: f32 = 0.1;
+
+/// A single sample of a ship's position and orientation.
+#[derive(Debug, Clone, Serialize)]
+pub struct Keyframe {
+    /// Game clock, in seconds
+    pub time: f32,
+    /// World position. Y is up.
+    pub position: Vec3,
+    /// Heading in radians, measured from +Z towards +X.
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+}
+
+/// The path taken by a single ship over the course of a game.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShipPath {
+    pub entity_id: u32,
+    pub player_name: String,
+    pub team_id: i64,
+    pub keyframes: Vec<Keyframe>,
+}
+
+/// A match's ship paths in a simple JSON format which can be imported into external
+/// 3D tools (e.g. via a small Blender script) to animate ship models.
+#[derive(Debug, Clone, Serialize)]
+pub struct PathExport {
+    pub format_version: u32,
+    pub map_name: String,
+    pub game_version: String,
+    pub ships: Vec<ShipPath>,
+}
+
+pub struct PathExportBuilder {
+    path: String,
+}
+
+impl PathExportBuilder {
+    pub fn new(output: &str) -> Self {
+        Self {
+            path: output.to_string(),
+        }
+    }
+}
+
+impl AnalyzerMutBuilder for PathExportBuilder {
+    fn build(&self, meta: &crate::ReplayMeta) -> Box<dyn AnalyzerMut> {
+        let version = crate::version::Version::from_client_exe(&meta.clientVersionFromExe);
+        Box::new(PathExporter {
+            path: self.path.clone(),
+            export: PathExport {
+                format_version: PATH_FORMAT_VERSION,
+                map_name: meta.mapName.clone(),
+                game_version: meta.clientVersionFromExe.clone(),
+                ships: vec![],
+            },
+            ships_by_id: HashMap::new(),
+            version,
+        })
+    }
+}
+
+struct PathExporter {
+    path: String,
+    export: PathExport,
+    /// Maps a ship's entity ID to its index in `export.ships`
+    ships_by_id: HashMap<u32, usize>,
+    version: crate::version::Version,
+}
+
+impl PathExporter {
+    fn add_keyframe(&mut self, clock: f32, entity_id: u32, position: &Vec3, rotation: &Rot3) {
+        let ship = match self.ships_by_id.get(&entity_id) {
+            Some(idx) => &mut self.export.ships[*idx],
+            None => return,
+        };
+        if let Some(last) = ship.keyframes.last() {
+            if clock - last.time < MIN_KEYFRAME_INTERVAL {
+                return;
+            }
+        }
+        ship.keyframes.push(Keyframe {
+            time: clock,
+            position: position.clone(),
+            yaw: rotation.yaw,
+            pitch: rotation.pitch,
+            roll: rotation.roll,
+        });
+    }
+}
+
+impl AnalyzerMut for PathExporter {
+    fn finish(&mut self) {
+        let file = std::fs::File::create(&self.path).unwrap();
+        serde_json::to_writer(file, &self.export).unwrap();
+    }
+
+    fn process_mut(&mut self, packet: &Packet<'_, '_>) {
+        let decoded = DecodedPacket::from(&self.version, false, packet);
+        match decoded.payload {
+            DecodedPacketPayload::OnArenaStateReceived { players, .. } => {
+                for player in players.iter() {
+                    let entity_id: u32 = player.entity_id.try_into().unwrap();
+                    if self.ships_by_id.contains_key(&entity_id) {
+                        continue;
+                    }
+                    self.ships_by_id.insert(entity_id, self.export.ships.len());
+                    self.export.ships.push(ShipPath {
+                        entity_id,
+                        player_name: player.username.clone(),
+                        team_id: player.team_id,
+                        keyframes: vec![],
+                    });
+                }
+            }
+            DecodedPacketPayload::Position(pos) => {
+                self.add_keyframe(decoded.clock, pos.pid, &pos.position, &pos.rotation);
+            }
+            DecodedPacketPayload::PlayerOrientation(orientation) if orientation.parent_id == 0 => {
+                self.add_keyframe(
+                    decoded.clock,
+                    orientation.pid,
+                    &orientation.position,
+                    &orientation.rotation,
+                );
+            }
+            _ => {}
+        }
+    }
+}
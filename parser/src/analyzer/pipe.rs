@@ -0,0 +1,168 @@
+//! Streams a replay's decoded packets to another program, so analyzers can be written in
+//! any language.
+//!
+//! # Protocol
+//!
+//! The stream is a sequence of frames. Each frame is a 4-byte big-endian length followed by
+//! that many bytes of a message encoded as JSON or, with the `msgpack` feature, MessagePack.
+//! Every message is an object whose `type` field says what it holds:
+//!
+//! 1. `{"type": "meta", "meta": {...}}`, once: the replay's metadata, as in the replay file
+//! 2. `{"type": "packet", "packet": {...}}` for every packet, in order: the packet as
+//!    [DecodedPacket] serializes it, i.e. `packet_type`, `clock` and `payload`
+//! 3. `{"type": "finish"}`, once the whole replay has been parsed
+//!
+//! The stream then ends. An analyzer which stops reading early, e.g. because it has found
+//! what it was looking for, stops the stream without failing the parse.
+
+use std::cell::RefCell;
+use std::io::Write;
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::analyzer::decoder::DecodedPacket;
+use crate::packet2::Packet;
+
+use super::analyzer::{AnalyzerMut, AnalyzerMutBuilder};
+
+/// How messages are encoded within frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeFormat {
+    Json,
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message<'a, 'replay, 'argtype, 'rawpacket> {
+    Meta {
+        meta: &'a crate::ReplayMeta,
+    },
+    Packet {
+        packet: &'a DecodedPacket<'replay, 'argtype, 'rawpacket>,
+    },
+    Finish,
+}
+
+pub struct PipeBuilder {
+    output: RefCell<Option<Box<dyn Write>>>,
+    format: PipeFormat,
+}
+
+impl PipeBuilder {
+    /// Streams to `output`, e.g. a child process's stdin. The output is closed once the
+    /// replay has been parsed, so a builder can only be used for one replay.
+    pub fn new<W: Write + 'static>(output: W, format: PipeFormat) -> Self {
+        Self {
+            output: RefCell::new(Some(Box::new(std::io::BufWriter::new(output)))),
+            format,
+        }
+    }
+}
+
+impl AnalyzerMutBuilder for PipeBuilder {
+    fn build(&self, meta: &crate::ReplayMeta) -> Box<dyn AnalyzerMut> {
+        let version = crate::version::Version::from_client_exe(&meta.clientVersionFromExe);
+        let mut pipe = Pipe {
+            output: self.output.borrow_mut().take(),
+            format: self.format,
+            version,
+        };
+        if pipe.output.is_none() {
+            warn!("pipe output was already used for another replay");
+        }
+        pipe.send(&Message::Meta { meta });
+        Box::new(pipe)
+    }
+}
+
+struct Pipe {
+    /// `None` once the reader has gone away
+    output: Option<Box<dyn Write>>,
+    format: PipeFormat,
+    version: crate::version::Version,
+}
+
+impl Pipe {
+    fn send(&mut self, message: &Message<'_, '_, '_, '_>) {
+        let output = match self.output.as_mut() {
+            Some(output) => output,
+            None => return,
+        };
+        let encoded = match self.format {
+            PipeFormat::Json => serde_json::to_vec(message).expect("failed to encode message"),
+            #[cfg(feature = "msgpack")]
+            PipeFormat::MsgPack => {
+                rmp_serde::to_vec_named(message).expect("failed to encode message")
+            }
+        };
+        let result = output
+            .write_all(&(encoded.len() as u32).to_be_bytes())
+            .and_then(|_| output.write_all(&encoded));
+        if let Err(e) = result {
+            warn!(%e, "pipe reader went away, no longer sending packets");
+            self.output = None;
+        }
+    }
+}
+
+impl AnalyzerMut for Pipe {
+    fn finish(&mut self) {
+        self.send(&Message::Finish);
+        if let Some(output) = self.output.as_mut() {
+            if let Err(e) = output.flush() {
+                warn!(%e, "failed to flush pipe");
+            }
+        }
+        // Close the output so the reader sees the end of the stream
+        self.output = None;
+    }
+
+    fn process_mut(&mut self, packet: &Packet<'_, '_>) {
+        let decoded = DecodedPacket::from(&self.version, false, packet);
+        self.send(&Message::Packet { packet: &decoded });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::convert::TryInto;
+    use std::rc::Rc;
+
+    /// Writes into a buffer the test can still read once the pipe has taken the writer
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_framing() {
+        let buffer = SharedBuffer::default();
+        let builder = PipeBuilder::new(buffer.clone(), PipeFormat::Json);
+        let mut pipe = builder.build(&crate::testkit::minimal_meta("0,10,9,0"));
+        pipe.finish();
+
+        let data = buffer.0.borrow();
+        let mut frames = vec![];
+        let mut rest = &data[..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let message: serde_json::Value = serde_json::from_slice(&rest[4..4 + len]).unwrap();
+            frames.push(message["type"].as_str().unwrap().to_string());
+            rest = &rest[4 + len..];
+        }
+        assert_eq!(frames, vec!["meta", "finish"]);
+    }
+}
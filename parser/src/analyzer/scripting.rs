@@ -0,0 +1,235 @@
+//! Runs user-provided [Rhai](https://rhai.rs) scripts against a replay, for custom analysis
+//! without recompiling. A script defines any of these handlers, each of which receives an
+//! object map describing the event:
+//!
+//! - `on_start(battle)`: `player`, `map`, `game_type`, `version`, `players` (an array of
+//!   `name`, `entity_id`, `team_id` maps)
+//! - `on_kill(kill)`: `clock`, `killer`, `killer_name`, `victim`, `victim_name`, `cause`
+//! - `on_chat(chat)`: `clock`, `sender_id`, `sender`, `audience`, `message`
+//! - `on_position(position)`: `clock`, `entity_id`, `x`, `y`, `z`, `yaw`
+//! - `on_finish()`
+//!
+//! Global statements run once before the replay is parsed. Since handlers can't see a
+//! script's global variables, state is kept in `this`, which is the same object map for
+//! every handler call:
+//!
+//! ```rhai
+//! fn on_start(battle) {
+//!     this.kills = 0;
+//! }
+//!
+//! fn on_kill(kill) {
+//!     this.kills += 1;
+//!     print(`${kill.killer_name} sunk ${kill.victim_name}`);
+//! }
+//!
+//! fn on_finish() {
+//!     print(`${this.kills} ships were sunk`);
+//! }
+//! ```
+//!
+//! Requires the `scripting` feature.
+
+use std::collections::HashMap;
+
+use rhai::{Array, CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use tracing::warn;
+
+use crate::analyzer::decoder::{DecodedPacket, DecodedPacketPayload};
+use crate::error::ErrorKind;
+use crate::packet2::Packet;
+
+use super::analyzer::{AnalyzerMut, AnalyzerMutBuilder};
+
+const HANDLERS: [&str; 5] = ["on_start", "on_kill", "on_chat", "on_position", "on_finish"];
+
+pub struct ScriptBuilder {
+    ast: AST,
+}
+
+impl ScriptBuilder {
+    /// Compiles the script, failing if it has a syntax error
+    pub fn new(source: &str) -> Result<Self, ErrorKind> {
+        let ast = Engine::new()
+            .compile(source)
+            .map_err(|e| ErrorKind::ScriptFailure(e.to_string()))?;
+        Ok(Self { ast })
+    }
+
+    pub fn from_file(path: &std::path::Path) -> Result<Self, ErrorKind> {
+        let source = std::fs::read_to_string(path)?;
+        Self::new(&source)
+    }
+}
+
+impl AnalyzerMutBuilder for ScriptBuilder {
+    fn build(&self, meta: &crate::ReplayMeta) -> Box<dyn AnalyzerMut> {
+        let version = crate::version::Version::from_client_exe(&meta.clientVersionFromExe);
+        let handlers = self
+            .ast
+            .iter_functions()
+            .map(|function| function.name)
+            .filter(|name| HANDLERS.contains(name))
+            .map(|name| name.to_string())
+            .collect();
+        let mut script = Script {
+            engine: Engine::new(),
+            ast: self.ast.clone(),
+            scope: Scope::new(),
+            state: Dynamic::from(Map::new()),
+            handlers,
+            version,
+            meta: meta.clone(),
+            started: false,
+            usernames: HashMap::new(),
+            ship_names: HashMap::new(),
+        };
+        if let Err(e) = script
+            .engine
+            .run_ast_with_scope(&mut script.scope, &script.ast)
+        {
+            warn!(%e, "script failed to initialize");
+        }
+        Box::new(script)
+    }
+}
+
+struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    /// Bound to `this` in every handler
+    state: Dynamic,
+    handlers: Vec<String>,
+    version: crate::version::Version,
+    meta: crate::ReplayMeta,
+    started: bool,
+    /// Avatar ID -> username
+    usernames: HashMap<i64, String>,
+    /// Entity ID -> username
+    ship_names: HashMap<i64, String>,
+}
+
+impl Script {
+    fn call(&mut self, handler: &str, args: Vec<Dynamic>) {
+        if !self.handlers.iter().any(|name| name == handler) {
+            return;
+        }
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            handler,
+            args,
+        );
+        if let Err(e) = result {
+            warn!(handler, %e, "script handler failed");
+        }
+    }
+
+    fn ship_name(&self, entity_id: i64) -> Dynamic {
+        match self.ship_names.get(&entity_id) {
+            Some(name) => Dynamic::from(name.clone()),
+            None => Dynamic::UNIT,
+        }
+    }
+}
+
+fn map(fields: Vec<(&str, Dynamic)>) -> Dynamic {
+    let map: Map = fields
+        .into_iter()
+        .map(|(name, value)| (name.into(), value))
+        .collect();
+    Dynamic::from(map)
+}
+
+impl AnalyzerMut for Script {
+    fn finish(&mut self) {
+        self.call("on_finish", vec![]);
+    }
+
+    fn process_mut(&mut self, packet: &Packet<'_, '_>) {
+        let decoded = DecodedPacket::from(&self.version, true, packet);
+        let clock = Dynamic::from(decoded.clock as f64);
+        match decoded.payload {
+            DecodedPacketPayload::OnArenaStateReceived { players, .. } => {
+                let mut battle_players = Array::new();
+                for player in players.iter() {
+                    self.usernames
+                        .insert(player.avatar_id, player.username.clone());
+                    self.ship_names
+                        .insert(player.entity_id, player.username.clone());
+                    battle_players.push(map(vec![
+                        ("name", Dynamic::from(player.username.clone())),
+                        ("entity_id", Dynamic::from(player.entity_id)),
+                        ("team_id", Dynamic::from(player.team_id)),
+                    ]));
+                }
+                // The arena state can be sent again, e.g. when a player reconnects
+                if !self.started {
+                    self.started = true;
+                    let battle = map(vec![
+                        ("player", Dynamic::from(self.meta.playerName.clone())),
+                        ("map", Dynamic::from(self.meta.mapDisplayName.clone())),
+                        ("game_type", Dynamic::from(self.meta.gameType.clone())),
+                        (
+                            "version",
+                            Dynamic::from(self.meta.clientVersionFromExe.clone()),
+                        ),
+                        ("players", Dynamic::from(battle_players)),
+                    ]);
+                    self.call("on_start", vec![battle]);
+                }
+            }
+            DecodedPacketPayload::ShipDestroyed {
+                killer,
+                victim,
+                cause,
+            } => {
+                let kill = map(vec![
+                    ("clock", clock),
+                    ("killer", Dynamic::from(killer as i64)),
+                    ("killer_name", self.ship_name(killer as i64)),
+                    ("victim", Dynamic::from(victim as i64)),
+                    ("victim_name", self.ship_name(victim as i64)),
+                    ("cause", Dynamic::from(format!("{:?}", cause))),
+                ]);
+                self.call("on_kill", vec![kill]);
+            }
+            DecodedPacketPayload::Chat {
+                sender_id,
+                audience,
+                message,
+                ..
+            } => {
+                let sender = match self.usernames.get(&(sender_id as i64)) {
+                    Some(name) => Dynamic::from(name.clone()),
+                    None => Dynamic::UNIT,
+                };
+                let chat = map(vec![
+                    ("clock", clock),
+                    ("sender_id", Dynamic::from(sender_id as i64)),
+                    ("sender", sender),
+                    ("audience", Dynamic::from(audience.to_string())),
+                    ("message", Dynamic::from(message.to_string())),
+                ]);
+                self.call("on_chat", vec![chat]);
+            }
+            DecodedPacketPayload::Position(position) => {
+                let position = map(vec![
+                    ("clock", clock),
+                    ("entity_id", Dynamic::from(position.pid as i64)),
+                    ("x", Dynamic::from(position.position.x as f64)),
+                    ("y", Dynamic::from(position.position.y as f64)),
+                    ("z", Dynamic::from(position.position.z as f64)),
+                    ("yaw", Dynamic::from(position.rotation.yaw as f64)),
+                ]);
+                self.call("on_position", vec![position]);
+            }
+            _ => {}
+        }
+    }
+}
@@ -62,6 +62,13 @@ struct Summary {
 
 impl AnalyzerMut for Summary {
     fn finish(&mut self) {
+        let count = |ribbon| self.ribbons.get(&ribbon).cloned().unwrap_or_default();
+        println!(
+            "Citadels: {}, fires set: {}, torpedo hits: {}",
+            count(Ribbon::Citadel),
+            count(Ribbon::SetFire),
+            count(Ribbon::TorpedoHit)
+        );
         for (ribbon, count) in self.ribbons.iter() {
             println!("{:?}: {}", ribbon, count);
         }
@@ -1,16 +1,43 @@
+use crate::analyzer::decoder::DecodedPacketPayloadKind;
 use crate::analyzer::*;
-use crate::packet2::{Entity, Packet};
+use crate::game_mode::GameModeKind;
+use crate::packet2::{Entity, Packet, PacketType};
 use std::cell::{RefCell, RefMut};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::rc::Rc;
 
 use super::analyzer::{AnalyzerMut, AnalyzerMutBuilder};
 
+/// How many packets of one kind were seen, and how many of those were decoded into
+/// something specific or couldn't be parsed at all
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketCoverage {
+    pub observed: usize,
+    pub decoded: usize,
+    pub unknown: usize,
+}
+
+impl PacketCoverage {
+    pub fn add(&mut self, other: &PacketCoverage) {
+        self.observed += other.observed;
+        self.decoded += other.decoded;
+        self.unknown += other.unknown;
+    }
+}
+
 pub struct SurveyStats {
     pub total_packets: usize,
     pub invalid_packets: usize,
+    /// Number of calls whose arguments couldn't be decoded, by method name
+    pub partial_decodes: BTreeMap<String, usize>,
     pub audits: Vec<String>,
     pub date_time: String,
+    pub game_mode: GameModeKind,
+    /// The replay's game version, e.g. `0,12,8,0`
+    pub version: String,
+    /// Coverage by packet kind, with entity methods counted by method name, e.g.
+    /// `EntityMethod::onBattleEnd`
+    pub coverage: BTreeMap<String, PacketCoverage>,
 }
 
 impl SurveyStats {
@@ -18,8 +45,12 @@ impl SurveyStats {
         Self {
             total_packets: 0,
             invalid_packets: 0,
+            partial_decodes: BTreeMap::new(),
             audits: vec![],
             date_time: "".to_string(),
+            game_mode: GameModeKind::Unknown,
+            version: "".to_string(),
+            coverage: BTreeMap::new(),
         }
     }
 }
@@ -44,6 +75,8 @@ impl AnalyzerMutBuilder for SurveyBuilder {
         {
             let mut stats: RefMut<_> = self.stats.borrow_mut();
             stats.date_time = meta.dateTime.clone();
+            stats.game_mode = GameModeKind::from_meta(meta);
+            stats.version = meta.clientVersionFromExe.clone();
         }
         Box::new(Survey {
             skip_decoder: self.skip_decoder,
@@ -69,9 +102,11 @@ impl AnalyzerMut for Survey {
     fn process_mut(&mut self, packet: &Packet<'_, '_>) {
         // Do stuff and such
         let mut stats: RefMut<_> = self.stats.borrow_mut();
+        let mut decoded_kind = None;
         if !self.skip_decoder {
             //let decoded = self.decoder.process(packet);
             let decoded = decoder::DecodedPacket::from(&self.version, true, packet);
+            decoded_kind = Some(decoded.payload.kind());
             match &decoded.payload {
                 crate::analyzer::decoder::DecodedPacketPayload::Audit(s) => {
                     stats.audits.push(s.to_string());
@@ -80,12 +115,49 @@ impl AnalyzerMut for Survey {
             }
         }
 
+        let coverage = stats.coverage.entry(packet_name(packet)).or_default();
+        coverage.observed += 1;
+        match &packet.payload {
+            PacketType::Unknown(_) | PacketType::Invalid(_) | PacketType::PartialDecode(_) => {
+                coverage.unknown += 1;
+            }
+            _ => {
+                // The decoder passes packets it doesn't interpret through as they are
+                let passed_through = matches!(
+                    decoded_kind,
+                    None | Some(DecodedPacketPayloadKind::EntityMethod)
+                        | Some(DecodedPacketPayloadKind::Audit)
+                );
+                if !passed_through {
+                    coverage.decoded += 1;
+                }
+            }
+        }
+
         match &packet.payload {
             crate::packet2::PacketType::Invalid(_) => {
                 stats.invalid_packets += 1;
             }
+            crate::packet2::PacketType::PartialDecode(partial) => {
+                *stats
+                    .partial_decodes
+                    .entry(partial.method.to_string())
+                    .or_default() += 1;
+            }
             _ => {}
         }
         stats.total_packets += 1;
     }
 }
+
+/// The name packets are counted under in [SurveyStats::coverage]
+fn packet_name(packet: &Packet<'_, '_>) -> String {
+    match &packet.payload {
+        PacketType::EntityMethod(method) => format!("EntityMethod::{}", method.method),
+        PacketType::CustomMethod(method) => format!("EntityMethod::{}", method.method),
+        PacketType::PartialDecode(method) => format!("EntityMethod::{}", method.method),
+        PacketType::Unknown(_) => format!("Unknown(0x{:x})", packet.packet_type),
+        PacketType::Invalid(_) => format!("Invalid(0x{:x})", packet.packet_type),
+        payload => format!("{:?}", payload.kind()),
+    }
+}
@@ -0,0 +1,397 @@
+use crate::analyzer::battle_controller::{Relation, Teams};
+use crate::analyzer::decoder::{DecodedPacket, DecodedPacketPayload};
+use crate::packet2::{Packet, Vec3};
+use serde::Serialize;
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::rc::Rc;
+
+use super::analyzer::{AnalyzerMut, AnalyzerMutBuilder};
+
+/// How far (in world units) a shell's aim point may be from a ship for the ship to be
+/// considered the target of that shell.
+const MAX_AIM_POINT_DISTANCE: f32 = 2.0;
+
+/// Maximum angle (in radians) between the camera's line of sight and a ship for the ship
+/// to be considered the camera's target.
+const MAX_CAMERA_AIM_ANGLE: f32 = 0.05;
+
+/// Minimum time, in seconds, between two camera-derived samples.
+const CAMERA_SAMPLE_INTERVAL: f32 = 1.0;
+
+/// Ships whose position hasn't been updated for this many seconds are assumed to be
+/// no longer visible, and are not considered as alternative targets.
+const VISIBILITY_TIMEOUT: f32 = 3.0;
+
+/// An alternative target is "closer" if it is at most this fraction of the
+/// distance to the selected target.
+const CLOSER_TARGET_RATIO: f32 = 0.75;
+
+/// An alternative target has "lower health" if it has at most this fraction of the
+/// selected target's remaining health.
+const LOWER_HEALTH_RATIO: f32 = 0.5;
+
+/// A ship is showing its broadside if the angle between its heading and the line of
+/// fire is within this many radians of 90 degrees.
+const BROADSIDE_TOLERANCE: f32 = std::f32::consts::PI / 8.0;
+
+/// Where the target of a sample was inferred from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AimSource {
+    /// The aim point of a main battery salvo fired by the player.
+    Salvo,
+    /// The player's camera line of sight.
+    Camera,
+}
+
+/// Describes a potentially better target which was available when the player chose
+/// their target.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum TargetFlag {
+    /// An enemy was considerably closer than the selected target.
+    CloserTargetAvailable { entity_id: u32, distance: f32 },
+    /// An enemy at the same range or closer had considerably less health remaining.
+    LowerHealthTargetAvailable { entity_id: u32, health: f32 },
+    /// The selected target was angled, while an enemy at the same range or closer was
+    /// showing its broadside.
+    BroadsideTargetAvailable { entity_id: u32 },
+}
+
+/// A single point in the target selection timeline.
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetSample {
+    /// Game clock the sample was taken at
+    pub clock: f32,
+    pub source: AimSource,
+    /// Ship ID of the targeted ship
+    pub target: u32,
+    /// Username of the player controlling the targeted ship
+    pub target_name: String,
+    /// Distance from the player to the target, in world units
+    pub distance: f32,
+    /// Health the target had remaining at the time of the sample
+    pub target_health: f32,
+    /// Whether the target was showing its broadside to the player
+    pub target_broadside: bool,
+    /// Better targets which were available. Empty if the target selection was reasonable.
+    pub flags: Vec<TargetFlag>,
+}
+
+impl TargetSample {
+    /// Returns true if a better target than the selected one was available.
+    pub fn is_questionable(&self) -> bool {
+        !self.flags.is_empty()
+    }
+}
+
+/// The list of targets the recording player aimed at over the course of a game.
+#[derive(Default)]
+pub struct TargetTimeline {
+    pub samples: Vec<TargetSample>,
+}
+
+impl TargetTimeline {
+    pub fn new() -> Self {
+        Self { samples: vec![] }
+    }
+
+    /// Returns the samples where a better target than the selected one was available.
+    pub fn questionable(&self) -> impl Iterator<Item = &TargetSample> {
+        self.samples
+            .iter()
+            .filter(|sample| sample.is_questionable())
+    }
+}
+
+pub struct TargetSelectionBuilder {
+    timeline: Rc<RefCell<TargetTimeline>>,
+}
+
+impl TargetSelectionBuilder {
+    pub fn new(timeline: Rc<RefCell<TargetTimeline>>) -> Self {
+        Self { timeline }
+    }
+}
+
+impl AnalyzerMutBuilder for TargetSelectionBuilder {
+    fn build(&self, meta: &crate::ReplayMeta) -> Box<dyn AnalyzerMut> {
+        let version = crate::version::Version::from_client_exe(&meta.clientVersionFromExe);
+        Box::new(TargetSelection {
+            player_name: meta.playerName.clone(),
+            self_id: None,
+            teams: Teams::default(),
+            ships: HashMap::new(),
+            last_camera_sample: None,
+            timeline: self.timeline.clone(),
+            version,
+        })
+    }
+}
+
+struct TrackedShip {
+    name: String,
+    health: f32,
+    alive: bool,
+    position: Option<Vec3>,
+    /// Heading of the ship in radians
+    yaw: f32,
+    last_seen: f32,
+}
+
+struct TargetSelection {
+    player_name: String,
+    self_id: Option<u32>,
+    teams: Teams,
+    ships: HashMap<u32, TrackedShip>,
+    last_camera_sample: Option<f32>,
+    timeline: Rc<RefCell<TargetTimeline>>,
+    version: crate::version::Version,
+}
+
+fn horizontal_distance(a: &Vec3, b: &Vec3) -> f32 {
+    ((a.x - b.x).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+/// Angle from the +Z axis, in radians, of the horizontal vector from `from` to `to`.
+fn bearing(from: &Vec3, to: &Vec3) -> f32 {
+    (to.x - from.x).atan2(to.z - from.z)
+}
+
+/// Smallest absolute difference between two angles, in radians.
+fn angle_between(a: f32, b: f32) -> f32 {
+    let diff = (a - b).rem_euclid(2.0 * std::f32::consts::PI);
+    diff.min(2.0 * std::f32::consts::PI - diff)
+}
+
+/// Returns true if a ship at `position` with the given heading is showing its
+/// broadside to an observer at `observer`.
+fn is_broadside(position: &Vec3, yaw: f32, observer: &Vec3) -> bool {
+    let line_of_fire = bearing(position, observer);
+    // Broadside is when the line of fire is perpendicular to the heading, regardless of
+    // which side of the ship is exposed.
+    let angle = angle_between(yaw, line_of_fire);
+    (angle - std::f32::consts::FRAC_PI_2).abs() <= BROADSIDE_TOLERANCE
+}
+
+impl TargetSelection {
+    fn self_position(&self) -> Option<&Vec3> {
+        self.ships.get(&self.self_id?)?.position.as_ref()
+    }
+
+    /// Returns the visible, living enemies of the recording player along with their
+    /// position and distance from `origin`. Ships whose position isn't known yet are left
+    /// out.
+    fn visible_enemies(&self, clock: f32, origin: &Vec3) -> Vec<(u32, &TrackedShip, &Vec3, f32)> {
+        self.ships
+            .iter()
+            .filter(|(id, ship)| {
+                ship.alive
+                    && self.teams.relation(**id) == Relation::Enemy
+                    && clock - ship.last_seen <= VISIBILITY_TIMEOUT
+            })
+            .filter_map(|(id, ship)| {
+                let position = ship.position.as_ref()?;
+                Some((*id, ship, position, horizontal_distance(origin, position)))
+            })
+            .collect()
+    }
+
+    fn record_sample(&mut self, clock: f32, source: AimSource, target: u32) {
+        let origin = match self.self_position() {
+            Some(position) => position.clone(),
+            None => return,
+        };
+        let enemies = self.visible_enemies(clock, &origin);
+        let (target_ship, target_position, distance) =
+            match enemies.iter().find(|(id, _, _, _)| *id == target) {
+                Some((_, ship, position, distance)) => (*ship, *position, *distance),
+                None => return,
+            };
+        let target_broadside = is_broadside(target_position, target_ship.yaw, &origin);
+
+        let mut flags = vec![];
+        for (id, ship, position, enemy_distance) in enemies.iter() {
+            if *id == target {
+                continue;
+            }
+            if *enemy_distance <= distance * CLOSER_TARGET_RATIO {
+                flags.push(TargetFlag::CloserTargetAvailable {
+                    entity_id: *id,
+                    distance: *enemy_distance,
+                });
+            }
+            if *enemy_distance <= distance {
+                if ship.health <= target_ship.health * LOWER_HEALTH_RATIO {
+                    flags.push(TargetFlag::LowerHealthTargetAvailable {
+                        entity_id: *id,
+                        health: ship.health,
+                    });
+                }
+                if !target_broadside && is_broadside(position, ship.yaw, &origin) {
+                    flags.push(TargetFlag::BroadsideTargetAvailable { entity_id: *id });
+                }
+            }
+        }
+
+        let sample = TargetSample {
+            clock,
+            source,
+            target,
+            target_name: target_ship.name.clone(),
+            distance,
+            target_health: target_ship.health,
+            target_broadside,
+            flags,
+        };
+        let mut timeline: RefMut<_> = self.timeline.borrow_mut();
+        timeline.samples.push(sample);
+    }
+
+    /// Finds the enemy closest to the given aim point.
+    fn target_at(&self, clock: f32, aim_point: &Vec3) -> Option<u32> {
+        self.visible_enemies(clock, aim_point)
+            .into_iter()
+            .filter(|(_, _, _, distance)| *distance <= MAX_AIM_POINT_DISTANCE)
+            .min_by(|a, b| a.3.total_cmp(&b.3))
+            .map(|(id, _, _, _)| id)
+    }
+
+    /// Finds the enemy closest to the camera's line of sight. The camera orbits the
+    /// player's ship, so the line of sight runs from the camera through the ship.
+    fn target_along(&self, clock: f32, camera: &Vec3) -> Option<u32> {
+        let origin = self.self_position()?;
+        let line_of_sight = bearing(camera, origin);
+        self.visible_enemies(clock, origin)
+            .into_iter()
+            .map(|(id, _, position, _)| {
+                (id, angle_between(line_of_sight, bearing(origin, position)))
+            })
+            .filter(|(_, angle)| *angle <= MAX_CAMERA_AIM_ANGLE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id)
+    }
+
+    fn update_position(&mut self, clock: f32, entity_id: u32, position: &Vec3, yaw: f32) {
+        if let Some(ship) = self.ships.get_mut(&entity_id) {
+            ship.position = Some(position.clone());
+            ship.yaw = yaw;
+            ship.last_seen = clock;
+        }
+    }
+}
+
+impl AnalyzerMut for TargetSelection {
+    fn finish(&mut self) {}
+
+    fn process_mut(&mut self, packet: &Packet<'_, '_>) {
+        let decoded = DecodedPacket::from(&self.version, false, packet);
+        match decoded.payload {
+            DecodedPacketPayload::OnArenaStateReceived { players, .. } => {
+                for player in players.iter() {
+                    // Corrupt replays may have IDs which can't be entity IDs
+                    let entity_id: u32 = match player.entity_id.try_into() {
+                        Ok(entity_id) => entity_id,
+                        Err(_) => continue,
+                    };
+                    if player.username == self.player_name {
+                        self.self_id = Some(entity_id);
+                    }
+                    self.ships.insert(
+                        entity_id,
+                        TrackedShip {
+                            name: player.username.clone(),
+                            health: player.max_health as f32,
+                            alive: true,
+                            position: None,
+                            yaw: 0.0,
+                            last_seen: 0.0,
+                        },
+                    );
+                }
+                self.teams = Teams::from_members(
+                    self.self_id,
+                    players.iter().filter_map(|player| {
+                        Some((player.entity_id.try_into().ok()?, player.team_id as u32))
+                    }),
+                );
+            }
+            DecodedPacketPayload::Position(pos) => {
+                self.update_position(decoded.clock, pos.pid, &pos.position, pos.rotation.yaw);
+            }
+            DecodedPacketPayload::PlayerOrientation(orientation) if orientation.parent_id == 0 => {
+                self.update_position(
+                    decoded.clock,
+                    orientation.pid,
+                    &orientation.position,
+                    orientation.rotation.yaw,
+                );
+            }
+            DecodedPacketPayload::EntityProperty(prop) if prop.property == "health" => {
+                if let Some(ship) = self.ships.get_mut(&prop.entity_id) {
+                    if let Ok(health) = (&prop.value).try_into() {
+                        ship.health = health;
+                    }
+                }
+            }
+            DecodedPacketPayload::ShipDestroyed { victim, .. } => {
+                if let Some(ship) = self.ships.get_mut(&(victim as u32)) {
+                    ship.alive = false;
+                }
+            }
+            DecodedPacketPayload::ArtilleryShots(salvos) => {
+                for salvo in salvos.iter() {
+                    if Some(salvo.owner_id as u32) != self.self_id {
+                        continue;
+                    }
+                    // All shells in a salvo are aimed at the same point (modulo dispersion),
+                    // so the first shell is representative.
+                    if let Some(shot) = salvo.shots.first() {
+                        if let Some(target) = self.target_at(decoded.clock, &shot.target) {
+                            self.record_sample(decoded.clock, AimSource::Salvo, target);
+                        }
+                    }
+                }
+            }
+            DecodedPacketPayload::Camera(camera) => {
+                if self
+                    .last_camera_sample
+                    .map(|last| decoded.clock - last < CAMERA_SAMPLE_INTERVAL)
+                    .unwrap_or(false)
+                {
+                    return;
+                }
+                self.last_camera_sample = Some(decoded.clock);
+                if let Some(target) = self.target_along(decoded.clock, &camera.absolute_position) {
+                    self.record_sample(decoded.clock, AimSource::Camera, target);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_broadside() {
+        let observer = Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        let ship = Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: 10.0,
+        };
+        // Sailing east, perpendicular to the line of fire
+        assert!(is_broadside(&ship, std::f32::consts::FRAC_PI_2, &observer));
+        // Sailing west
+        assert!(is_broadside(&ship, -std::f32::consts::FRAC_PI_2, &observer));
+        // Bow-on, sailing straight at the observer
+        assert!(!is_broadside(&ship, std::f32::consts::PI, &observer));
+    }
+}
@@ -0,0 +1,122 @@
+//! An "edit list" of annotations to overlay on a rendered replay, e.g. to make an
+//! annotated educational video of a battle. Annotations are written by hand as a JSON
+//! file such as:
+//!
+//! ```json
+//! [
+//!     { "start": 95.0, "end": 120.0, "type": "callout", "text": "Push B", "entity_id": 529780 },
+//!     { "start": 130.0, "type": "arrow", "from": [-200.0, 150.0], "to": [-50.0, 40.0] },
+//!     { "start": 130.0, "end": 190.0, "type": "highlight", "entity_id": 529782 }
+//! ]
+//! ```
+//!
+//! Times are on the [GameClock] and positions are in world coordinates (x, z), the same
+//! as in position packets.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::GameClock;
+use crate::error::ErrorKind;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnnotationKind {
+    /// Text shown next to a ship, or at a fixed position if `entity_id` isn't given
+    Callout {
+        text: String,
+        #[serde(default)]
+        entity_id: Option<u32>,
+        #[serde(default)]
+        position: Option<(f32, f32)>,
+    },
+    /// An arrow between two positions, e.g. to show a flanking route
+    Arrow { from: (f32, f32), to: (f32, f32) },
+    /// Draws attention to a ship and the path it takes while the annotation is shown
+    Highlight { entity_id: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    pub start: GameClock,
+    /// When the annotation disappears. Annotations without an end are shown until the end
+    /// of the replay.
+    #[serde(default)]
+    pub end: Option<GameClock>,
+    #[serde(flatten)]
+    pub kind: AnnotationKind,
+}
+
+impl Annotation {
+    pub fn is_active_at(&self, clock: GameClock) -> bool {
+        self.start <= clock && self.end.map_or(true, |end| clock < end)
+    }
+}
+
+/// The annotations of one replay, in the order they start
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Annotations {
+    annotations: Vec<Annotation>,
+}
+
+impl Annotations {
+    pub fn new(mut annotations: Vec<Annotation>) -> Self {
+        annotations.sort_by(|a, b| a.start.0.total_cmp(&b.start.0));
+        Annotations { annotations }
+    }
+
+    /// Loads an annotations file
+    pub fn load(path: &Path) -> Result<Self, ErrorKind> {
+        let file = std::fs::File::open(path)?;
+        let annotations: Vec<Annotation> = serde_json::from_reader(std::io::BufReader::new(file))?;
+        Ok(Self::new(annotations))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Annotation> {
+        self.annotations.iter()
+    }
+
+    /// The annotations to draw on a frame at `clock`
+    pub fn active_at(&self, clock: GameClock) -> impl Iterator<Item = &Annotation> {
+        self.annotations
+            .iter()
+            .take_while(move |annotation| annotation.start <= clock)
+            .filter(move |annotation| annotation.is_active_at(clock))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_annotations() {
+        let json = r#"[
+            { "start": 130.0, "type": "arrow", "from": [-200.0, 150.0], "to": [-50.0, 40.0] },
+            { "start": 95.0, "end": 120.0, "type": "callout", "text": "Push B", "entity_id": 7 },
+            { "start": 130.0, "end": 190.0, "type": "highlight", "entity_id": 8 }
+        ]"#;
+        let annotations = Annotations::new(serde_json::from_str(json).unwrap());
+        assert_eq!(
+            annotations.iter().next().unwrap().kind,
+            AnnotationKind::Callout {
+                text: "Push B".to_string(),
+                entity_id: Some(7),
+                position: None,
+            }
+        );
+
+        let active = |secs: f32| annotations.active_at(GameClock(secs)).count();
+        assert_eq!(active(90.0), 0);
+        assert_eq!(active(100.0), 1);
+        assert_eq!(active(120.0), 0);
+        assert_eq!(active(150.0), 2);
+        assert_eq!(active(600.0), 1);
+    }
+}
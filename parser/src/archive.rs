@@ -0,0 +1,197 @@
+//! Reads replays stored inside of `.zip` and `.tar.zst` archives, so that large replay
+//! corpora don't need to be extracted first. A replay inside of an archive is addressed
+//! with a path like `replays.zip!2022/some.wowsreplay`.
+//!
+//! Reading archives requires the `archives` feature.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::ErrorKind;
+
+/// Separates the archive's path from the path of a file within it
+pub const ARCHIVE_SEPARATOR: char = '!';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    TarZstd,
+}
+
+impl ArchiveKind {
+    /// Determines the archive kind from a path's extension
+    pub fn from_path(path: &Path) -> Option<ArchiveKind> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(ArchiveKind::TarZstd)
+        } else {
+            None
+        }
+    }
+}
+
+/// Splits a path like `replays.zip!inner.wowsreplay` into the archive's path and the
+/// path within the archive. Returns `None` if the path doesn't point into an archive.
+pub fn split_archive_path(path: &Path) -> Option<(PathBuf, String)> {
+    let path = path.to_str()?;
+    let (archive, inner) = path.rsplit_once(ARCHIVE_SEPARATOR)?;
+    let archive = PathBuf::from(archive);
+    ArchiveKind::from_path(&archive)?;
+    Some((archive, inner.to_string()))
+}
+
+/// Reads the file at `inner` from the archive at `archive`
+pub fn read_archived_file(archive: &Path, inner: &str) -> Result<Vec<u8>, ErrorKind> {
+    let mut contents = None;
+    for_each_file(archive, |name, read| {
+        if name == inner {
+            contents = Some(read()?);
+            Ok(false)
+        } else {
+            Ok(true)
+        }
+    })?;
+
+    contents
+        .ok_or_else(|| ErrorKind::Archive(format!("{} not found in {}", inner, archive.display())))
+}
+
+/// Returns the archive paths (e.g. `replays.zip!some.wowsreplay`) of every replay in the
+/// archive, which can be passed to [crate::ReplayFile::from_file].
+///
+/// Note that `.tar.zst` archives can't be randomly accessed, so reading every replay this
+/// way decompresses the archive once per replay. Prefer [for_each_archived_replay] when
+/// processing every replay.
+pub fn archived_replays(archive: &Path) -> Result<Vec<PathBuf>, ErrorKind> {
+    let mut replays = Vec::new();
+    for_each_file(archive, |name, _read| {
+        if is_replay(name) {
+            replays.push(PathBuf::from(format!(
+                "{}{}{}",
+                archive.display(),
+                ARCHIVE_SEPARATOR,
+                name
+            )));
+        }
+        Ok(true)
+    })?;
+    Ok(replays)
+}
+
+/// Calls `f` with the archive path and contents of every replay in the archive, reading
+/// the archive only once
+pub fn for_each_archived_replay<F>(archive: &Path, mut f: F) -> Result<(), ErrorKind>
+where
+    F: FnMut(PathBuf, Vec<u8>),
+{
+    for_each_file(archive, |name, read| {
+        if is_replay(name) {
+            let path = PathBuf::from(format!(
+                "{}{}{}",
+                archive.display(),
+                ARCHIVE_SEPARATOR,
+                name
+            ));
+            f(path, read()?);
+        }
+        Ok(true)
+    })
+}
+
+fn is_replay(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(".wowsreplay")
+}
+
+/// Lazily reads the contents of an archived file
+type ReadFile<'a> = &'a mut dyn FnMut() -> Result<Vec<u8>, ErrorKind>;
+
+/// Visits every file in the archive in order, until `visit` returns `false`
+#[cfg(feature = "archives")]
+fn for_each_file<F>(archive: &Path, mut visit: F) -> Result<(), ErrorKind>
+where
+    F: FnMut(&str, ReadFile<'_>) -> Result<bool, ErrorKind>,
+{
+    use std::io::Read;
+
+    let kind = ArchiveKind::from_path(archive).ok_or_else(|| {
+        ErrorKind::Archive(format!("{} is not a known archive type", archive.display()))
+    })?;
+    let file = std::fs::File::open(archive)?;
+
+    match kind {
+        ArchiveKind::Zip => {
+            let zip_error = |e: zip::result::ZipError| ErrorKind::Archive(e.to_string());
+            let mut zip = zip::ZipArchive::new(std::io::BufReader::new(file)).map_err(zip_error)?;
+            for idx in 0..zip.len() {
+                let mut entry = zip.by_index(idx).map_err(zip_error)?;
+                if !entry.is_file() {
+                    continue;
+                }
+                let name = entry.name().to_string();
+                let mut read = || {
+                    let mut contents = Vec::with_capacity(entry.size() as usize);
+                    entry.read_to_end(&mut contents)?;
+                    Ok(contents)
+                };
+                if !visit(&name, &mut read)? {
+                    break;
+                }
+            }
+        }
+        ArchiveKind::TarZstd => {
+            let decoder = zstd::stream::read::Decoder::new(file)?;
+            let mut tar = tar::Archive::new(decoder);
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry.path()?.to_string_lossy().into_owned();
+                let mut read = || {
+                    let mut contents = Vec::with_capacity(entry.size() as usize);
+                    entry.read_to_end(&mut contents)?;
+                    Ok(contents)
+                };
+                if !visit(&name, &mut read)? {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "archives"))]
+fn for_each_file<F>(archive: &Path, _visit: F) -> Result<(), ErrorKind>
+where
+    F: FnMut(&str, ReadFile<'_>) -> Result<bool, ErrorKind>,
+{
+    Err(ErrorKind::Archive(format!(
+        "cannot read {}: wows-replays was built without the `archives` feature",
+        archive.display()
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_archive_path() {
+        assert_eq!(
+            split_archive_path(Path::new("corpus/2022.zip!ranked/a.wowsreplay")),
+            Some((
+                PathBuf::from("corpus/2022.zip"),
+                "ranked/a.wowsreplay".to_string()
+            ))
+        );
+        assert_eq!(
+            split_archive_path(Path::new("corpus.tar.zst!a.wowsreplay")),
+            Some((PathBuf::from("corpus.tar.zst"), "a.wowsreplay".to_string()))
+        );
+        assert_eq!(split_archive_path(Path::new("wow!.wowsreplay")), None);
+        assert_eq!(split_archive_path(Path::new("a.wowsreplay")), None);
+    }
+}
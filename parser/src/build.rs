@@ -0,0 +1,465 @@
+//! Resolves a ship's effective stats from its base GameParams stats and the modernizations
+//! and captain skills equipped in a replay.
+
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analyzer::{
+        battle_controller::{ShipConfig, VehicleEntity},
+        decoder::Consumable,
+    },
+    game_params::{
+        AbilityCategory, CrewSkill, CrewSkillModifier, Param, ShipStats, Species, Vehicle,
+    },
+    resource_loader::ResourceLoader,
+};
+
+/// The consumable type of Repair Party
+const HEAL_CONSUMABLE_TYPE: &str = "regenCrew";
+
+/// The modifier which scales the reload time of all consumables
+const CONSUMABLE_RELOAD_MODIFIER: &str = "ConsumableReloadTime";
+
+/// A stat which modifiers can affect, along with the GameParams modifier names which
+/// scale it.
+struct ModifiedStat {
+    modifiers: &'static [&'static str],
+    stat: fn(&mut ShipStats) -> &mut Option<f32>,
+}
+
+const MODIFIED_STATS: &[ModifiedStat] = &[
+    ModifiedStat {
+        modifiers: &["visibilityDistCoeff"],
+        stat: |stats| &mut stats.concealment_by_sea,
+    },
+    ModifiedStat {
+        modifiers: &["visibilityDistCoeff"],
+        stat: |stats| &mut stats.concealment_by_air,
+    },
+    ModifiedStat {
+        modifiers: &["GMMaxDist"],
+        stat: |stats| &mut stats.main_battery_range,
+    },
+    ModifiedStat {
+        modifiers: &["GMShotDelay"],
+        stat: |stats| &mut stats.main_battery_reload,
+    },
+    ModifiedStat {
+        modifiers: &["GSMaxDist"],
+        stat: |stats| &mut stats.secondary_range,
+    },
+    ModifiedStat {
+        modifiers: &["GSShotDelay"],
+        stat: |stats| &mut stats.secondary_reload,
+    },
+    ModifiedStat {
+        modifiers: &["torpedoReloadTime", "launcherCoeffDelay"],
+        stat: |stats| &mut stats.torpedo_reload,
+    },
+];
+
+/// A ship's health pool as configured in a replay, for comparing the damage it took against
+/// what it could take
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Survivability {
+    max_health: f32,
+    torpedo_protection: f32,
+    heal_charges: Option<isize>,
+}
+
+impl Survivability {
+    pub fn max_health(&self) -> f32 {
+        self.max_health
+    }
+
+    /// Fraction of torpedo damage the torpedo belt absorbs, from 0 to 1
+    pub fn torpedo_protection(&self) -> f32 {
+        self.torpedo_protection
+    }
+
+    /// Charges of Repair Party, -1 if unlimited. `None` if the ship has no Repair Party.
+    pub fn heal_charges(&self) -> Option<isize> {
+        self.heal_charges
+    }
+}
+
+/// A consumable as equipped on a ship, for tracking when it's available during a battle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsumableSpec {
+    pub consumable: Consumable,
+    /// `None` if unlimited
+    pub charges: Option<usize>,
+    /// Seconds the consumable stays active
+    pub work_time: f32,
+    /// Seconds the consumable takes to reload after it stops working
+    pub reload_time: f32,
+    /// See [AbilityCategory::title_id]
+    pub title_id: String,
+    /// See [AbilityCategory::description_id]
+    pub description_id: String,
+}
+
+/// Applies modernization and captain skill modifiers to a ship's base stats.
+pub struct BuildResolver<'res, G> {
+    game_resources: &'res G,
+}
+
+impl<'res, G> BuildResolver<'res, G>
+where
+    G: ResourceLoader,
+{
+    pub fn new(game_resources: &'res G) -> Self {
+        Self { game_resources }
+    }
+
+    /// Resolves the stats of a ship with the given modernization IDs and captain skills.
+    /// Returns `None` if the ship has no base stats or species in the GameParams.
+    pub fn resolve(
+        &self,
+        ship: &Param,
+        modernizations: &[u32],
+        skills: &[&CrewSkill],
+    ) -> Option<ShipStats> {
+        let species = ship.species()?;
+        let mut stats = ship.data().vehicle_ref()?.base_stats()?.clone();
+
+        let modernizations: Vec<_> = modernizations
+            .iter()
+            .filter_map(|id| self.game_resources.game_param_by_id(*id))
+            .collect();
+        let modifiers = modernizations
+            .iter()
+            .filter_map(|param| param.data().modernization_ref())
+            .flat_map(|modernization| modernization.modifiers().iter())
+            // Triggered skills only apply some of the time, so only their passive
+            // modifiers are taken into account
+            .chain(
+                skills
+                    .iter()
+                    .filter_map(|skill| skill.modifiers())
+                    .flatten(),
+            );
+
+        for modifier in modifiers {
+            apply_modifier(&mut stats, modifier, &species);
+        }
+
+        Some(stats)
+    }
+
+    /// Resolves the health, torpedo protection and heals of a ship with the given config and
+    /// captain skills. Returns `None` if the config's hull isn't in the GameParams.
+    pub fn resolve_survivability(
+        &self,
+        ship: &Param,
+        config: &ShipConfig,
+        skills: &[&CrewSkill],
+    ) -> Option<Survivability> {
+        let species = ship.species()?;
+        let vehicle = ship.data().vehicle_ref()?;
+        let hull = vehicle.hull(config.hull())?;
+        let modifiers = self.config_modifiers(config, skills);
+
+        let heal_charges = self
+            .equipped_consumables(vehicle, config)
+            .into_iter()
+            .find(|category| category.consumable_type() == HEAL_CONSUMABLE_TYPE)
+            .map(|category| category.num_consumables());
+
+        Some(Survivability {
+            max_health: hull.health
+                + modifier_sum(&modifiers, "healthPerLevel", &species) * vehicle.level() as f32,
+            torpedo_protection: hull.torpedo_protection,
+            heal_charges: heal_charges.map(|charges| {
+                if charges < 0 {
+                    charges
+                } else {
+                    charges + modifier_sum(&modifiers, "additionalConsumables", &species) as isize
+                }
+            }),
+        })
+    }
+
+    /// Resolves the charges and timings of the consumables equipped in a ship's config.
+    /// Returns `None` if the ship isn't a vehicle in the GameParams.
+    pub fn resolve_consumables(
+        &self,
+        ship: &Param,
+        config: &ShipConfig,
+        skills: &[&CrewSkill],
+    ) -> Option<Vec<ConsumableSpec>> {
+        let species = ship.species()?;
+        let vehicle = ship.data().vehicle_ref()?;
+        let modifiers = self.config_modifiers(config, skills);
+        let additional_charges = modifier_sum(&modifiers, "additionalConsumables", &species);
+        let reload_coefficient = modifiers
+            .iter()
+            .filter(|modifier| {
+                modifier
+                    .name()
+                    .eq_ignore_ascii_case(CONSUMABLE_RELOAD_MODIFIER)
+            })
+            .filter_map(|modifier| modifier.get_for_species(&species))
+            .product::<f32>();
+
+        let specs = self
+            .equipped_consumables(vehicle, config)
+            .into_iter()
+            .filter_map(|category| {
+                Some(ConsumableSpec {
+                    consumable: Consumable::from_consumable_type(category.consumable_type())?,
+                    charges: usize::try_from(category.num_consumables())
+                        .ok()
+                        .map(|charges| charges + additional_charges as usize),
+                    work_time: category.work_time(),
+                    reload_time: category.reload_time() * reload_coefficient,
+                    title_id: category.title_id().to_string(),
+                    description_id: category.description_id().to_string(),
+                })
+            })
+            .collect();
+        Some(specs)
+    }
+
+    /// The passive modifiers of the config's modernizations and the captain skills.
+    /// Triggered skills only apply some of the time, so their other modifiers are left out.
+    fn config_modifiers(
+        &self,
+        config: &ShipConfig,
+        skills: &[&CrewSkill],
+    ) -> Vec<CrewSkillModifier> {
+        config
+            .modernization()
+            .iter()
+            .filter_map(|id| self.game_resources.game_param_by_id(*id))
+            .filter_map(|param| {
+                let modernization = param.data().modernization_ref()?;
+                Some(modernization.modifiers().to_vec())
+            })
+            .flatten()
+            .chain(
+                skills
+                    .iter()
+                    .filter_map(|skill| skill.modifiers())
+                    .flatten()
+                    .cloned(),
+            )
+            .collect()
+    }
+
+    /// The categories of the consumables equipped in the config. A consumable's category is
+    /// picked per ship, from the slot it's equipped in.
+    fn equipped_consumables(&self, vehicle: &Vehicle, config: &ShipConfig) -> Vec<AbilityCategory> {
+        config
+            .abilities()
+            .iter()
+            .filter_map(|id| {
+                let param = self.game_resources.game_param_by_id(*id)?;
+                let ability = param.data().ability_ref()?;
+                vehicle
+                    .abilities()
+                    .iter()
+                    .flatten()
+                    .filter(|(name, _category)| name == param.name())
+                    .find_map(|(_name, category)| ability.category(category))
+                    .cloned()
+            })
+            .collect()
+    }
+
+    /// Resolves the stats of a player's ship as configured in the replay
+    pub fn resolve_vehicle(&self, vehicle: &VehicleEntity) -> Option<ShipStats> {
+        let ship = vehicle.player()?.vehicle();
+        let skills = vehicle.commander_skills().unwrap_or_default();
+
+        self.resolve(
+            ship,
+            vehicle.props().ship_config().modernization(),
+            skills.as_slice(),
+        )
+    }
+
+    /// Resolves the survivability of a player's ship as configured in the replay
+    pub fn resolve_vehicle_survivability(&self, vehicle: &VehicleEntity) -> Option<Survivability> {
+        let ship = vehicle.player()?.vehicle();
+        let skills = vehicle.commander_skills().unwrap_or_default();
+
+        self.resolve_survivability(ship, vehicle.props().ship_config(), skills.as_slice())
+    }
+
+    /// Resolves the consumables of a player's ship as configured in the replay
+    pub fn resolve_vehicle_consumables(
+        &self,
+        vehicle: &VehicleEntity,
+    ) -> Option<Vec<ConsumableSpec>> {
+        let ship = vehicle.player()?.vehicle();
+        let skills = vehicle.commander_skills().unwrap_or_default();
+
+        self.resolve_consumables(ship, vehicle.props().ship_config(), skills.as_slice())
+    }
+}
+
+fn modifier_sum(modifiers: &[CrewSkillModifier], name: &str, species: &Species) -> f32 {
+    modifiers
+        .iter()
+        .filter(|modifier| modifier.name().eq_ignore_ascii_case(name))
+        .filter_map(|modifier| modifier.get_for_species(species))
+        .sum()
+}
+
+fn apply_modifier(stats: &mut ShipStats, modifier: &CrewSkillModifier, species: &Species) {
+    for modified in MODIFIED_STATS {
+        if !modified
+            .modifiers
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(modifier.name()))
+        {
+            continue;
+        }
+
+        if let (Some(value), Some(coefficient)) = (
+            (modified.stat)(stats).as_mut(),
+            modifier.get_for_species(species),
+        ) {
+            *value *= coefficient;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game_params::{
+        AbilityBuilder, AbilityCategoryBuilder, CrewSkillModifierBuilder, GameParams, HullStats,
+        ParamBuilder, ParamData, VehicleBuilder,
+    };
+    use crate::resource_loader::GameDataSource;
+    use std::collections::HashMap;
+
+    fn modifier(name: &str, destroyer: f32) -> CrewSkillModifier {
+        CrewSkillModifierBuilder::default()
+            .name(name.to_string())
+            .aircraft_carrier(1.0)
+            .auxiliary(1.0)
+            .battleship(1.0)
+            .cruiser(1.0)
+            .destroyer(destroyer)
+            .submarine(1.0)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_apply_modifier() {
+        let mut stats = ShipStats {
+            concealment_by_sea: Some(6.0),
+            concealment_by_air: Some(3.0),
+            main_battery_range: Some(10.0),
+            ..Default::default()
+        };
+
+        apply_modifier(
+            &mut stats,
+            &modifier("visibilityDistCoeff", 0.9),
+            &Species::Destroyer,
+        );
+        apply_modifier(&mut stats, &modifier("GMMaxDist", 1.2), &Species::Cruiser);
+        apply_modifier(
+            &mut stats,
+            &modifier("torpedoReloadTime", 0.8),
+            &Species::Destroyer,
+        );
+
+        assert!((stats.concealment_by_sea.unwrap() - 5.4).abs() < 0.001);
+        assert!((stats.concealment_by_air.unwrap() - 2.7).abs() < 0.001);
+        // The modifier's cruiser value is neutral
+        assert_eq!(stats.main_battery_range, Some(10.0));
+        assert_eq!(stats.torpedo_reload, None);
+    }
+
+    #[test]
+    fn test_resolve_survivability() {
+        let heal = AbilityCategoryBuilder::default()
+            .special_sound_id(None)
+            .consumable_type("regenCrew".to_string())
+            .description_id(String::new())
+            .group(String::new())
+            .icon_id(String::new())
+            .num_consumables(2)
+            .preparation_time(0.0)
+            .reload_time(80.0)
+            .title_id(String::new())
+            .work_time(28.0)
+            .build()
+            .unwrap();
+        let ability = AbilityBuilder::default()
+            .can_buy(true)
+            .cost_credits(0)
+            .cost_gold(0)
+            .is_free(false)
+            .categories(HashMap::from([("Premium".to_string(), heal)]))
+            .build()
+            .unwrap();
+        let param = |id: u32, name: &str, species, data| {
+            ParamBuilder::default()
+                .id(id)
+                .index(name.to_string())
+                .name(name.to_string())
+                .species(Some(species))
+                .nation(String::new())
+                .data(data)
+                .build()
+                .unwrap()
+        };
+        let data = GameDataSource::new(
+            GameParams::from(vec![param(
+                2,
+                "PCY009_RegenerateHealthPremium",
+                Species::Unknown("RegenCrew".to_string()),
+                ParamData::Ability(ability),
+            )]),
+            HashMap::new(),
+        );
+
+        let ship = param(
+            1,
+            "PBSB001_Battleship",
+            Species::Battleship,
+            ParamData::Vehicle(
+                VehicleBuilder::default()
+                    .level(10)
+                    .group("upgradeable".to_string())
+                    .abilities(vec![vec![(
+                        "PCY009_RegenerateHealthPremium".to_string(),
+                        "Premium".to_string(),
+                    )]])
+                    .hulls(HashMap::from([(
+                        3,
+                        HullStats {
+                            health: 80000.0,
+                            torpedo_protection: 0.3,
+                        },
+                    )]))
+                    .build()
+                    .unwrap(),
+            ),
+        );
+        let config: ShipConfig = serde_json::from_value(serde_json::json!({
+            "abilities": [2],
+            "hull": 3,
+            "modernization": [],
+            "units": [3],
+            "signals": [],
+        }))
+        .unwrap();
+
+        let data = data.with_specs(vec![]);
+        let resolver = BuildResolver::new(&data);
+        let survivability = resolver.resolve_survivability(&ship, &config, &[]).unwrap();
+        assert_eq!(survivability.max_health(), 80000.0);
+        assert_eq!(survivability.torpedo_protection(), 0.3);
+        assert_eq!(survivability.heal_charges(), Some(2));
+    }
+}
@@ -0,0 +1,140 @@
+//! A common representation of the game clock, along with formatting helpers.
+
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Time between the replay starting and the battle starting, i.e. the pre-battle
+/// countdown
+pub const BATTLE_START_OFFSET: Duration = Duration::from_secs(30);
+
+/// A point in time in a replay, in seconds since the replay started recording. This is
+/// the clock that every packet is timestamped with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct GameClock(pub f32);
+
+impl GameClock {
+    pub fn seconds(&self) -> f32 {
+        self.0
+    }
+
+    /// Converts the time since the battle started (e.g. as shown on the in-game timer
+    /// counting up) to the game clock
+    pub fn from_battle_time(battle_time: Duration) -> Self {
+        GameClock((battle_time + BATTLE_START_OFFSET).as_secs_f32())
+    }
+
+    /// Seconds since the battle started. Negative during the pre-battle countdown.
+    pub fn battle_seconds(&self) -> f32 {
+        self.0 - BATTLE_START_OFFSET.as_secs_f32()
+    }
+
+    /// Time since the battle started, or `None` during the pre-battle countdown
+    pub fn battle_time(&self) -> Option<Duration> {
+        let seconds = self.battle_seconds();
+        if seconds >= 0.0 {
+            Some(Duration::from_secs_f32(seconds))
+        } else {
+            None
+        }
+    }
+
+    /// Formats the clock as `mm:ss`. Minutes go beyond 59 rather than rolling over.
+    pub fn to_mmss(&self) -> String {
+        let (sign, total) = split_sign(self.0);
+        format!("{}{:02}:{:02}", sign, total / 60, total % 60)
+    }
+
+    /// Formats the clock as `hh:mm:ss`
+    pub fn to_hhmmss(&self) -> String {
+        let (sign, total) = split_sign(self.0);
+        format!(
+            "{}{:02}:{:02}:{:02}",
+            sign,
+            total / 3600,
+            (total / 60) % 60,
+            total % 60
+        )
+    }
+}
+
+/// Splits a number of seconds into a sign prefix and whole seconds
+fn split_sign(seconds: f32) -> (&'static str, u32) {
+    let sign = if seconds < 0.0 { "-" } else { "" };
+    (sign, seconds.abs().floor() as u32)
+}
+
+impl fmt::Display for GameClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_mmss())
+    }
+}
+
+impl From<f32> for GameClock {
+    fn from(seconds: f32) -> Self {
+        GameClock(seconds)
+    }
+}
+
+impl From<Duration> for GameClock {
+    fn from(duration: Duration) -> Self {
+        GameClock(duration.as_secs_f32())
+    }
+}
+
+impl From<GameClock> for Duration {
+    /// Negative clocks are clamped to zero
+    fn from(clock: GameClock) -> Self {
+        Duration::from_secs_f32(clock.0.max(0.0))
+    }
+}
+
+impl Add<Duration> for GameClock {
+    type Output = GameClock;
+
+    fn add(self, rhs: Duration) -> GameClock {
+        GameClock(self.0 + rhs.as_secs_f32())
+    }
+}
+
+impl Sub<Duration> for GameClock {
+    type Output = GameClock;
+
+    fn sub(self, rhs: Duration) -> GameClock {
+        GameClock(self.0 - rhs.as_secs_f32())
+    }
+}
+
+impl Sub for GameClock {
+    type Output = Duration;
+
+    /// The time elapsed between two clocks. Clamped to zero if `rhs` is later.
+    fn sub(self, rhs: GameClock) -> Duration {
+        Duration::from_secs_f32((self.0 - rhs.0).max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formatting() {
+        assert_eq!(GameClock(0.0).to_mmss(), "00:00");
+        assert_eq!(GameClock(75.9).to_mmss(), "01:15");
+        assert_eq!(GameClock(3725.0).to_mmss(), "62:05");
+        assert_eq!(GameClock(3725.0).to_hhmmss(), "01:02:05");
+        assert_eq!(GameClock(-5.0).to_string(), "-00:05");
+    }
+
+    #[test]
+    fn battle_time() {
+        let clock = GameClock::from_battle_time(Duration::from_secs(60));
+        assert_eq!(clock, GameClock(90.0));
+        assert_eq!(clock.battle_time(), Some(Duration::from_secs(60)));
+        assert_eq!(GameClock(10.0).battle_time(), None);
+        assert_eq!(GameClock(90.0) - GameClock(30.0), Duration::from_secs(60));
+    }
+}
@@ -62,6 +62,19 @@ pub enum ErrorKind {
     DecoderRingFailure(String),
     #[error("Unable to process packet")]
     ParsingFailure(String),
+    #[error("Unable to encode packet")]
+    EncodingFailure(String),
+    #[error("Unable to read archive")]
+    Archive(String),
+    #[error("Unable to apply property update")]
+    PropertyUpdateFailure(String),
+    #[error("Script error")]
+    ScriptFailure(String),
+    #[error("I/O error")]
+    Io {
+        #[from]
+        err: std::io::Error,
+    },
 }
 
 impl nom::error::ParseError<&[u8]> for Error {
@@ -87,7 +100,7 @@ impl std::convert::From<nom::Err<Error>> for ErrorKind {
     fn from(x: nom::Err<Error>) -> ErrorKind {
         match x {
             nom::Err::<Error>::Incomplete(_) => {
-                panic!("We can't handle incomplete replay files");
+                ErrorKind::ParsingFailure("We can't handle incomplete replay files".to_string())
             }
             nom::Err::<Error>::Error(e) => e.kind,
             nom::Err::<Error>::Failure(e) => e.kind,
@@ -130,3 +143,9 @@ pub fn failure_from_kind(kind: ErrorKind) -> nom::Err<Error> {
         backtrace: vec![],
     })
 }
+
+/// Creates an unrecoverable [ErrorKind::ParsingFailure] for data which doesn't look like
+/// what we expect, e.g. an out of range count or index read from a corrupt replay
+pub fn parsing_failure(message: String) -> nom::Err<Error> {
+    failure_from_kind(ErrorKind::ParsingFailure(message))
+}
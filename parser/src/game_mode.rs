@@ -0,0 +1,96 @@
+//! Classification of a battle's mode, so that modes with their own entities and methods,
+//! such as special events, can be handled separately from regular battles.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ReplayMeta;
+
+/// The broad kind of a battle's mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum GameModeKind {
+    Random,
+    Ranked,
+    Clan,
+    Cooperative,
+    Operation,
+    Training,
+    Brawl,
+    /// Limited time events, e.g. Savage Battle or Asymmetric Battles. These often have
+    /// entities and methods which regular battles don't.
+    Event,
+    /// A mode the classification doesn't know yet
+    Unknown,
+}
+
+impl GameModeKind {
+    /// Classifies the mode from the replay's `matchGroup`, falling back to its `gameType`
+    pub fn from_meta(meta: &ReplayMeta) -> Self {
+        Self::classify(&meta.matchGroup, &meta.gameType)
+    }
+
+    pub fn classify(match_group: &str, game_type: &str) -> Self {
+        match match_group.to_ascii_lowercase().as_str() {
+            "pvp" => return GameModeKind::Random,
+            "ranked" => return GameModeKind::Ranked,
+            "clan" => return GameModeKind::Clan,
+            "cooperative" => return GameModeKind::Cooperative,
+            "pve" | "pve_premium" => return GameModeKind::Operation,
+            "training" => return GameModeKind::Training,
+            "brawl" => return GameModeKind::Brawl,
+            "event" => return GameModeKind::Event,
+            _ => {}
+        }
+
+        let game_type = game_type.to_ascii_lowercase();
+        if game_type.contains("random") {
+            GameModeKind::Random
+        } else if game_type.contains("ranked") {
+            GameModeKind::Ranked
+        } else if game_type.contains("clan") {
+            GameModeKind::Clan
+        } else if game_type.contains("cooperative") {
+            GameModeKind::Cooperative
+        } else if game_type.contains("pve") {
+            GameModeKind::Operation
+        } else if game_type.contains("training") {
+            GameModeKind::Training
+        } else if game_type.contains("brawl") {
+            GameModeKind::Brawl
+        } else if game_type.contains("event") {
+            GameModeKind::Event
+        } else {
+            GameModeKind::Unknown
+        }
+    }
+
+    /// Whether the mode is likely to use entities and methods which regular battles don't
+    pub fn is_special(&self) -> bool {
+        matches!(
+            self,
+            GameModeKind::Brawl | GameModeKind::Event | GameModeKind::Unknown
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            GameModeKind::classify("pvp", "RandomBattle"),
+            GameModeKind::Random
+        );
+        assert_eq!(
+            GameModeKind::classify("", "EventBattle"),
+            GameModeKind::Event
+        );
+        assert_eq!(
+            GameModeKind::classify("ranked", "RankedBattle"),
+            GameModeKind::Ranked
+        );
+        assert_eq!(GameModeKind::classify("", ""), GameModeKind::Unknown);
+        assert!(GameModeKind::Unknown.is_special());
+    }
+}
@@ -199,11 +199,43 @@ pub enum ParamType {
 
 // }
 
+/// A ship's stats with its stock modules and no modernizations or captain skills applied.
+/// Distances are in kilometers and reload times in seconds. Stats for armaments the ship
+/// does not have are `None`.
+#[derive(Serialize, Deserialize, Clone, Builder, Debug, Default, PartialEq)]
+#[builder(default)]
+pub struct ShipStats {
+    pub concealment_by_sea: Option<f32>,
+    pub concealment_by_air: Option<f32>,
+    pub main_battery_range: Option<f32>,
+    pub main_battery_reload: Option<f32>,
+    pub secondary_range: Option<f32>,
+    pub secondary_reload: Option<f32>,
+    pub torpedo_reload: Option<f32>,
+}
+
+/// Survivability stats of one of a ship's hull modules
+#[derive(Serialize, Deserialize, Clone, Builder, Debug, Default, PartialEq)]
+#[builder(default)]
+pub struct HullStats {
+    pub health: f32,
+    /// Fraction of torpedo damage the torpedo belt absorbs, from 0 to 1
+    pub torpedo_protection: f32,
+}
+
 #[derive(Serialize, Deserialize, Clone, Builder, Debug)]
 pub struct Vehicle {
     level: u32,
     group: String,
+    /// Consumable slots, each a list of `(ability name, ability category)` choices
     abilities: Vec<Vec<(String, String)>>,
+    #[serde(default)]
+    #[builder(default)]
+    base_stats: Option<ShipStats>,
+    /// Hull module ID -> the hull's stats
+    #[serde(default)]
+    #[builder(default)]
+    hulls: HashMap<u32, HullStats>,
 }
 
 impl Vehicle {
@@ -214,6 +246,31 @@ impl Vehicle {
     pub fn group(&self) -> &str {
         self.group.as_ref()
     }
+
+    pub fn base_stats(&self) -> Option<&ShipStats> {
+        self.base_stats.as_ref()
+    }
+
+    pub fn abilities(&self) -> &[Vec<(String, String)>] {
+        self.abilities.as_ref()
+    }
+
+    /// The stats of the hull module with the given ID, e.g. a ship config's hull
+    pub fn hull(&self, id: u32) -> Option<&HullStats> {
+        self.hulls.get(&id)
+    }
+}
+
+/// A ship upgrade. Its modifiers use the same format as captain skill modifiers.
+#[derive(Serialize, Deserialize, Clone, Builder, Debug)]
+pub struct Modernization {
+    modifiers: Vec<CrewSkillModifier>,
+}
+
+impl Modernization {
+    pub fn modifiers(&self) -> &[CrewSkillModifier] {
+        self.modifiers.as_ref()
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Builder, Debug)]
@@ -230,6 +287,38 @@ pub struct AbilityCategory {
     work_time: f32,
 }
 
+impl AbilityCategory {
+    /// The consumable's kind, e.g. `regenCrew` for Repair Party
+    pub fn consumable_type(&self) -> &str {
+        self.consumable_type.as_ref()
+    }
+
+    /// Number of charges, or -1 if unlimited
+    pub fn num_consumables(&self) -> isize {
+        self.num_consumables
+    }
+
+    /// Seconds the consumable takes to reload after it stops working
+    pub fn reload_time(&self) -> f32 {
+        self.reload_time
+    }
+
+    /// Seconds the consumable stays active
+    pub fn work_time(&self) -> f32 {
+        self.work_time
+    }
+
+    /// Identifies the consumable's name in the translations
+    pub fn title_id(&self) -> &str {
+        self.title_id.as_ref()
+    }
+
+    /// Identifies the consumable's description in the translations
+    pub fn description_id(&self) -> &str {
+        self.description_id.as_ref()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Builder, Debug)]
 pub struct Ability {
     can_buy: bool,
@@ -239,6 +328,12 @@ pub struct Ability {
     categories: HashMap<String, AbilityCategory>,
 }
 
+impl Ability {
+    pub fn category(&self, name: &str) -> Option<&AbilityCategory> {
+        self.categories.get(name)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Builder, Debug)]
 pub struct CrewPersonalityShips {
     groups: Vec<String>,
@@ -282,15 +377,16 @@ pub struct ConsumableReloadTimeModifier {
 }
 
 impl ConsumableReloadTimeModifier {
-    pub fn get_for_species(&self, species: Species) -> f32 {
+    /// The value for ships of `species`, or `None` if `species` isn't a ship species
+    pub fn get_for_species(&self, species: &Species) -> Option<f32> {
         match species {
-            Species::AirCarrier => self.aircraft_carrier,
-            Species::Battleship => self.battleship,
-            Species::Cruiser => self.cruiser,
-            Species::Destroyer => self.destroyer,
-            Species::Submarine => self.submarine,
-            Species::Auxiliary => self.auxiliary,
-            other => panic!("Unexpected species {:?}", other),
+            Species::AirCarrier => Some(self.aircraft_carrier),
+            Species::Battleship => Some(self.battleship),
+            Species::Cruiser => Some(self.cruiser),
+            Species::Destroyer => Some(self.destroyer),
+            Species::Submarine => Some(self.submarine),
+            Species::Auxiliary => Some(self.auxiliary),
+            _ => None,
         }
     }
 
@@ -330,6 +426,25 @@ pub struct CrewSkillModifier {
     submarine: f32,
 }
 
+impl CrewSkillModifier {
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    /// The value for ships of `species`, or `None` if `species` isn't a ship species
+    pub fn get_for_species(&self, species: &Species) -> Option<f32> {
+        match species {
+            Species::AirCarrier => Some(self.aircraft_carrier),
+            Species::Battleship => Some(self.battleship),
+            Species::Cruiser => Some(self.cruiser),
+            Species::Destroyer => Some(self.destroyer),
+            Species::Submarine => Some(self.submarine),
+            Species::Auxiliary => Some(self.auxiliary),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Builder, Debug)]
 pub struct CrewSkillLogicTrigger {
     /// Sometimes this field isn't present?
@@ -352,6 +467,16 @@ pub struct CrewSkillLogicTrigger {
     trigger_type: String,
 }
 
+impl CrewSkillLogicTrigger {
+    pub fn modifiers(&self) -> Option<&Vec<CrewSkillModifier>> {
+        self.modifiers.as_ref()
+    }
+
+    pub fn trigger_type(&self) -> &str {
+        self.trigger_type.as_ref()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Builder, Debug)]
 pub struct CrewSkillTiers {
     aircraft_carrier: usize,
@@ -363,15 +488,16 @@ pub struct CrewSkillTiers {
 }
 
 impl CrewSkillTiers {
-    pub fn get_for_species(&self, species: Species) -> usize {
+    /// The tier for ships of `species`, or `None` if `species` isn't a ship species
+    pub fn get_for_species(&self, species: Species) -> Option<usize> {
         match species {
-            Species::AirCarrier => self.aircraft_carrier,
-            Species::Battleship => self.battleship,
-            Species::Cruiser => self.cruiser,
-            Species::Destroyer => self.destroyer,
-            Species::Submarine => self.submarine,
-            Species::Auxiliary => self.auxiliary,
-            other => panic!("Unexpected species {:?}", other),
+            Species::AirCarrier => Some(self.aircraft_carrier),
+            Species::Battleship => Some(self.battleship),
+            Species::Cruiser => Some(self.cruiser),
+            Species::Destroyer => Some(self.destroyer),
+            Species::Submarine => Some(self.submarine),
+            Species::Auxiliary => Some(self.auxiliary),
+            _ => None,
         }
     }
 
@@ -466,7 +592,7 @@ pub enum ParamData {
     Vehicle(Vehicle),
     Crew(Crew),
     Ability(Ability),
-    Modernization,
+    Modernization(Modernization),
     Exterior,
     Unit,
 }
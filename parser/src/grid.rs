@@ -0,0 +1,181 @@
+//! Conversions between positions and the lettered/numbered squares of the in-game map grid.
+//!
+//! The map is divided into a 10x10 grid. Rows are lettered A through J from the top of the
+//! map to the bottom, and columns are numbered 1 through 10 from left to right, so the top
+//! left square is A1 and the bottom right square is J10.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Number of rows and columns in the map grid
+pub const GRID_SIZE: u8 = 10;
+
+const ROW_LETTERS: [char; GRID_SIZE as usize] = ['A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J'];
+
+/// A square of the map grid, e.g. "F2"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GridSquare {
+    /// Zero-indexed row, where 0 is the top ("A") row
+    row: u8,
+    /// Zero-indexed column, where 0 is the leftmost ("1") column
+    column: u8,
+}
+
+impl GridSquare {
+    /// Creates a grid square from zero-indexed row (letter) and column (number) indices.
+    /// This is the representation used by [crate::analyzer::decoder::VoiceLine::AttentionToSquare],
+    /// e.g. F2 is (5, 1).
+    pub fn from_indices(row: u32, column: u32) -> Option<Self> {
+        if row < GRID_SIZE as u32 && column < GRID_SIZE as u32 {
+            Some(GridSquare {
+                row: row as u8,
+                column: column as u8,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Creates a grid square from normalized minimap coordinates, where (0, 0) is the
+    /// bottom left corner of the map and (1, 1) is the top right corner. This is the
+    /// coordinate system used by [crate::analyzer::decoder::MinimapUpdate].
+    ///
+    /// Returns `None` if the position is outside of the map.
+    pub fn from_normalized(x: f32, y: f32) -> Option<Self> {
+        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+            return None;
+        }
+
+        let max = (GRID_SIZE - 1) as f32;
+        let column = (x * GRID_SIZE as f32).floor().min(max);
+        // Rows are counted from the top of the map
+        let row = ((1.0 - y) * GRID_SIZE as f32).floor().min(max);
+
+        Some(GridSquare {
+            row: row as u8,
+            column: column as u8,
+        })
+    }
+
+    /// Creates a grid square from a world position. Maps are centered on the world origin,
+    /// and `map_size` is the length of one side of the (square) map in world units.
+    ///
+    /// Returns `None` if the position is outside of the map.
+    pub fn from_world(x: f32, z: f32, map_size: f32) -> Option<Self> {
+        Self::from_normalized(x / map_size + 0.5, z / map_size + 0.5)
+    }
+
+    /// World position of the center of this square, the inverse of [GridSquare::from_world]
+    pub fn center_world(&self, map_size: f32) -> (f32, f32) {
+        let square_size = map_size / GRID_SIZE as f32;
+        let x = (self.column as f32 + 0.5) * square_size - map_size / 2.0;
+        let z = map_size / 2.0 - (self.row as f32 + 0.5) * square_size;
+        (x, z)
+    }
+
+    /// Zero-indexed row, where 0 is the top ("A") row
+    pub fn row(&self) -> u8 {
+        self.row
+    }
+
+    /// Zero-indexed column, where 0 is the leftmost ("1") column
+    pub fn column(&self) -> u8 {
+        self.column
+    }
+
+    /// The letter used for this square's row
+    pub fn letter(&self) -> char {
+        ROW_LETTERS[self.row as usize]
+    }
+
+    /// The one-indexed number used for this square's column
+    pub fn number(&self) -> u8 {
+        self.column + 1
+    }
+}
+
+impl fmt::Display for GridSquare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.letter(), self.number())
+    }
+}
+
+impl FromStr for GridSquare {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let letter = chars
+            .next()
+            .ok_or_else(|| "empty grid square".to_string())?
+            .to_ascii_uppercase();
+        let row = ROW_LETTERS
+            .iter()
+            .position(|l| *l == letter)
+            .ok_or_else(|| format!("invalid grid row {}", letter))?;
+        let number: u32 = chars
+            .as_str()
+            .parse()
+            .map_err(|_| format!("invalid grid column in {}", s))?;
+        if number == 0 {
+            return Err(format!("invalid grid column in {}", s));
+        }
+
+        GridSquare::from_indices(row as u32, number - 1)
+            .ok_or_else(|| format!("grid square {} is out of range", s))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_normalized() {
+        assert_eq!(
+            GridSquare::from_normalized(0.0, 1.0).unwrap().to_string(),
+            "A1"
+        );
+        assert_eq!(
+            GridSquare::from_normalized(1.0, 0.0).unwrap().to_string(),
+            "J10"
+        );
+        assert_eq!(
+            GridSquare::from_normalized(0.15, 0.45).unwrap().to_string(),
+            "F2"
+        );
+        assert_eq!(GridSquare::from_normalized(-0.1, 0.5), None);
+    }
+
+    #[test]
+    fn test_from_world() {
+        assert_eq!(
+            GridSquare::from_world(-350.0, 350.0, 800.0)
+                .unwrap()
+                .to_string(),
+            "A1"
+        );
+        assert_eq!(GridSquare::from_world(500.0, 0.0, 800.0), None);
+    }
+
+    #[test]
+    fn test_center_world() {
+        let square = GridSquare::from_indices(0, 0).unwrap();
+        assert_eq!(square.center_world(800.0), (-360.0, 360.0));
+        let square = GridSquare::from_indices(5, 1).unwrap();
+        let (x, z) = square.center_world(800.0);
+        assert_eq!(GridSquare::from_world(x, z, 800.0), Some(square));
+    }
+
+    #[test]
+    fn test_parse() {
+        let square: GridSquare = "f2".parse().unwrap();
+        assert_eq!(square, GridSquare::from_indices(5, 1).unwrap());
+        assert_eq!(square.to_string(), "F2");
+        assert!("K1".parse::<GridSquare>().is_err());
+        assert!("A11".parse::<GridSquare>().is_err());
+        assert!("A0".parse::<GridSquare>().is_err());
+    }
+}
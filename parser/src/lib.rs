@@ -1,15 +1,35 @@
+#[cfg(feature = "analyzer")]
 pub mod analyzer;
+pub mod annotations;
+pub mod archive;
+#[cfg(feature = "analyzer")]
+pub mod build;
+pub mod clock;
 mod error;
+pub mod game_mode;
+#[cfg(feature = "analyzer")]
 pub mod game_params;
-mod nested_property_path;
+pub mod grid;
+pub mod nested_property_path;
+pub mod notes;
 pub mod packet2;
+#[cfg(feature = "analyzer")]
+pub mod packet_encoder;
+#[cfg(feature = "analyzer")]
 pub mod resource_loader;
 pub mod rpc;
+#[cfg(feature = "analyzer")]
+pub mod testkit;
+#[cfg(feature = "analyzer")]
+pub mod translations;
+#[cfg(feature = "ui-support")]
+pub mod ui_support;
 pub mod version;
 mod wowsreplay;
 
 pub use error::*;
 pub use rpc::entitydefs::parse_scripts;
+#[cfg(feature = "analyzer")]
 pub use strum;
 pub use wowsreplay::*;
 
@@ -1,40 +1,136 @@
+//! Updates of values nested inside of entity properties, as carried by
+//! [crate::packet2::PropertyUpdatePacket].
+//!
+//! Rather than sending a whole property again when part of it changes, the game sends
+//! a path from the property's root to the changed value, and an action to perform on
+//! that value. For example, setting `state.controlPoints[2].hasInvaders` is sent as the
+//! levels `[DictKey("controlPoints"), ArrayIndex(2)]` on the `state` property, with the
+//! action `SetKey { key: "hasInvaders", .. }`.
+//!
+//! The parser applies updates to its own copy of each property. Consumers which keep
+//! properties as JSON, e.g. from serializing [crate::packet2::EntityCreatePacket]s, can
+//! apply updates to them with [PropertyNesting::apply].
+
 use crate::rpc::typedefs::{ArgType, ArgValue};
+use crate::ErrorKind;
 use bitreader::BitReader;
 use serde::Serialize;
 
+/// One step of the path from a property's root to the value being updated
 #[derive(Debug, Serialize)]
 pub enum PropertyNestLevel<'argtype> {
+    /// An element of an array
     ArrayIndex(usize),
+    /// A field of a dictionary
     DictKey(&'argtype str),
 }
 
+/// How the value at the end of a [PropertyNesting]'s path is updated
 #[derive(Debug, Serialize)]
 pub enum UpdateAction<'argtype> {
+    /// Sets a field of a dictionary
     SetKey {
         key: &'argtype str,
         value: ArgValue<'argtype>,
     },
+    /// Replaces the elements `start..stop` of an array with `values`, following Python's
+    /// slice assignment semantics. The number of values may differ from the length of the
+    /// range, in which case the array grows or shrinks.
     SetRange {
         start: usize,
         stop: usize,
         values: Vec<ArgValue<'argtype>>,
     },
+    /// Sets an element of an array
     SetElement {
         index: usize,
         value: ArgValue<'argtype>,
     },
-    RemoveRange {
-        start: usize,
-        stop: usize,
-    },
+    /// Removes the elements `start..stop` of an array
+    RemoveRange { start: usize, stop: usize },
 }
 
+impl<'argtype> UpdateAction<'argtype> {
+    /// Performs the action on `target`, which must be an object for [UpdateAction::SetKey]
+    /// and an array otherwise
+    pub fn apply(&self, target: &mut serde_json::Value) -> Result<(), ErrorKind> {
+        let to_json = |value: &ArgValue<'_>| {
+            serde_json::to_value(value)
+                .map_err(|e| ErrorKind::PropertyUpdateFailure(format!("{}", e)))
+        };
+        match (self, target) {
+            (UpdateAction::SetKey { key, value }, serde_json::Value::Object(object)) => {
+                object.insert(key.to_string(), to_json(value)?);
+            }
+            (
+                UpdateAction::SetRange {
+                    start,
+                    stop,
+                    values,
+                },
+                serde_json::Value::Array(array),
+            ) => {
+                let values = values.iter().map(to_json).collect::<Result<_, _>>()?;
+                slice_insert(*start, *stop, array, values);
+            }
+            (UpdateAction::SetElement { index, value }, serde_json::Value::Array(array)) => {
+                let len = array.len();
+                let element = array.get_mut(*index).ok_or_else(|| {
+                    ErrorKind::PropertyUpdateFailure(format!(
+                        "cannot set element {} of an array of length {}",
+                        index, len
+                    ))
+                })?;
+                *element = to_json(value)?;
+            }
+            (UpdateAction::RemoveRange { start, stop }, serde_json::Value::Array(array)) => {
+                slice_insert(*start, *stop, array, vec![]);
+            }
+            (action, target) => {
+                return Err(ErrorKind::PropertyUpdateFailure(format!(
+                    "cannot apply {:?} to {}",
+                    action, target
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An update of a value nested inside of a property
 #[derive(Debug, Serialize)]
 pub struct PropertyNesting<'argtype> {
+    /// The path from the property's root to the value being updated. Empty if the action
+    /// applies to the property itself.
     pub levels: Vec<PropertyNestLevel<'argtype>>,
     pub action: UpdateAction<'argtype>,
 }
 
+impl<'argtype> PropertyNesting<'argtype> {
+    /// Follows [Self::levels] from `property`, returning the value the action applies to,
+    /// or `None` if the path doesn't exist in `property`
+    pub fn target_mut<'v>(
+        &self,
+        property: &'v mut serde_json::Value,
+    ) -> Option<&'v mut serde_json::Value> {
+        self.levels
+            .iter()
+            .try_fold(property, |value, level| match level {
+                PropertyNestLevel::ArrayIndex(index) => value.get_mut(*index),
+                PropertyNestLevel::DictKey(key) => value.get_mut(*key),
+            })
+    }
+
+    /// Applies the update to `property`, the JSON representation of the property's
+    /// current value
+    pub fn apply(&self, property: &mut serde_json::Value) -> Result<(), ErrorKind> {
+        let target = self.target_mut(property).ok_or_else(|| {
+            ErrorKind::PropertyUpdateFailure(format!("{:?} not found in property", self.levels))
+        })?;
+        self.action.apply(target)
+    }
+}
+
 /// This function emulates Python's slice semantics
 fn slice_insert<T>(idx1: usize, idx2: usize, target: &mut Vec<T>, mut source: Vec<T>) {
     // First we delete target[idx1..idx2]
@@ -168,7 +264,7 @@ fn nested_update_command<'argtype>(
     }
 }
 
-pub fn get_nested_prop_path_helper<'argtype>(
+pub(crate) fn get_nested_prop_path_helper<'argtype>(
     is_slice: bool,
     t: &'argtype ArgType,
     prop_value: &mut ArgValue<'argtype>,
@@ -294,4 +390,45 @@ mod test {
         slice_insert(5, 12, &mut v, vec![6, 7, 8]);
         assert_eq!(v, vec![1, 2, 3, 4, 5, 6, 7, 8]);
     }
+
+    #[test]
+    fn apply_to_json() {
+        let mut property = serde_json::json!({
+            "controlPoints": [{"hasInvaders": false}, {"hasInvaders": false}],
+            "ids": [1, 2, 3],
+        });
+
+        let update = PropertyNesting {
+            levels: vec![
+                PropertyNestLevel::DictKey("controlPoints"),
+                PropertyNestLevel::ArrayIndex(1),
+            ],
+            action: UpdateAction::SetKey {
+                key: "hasInvaders",
+                value: ArgValue::Uint8(1),
+            },
+        };
+        update.apply(&mut property).unwrap();
+        assert_eq!(property["controlPoints"][1]["hasInvaders"], 1);
+
+        let update = PropertyNesting {
+            levels: vec![PropertyNestLevel::DictKey("ids")],
+            action: UpdateAction::SetRange {
+                start: 1,
+                stop: 3,
+                values: vec![ArgValue::Int32(7)],
+            },
+        };
+        update.apply(&mut property).unwrap();
+        assert_eq!(property["ids"], serde_json::json!([1, 7]));
+
+        let update = PropertyNesting {
+            levels: vec![PropertyNestLevel::DictKey("ids")],
+            action: UpdateAction::SetElement {
+                index: 2,
+                value: ArgValue::Int32(7),
+            },
+        };
+        assert!(update.apply(&mut property).is_err());
+    }
 }
@@ -0,0 +1,136 @@
+//! User-curated tags and notes for a replay, stored in a JSON sidecar file next to it so
+//! the replay itself is never modified. The sidecar of `some.wowsreplay` is
+//! `some.wowsreplay.notes.json`.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ErrorKind;
+
+/// Suffix appended to a replay's file name to get its sidecar's
+pub const SIDECAR_SUFFIX: &str = ".notes.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplayNotes {
+    #[serde(default)]
+    tags: BTreeSet<String>,
+    #[serde(default)]
+    notes: Vec<String>,
+}
+
+impl ReplayNotes {
+    /// The path of the replay's sidecar file
+    pub fn sidecar_path(replay: &Path) -> PathBuf {
+        let mut path = replay.as_os_str().to_owned();
+        path.push(SIDECAR_SUFFIX);
+        PathBuf::from(path)
+    }
+
+    /// Whether the path is a sidecar file rather than a replay
+    pub fn is_sidecar(path: &Path) -> bool {
+        path.to_str()
+            .is_some_and(|path| path.ends_with(SIDECAR_SUFFIX))
+    }
+
+    /// Loads the replay's tags and notes. A replay without a sidecar has none.
+    pub fn load(replay: &Path) -> Result<Self, ErrorKind> {
+        let path = Self::sidecar_path(replay);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = std::fs::File::open(&path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    /// Writes the sidecar file, or removes it if there are no tags or notes left
+    pub fn save(&self, replay: &Path) -> Result<(), ErrorKind> {
+        let path = Self::sidecar_path(replay);
+        if self.is_empty() {
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+            return Ok(());
+        }
+        let file = std::fs::File::create(&path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.notes.is_empty()
+    }
+
+    /// Tags in alphabetical order
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags.iter().map(|tag| tag.as_str())
+    }
+
+    /// Tags are case-insensitive, so this matches regardless of case
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(&normalize_tag(tag))
+    }
+
+    /// Adds a tag, returning `false` if the replay already had it
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        self.tags.insert(normalize_tag(tag))
+    }
+
+    /// Removes a tag, returning `false` if the replay didn't have it
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        self.tags.remove(&normalize_tag(tag))
+    }
+
+    /// Notes in the order they were added
+    pub fn notes(&self) -> &[String] {
+        self.notes.as_ref()
+    }
+
+    pub fn add_note(&mut self, note: &str) {
+        self.notes.push(note.to_string());
+    }
+
+    pub fn clear_notes(&mut self) {
+        self.notes.clear();
+    }
+}
+
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tags() {
+        let replay = Path::new("replays/20220101_000000_PASB017.wowsreplay");
+        assert_eq!(
+            ReplayNotes::sidecar_path(replay),
+            Path::new("replays/20220101_000000_PASB017.wowsreplay.notes.json")
+        );
+        assert!(ReplayNotes::is_sidecar(&ReplayNotes::sidecar_path(replay)));
+        assert!(!ReplayNotes::is_sidecar(replay));
+
+        let mut notes = ReplayNotes::default();
+        assert!(notes.add_tag("devstrike"));
+        assert!(!notes.add_tag(" DevStrike"));
+        assert!(notes.add_tag("clutch"));
+        notes.add_note("Detonated at 3:12");
+        assert!(notes.has_tag("DEVSTRIKE"));
+        assert_eq!(
+            notes.tags().collect::<Vec<_>>(),
+            vec!["clutch", "devstrike"]
+        );
+
+        let json = serde_json::to_string(&notes).unwrap();
+        assert_eq!(serde_json::from_str::<ReplayNotes>(&json).unwrap(), notes);
+
+        assert!(notes.remove_tag("clutch"));
+        assert!(notes.remove_tag("devstrike"));
+        notes.clear_notes();
+        assert!(notes.is_empty());
+    }
+}
@@ -5,15 +5,16 @@ use nom::{
     number::complete::le_u8,
 };
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::convert::TryInto;
+use tracing::{debug, debug_span, warn};
 
 use crate::error::*;
+use crate::game_mode::GameModeKind;
 use crate::rpc::entitydefs::*;
 use crate::rpc::typedefs::ArgValue;
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -86,6 +87,7 @@ pub struct EntityCreatePacket<'argtype> {
     pub position: Vec3,
     pub rotation: Rot3,
     pub state_length: u32,
+    #[serde(serialize_with = "crate::rpc::typedefs::serialize_sorted_map")]
     pub props: HashMap<&'argtype str, crate::rpc::typedefs::ArgValue<'argtype>>,
 }
 
@@ -101,6 +103,32 @@ pub struct PlayerOrientationPacket {
     pub rotation: Rot3,
 }
 
+/// An entity method decoded by a decoder registered with
+/// [Parser::register_method_decoder]
+#[derive(Debug, Serialize)]
+pub struct CustomMethodPacket<'replay, 'argtype> {
+    pub entity_id: u32,
+    pub entity_type: &'argtype str,
+    pub method: &'argtype str,
+    /// The method's argument payload, which the custom decoder was given
+    pub raw_args: &'replay [u8],
+    /// What the custom decoder decoded the call into
+    pub value: serde_json::Value,
+}
+
+/// An entity method whose arguments could not be decoded, e.g. because the method's
+/// arguments changed in a new game version
+#[derive(Debug, Clone, Serialize)]
+pub struct PartialDecodePacket<'replay, 'argtype> {
+    pub entity_id: u32,
+    pub method: &'argtype str,
+    /// The method's undecoded argument payload. Empty if the arguments were decoded, but
+    /// [crate::analyzer::decoder::DecodedPacketPayload] couldn't make sense of them.
+    pub raw_args: &'replay [u8],
+    /// Why the arguments couldn't be decoded
+    pub error: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct InvalidPacket<'a> {
     message: String,
@@ -111,6 +139,7 @@ pub struct InvalidPacket<'a> {
 pub struct BasePlayerCreatePacket<'argtype> {
     pub entity_id: u32,
     pub entity_type: &'argtype str,
+    #[serde(serialize_with = "crate::rpc::typedefs::serialize_sorted_map")]
     pub props: HashMap<&'argtype str, crate::rpc::typedefs::ArgValue<'argtype>>,
 }
 
@@ -123,9 +152,18 @@ pub struct CellPlayerCreatePacket<'argtype> {
     pub vehicle_id: u32,
     pub position: Vec3,
     pub rotation: Rot3,
+    #[serde(serialize_with = "crate::rpc::typedefs::serialize_sorted_map")]
     pub props: HashMap<&'argtype str, crate::rpc::typedefs::ArgValue<'argtype>>,
 }
 
+/// Sent when the client gains or loses control of an entity. Observer clients receive
+/// this as they switch between the ships they're watching.
+#[derive(Debug, Serialize)]
+pub struct EntityControlPacket {
+    pub entity_id: u32,
+    pub is_controlled: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct EntityLeavePacket {
     pub entity_id: u32,
@@ -184,6 +222,7 @@ pub enum PacketType<'replay, 'argtype> {
     Position(PositionPacket),
     BasePlayerCreate(BasePlayerCreatePacket<'argtype>),
     CellPlayerCreate(CellPlayerCreatePacket<'argtype>),
+    EntityControl(EntityControlPacket),
     EntityEnter(EntityEnterPacket),
     EntityLeave(EntityLeavePacket),
     EntityCreate(EntityCreatePacket<'argtype>),
@@ -199,11 +238,45 @@ pub enum PacketType<'replay, 'argtype> {
     Map(MapPacket<'replay>),
     BattleResults(&'replay str),
     Unknown(&'replay [u8]),
+    /// An entity method decoded by a custom decoder
+    CustomMethod(CustomMethodPacket<'replay, 'argtype>),
+    /// A known entity method whose arguments couldn't be parsed
+    PartialDecode(PartialDecodePacket<'replay, 'argtype>),
 
     /// These are packets which we thought we understood, but couldn't parse
     Invalid(InvalidPacket<'replay>),
 }
 
+impl<'replay, 'argtype> PacketType<'replay, 'argtype> {
+    /// The packet type ID this payload is parsed from, or `None` if it can come from any
+    /// packet type
+    pub fn type_id(&self) -> Option<u32> {
+        Some(match self {
+            PacketType::BasePlayerCreate(_) => 0x0,
+            PacketType::CellPlayerCreate(_) => 0x1,
+            PacketType::EntityControl(_) => 0x2,
+            PacketType::EntityEnter(_) => 0x3,
+            PacketType::EntityLeave(_) => 0x4,
+            PacketType::EntityCreate(_) => 0x5,
+            PacketType::EntityProperty(_) => 0x7,
+            PacketType::EntityMethod(_)
+            | PacketType::CustomMethod(_)
+            | PacketType::PartialDecode(_) => 0x8,
+            PacketType::Position(_) => 0xA,
+            PacketType::Version(_) => 0x16,
+            PacketType::BattleResults(_) => 0x22,
+            PacketType::PropertyUpdate(_) => 0x23,
+            PacketType::Camera(_) => 0x25,
+            PacketType::CameraMode(_) => 0x27,
+            PacketType::Map(_) => 0x28,
+            PacketType::PlayerOrientation(_) => 0x2c,
+            PacketType::CameraFreeLook(_) => 0x2f,
+            PacketType::CruiseState(_) => 0x32,
+            PacketType::Unknown(_) | PacketType::Invalid(_) => return None,
+        })
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct Packet<'replay, 'argtype> {
     pub packet_size: u32,
@@ -213,15 +286,53 @@ pub struct Packet<'replay, 'argtype> {
     pub raw: &'replay [u8],
 }
 
+impl<'replay, 'argtype> Packet<'replay, 'argtype> {
+    pub fn game_clock(&self) -> crate::clock::GameClock {
+        crate::clock::GameClock(self.clock)
+    }
+}
+
 #[derive(Debug)]
 pub struct Entity<'argtype> {
     entity_type: u16,
     properties: Vec<ArgValue<'argtype>>,
 }
 
+/// A call of an entity method, as passed to a [MethodDecoder]
+pub struct MethodCall<'a, 'replay, 'argtype> {
+    pub entity_id: u32,
+    pub entity_type: &'argtype str,
+    pub method: &'argtype str,
+    /// The method's argument payload
+    pub raw_args: &'replay [u8],
+    /// The arguments as parsed according to the method's spec, or `None` if they
+    /// couldn't be parsed
+    pub args: Option<&'a [ArgValue<'argtype>]>,
+}
+
+/// Decodes calls of an entity method, returning `None` to fall back to the built-in
+/// decoding. See [Parser::register_method_decoder].
+pub type MethodDecoder = Box<dyn Fn(&MethodCall<'_, '_, '_>) -> Option<serde_json::Value>>;
+
+/// Decodes the method calls of a game mode which the entity specs can't, e.g. methods
+/// added for a special event. See [Parser::register_mode_decoder].
+pub trait ModeDecoder {
+    /// Whether the decoder applies to battles of the mode
+    fn supports(&self, mode: GameModeKind) -> bool;
+
+    /// Decodes a call whose arguments didn't match the method's spec, returning `None` if
+    /// the call isn't one the decoder knows
+    fn decode_method(&self, call: &MethodCall<'_, '_, '_>) -> Option<serde_json::Value>;
+}
+
 pub struct Parser<'argtype> {
     specs: &'argtype [EntitySpec],
     entities: HashMap<u32, Entity<'argtype>>,
+    bridged: bool,
+    /// Entity type -> method name -> decoder
+    method_decoders: HashMap<String, HashMap<String, MethodDecoder>>,
+    game_mode: GameModeKind,
+    mode_decoders: Vec<Box<dyn ModeDecoder>>,
 }
 
 impl<'argtype> Parser<'argtype> {
@@ -229,9 +340,73 @@ impl<'argtype> Parser<'argtype> {
         Parser {
             specs: entities,
             entities: HashMap::new(),
+            bridged: false,
+            method_decoders: HashMap::new(),
+            game_mode: GameModeKind::Unknown,
+            mode_decoders: Vec::new(),
         }
     }
 
+    /// Sets the mode of the battle being parsed, see [GameModeKind::from_meta]. Method
+    /// calls which can't be decoded are passed to the [ModeDecoder]s which support it.
+    pub fn set_game_mode(&mut self, mode: GameModeKind) {
+        self.game_mode = mode;
+    }
+
+    /// Adds a decoder for method calls which fail to decode in the modes it supports. Calls
+    /// it decodes become [PacketType::CustomMethod] packets rather than
+    /// [PacketType::PartialDecode]s. Decoders are tried in the order they were added.
+    pub fn register_mode_decoder<D: ModeDecoder + 'static>(&mut self, decoder: D) {
+        self.mode_decoders.push(Box::new(decoder));
+    }
+
+    /// Decodes calls of `method` on entities of type `entity_type` (e.g. `Avatar`) with
+    /// `decoder` rather than the method's spec, producing [PacketType::CustomMethod]
+    /// packets. This allows experimenting with methods which aren't decoded yet, or whose
+    /// arguments changed, without modifying the parser. Replaces any decoder previously
+    /// registered for the method.
+    pub fn register_method_decoder<F>(&mut self, entity_type: &str, method: &str, decoder: F)
+    where
+        F: Fn(&MethodCall<'_, '_, '_>) -> Option<serde_json::Value> + 'static,
+    {
+        self.method_decoders
+            .entry(entity_type.to_string())
+            .or_default()
+            .insert(method.to_string(), Box::new(decoder));
+    }
+
+    /// Indicates that the specs are from a different game version than the replay, see
+    /// [crate::version::EmbeddedDataFiles::nearest]. Entity methods must then consume
+    /// their entire payload, so that methods whose arguments changed become invalid
+    /// packets rather than being decoded incorrectly. Packets which fail to parse are
+    /// only logged at the debug level, since failures are expected.
+    pub fn set_bridged(&mut self, bridged: bool) {
+        self.bridged = bridged;
+    }
+
+    /// Looks up the spec for an entity type id read from the replay
+    fn entity_spec(&self, entity_type: u16) -> Result<&'argtype EntitySpec, nom::Err<Error>> {
+        let specs: &'argtype [EntitySpec] = self.specs;
+        (entity_type as usize)
+            .checked_sub(1)
+            .and_then(|idx| specs.get(idx))
+            .ok_or_else(|| {
+                parsing_failure(format!(
+                    "entity type {} is out of range ({} entity types are known)",
+                    entity_type,
+                    specs.len()
+                ))
+            })
+    }
+
+    /// Looks up the type of an entity which was previously created
+    fn entity_type(&self, entity_id: u32) -> Result<u16, nom::Err<Error>> {
+        self.entities
+            .get(&entity_id)
+            .map(|entity| entity.entity_type)
+            .ok_or_else(|| parsing_failure(format!("entity {} was never created", entity_id)))
+    }
+
     fn parse_entity_property_packet<'a, 'b>(
         &'b self,
         i: &'a [u8],
@@ -241,10 +416,18 @@ impl<'argtype> Parser<'argtype> {
         let (i, payload_length) = le_u32(i)?;
         let (i, payload) = take(payload_length)(i)?;
 
-        let entity_type = self.entities.get(&entity_id).unwrap().entity_type;
-        let spec = &self.specs[entity_type as usize - 1].properties[prop_id as usize];
+        let entity_spec = self.entity_spec(self.entity_type(entity_id)?)?;
+        let spec = entity_spec
+            .properties
+            .get(prop_id as usize)
+            .ok_or_else(|| {
+                parsing_failure(format!(
+                    "property {} is out of range for {}",
+                    prop_id, entity_spec.name
+                ))
+            })?;
 
-        let (_, pval) = spec.prop_type.parse_value(payload).unwrap();
+        let (_, pval) = spec.prop_type.parse_value(payload)?;
 
         Ok((
             i,
@@ -264,39 +447,128 @@ impl<'argtype> Parser<'argtype> {
         let (i, method_id) = le_u32(i)?;
         let (i, payload_length) = le_u32(i)?;
         let (i, payload) = take(payload_length)(i)?;
-        assert!(i.len() == 0);
+        if !i.is_empty() {
+            return Err(parsing_failure(format!(
+                "{} trailing bytes after entity method payload",
+                i.len()
+            )));
+        }
 
-        let entity_type = self.entities.get(&entity_id).unwrap().entity_type;
+        let entity_spec = self.entity_spec(self.entity_type(entity_id)?)?;
+        let spec = entity_spec
+            .client_methods
+            .get(method_id as usize)
+            .ok_or_else(|| {
+                parsing_failure(format!(
+                    "method {} is out of range for {}",
+                    method_id, entity_spec.name
+                ))
+            })?;
+
+        let parsed = self.parse_method_args(spec, payload);
+
+        if let Some(decoder) = self
+            .method_decoders
+            .get(&entity_spec.name)
+            .and_then(|methods| methods.get(&spec.name))
+        {
+            let call = MethodCall {
+                entity_id,
+                entity_type: &entity_spec.name,
+                method: &spec.name,
+                raw_args: payload,
+                args: parsed.as_ref().ok().map(|(_, args)| args.as_slice()),
+            };
+            if let Some(value) = decoder(&call) {
+                return Ok((
+                    &[],
+                    PacketType::CustomMethod(CustomMethodPacket {
+                        entity_id,
+                        entity_type: &entity_spec.name,
+                        method: &spec.name,
+                        raw_args: payload,
+                        value,
+                    }),
+                ));
+            }
+        }
+
+        match parsed {
+            Ok((i, args)) => Ok((
+                i,
+                PacketType::EntityMethod(EntityMethodPacket {
+                    entity_id,
+                    method: &spec.name,
+                    args,
+                }),
+            )),
+            Err(error) => {
+                let call = MethodCall {
+                    entity_id,
+                    entity_type: &entity_spec.name,
+                    method: &spec.name,
+                    raw_args: payload,
+                    args: None,
+                };
+                if let Some(value) = self
+                    .mode_decoders
+                    .iter()
+                    .filter(|decoder| decoder.supports(self.game_mode))
+                    .find_map(|decoder| decoder.decode_method(&call))
+                {
+                    return Ok((
+                        &[],
+                        PacketType::CustomMethod(CustomMethodPacket {
+                            entity_id,
+                            entity_type: &entity_spec.name,
+                            method: &spec.name,
+                            raw_args: payload,
+                            value,
+                        }),
+                    ));
+                }
 
-        let spec = &self.specs[entity_type as usize - 1].client_methods[method_id as usize];
+                if self.bridged {
+                    debug!(method = %spec.name, %error, "failed to decode method arguments");
+                } else {
+                    warn!(method = %spec.name, %error, "failed to decode method arguments");
+                }
+                Ok((
+                    &[],
+                    PacketType::PartialDecode(PartialDecodePacket {
+                        entity_id,
+                        method: &spec.name,
+                        raw_args: payload,
+                        error,
+                    }),
+                ))
+            }
+        }
+    }
 
+    /// Parses a method's arguments according to its spec, returning the unparsed bytes
+    /// and arguments, or why they couldn't be parsed
+    fn parse_method_args<'a, 'b>(
+        &self,
+        spec: &'b Method,
+        payload: &'a [u8],
+    ) -> Result<(&'a [u8], Vec<ArgValue<'b>>), String> {
         let mut i = payload;
         let mut args = vec![];
         for (idx, arg) in spec.args.iter().enumerate() {
-            let (new_i, pval) = match arg.parse_value(i) {
-                Ok(x) => x,
-                Err(e) => {
-                    return Err(failure_from_kind(crate::ErrorKind::UnableToParseRpcValue {
-                        method: format!("{}", spec.name),
-                        argnum: idx,
-                        argtype: format!("{:?}", arg),
-                        packet: i.to_vec(),
-                        error: format!("{:?}", e),
-                    }));
-                }
-            };
+            let (new_i, pval) = arg
+                .parse_value(i)
+                .map_err(|e| format!("unable to parse argument {} ({:?}): {:?}", idx, arg, e))?;
             args.push(pval);
             i = new_i;
         }
-
-        Ok((
-            i,
-            PacketType::EntityMethod(EntityMethodPacket {
-                entity_id,
-                method: &spec.name,
-                args,
-            }),
-        ))
+        if self.bridged && !i.is_empty() {
+            return Err(format!(
+                "{} unparsed argument bytes, the arguments may have changed",
+                i.len()
+            ));
+        }
+        Ok((i, args))
     }
 
     fn parse_battle_results<'replay, 'b>(
@@ -304,7 +576,13 @@ impl<'argtype> Parser<'argtype> {
         i: &'replay [u8],
     ) -> IResult<&'replay [u8], PacketType<'replay, 'argtype>> {
         let (i, len) = le_u32(i)?;
-        assert_eq!(len as usize, i.len());
+        if len as usize != i.len() {
+            return Err(parsing_failure(format!(
+                "battle results claim {} bytes but the packet has {}",
+                len,
+                i.len()
+            )));
+        }
         let (i, battle_results) = take(len)(i)?;
 
         let results = std::str::from_utf8(battle_results).map_err(|_| {
@@ -324,23 +602,40 @@ impl<'argtype> Parser<'argtype> {
         let (i, is_slice) = le_u8(i)?;
         let (i, payload_size) = le_u8(i)?;
         let (i, unknown) = take(3usize)(i)?;
-        assert_eq!(unknown, [0, 0, 0]); // Note: This is almost certainly the upper 3 bytes of a u32
+        // Note: This is almost certainly the upper 3 bytes of a u32
+        if unknown != [0, 0, 0] || is_slice & 0xFE != 0 {
+            return Err(parsing_failure(format!(
+                "unexpected nested property header {:?}",
+                (is_slice, unknown)
+            )));
+        }
         let payload = i;
-        assert_eq!(payload_size as usize, payload.len());
-
-        let entity = self.entities.get_mut(&entity_id).unwrap();
-        let entity_type = entity.entity_type;
-
-        let spec = &self.specs[entity_type as usize - 1];
+        if payload_size as usize != payload.len() {
+            return Err(parsing_failure(format!(
+                "nested property update claims {} bytes but the packet has {}",
+                payload_size,
+                payload.len()
+            )));
+        }
 
-        assert!(is_slice & 0xFE == 0);
+        let spec = self.entity_spec(self.entity_type(entity_id)?)?;
+        let entity = self
+            .entities
+            .get_mut(&entity_id)
+            .expect("entity_type() checked that the entity exists");
 
+        let bit_error =
+            |e: bitreader::BitReaderError| parsing_failure(format!("nested property: {}", e));
         let mut reader = bitreader::BitReader::new(payload);
-        let cont = reader.read_u8(1).unwrap();
-        assert!(cont == 1);
+        let cont = reader.read_u8(1).map_err(bit_error)?;
+        if cont != 1 {
+            return Err(parsing_failure(
+                "nested property update has no continuation bit".to_string(),
+            ));
+        }
         let prop_idx = reader
             .read_u8(spec.properties.len().next_power_of_two().trailing_zeros() as u8)
-            .unwrap();
+            .map_err(bit_error)?;
         if prop_idx as usize >= entity.properties.len() {
             // This is almost certainly a nested property set on the player avatar.
             // Currently, we assume that all properties are created when the entity is
@@ -385,7 +680,11 @@ impl<'argtype> Parser<'argtype> {
         let (i, data) = take(len)(i)?;
         Ok((
             i,
-            PacketType::Version(std::str::from_utf8(data).unwrap().to_string()),
+            PacketType::Version(
+                std::str::from_utf8(data)
+                    .map_err(|_| parsing_failure("Invalid UTF-8 data in version".to_string()))?
+                    .to_string(),
+            ),
         ))
     }
 
@@ -412,7 +711,11 @@ impl<'argtype> Parser<'argtype> {
         let (i, pid) = le_u32(i)?;
         let (i, zero) = le_u32(i)?;
         if zero != 0 {
-            panic!("What does this field mean?");
+            // What does this field mean?
+            return Err(parsing_failure(format!(
+                "unexpected non-zero position field {:#x}",
+                zero
+            )));
         }
         let (i, position) = Vec3::parse(i)?;
         let (i, position_error) = Vec3::parse(i)?;
@@ -435,7 +738,12 @@ impl<'argtype> Parser<'argtype> {
         &'b self,
         i: &'a [u8],
     ) -> IResult<&'a [u8], PacketType<'a, 'b>> {
-        assert!(i.len() == 0x20);
+        if i.len() != 0x20 {
+            return Err(parsing_failure(format!(
+                "player orientation packet has {} bytes, expected 32",
+                i.len()
+            )));
+        }
         let (i, pid) = le_u32(i)?;
         let (i, parent_id) = le_u32(i)?;
         let (i, position) = Vec3::parse(i)?;
@@ -486,7 +794,7 @@ impl<'argtype> Parser<'argtype> {
     ) -> IResult<&'a [u8], PacketType<'a, 'b>> {
         let (i, entity_id) = le_u32(i)?;
         let (i, entity_type) = le_u16(i)?;
-        let spec = &self.specs[entity_type as usize - 1];
+        let spec = self.entity_spec(entity_type)?;
 
         let mut i = i;
         let mut props: HashMap<&str, _> = HashMap::new();
@@ -496,7 +804,6 @@ impl<'argtype> Parser<'argtype> {
             let (new_i, value) = match spec.prop_type.parse_value(i) {
                 Ok(x) => x,
                 Err(e) => {
-                    panic!("ERROR");
                     return Err(failure_from_kind(crate::ErrorKind::UnableToParseRpcValue {
                         method: format!("BasePlayerCreate::{}", spec.name),
                         argnum: prop_id as usize,
@@ -543,6 +850,7 @@ impl<'argtype> Parser<'argtype> {
         let (i, rotation) = Rot3::parse(i)?;
         let (i, state_length) = le_u32(i)?;
         let (_, state) = take(i.len())(i)?;
+        let entity_spec = self.entity_spec(entity_type)?;
         if self.entities.contains_key(&entity_id) {
             //println!("DBG: Entity {} got created twice!", entity_id);
         }
@@ -553,7 +861,15 @@ impl<'argtype> Parser<'argtype> {
         let mut stored_props: Vec<_> = vec![];
         for _ in 0..num_props {
             let (new_i, prop_id) = le_u8(i)?;
-            let spec = &self.specs[entity_type as usize - 1].properties[prop_id as usize];
+            let spec = entity_spec
+                .properties
+                .get(prop_id as usize)
+                .ok_or_else(|| {
+                    parsing_failure(format!(
+                        "property {} is out of range for {}",
+                        prop_id, entity_spec.name
+                    ))
+                })?;
             let (new_i, value) = match spec.prop_type.parse_value(new_i) {
                 Ok(x) => x,
                 Err(e) => {
@@ -584,7 +900,7 @@ impl<'argtype> Parser<'argtype> {
             PacketType::EntityCreate(EntityCreatePacket {
                 entity_id,
                 spec_idx: entity_type as usize,
-                entity_type: &self.specs[entity_type as usize - 1].name,
+                entity_type: &entity_spec.name,
                 space_id,
                 vehicle_id,
                 position,
@@ -609,10 +925,10 @@ impl<'argtype> Parser<'argtype> {
         let (i, props_data) = take(props_len)(i)?;
 
         if !self.entities.contains_key(&entity_id) {
-            panic!(
+            return Err(parsing_failure(format!(
                 "Cell player, entity id {}, was created before base player!",
                 entity_id
-            );
+            )));
         }
 
         // The value can be parsed into all internal properties
@@ -630,8 +946,7 @@ impl<'argtype> Parser<'argtype> {
             dirz,
             value.len()
         );*/
-        let entity_type = self.entities.get(&entity_id).unwrap().entity_type;
-        let spec = &self.specs[entity_type as usize - 1];
+        let spec = self.entity_spec(self.entity_type(entity_id)?)?;
 
         let mut i = props_data;
         let mut props: HashMap<&str, _> = HashMap::new();
@@ -641,7 +956,6 @@ impl<'argtype> Parser<'argtype> {
             let (new_i, value) = match spec.prop_type.parse_value(i) {
                 Ok(x) => x,
                 Err(e) => {
-                    panic!("ERROR");
                     return Err(failure_from_kind(crate::ErrorKind::UnableToParseRpcValue {
                         method: format!("CellPlayerCreate::{}", spec.name),
                         argnum: prop_id as usize,
@@ -671,6 +985,21 @@ impl<'argtype> Parser<'argtype> {
         ))
     }
 
+    fn parse_entity_control<'a, 'b>(
+        &'b self,
+        i: &'a [u8],
+    ) -> IResult<&'a [u8], PacketType<'a, 'b>> {
+        let (i, entity_id) = le_u32(i)?;
+        let (i, is_controlled) = le_u8(i)?;
+        Ok((
+            i,
+            PacketType::EntityControl(EntityControlPacket {
+                entity_id,
+                is_controlled: is_controlled != 0,
+            }),
+        ))
+    }
+
     fn parse_entity_leave<'a, 'b>(&'b self, i: &'a [u8]) -> IResult<&'a [u8], PacketType<'a, 'b>> {
         let (i, entity_id) = le_u32(i)?;
         Ok((i, PacketType::EntityLeave(EntityLeavePacket { entity_id })))
@@ -706,7 +1035,6 @@ impl<'argtype> Parser<'argtype> {
         &'b mut self,
         i: &'a [u8],
     ) -> IResult<&'a [u8], PacketType<'a, 'b>> {
-        std::fs::write("map.bin", i);
         let (i, space_id) = le_u32(i)?;
         let (i, arena_id) = le_i64(i)?;
         let (i, unknown1) = le_u32(i)?;
@@ -722,8 +1050,8 @@ impl<'argtype> Parser<'argtype> {
             unknown1,
             unknown2,
             blob,
-            // TODO: Use a nom combinator for this (for error handling)
-            map_name: std::str::from_utf8(map_name).unwrap(),
+            map_name: std::str::from_utf8(map_name)
+                .map_err(|_| parsing_failure("Invalid UTF-8 data in map name".to_string()))?,
             matrix,
             unknown,
         };
@@ -755,6 +1083,7 @@ impl<'argtype> Parser<'argtype> {
             //0x7 | 0x8 => self.parse_entity_packet(version, packet_type, i)?,
             0x0 => self.parse_base_player_create(packet)?,
             0x1 => self.parse_cell_player_create(packet)?,
+            0x2 => self.parse_entity_control(packet)?,
             0x3 => self.parse_entity_enter(packet)?,
             0x4 => self.parse_entity_leave(packet)?,
             0x5 => self.parse_entity_create(packet)?,
@@ -770,17 +1099,30 @@ impl<'argtype> Parser<'argtype> {
             0x2c => self.parse_player_orientation_packet(packet)?,
             0x2f => self.parse_camera_freelook_packet(packet)?,
             0x32 => self.parse_cruise_state(packet)?,
-            _ => self.parse_unknown_packet(packet, packet.len().try_into().unwrap())?,
+            _ => self.parse_unknown_packet(packet, packet.len() as u32)?,
         };
         Ok((i, payload))
     }
 
+    /// Parses a single packet from the start of `i`, returning the packet and the
+    /// remaining input. Useful for exercising the parser on individual packets, e.g. when
+    /// fuzzing.
+    pub fn parse_packet_bytes<'a, 'b>(
+        &'b mut self,
+        i: &'a [u8],
+    ) -> Result<(&'a [u8], Packet<'a, 'b>), ErrorKind> {
+        Ok(self.parse_packet(i)?)
+    }
+
     fn parse_packet<'a, 'b>(&'b mut self, i: &'a [u8]) -> IResult<&'a [u8], Packet<'a, 'b>> {
         let (i, packet_size) = le_u32(i)?;
         let (i, packet_type) = le_u32(i)?;
         let (i, clock) = le_f32(i)?;
         let (remaining, packet_data) = take(packet_size)(i)?;
         let raw = packet_data;
+        #[cfg(feature = "packet-tracing")]
+        let _span = tracing::trace_span!("packet", packet_type, clock, packet_size).entered();
+        let bridged = self.bridged;
         let (_i, payload) = match self.parse_naked_packet(packet_type, packet_data) {
             Ok(x) => x,
             Err(nom::Err::Failure(Error {
@@ -790,6 +1132,11 @@ impl<'argtype> Parser<'argtype> {
                 return Err(failure_from_kind(ErrorKind::UnsupportedReplayVersion(n)));
             }
             Err(e) => {
+                if bridged {
+                    debug!(packet_type, clock, error = ?e, "failed to parse packet");
+                } else {
+                    warn!(packet_type, clock, error = ?e, "failed to parse packet");
+                }
                 (
                     &packet_data[0..0], // Empty reference
                     PacketType::Invalid(InvalidPacket {
@@ -818,6 +1165,7 @@ impl<'argtype> Parser<'argtype> {
         i: &'a [u8],
         p: &mut P,
     ) -> Result<(), ErrorKind> {
+        let _span = debug_span!("parse_packets", len = i.len()).entered();
         let mut i = i;
         while i.len() > 0 {
             let (remaining, packet) = self.parse_packet(i)?;
@@ -832,6 +1180,7 @@ impl<'argtype> Parser<'argtype> {
         i: &'a [u8],
         p: &P,
     ) -> Result<(), ErrorKind> {
+        let _span = debug_span!("parse_packets", len = i.len()).entered();
         let mut i = i;
         while i.len() > 0 {
             let (remaining, packet) = self.parse_packet(i)?;
@@ -840,6 +1189,60 @@ impl<'argtype> Parser<'argtype> {
         }
         Ok(())
     }
+
+    /// Parses only the packets which tell how the battle ended: the battle results, the
+    /// `onBattleEnd` method call, and the entity creations needed to look up which method
+    /// a call is. Every other packet is skipped without being decoded, which makes this
+    /// much faster than [Parser::parse_packets_mut] when only the outcome is needed, e.g.
+    /// to upload stats after a battle. Only the packets which were parsed are passed to
+    /// `p`.
+    pub fn parse_results_mut<P: PacketProcessorMut>(
+        &mut self,
+        i: &[u8],
+        p: &mut P,
+    ) -> Result<(), ErrorKind> {
+        let _span = debug_span!("parse_results", len = i.len()).entered();
+        let mut i = i;
+        while !i.is_empty() {
+            let (remaining, (packet_type, packet_data)) = Self::packet_header(i)?;
+            let wanted = match packet_type {
+                0x0 | 0x1 | 0x5 | 0x22 => true,
+                0x8 => self.is_battle_end_call(packet_data),
+                _ => false,
+            };
+            if wanted {
+                let (_, packet) = self.parse_packet(i)?;
+                p.process_mut(packet);
+            }
+            i = remaining;
+        }
+        Ok(())
+    }
+
+    /// Reads a packet's type and payload without parsing the payload, returning the input
+    /// after the packet
+    fn packet_header(i: &[u8]) -> IResult<&[u8], (u32, &[u8])> {
+        let (i, packet_size) = le_u32(i)?;
+        let (i, packet_type) = le_u32(i)?;
+        let (i, _clock) = le_f32(i)?;
+        let (remaining, packet_data) = take(packet_size)(i)?;
+        Ok((remaining, (packet_type, packet_data)))
+    }
+
+    /// Whether an entity method packet is a call of `onBattleEnd`, without parsing its
+    /// arguments
+    fn is_battle_end_call(&self, packet: &[u8]) -> bool {
+        let header: IResult<&[u8], (u32, u32)> = nom::sequence::pair(le_u32, le_u32)(packet);
+        let (_, (entity_id, method_id)) = match header {
+            Ok(header) => header,
+            Err(_) => return false,
+        };
+        self.entity_type(entity_id)
+            .and_then(|entity_type| self.entity_spec(entity_type))
+            .ok()
+            .and_then(|spec| spec.client_methods.get(method_id as usize))
+            .is_some_and(|method| method.name == "onBattleEnd")
+    }
 }
 
 pub trait PacketProcessor {
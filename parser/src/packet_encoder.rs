@@ -0,0 +1,587 @@
+//! Serializes packets back into the raw format that [crate::packet2::Parser] reads. This
+//! makes it possible to round-trip packets in tests, and to synthesize replays for testing
+//! analyzers deterministically.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use crate::analyzer::decoder::{self, CameraMode, DecodedPacket, DecodedPacketPayload};
+use crate::error::ErrorKind;
+use crate::packet2::*;
+use crate::rpc::entitydefs::{EntitySpec, Method};
+use crate::rpc::typedefs::{ArgType, ArgValue};
+
+/// Encodes packets for a given set of entity specs. Like the parser, the encoder keeps
+/// track of the entities that have been created, since method calls and property updates
+/// are encoded relative to the entity's type.
+pub struct PacketEncoder<'argtype> {
+    specs: &'argtype [EntitySpec],
+    /// Maps entity IDs to their (1-based) entity type
+    entities: HashMap<u32, u16>,
+}
+
+impl<'argtype> PacketEncoder<'argtype> {
+    pub fn new(specs: &'argtype [EntitySpec]) -> Self {
+        PacketEncoder {
+            specs,
+            entities: HashMap::new(),
+        }
+    }
+
+    /// Encodes a packet along with its header. The packet type is derived from the payload,
+    /// so payloads without a known packet type (e.g. [PacketType::Unknown]) are rejected.
+    pub fn encode(
+        &mut self,
+        clock: f32,
+        payload: &PacketType<'_, '_>,
+    ) -> Result<Vec<u8>, ErrorKind> {
+        let packet_type = payload.type_id().ok_or_else(|| {
+            ErrorKind::EncodingFailure(format!("{:?} packets have no packet type", payload.kind()))
+        })?;
+        let payload = self.encode_payload(payload)?;
+        Ok(frame(packet_type, clock, &payload))
+    }
+
+    /// Re-encodes a parsed packet, including its header
+    pub fn encode_packet(&mut self, packet: &Packet<'_, '_>) -> Result<Vec<u8>, ErrorKind> {
+        let payload = self.encode_payload(&packet.payload)?;
+        Ok(frame(packet.packet_type, packet.clock, &payload))
+    }
+
+    /// Encodes a decoded packet, including its header. Only payloads which map directly to
+    /// a single raw packet are supported: payloads decoded from entity methods (e.g. chat
+    /// messages or ship deaths) must be encoded as [PacketType::EntityMethod] instead.
+    pub fn encode_decoded(
+        &mut self,
+        packet: &DecodedPacket<'_, '_, '_>,
+    ) -> Result<Vec<u8>, ErrorKind> {
+        let mut out = Vec::new();
+        match &packet.payload {
+            DecodedPacketPayload::Position(position) => encode_position(position, &mut out),
+            DecodedPacketPayload::PlayerOrientation(orientation) => {
+                encode_player_orientation(orientation, &mut out)
+            }
+            DecodedPacketPayload::EntityMethod(method) => {
+                self.encode_entity_method(method, &mut out)?
+            }
+            DecodedPacketPayload::EntityProperty(property) => {
+                self.encode_entity_property(property, &mut out)?
+            }
+            DecodedPacketPayload::BasePlayerCreate(create) => {
+                self.encode_base_player_create(create, &mut out)?
+            }
+            DecodedPacketPayload::CellPlayerCreate(create) => {
+                self.encode_cell_player_create(create, &mut out)?
+            }
+            DecodedPacketPayload::EntityControl(control) => {
+                encode_entity_control(control, &mut out)
+            }
+            DecodedPacketPayload::EntityEnter(enter) => encode_entity_enter(enter, &mut out),
+            DecodedPacketPayload::EntityLeave(leave) => put_u32(&mut out, leave.entity_id),
+            DecodedPacketPayload::EntityCreate(create) => {
+                self.encode_entity_create(create, &mut out)?
+            }
+            DecodedPacketPayload::CruiseState { state, value } => {
+                let key = match state {
+                    decoder::CruiseState::Throttle => 0,
+                    decoder::CruiseState::Rudder => 1,
+                    decoder::CruiseState::DiveDepth => 2,
+                    decoder::CruiseState::Unknown(key) => *key,
+                };
+                put_u32(&mut out, key);
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            DecodedPacketPayload::Map(map) => encode_map(map, &mut out)?,
+            DecodedPacketPayload::Version(version) => put_str(&mut out, version)?,
+            DecodedPacketPayload::Camera(camera) => encode_camera(camera, &mut out),
+            DecodedPacketPayload::CameraMode(mode) => put_u32(
+                &mut out,
+                match mode {
+                    CameraMode::OverheadMap => 3,
+                    CameraMode::FollowingShells => 5,
+                    CameraMode::FollowingPlanes => 6,
+                    CameraMode::FollowingShip => 8,
+                    CameraMode::FreeFlying => 9,
+                    CameraMode::FollowingSubmarine => 11,
+                    CameraMode::Unknown(mode) => *mode,
+                },
+            ),
+            DecodedPacketPayload::CameraFreeLook(freelook) => out.push(*freelook as u8),
+            DecodedPacketPayload::BattleResults(results) => put_str(&mut out, results)?,
+            DecodedPacketPayload::Unknown(raw) => out.extend_from_slice(raw),
+            payload => {
+                return Err(ErrorKind::EncodingFailure(format!(
+                    "Cannot encode decoded payload {:?}",
+                    payload
+                )))
+            }
+        }
+        Ok(frame(packet.packet_type, packet.clock, &out))
+    }
+
+    /// Encodes a packet's payload, without the packet header
+    pub fn encode_payload(&mut self, payload: &PacketType<'_, '_>) -> Result<Vec<u8>, ErrorKind> {
+        let mut out = Vec::new();
+        match payload {
+            PacketType::Position(position) => encode_position(position, &mut out),
+            PacketType::BasePlayerCreate(create) => {
+                self.encode_base_player_create(create, &mut out)?
+            }
+            PacketType::CellPlayerCreate(create) => {
+                self.encode_cell_player_create(create, &mut out)?
+            }
+            PacketType::EntityControl(control) => encode_entity_control(control, &mut out),
+            PacketType::EntityEnter(enter) => encode_entity_enter(enter, &mut out),
+            PacketType::EntityLeave(leave) => put_u32(&mut out, leave.entity_id),
+            PacketType::EntityCreate(create) => self.encode_entity_create(create, &mut out)?,
+            PacketType::EntityProperty(property) => {
+                self.encode_entity_property(property, &mut out)?
+            }
+            PacketType::EntityMethod(method) => self.encode_entity_method(method, &mut out)?,
+            PacketType::CustomMethod(custom) => {
+                self.encode_raw_method(custom.entity_id, custom.method, custom.raw_args, &mut out)?
+            }
+            PacketType::PartialDecode(partial) => self.encode_raw_method(
+                partial.entity_id,
+                partial.method,
+                partial.raw_args,
+                &mut out,
+            )?,
+            PacketType::PlayerOrientation(orientation) => {
+                encode_player_orientation(orientation, &mut out)
+            }
+            PacketType::CruiseState(state) => {
+                put_u32(&mut out, state.key);
+                out.extend_from_slice(&state.value.to_le_bytes());
+            }
+            PacketType::Version(version) => put_str(&mut out, version)?,
+            PacketType::Camera(camera) => encode_camera(camera, &mut out),
+            PacketType::CameraMode(mode) => put_u32(&mut out, *mode),
+            PacketType::CameraFreeLook(freelook) => out.push(*freelook),
+            PacketType::Map(map) => encode_map(map, &mut out)?,
+            PacketType::BattleResults(results) => put_str(&mut out, results)?,
+            PacketType::Unknown(raw) => out.extend_from_slice(raw),
+            PacketType::PropertyUpdate(_) | PacketType::Invalid(_) => {
+                return Err(ErrorKind::EncodingFailure(format!(
+                    "Cannot encode {:?} packets",
+                    payload.kind()
+                )))
+            }
+        }
+        Ok(out)
+    }
+
+    fn spec(&self, entity_type: u16) -> Result<&'argtype EntitySpec, ErrorKind> {
+        (entity_type as usize)
+            .checked_sub(1)
+            .and_then(|idx| self.specs.get(idx))
+            .ok_or_else(|| {
+                ErrorKind::EncodingFailure(format!("Unknown entity type {}", entity_type))
+            })
+    }
+
+    fn spec_by_name(&self, name: &str) -> Result<(u16, &'argtype EntitySpec), ErrorKind> {
+        self.specs
+            .iter()
+            .enumerate()
+            .find(|(_, spec)| spec.name == name)
+            .map(|(idx, spec)| (idx as u16 + 1, spec))
+            .ok_or_else(|| ErrorKind::EncodingFailure(format!("Unknown entity type {}", name)))
+    }
+
+    fn entity_spec(&self, entity_id: u32) -> Result<&'argtype EntitySpec, ErrorKind> {
+        let entity_type = self.entities.get(&entity_id).ok_or_else(|| {
+            ErrorKind::EncodingFailure(format!("Entity {} was never created", entity_id))
+        })?;
+        self.spec(*entity_type)
+    }
+
+    fn encode_base_player_create(
+        &mut self,
+        create: &BasePlayerCreatePacket<'_>,
+        out: &mut Vec<u8>,
+    ) -> Result<(), ErrorKind> {
+        let (entity_type, spec) = self.spec_by_name(create.entity_type)?;
+        put_u32(out, create.entity_id);
+        out.extend_from_slice(&entity_type.to_le_bytes());
+        for property in spec.base_properties.iter() {
+            encode_prop(&property.name, &property.prop_type, &create.props, out)?;
+        }
+        self.entities.insert(create.entity_id, entity_type);
+        Ok(())
+    }
+
+    fn encode_cell_player_create(
+        &mut self,
+        create: &CellPlayerCreatePacket<'_>,
+        out: &mut Vec<u8>,
+    ) -> Result<(), ErrorKind> {
+        let spec = self.entity_spec(create.entity_id)?;
+        let mut props = Vec::new();
+        for property in spec.internal_properties.iter() {
+            encode_prop(
+                &property.name,
+                &property.prop_type,
+                &create.props,
+                &mut props,
+            )?;
+        }
+
+        put_u32(out, create.entity_id);
+        put_u32(out, create.space_id);
+        put_u32(out, create.vehicle_id);
+        put_vec3(out, &create.position);
+        put_rot3(out, &create.rotation);
+        put_u32(out, props.len() as u32);
+        out.extend_from_slice(&props);
+        Ok(())
+    }
+
+    fn encode_entity_create(
+        &mut self,
+        create: &EntityCreatePacket<'_>,
+        out: &mut Vec<u8>,
+    ) -> Result<(), ErrorKind> {
+        let entity_type = create.spec_idx as u16;
+        let spec = self.spec(entity_type)?;
+
+        // Properties are written in spec order so that the output is deterministic
+        let mut state = vec![create.props.len() as u8];
+        let mut encoded = 0;
+        for (prop_id, property) in spec.properties.iter().enumerate() {
+            if let Some(value) = create.props.get(property.name.as_str()) {
+                state.push(prop_id as u8);
+                property.prop_type.encode_value(value, &mut state)?;
+                encoded += 1;
+            }
+        }
+        if encoded != create.props.len() {
+            return Err(ErrorKind::EncodingFailure(format!(
+                "{} entity has properties which are not in its spec",
+                spec.name
+            )));
+        }
+
+        put_u32(out, create.entity_id);
+        out.extend_from_slice(&entity_type.to_le_bytes());
+        put_u32(out, create.vehicle_id);
+        put_u32(out, create.space_id);
+        put_vec3(out, &create.position);
+        put_rot3(out, &create.rotation);
+        put_u32(out, state.len() as u32);
+        out.extend_from_slice(&state);
+        self.entities.insert(create.entity_id, entity_type);
+        Ok(())
+    }
+
+    fn encode_entity_property(
+        &self,
+        property: &EntityPropertyPacket<'_>,
+        out: &mut Vec<u8>,
+    ) -> Result<(), ErrorKind> {
+        let spec = self.entity_spec(property.entity_id)?;
+        let (prop_id, prop_spec) = spec
+            .properties
+            .iter()
+            .enumerate()
+            .find(|(_, prop)| prop.name == property.property)
+            .ok_or_else(|| {
+                ErrorKind::EncodingFailure(format!(
+                    "{} has no property {}",
+                    spec.name, property.property
+                ))
+            })?;
+
+        let mut payload = Vec::new();
+        prop_spec
+            .prop_type
+            .encode_value(&property.value, &mut payload)?;
+        put_u32(out, property.entity_id);
+        put_u32(out, prop_id as u32);
+        put_u32(out, payload.len() as u32);
+        out.extend_from_slice(&payload);
+        Ok(())
+    }
+
+    fn encode_entity_method(
+        &self,
+        method: &EntityMethodPacket<'_>,
+        out: &mut Vec<u8>,
+    ) -> Result<(), ErrorKind> {
+        let (method_id, method_spec) = self.client_method(method.entity_id, method.method)?;
+        if method_spec.args.len() != method.args.len() {
+            return Err(ErrorKind::EncodingFailure(format!(
+                "{} takes {} arguments, got {}",
+                method.method,
+                method_spec.args.len(),
+                method.args.len()
+            )));
+        }
+
+        let mut payload = Vec::new();
+        for (arg, value) in method_spec.args.iter().zip(method.args.iter()) {
+            arg.encode_value(value, &mut payload)?;
+        }
+        put_u32(out, method.entity_id);
+        put_u32(out, method_id as u32);
+        put_u32(out, payload.len() as u32);
+        out.extend_from_slice(&payload);
+        Ok(())
+    }
+
+    /// Writes a method call with its arguments' raw payload
+    fn encode_raw_method(
+        &self,
+        entity_id: u32,
+        method: &str,
+        raw_args: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<(), ErrorKind> {
+        let (method_id, _) = self.client_method(entity_id, method)?;
+        put_u32(out, entity_id);
+        put_u32(out, method_id as u32);
+        put_u32(out, raw_args.len() as u32);
+        out.extend_from_slice(raw_args);
+        Ok(())
+    }
+
+    fn client_method(
+        &self,
+        entity_id: u32,
+        name: &str,
+    ) -> Result<(usize, &'argtype Method), ErrorKind> {
+        let spec = self.entity_spec(entity_id)?;
+        spec.client_methods
+            .iter()
+            .enumerate()
+            .find(|(_, m)| m.name == name)
+            .ok_or_else(|| {
+                ErrorKind::EncodingFailure(format!("{} has no client method {}", spec.name, name))
+            })
+    }
+}
+
+/// Prepends the packet header to a payload
+fn frame(packet_type: u32, clock: f32, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(12 + payload.len());
+    put_u32(&mut out, payload.len() as u32);
+    put_u32(&mut out, packet_type);
+    out.extend_from_slice(&clock.to_le_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+fn encode_prop(
+    name: &str,
+    prop_type: &ArgType,
+    props: &HashMap<&str, ArgValue<'_>>,
+    out: &mut Vec<u8>,
+) -> Result<(), ErrorKind> {
+    let value = props
+        .get(name)
+        .ok_or_else(|| ErrorKind::EncodingFailure(format!("Missing property {}", name)))?;
+    prop_type.encode_value(value, out)
+}
+
+fn encode_position(position: &PositionPacket, out: &mut Vec<u8>) {
+    put_u32(out, position.pid);
+    put_u32(out, 0);
+    put_vec3(out, &position.position);
+    put_vec3(out, &position.position_error);
+    put_rot3(out, &position.rotation);
+    out.push(position.is_error as u8);
+}
+
+fn encode_player_orientation(orientation: &PlayerOrientationPacket, out: &mut Vec<u8>) {
+    put_u32(out, orientation.pid);
+    put_u32(out, orientation.parent_id);
+    put_vec3(out, &orientation.position);
+    put_rot3(out, &orientation.rotation);
+}
+
+fn encode_entity_control(control: &EntityControlPacket, out: &mut Vec<u8>) {
+    put_u32(out, control.entity_id);
+    out.push(control.is_controlled as u8);
+}
+
+fn encode_entity_enter(enter: &EntityEnterPacket, out: &mut Vec<u8>) {
+    put_u32(out, enter.entity_id);
+    put_u32(out, enter.space_id);
+    put_u32(out, enter.vehicle_id);
+}
+
+fn encode_camera(camera: &CameraPacket, out: &mut Vec<u8>) {
+    put_vec3(out, &camera.unknown);
+    put_u32(out, camera.unknown2);
+    put_vec3(out, &camera.absolute_position);
+    out.extend_from_slice(&camera.fov.to_le_bytes());
+    put_vec3(out, &camera.position);
+    put_rot3(out, &camera.rotation);
+}
+
+fn encode_map(map: &MapPacket<'_>, out: &mut Vec<u8>) -> Result<(), ErrorKind> {
+    if map.blob.len() != 128 || map.matrix.len() != 64 {
+        return Err(ErrorKind::EncodingFailure(
+            "Map packets need a 128 byte blob and a 64 byte matrix".to_string(),
+        ));
+    }
+    put_u32(out, map.space_id);
+    out.extend_from_slice(&map.arena_id.to_le_bytes());
+    put_u32(out, map.unknown1);
+    put_u32(out, map.unknown2);
+    out.extend_from_slice(map.blob);
+    put_str(out, map.map_name)?;
+    out.extend_from_slice(map.matrix);
+    out.push(map.unknown);
+    Ok(())
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Writes a u32 length-prefixed string
+fn put_str(out: &mut Vec<u8>, value: &str) -> Result<(), ErrorKind> {
+    let len = u32::try_from(value.len())
+        .map_err(|_| ErrorKind::EncodingFailure("String is too long".to_string()))?;
+    put_u32(out, len);
+    out.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn put_vec3(out: &mut Vec<u8>, v: &Vec3) {
+    for f in [v.x, v.y, v.z] {
+        out.extend_from_slice(&f.to_le_bytes());
+    }
+}
+
+fn put_rot3(out: &mut Vec<u8>, r: &Rot3) {
+    for f in [r.roll, r.pitch, r.yaw] {
+        out.extend_from_slice(&f.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roundtrip(payload: PacketType<'_, '_>) {
+        let mut encoder = PacketEncoder::new(&[]);
+        let encoded = encoder.encode(12.5, &payload).unwrap();
+
+        let mut parser = Parser::new(&[]);
+        let (remaining, packet) = parser.parse_packet_bytes(&encoded).unwrap();
+        assert!(remaining.is_empty());
+        assert_eq!(packet.clock, 12.5);
+        assert_eq!(packet.packet_type, payload.type_id().unwrap());
+        assert_eq!(
+            serde_json::to_value(&packet.payload).unwrap(),
+            serde_json::to_value(&payload).unwrap()
+        );
+        assert_eq!(encoder.encode_packet(&packet).unwrap(), encoded);
+    }
+
+    #[test]
+    fn test_roundtrip_simple_packets() {
+        roundtrip(PacketType::Position(PositionPacket {
+            pid: 1234,
+            position: Vec3 {
+                x: 1.0,
+                y: 2.0,
+                z: -3.5,
+            },
+            position_error: Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            rotation: Rot3 {
+                roll: 0.1,
+                pitch: 0.2,
+                yaw: 3.0,
+            },
+            is_error: false,
+        }));
+        roundtrip(PacketType::EntityEnter(EntityEnterPacket {
+            entity_id: 1,
+            space_id: 2,
+            vehicle_id: 3,
+        }));
+        roundtrip(PacketType::EntityControl(EntityControlPacket {
+            entity_id: 4,
+            is_controlled: true,
+        }));
+        roundtrip(PacketType::CruiseState(CruiseState { key: 1, value: -2 }));
+        roundtrip(PacketType::Version("0,11,7,0".to_string()));
+        roundtrip(PacketType::BattleResults("{\"arenaUniqueID\": 1}"));
+    }
+
+    #[test]
+    fn test_parse_results_skips_packets() {
+        struct Collector(Vec<u32>);
+        impl crate::packet2::PacketProcessorMut for Collector {
+            fn process_mut(&mut self, packet: Packet<'_, '_>) {
+                self.0.push(packet.packet_type);
+            }
+        }
+
+        let mut encoder = PacketEncoder::new(&[]);
+        let mut data = encoder
+            .encode(1.0, &PacketType::Version("0,11,7,0".to_string()))
+            .unwrap();
+        data.extend(
+            encoder
+                .encode(
+                    2.0,
+                    &PacketType::CruiseState(CruiseState { key: 1, value: 2 }),
+                )
+                .unwrap(),
+        );
+        data.extend(
+            encoder
+                .encode(3.0, &PacketType::BattleResults("{}"))
+                .unwrap(),
+        );
+
+        let mut collector = Collector(vec![]);
+        Parser::new(&[])
+            .parse_results_mut(&data, &mut collector)
+            .unwrap();
+        assert_eq!(collector.0, vec![0x22]);
+    }
+
+    #[test]
+    fn test_roundtrip_arg_values() {
+        use crate::rpc::typedefs::{FixedDictProperty, PrimitiveType};
+
+        let argtype = ArgType::Array((
+            None,
+            Box::new(ArgType::FixedDict((
+                true,
+                vec![
+                    FixedDictProperty {
+                        name: "name".to_string(),
+                        prop_type: ArgType::Primitive(PrimitiveType::String),
+                    },
+                    FixedDictProperty {
+                        name: "value".to_string(),
+                        prop_type: ArgType::Primitive(PrimitiveType::Float32),
+                    },
+                ],
+            ))),
+        ));
+        let mut dict = HashMap::new();
+        dict.insert("name", ArgValue::String(vec![b'a'; 300]));
+        dict.insert("value", ArgValue::Float32(1.5));
+        let value = ArgValue::Array(vec![
+            ArgValue::NullableFixedDict(Some(dict)),
+            ArgValue::NullableFixedDict(None),
+        ]);
+
+        let mut encoded = Vec::new();
+        argtype.encode_value(&value, &mut encoded).unwrap();
+        let (remaining, parsed) = argtype.parse_value(&encoded).unwrap();
+        assert!(remaining.is_empty());
+        assert_eq!(parsed, value);
+    }
+}
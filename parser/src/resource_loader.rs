@@ -1,10 +1,257 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
 use crate::Rc;
 
-use crate::{game_params::Param, rpc::entitydefs::EntitySpec};
+use crate::{
+    build::ConsumableSpec,
+    game_params::{AbilityCategory, CrewSkill, GameParamProvider, GameParams, Param},
+    rpc::entitydefs::EntitySpec,
+    ErrorKind,
+};
+
+/// The localized name of something shown to players, along with its description if the
+/// translations have one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalizedText {
+    pub name: String,
+    pub description: Option<String>,
+}
 
 pub trait ResourceLoader {
     fn localized_name_from_param(&self, param: &Param) -> Option<&str>;
     fn localized_name_from_id(&self, id: &str) -> Option<String>;
     fn game_param_by_id(&self, id: u32) -> Option<Rc<Param>>;
     fn entity_specs(&self) -> &[EntitySpec];
+
+    /// The name and description of a consumable as it's equipped in `category`. The same
+    /// consumable can have a different name in each category, e.g. its premium version.
+    fn localized_ability(&self, category: &AbilityCategory) -> Option<LocalizedText> {
+        localized_consumable(self, category.title_id(), category.description_id())
+    }
+
+    /// The name and description of a consumable equipped on a ship in a battle report
+    fn localized_consumable(&self, spec: &ConsumableSpec) -> Option<LocalizedText> {
+        localized_consumable(self, &spec.title_id, &spec.description_id)
+    }
+
+    /// The name and description of a captain skill
+    fn localized_skill(&self, skill: &CrewSkill) -> Option<LocalizedText> {
+        // Skills are named in CamelCase, but their translation IDs are upper snake case
+        let id = upper_snake_case(skill.name());
+        Some(LocalizedText {
+            name: self.localized_name_from_id(&format!("IDS_SKILL_{}", id))?,
+            description: self.localized_name_from_id(&format!("IDS_SKILL_DESC_{}", id)),
+        })
+    }
+
+    /// The name and description of a modernization's GameParams entry
+    fn localized_modernization(&self, modernization: &Param) -> Option<LocalizedText> {
+        let id = modernization.name().to_uppercase();
+        Some(LocalizedText {
+            name: self.localized_name_from_id(&format!("IDS_TITLE_{}", id))?,
+            description: self.localized_name_from_id(&format!("IDS_DESC_{}", id)),
+        })
+    }
+}
+
+fn localized_consumable<R: ResourceLoader + ?Sized>(
+    loader: &R,
+    title_id: &str,
+    description_id: &str,
+) -> Option<LocalizedText> {
+    Some(LocalizedText {
+        name: loader.localized_name_from_id(&format!(
+            "IDS_DOCK_CONSUME_TITLE_{}",
+            title_id.to_uppercase()
+        ))?,
+        description: loader.localized_name_from_id(&format!(
+            "IDS_DOCK_CONSUME_DESCRIPTION_{}",
+            description_id.to_uppercase()
+        )),
+    })
+}
+
+/// Converts e.g. `ArmamentReloadAaDamage` to `ARMAMENT_RELOAD_AA_DAMAGE`
+fn upper_snake_case(name: &str) -> String {
+    let mut converted = String::with_capacity(name.len() + 4);
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_uppercase() && previous.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit()) {
+            converted.push('_');
+        }
+        converted.extend(c.to_uppercase());
+        previous = Some(c);
+    }
+    converted
+}
+
+/// The on-disk format of a GameParams dump, as produced by serializing
+/// [crate::game_params::GameParams]
+#[derive(Deserialize)]
+struct SerializedGameParams {
+    params: Vec<Param>,
+}
+
+/// Game data which doesn't depend on the game version of a replay: the GameParams and
+/// translations. Pair it with the entity specs for a replay's version to get a
+/// [ResourceLoader], or use [crate::analyze_replay] which takes care of that.
+pub struct GameDataSource {
+    params: GameParams,
+    translations: HashMap<String, String>,
+}
+
+impl GameDataSource {
+    pub fn new(params: GameParams, translations: HashMap<String, String>) -> Self {
+        Self {
+            params,
+            translations,
+        }
+    }
+
+    /// Loads a JSON GameParams dump and, optionally, a JSON object mapping translation
+    /// IDs (e.g. `IDS_PASB017`) to localized strings.
+    pub fn load(
+        game_params: &std::path::Path,
+        translations: Option<&std::path::Path>,
+    ) -> Result<Self, ErrorKind> {
+        let file = std::fs::File::open(game_params)
+            .map_err(|e| ErrorKind::ParsingFailure(format!("{:?}: {}", game_params, e)))?;
+        let params: SerializedGameParams = serde_json::from_reader(std::io::BufReader::new(file))?;
+
+        let translations = match translations {
+            Some(path) => {
+                let file = std::fs::File::open(path)
+                    .map_err(|e| ErrorKind::ParsingFailure(format!("{:?}: {}", path, e)))?;
+                serde_json::from_reader(std::io::BufReader::new(file))?
+            }
+            None => HashMap::new(),
+        };
+
+        Ok(Self::new(GameParams::from(params.params), translations))
+    }
+
+    pub fn params(&self) -> &GameParams {
+        &self.params
+    }
+
+    pub fn localized_name_from_param(&self, param: &Param) -> Option<&str> {
+        self.translations
+            .get(&format!("IDS_{}", param.index()))
+            .map(|name| name.as_str())
+    }
+
+    pub fn localized_name_from_id(&self, id: &str) -> Option<String> {
+        self.translations.get(id).cloned()
+    }
+
+    pub fn game_param_by_id(&self, id: u32) -> Option<Rc<Param>> {
+        self.params.game_param_by_id(id)
+    }
+
+    /// Combines this data with the entity specs of a specific game version
+    pub fn with_specs(&self, specs: Vec<EntitySpec>) -> VersionedGameData<'_> {
+        VersionedGameData {
+            source: self,
+            specs,
+        }
+    }
+}
+
+/// A [GameDataSource] along with the entity specs for one game version
+pub struct VersionedGameData<'a> {
+    source: &'a GameDataSource,
+    specs: Vec<EntitySpec>,
+}
+
+impl<'a> ResourceLoader for VersionedGameData<'a> {
+    fn localized_name_from_param(&self, param: &Param) -> Option<&str> {
+        self.source.localized_name_from_param(param)
+    }
+
+    fn localized_name_from_id(&self, id: &str) -> Option<String> {
+        self.source.localized_name_from_id(id)
+    }
+
+    fn game_param_by_id(&self, id: u32) -> Option<Rc<Param>> {
+        self.source.game_param_by_id(id)
+    }
+
+    fn entity_specs(&self) -> &[EntitySpec] {
+        self.specs.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::game_params::{AbilityCategoryBuilder, ParamBuilder, ParamData, Species};
+
+    #[test]
+    fn test_localized_text() {
+        assert_eq!(
+            upper_snake_case("ArmamentReloadAaDamage"),
+            "ARMAMENT_RELOAD_AA_DAMAGE"
+        );
+
+        let translations = HashMap::from([
+            (
+                "IDS_DOCK_CONSUME_TITLE_PCY009_REGENERATEHEALTHPREMIUM".to_string(),
+                "Repair Party".to_string(),
+            ),
+            (
+                "IDS_TITLE_PCM001_MAINGUN_MOD_I".to_string(),
+                "Main Armaments Modification 1".to_string(),
+            ),
+            (
+                "IDS_DESC_PCM001_MAINGUN_MOD_I".to_string(),
+                "Main battery survivability".to_string(),
+            ),
+        ]);
+        let data = GameDataSource::new(GameParams::from(vec![]), translations);
+        let data = data.with_specs(vec![]);
+
+        let heal = AbilityCategoryBuilder::default()
+            .special_sound_id(None)
+            .consumable_type("regenCrew".to_string())
+            .description_id("PCY009_RegenerateHealthPremium".to_string())
+            .group(String::new())
+            .icon_id(String::new())
+            .num_consumables(2)
+            .preparation_time(0.0)
+            .reload_time(80.0)
+            .title_id("PCY009_RegenerateHealthPremium".to_string())
+            .work_time(28.0)
+            .build()
+            .unwrap();
+        assert_eq!(
+            data.localized_ability(&heal),
+            Some(LocalizedText {
+                name: "Repair Party".to_string(),
+                description: None,
+            })
+        );
+
+        let modernization = ParamBuilder::default()
+            .id(1)
+            .index("PCM001".to_string())
+            .name("PCM001_MainGun_Mod_I".to_string())
+            .species(Some(Species::Modifier))
+            .nation(String::new())
+            .data(ParamData::Modernization(
+                crate::game_params::ModernizationBuilder::default()
+                    .modifiers(vec![])
+                    .build()
+                    .unwrap(),
+            ))
+            .build()
+            .unwrap();
+        let text = data.localized_modernization(&modernization).unwrap();
+        assert_eq!(text.name, "Main Armaments Modification 1");
+        assert_eq!(
+            text.description.as_deref(),
+            Some("Main battery survivability")
+        );
+    }
 }
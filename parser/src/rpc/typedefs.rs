@@ -4,7 +4,7 @@ use nom::{
     bytes::complete::take, number::complete::le_f32, number::complete::le_f64,
     number::complete::le_u16, number::complete::le_u32,
 };
-use serde::ser::{SerializeMap, SerializeSeq, SerializeTuple};
+use serde::ser::{SerializeSeq, SerializeTuple};
 use std::collections::HashMap;
 use std::convert::TryInto;
 
@@ -136,6 +136,61 @@ impl PrimitiveType {
             }
         }
     }
+
+    /// Serializes `value` in the wire format [PrimitiveType::parse_value] reads
+    fn encode_value(&self, value: &ArgValue<'_>, out: &mut Vec<u8>) -> Result<(), ErrorKind> {
+        match (self, value) {
+            (PrimitiveType::Uint8, ArgValue::Uint8(v)) => out.push(*v),
+            (PrimitiveType::Uint16, ArgValue::Uint16(v)) => out.extend_from_slice(&v.to_le_bytes()),
+            (PrimitiveType::Uint32, ArgValue::Uint32(v)) => out.extend_from_slice(&v.to_le_bytes()),
+            (PrimitiveType::Uint64, ArgValue::Uint64(v)) => out.extend_from_slice(&v.to_le_bytes()),
+            (PrimitiveType::Int8, ArgValue::Int8(v)) => out.extend_from_slice(&v.to_le_bytes()),
+            (PrimitiveType::Int16, ArgValue::Int16(v)) => out.extend_from_slice(&v.to_le_bytes()),
+            (PrimitiveType::Int32, ArgValue::Int32(v)) => out.extend_from_slice(&v.to_le_bytes()),
+            (PrimitiveType::Int64, ArgValue::Int64(v)) => out.extend_from_slice(&v.to_le_bytes()),
+            (PrimitiveType::Float32, ArgValue::Float32(v)) => {
+                out.extend_from_slice(&v.to_le_bytes())
+            }
+            (PrimitiveType::Float64, ArgValue::Float64(v)) => {
+                out.extend_from_slice(&v.to_le_bytes())
+            }
+            (PrimitiveType::Vector2, ArgValue::Vector2((x, y))) => {
+                out.extend_from_slice(&x.to_le_bytes());
+                out.extend_from_slice(&y.to_le_bytes());
+            }
+            (PrimitiveType::Vector3, ArgValue::Vector3((x, y, z))) => {
+                out.extend_from_slice(&x.to_le_bytes());
+                out.extend_from_slice(&y.to_le_bytes());
+                out.extend_from_slice(&z.to_le_bytes());
+            }
+            (PrimitiveType::Blob, ArgValue::Blob(data))
+            | (PrimitiveType::String, ArgValue::String(data))
+            | (PrimitiveType::UnicodeString, ArgValue::UnicodeString(data)) => {
+                if data.len() < 0xff {
+                    out.push(data.len() as u8);
+                } else {
+                    let size: u16 = data.len().try_into().map_err(|_| {
+                        ErrorKind::EncodingFailure(format!(
+                            "{:?} of {} bytes is too long",
+                            self,
+                            data.len()
+                        ))
+                    })?;
+                    out.push(0xff);
+                    out.extend_from_slice(&size.to_le_bytes());
+                    out.push(0);
+                }
+                out.extend_from_slice(data);
+            }
+            _ => {
+                return Err(ErrorKind::EncodingFailure(format!(
+                    "Cannot encode {:?} as {:?}",
+                    value, self
+                )))
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -177,6 +232,21 @@ pub enum ArgValue<'argtype> {
     Tuple(Vec<ArgValue<'argtype>>),
 }
 
+/// Serializes a map with its keys in ascending order, so that the output is the same
+/// every time the same data is serialized
+pub fn serialize_sorted_map<
+    K: Ord + serde::Serialize,
+    V: serde::Serialize,
+    S: serde::Serializer,
+>(
+    map: &HashMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort_by_key(|(key, _)| *key);
+    serializer.collect_map(entries)
+}
+
 impl<'argtype> serde::Serialize for ArgValue<'argtype> {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         //serializer.serialize_i32(5)
@@ -226,20 +296,8 @@ impl<'argtype> serde::Serialize for ArgValue<'argtype> {
                 }
                 seq.end()
             }
-            Self::FixedDict(d) => {
-                let mut obj = serializer.serialize_map(Some(d.len()))?;
-                for (k, v) in d.iter() {
-                    obj.serialize_entry(k, v)?;
-                }
-                obj.end()
-            }
-            Self::NullableFixedDict(Some(d)) => {
-                let mut obj = serializer.serialize_map(Some(d.len()))?;
-                for (k, v) in d.iter() {
-                    obj.serialize_entry(k, v)?;
-                }
-                obj.end()
-            }
+            Self::FixedDict(d) => serialize_sorted_map(d, serializer),
+            Self::NullableFixedDict(Some(d)) => serialize_sorted_map(d, serializer),
             Self::NullableFixedDict(None) => serializer.serialize_none(),
             Self::Tuple(_t) => {
                 unimplemented!();
@@ -354,6 +412,60 @@ impl ArgType {
             }
         }
     }
+
+    /// Serializes `value` in the wire format [ArgType::parse_value] reads, such that parsing
+    /// the output yields `value` again
+    pub fn encode_value(&self, value: &ArgValue<'_>, out: &mut Vec<u8>) -> Result<(), ErrorKind> {
+        match (self, value) {
+            (Self::Primitive(p), value) => p.encode_value(value, out),
+            (Self::Array((count, atype)), ArgValue::Array(values)) => {
+                match count {
+                    Some(count) if *count != values.len() => {
+                        return Err(ErrorKind::EncodingFailure(format!(
+                            "Expected {} array elements, got {}",
+                            count,
+                            values.len()
+                        )));
+                    }
+                    Some(_) => {}
+                    None => out.push(values.len().try_into().map_err(|_| {
+                        ErrorKind::EncodingFailure(format!(
+                            "Array of {} elements is too long",
+                            values.len()
+                        ))
+                    })?),
+                }
+                for value in values {
+                    atype.encode_value(value, out)?;
+                }
+                Ok(())
+            }
+            (Self::FixedDict((true, _)), ArgValue::NullableFixedDict(None)) => {
+                out.push(0);
+                Ok(())
+            }
+            (Self::FixedDict((allow_none, props)), ArgValue::FixedDict(dict))
+            | (Self::FixedDict((allow_none, props)), ArgValue::NullableFixedDict(Some(dict))) => {
+                if *allow_none {
+                    out.push(1);
+                }
+                for property in props.iter() {
+                    let value = dict.get(property.name.as_str()).ok_or_else(|| {
+                        ErrorKind::EncodingFailure(format!(
+                            "FixedDict is missing property {}",
+                            property.name
+                        ))
+                    })?;
+                    property.prop_type.encode_value(value, out)?;
+                }
+                Ok(())
+            }
+            _ => Err(ErrorKind::EncodingFailure(format!(
+                "Cannot encode {:?} as {:?}",
+                value, self
+            ))),
+        }
+    }
 }
 
 pub fn parse_type(arg: &roxmltree::Node, aliases: &HashMap<String, ArgType>) -> ArgType {
@@ -687,4 +799,17 @@ mod test {
         assert_eq!(args.1, -54);
         assert_eq!(args.2, vec![1, 3]);
     }
+
+    #[test]
+    fn test_fixed_dict_serialization_is_sorted() {
+        let dict: HashMap<&str, ArgValue> = ["zeta", "alpha", "mu", "beta"]
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (*key, ArgValue::Uint8(i as u8)))
+            .collect();
+        assert_eq!(
+            serde_json::to_string(&ArgValue::FixedDict(dict)).unwrap(),
+            r#"{"alpha":1,"beta":3,"mu":2,"zeta":0}"#
+        );
+    }
 }
@@ -0,0 +1,506 @@
+//! Builds small synthetic replays, so that analyzers can be tested against a scripted
+//! sequence of packets instead of real (and large) replay files.
+//!
+//! ```no_run
+//! use wows_replays::packet2::Vec3;
+//! use wows_replays::testkit::{minimal_meta, ReplayBuilder};
+//! use wows_replays::version::{EmbeddedDataFiles, Version};
+//!
+//! let version = "0,11,7,0";
+//! let datafiles =
+//!     EmbeddedDataFiles::new("versions".into(), Version::from_client_exe(version)).unwrap();
+//! let specs = wows_replays::parse_scripts(&datafiles).unwrap();
+//!
+//! let mut builder = ReplayBuilder::new(&specs, minimal_meta(version));
+//! builder
+//!     .create_entity(0.0, 1, "Avatar").unwrap()
+//!     .chat(31.0, 1, 1, "battle_common", "gl hf").unwrap()
+//!     .position(32.0, 1, Vec3 { x: 10.0, y: 0.0, z: -5.0 }, 0.0).unwrap();
+//! std::fs::write("synthetic.wowsreplay", builder.build().unwrap()).unwrap();
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::analyzer::decoder::DeathCause;
+use crate::error::ErrorKind;
+use crate::packet2::*;
+use crate::packet_encoder::PacketEncoder;
+use crate::rpc::entitydefs::EntitySpec;
+use crate::rpc::typedefs::ArgValue;
+use crate::{ReplayCodec, ReplayFile, ReplayMeta};
+
+const REPLAY_MAGIC: u32 = 0x11343212;
+
+/// Replay metadata with just enough filled in to be parsed, for the given client version
+/// (e.g. `0,11,7,0`)
+pub fn minimal_meta(client_version: &str) -> ReplayMeta {
+    ReplayMeta {
+        matchGroup: "pvp".to_string(),
+        gameMode: 7,
+        gameType: "RandomBattle".to_string(),
+        clientVersionFromExe: client_version.to_string(),
+        scenarioUiCategoryId: 0,
+        mapDisplayName: "16_OC_bees_to_honey".to_string(),
+        mapId: 0,
+        clientVersionFromXml: client_version.replace(',', "_"),
+        weatherParams: BTreeMap::new(),
+        duration: 1200,
+        gameLogic: Some("Domination".to_string()),
+        name: "12x12".to_string(),
+        scenario: "Domination".to_string(),
+        playerID: 0,
+        vehicles: Vec::new(),
+        playersPerTeam: 12,
+        dateTime: "01.01.2022 00:00:00".to_string(),
+        mapName: "spaces/16_OC_bees_to_honey".to_string(),
+        playerName: "player".to_string(),
+        scenarioConfigId: 0,
+        teamsCount: 2,
+        logic: None,
+        playerVehicle: String::new(),
+        battleDuration: 1200,
+        extra: Default::default(),
+    }
+}
+
+/// Builds a replay from a scripted sequence of packets. Packets are encoded against the
+/// given entity specs, which should match the client version in the metadata.
+pub struct ReplayBuilder<'argtype> {
+    specs: &'argtype [EntitySpec],
+    meta: ReplayMeta,
+    encoder: PacketEncoder<'argtype>,
+    packet_data: Vec<u8>,
+}
+
+impl<'argtype> ReplayBuilder<'argtype> {
+    pub fn new(specs: &'argtype [EntitySpec], meta: ReplayMeta) -> Self {
+        ReplayBuilder {
+            specs,
+            meta,
+            encoder: PacketEncoder::new(specs),
+            packet_data: Vec::new(),
+        }
+    }
+
+    pub fn meta_mut(&mut self) -> &mut ReplayMeta {
+        &mut self.meta
+    }
+
+    /// The packets added so far, in the format of [ReplayFile::packet_data]
+    pub fn packet_data(&self) -> &[u8] {
+        self.packet_data.as_ref()
+    }
+
+    /// Appends an arbitrary packet
+    pub fn packet(
+        &mut self,
+        clock: f32,
+        payload: &PacketType<'_, '_>,
+    ) -> Result<&mut Self, ErrorKind> {
+        let encoded = self.encoder.encode(clock, payload)?;
+        self.packet_data.extend_from_slice(&encoded);
+        Ok(self)
+    }
+
+    /// Creates an entity of the given type (e.g. `Avatar` or `Vehicle`) with none of its
+    /// properties set
+    pub fn create_entity(
+        &mut self,
+        clock: f32,
+        entity_id: u32,
+        entity_type: &str,
+    ) -> Result<&mut Self, ErrorKind> {
+        let specs = self.specs;
+        let (spec_idx, spec) = specs
+            .iter()
+            .enumerate()
+            .find(|(_, spec)| spec.name == entity_type)
+            .ok_or_else(|| {
+                ErrorKind::EncodingFailure(format!("Unknown entity type {}", entity_type))
+            })?;
+
+        self.packet(
+            clock,
+            &PacketType::EntityCreate(EntityCreatePacket {
+                entity_id,
+                spec_idx: spec_idx + 1,
+                entity_type: &spec.name,
+                space_id: 0,
+                vehicle_id: 0,
+                position: Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                rotation: Rot3 {
+                    roll: 0.0,
+                    pitch: 0.0,
+                    yaw: 0.0,
+                },
+                state_length: 0,
+                props: HashMap::new(),
+            }),
+        )
+    }
+
+    /// Moves an entity to `position`, facing `yaw`
+    pub fn position(
+        &mut self,
+        clock: f32,
+        entity_id: u32,
+        position: Vec3,
+        yaw: f32,
+    ) -> Result<&mut Self, ErrorKind> {
+        self.packet(
+            clock,
+            &PacketType::Position(PositionPacket {
+                pid: entity_id,
+                position,
+                position_error: Vec3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+                rotation: Rot3 {
+                    roll: 0.0,
+                    pitch: 0.0,
+                    yaw,
+                },
+                is_error: false,
+            }),
+        )
+    }
+
+    /// Sends a chat message from `sender_id` to the avatar `avatar_id`. `audience` is e.g.
+    /// `battle_common` or `battle_team`.
+    pub fn chat(
+        &mut self,
+        clock: f32,
+        avatar_id: u32,
+        sender_id: i32,
+        audience: &str,
+        message: &str,
+    ) -> Result<&mut Self, ErrorKind> {
+        let mut args = vec![
+            ArgValue::Int32(sender_id),
+            ArgValue::String(audience.as_bytes().to_vec()),
+            ArgValue::String(message.as_bytes().to_vec()),
+        ];
+        // Newer versions have an extra (pickled) data argument
+        if self.method_arg_count("Avatar", "onChatMessage") == Some(4) {
+            args.push(ArgValue::String(Vec::new()));
+        }
+        self.method(clock, avatar_id, "onChatMessage", args)
+    }
+
+    /// Notifies the avatar `avatar_id` that `killer` destroyed `victim`. Both are ship IDs.
+    pub fn kill(
+        &mut self,
+        clock: f32,
+        avatar_id: u32,
+        victim: i32,
+        killer: i32,
+        cause: DeathCause,
+    ) -> Result<&mut Self, ErrorKind> {
+        let cause = match cause {
+            DeathCause::Secondaries => 2,
+            DeathCause::Torpedo => 3,
+            DeathCause::DiveBomber => 4,
+            DeathCause::AerialTorpedo => 5,
+            DeathCause::Fire => 6,
+            DeathCause::Ramming => 7,
+            DeathCause::Flooding => 9,
+            DeathCause::DepthCharge => 13,
+            DeathCause::AerialRocket => 14,
+            DeathCause::Detonation => 15,
+            DeathCause::Artillery => 17,
+            DeathCause::SkipBombs => 22,
+            DeathCause::Unknown(cause) => cause,
+        };
+        self.method(
+            clock,
+            avatar_id,
+            "receiveVehicleDeath",
+            vec![
+                ArgValue::Int32(victim),
+                ArgValue::Int32(killer),
+                ArgValue::Uint32(cause),
+            ],
+        )
+    }
+
+    /// Calls a client method on an entity
+    pub fn method(
+        &mut self,
+        clock: f32,
+        entity_id: u32,
+        method: &str,
+        args: Vec<ArgValue<'_>>,
+    ) -> Result<&mut Self, ErrorKind> {
+        self.packet(
+            clock,
+            &PacketType::EntityMethod(EntityMethodPacket {
+                entity_id,
+                method,
+                args,
+            }),
+        )
+    }
+
+    fn method_arg_count(&self, entity_type: &str, method: &str) -> Option<usize> {
+        self.specs
+            .iter()
+            .find(|spec| spec.name == entity_type)?
+            .client_methods
+            .iter()
+            .find(|m| m.name == method)
+            .map(|m| m.args.len())
+    }
+
+    /// Returns the replay as it would be after parsing, skipping encryption and compression
+    pub fn build_replay_file(&self) -> Result<ReplayFile, ErrorKind> {
+        Ok(ReplayFile {
+            meta: self.meta.clone(),
+            raw_meta: serde_json::to_string(&self.meta)?,
+            packet_data: self.packet_data.clone(),
+            extra_data: vec![],
+        })
+    }
+
+    /// Returns the contents of a `.wowsreplay` file, encoded like the standard client does
+    pub fn build(&self) -> Result<Vec<u8>, ErrorKind> {
+        self.build_with_codec(&ReplayCodec::default())
+    }
+
+    /// Returns the contents of a `.wowsreplay` file whose packet stream is encoded with
+    /// `codec`, e.g. to mimic other client variants
+    pub fn build_with_codec(&self, codec: &ReplayCodec) -> Result<Vec<u8>, ErrorKind> {
+        let raw_meta = serde_json::to_string(&self.meta)?;
+        let (stream, compressed_size) = codec.encode(&self.packet_data)?;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&REPLAY_MAGIC.to_le_bytes());
+        // Only the metadata block
+        out.extend_from_slice(&1u32.to_le_bytes());
+        out.extend_from_slice(&(raw_meta.len() as u32).to_le_bytes());
+        out.extend_from_slice(raw_meta.as_bytes());
+        out.extend_from_slice(&(self.packet_data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&compressed_size.to_le_bytes());
+        out.extend_from_slice(&stream);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::analyzer::decoder::DecodedPacket;
+    use crate::version::{EmbeddedDataFiles, Version};
+
+    #[test]
+    fn test_synthetic_replay() {
+        let version = "0,11,7,0";
+        let datafiles =
+            EmbeddedDataFiles::new("versions".into(), Version::from_client_exe(version)).unwrap();
+        let specs = crate::parse_scripts(&datafiles).unwrap();
+
+        let mut builder = ReplayBuilder::new(&specs, minimal_meta(version));
+        builder
+            .create_entity(0.0, 1, "Avatar")
+            .unwrap()
+            .chat(31.0, 1, 7, "battle_common", "gl hf")
+            .unwrap()
+            .kill(95.0, 1, 2, 3, DeathCause::Torpedo)
+            .unwrap();
+        let replay = ReplayFile::from_bytes(&builder.build().unwrap()).unwrap();
+        assert_eq!(replay.meta.clientVersionFromExe, version);
+        assert_eq!(replay.packet_data, builder.packet_data());
+        assert!(replay.extra_data().is_empty());
+
+        let mut parser = Parser::new(&specs);
+        let mut i = replay.packet_data.as_slice();
+        let mut decoded = Vec::new();
+        while !i.is_empty() {
+            let (remaining, packet) = parser.parse_packet_bytes(i).unwrap();
+            i = remaining;
+            let packet = DecodedPacket::from(&Version::from_client_exe(version), false, &packet);
+            decoded.push(serde_json::to_value(&packet.payload).unwrap());
+        }
+
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[1]["Chat"]["message"], "gl hf");
+        assert_eq!(decoded[2]["ShipDestroyed"]["cause"], "Torpedo");
+    }
+
+    #[test]
+    fn test_partial_decode() {
+        let version = "0,11,7,0";
+        let datafiles =
+            EmbeddedDataFiles::new("versions".into(), Version::from_client_exe(version)).unwrap();
+        let specs = crate::parse_scripts(&datafiles).unwrap();
+
+        // Truncated arguments, as if the method's signature had changed
+        let raw_args = [1, 0, 0, 0, 2];
+        let mut builder = ReplayBuilder::new(&specs, minimal_meta(version));
+        builder
+            .create_entity(0.0, 1, "Avatar")
+            .unwrap()
+            .packet(
+                1.0,
+                &PacketType::PartialDecode(crate::packet2::PartialDecodePacket {
+                    entity_id: 1,
+                    method: "onChatMessage",
+                    raw_args: &raw_args,
+                    error: String::new(),
+                }),
+            )
+            .unwrap();
+
+        let mut parser = Parser::new(&specs);
+        let (i, _) = parser.parse_packet_bytes(builder.packet_data()).unwrap();
+        let (i, packet) = parser.parse_packet_bytes(i).unwrap();
+        assert!(i.is_empty());
+        match packet.payload {
+            PacketType::PartialDecode(partial) => {
+                assert_eq!(partial.method, "onChatMessage");
+                assert_eq!(partial.raw_args, raw_args);
+                assert!(!partial.error.is_empty());
+            }
+            payload => panic!("expected a partial decode, got {:?}", payload),
+        }
+    }
+
+    #[test]
+    fn test_malformed_projectiles() {
+        for method in &[
+            "receiveArtilleryShots",
+            "receiveTorpedoes",
+            "receiveShotKills",
+        ] {
+            // A salvo that's missing all of its fields
+            let packet = Packet {
+                packet_size: 0,
+                packet_type: 0x8,
+                clock: 1.0,
+                payload: PacketType::EntityMethod(EntityMethodPacket {
+                    entity_id: 1,
+                    method,
+                    args: vec![ArgValue::Array(vec![ArgValue::FixedDict(
+                        Default::default(),
+                    )])],
+                }),
+                raw: &[],
+            };
+            let packet = DecodedPacket::from(&Version::from_client_exe("0,11,7,0"), false, &packet);
+            match packet.payload {
+                crate::analyzer::decoder::DecodedPacketPayload::PartialDecode(partial) => {
+                    assert_eq!(partial.method, *method);
+                    assert!(partial.raw_args.is_empty());
+                    assert!(!partial.error.is_empty());
+                }
+                payload => panic!("expected a partial decode, got {:?}", payload),
+            }
+        }
+    }
+
+    #[test]
+    fn test_malformed_plane_death() {
+        // The plane IDs should be an array
+        let packet = Packet {
+            packet_size: 0,
+            packet_type: 0x8,
+            clock: 1.0,
+            payload: PacketType::EntityMethod(EntityMethodPacket {
+                entity_id: 1,
+                method: "receive_planeDeath",
+                args: vec![
+                    ArgValue::Int64(1),
+                    ArgValue::Uint8(2),
+                    ArgValue::Uint8(0),
+                    ArgValue::Int64(3),
+                ],
+            }),
+            raw: &[],
+        };
+        let packet = DecodedPacket::from(&Version::from_client_exe("0,11,7,0"), false, &packet);
+        match packet.payload {
+            crate::analyzer::decoder::DecodedPacketPayload::PartialDecode(partial) => {
+                assert_eq!(partial.method, "receive_planeDeath");
+                assert!(partial.error.contains("argument 1"));
+            }
+            payload => panic!("expected a partial decode, got {:?}", payload),
+        }
+    }
+
+    #[test]
+    fn test_method_decoder() {
+        let version = "0,11,7,0";
+        let datafiles =
+            EmbeddedDataFiles::new("versions".into(), Version::from_client_exe(version)).unwrap();
+        let specs = crate::parse_scripts(&datafiles).unwrap();
+
+        let mut builder = ReplayBuilder::new(&specs, minimal_meta(version));
+        builder
+            .create_entity(0.0, 1, "Avatar")
+            .unwrap()
+            .chat(31.0, 1, 7, "battle_common", "gl hf")
+            .unwrap()
+            .chat(32.0, 1, 7, "battle_common", "ignored")
+            .unwrap();
+
+        let mut parser = Parser::new(&specs);
+        parser.register_method_decoder("Avatar", "onChatMessage", |call| {
+            let message = match call.args?.get(2)? {
+                ArgValue::String(message) => message,
+                _ => return None,
+            };
+            if message.as_slice() == b"ignored" {
+                return None;
+            }
+            Some(serde_json::json!({ "raw_len": call.raw_args.len() }))
+        });
+
+        let mut i = builder.packet_data();
+        let mut kinds = Vec::new();
+        while !i.is_empty() {
+            let (remaining, packet) = parser.parse_packet_bytes(i).unwrap();
+            i = remaining;
+            if let PacketType::CustomMethod(custom) = &packet.payload {
+                assert_eq!(custom.entity_type, "Avatar");
+                assert_eq!(custom.method, "onChatMessage");
+                assert_eq!(custom.value["raw_len"], custom.raw_args.len());
+            }
+            kinds.push(packet.payload.kind());
+        }
+        assert_eq!(
+            kinds,
+            vec![
+                PacketTypeKind::EntityCreate,
+                PacketTypeKind::CustomMethod,
+                PacketTypeKind::EntityMethod
+            ]
+        );
+    }
+
+    #[test]
+    fn test_codec_detection() {
+        let mut builder = ReplayBuilder::new(&[], minimal_meta("0,11,7,0"));
+        builder
+            .packet(0.0, &PacketType::Version("0,11,7,0".to_string()))
+            .unwrap();
+
+        let custom_key = ReplayCodec {
+            encryption: crate::ReplayEncryption::Blowfish(b"another key".to_vec()),
+            compression: crate::ReplayCompression::Zlib,
+        };
+        for codec in ReplayCodec::known() {
+            let replay = ReplayFile::from_bytes(&builder.build_with_codec(&codec).unwrap());
+            assert_eq!(replay.unwrap().packet_data, builder.packet_data());
+        }
+
+        let contents = builder.build_with_codec(&custom_key).unwrap();
+        assert!(ReplayFile::from_bytes(&contents).is_err());
+        let replay = ReplayFile::from_bytes_with_codec(&contents, &custom_key).unwrap();
+        assert_eq!(replay.packet_data, builder.packet_data());
+    }
+}
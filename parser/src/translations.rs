@@ -0,0 +1,161 @@
+//! Extracts the translations replays actually refer to, such as ship, map and scenario
+//! names, into a small catalog. The game's `global.mo` holds every string of one
+//! language; a catalog made from it can be shipped instead and loaded with
+//! [crate::resource_loader::GameDataSource::load].
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryInto;
+
+use crate::{
+    game_params::{GameParamProvider, Species},
+    ErrorKind, ReplayMeta,
+};
+
+/// Reads a gettext `.mo` catalog, such as the game's `global.mo`, into a map of
+/// translation IDs (e.g. `IDS_PASB017`) to strings
+pub fn read_mo(data: &[u8]) -> Result<HashMap<String, String>, ErrorKind> {
+    const MAGIC: u32 = 0x950412de;
+
+    let invalid = |what: &str| ErrorKind::ParsingFailure(format!("invalid .mo file: {}", what));
+    let magic = data
+        .get(..4)
+        .ok_or_else(|| invalid("missing header"))?
+        .try_into()
+        .unwrap();
+    let read_u32: fn([u8; 4]) -> u32 = if u32::from_le_bytes(magic) == MAGIC {
+        u32::from_le_bytes
+    } else if u32::from_be_bytes(magic) == MAGIC {
+        u32::from_be_bytes
+    } else {
+        return Err(invalid("bad magic"));
+    };
+    let u32_at = |offset: usize| -> Result<usize, ErrorKind> {
+        let bytes = data
+            .get(offset..offset + 4)
+            .ok_or_else(|| invalid("truncated"))?;
+        Ok(read_u32(bytes.try_into().unwrap()) as usize)
+    };
+    // Each table entry is the string's length followed by its offset
+    let string_at = |table: usize, idx: usize| -> Result<String, ErrorKind> {
+        let length = u32_at(table + idx * 8)?;
+        let offset = u32_at(table + idx * 8 + 4)?;
+        let bytes = data
+            .get(offset..offset + length)
+            .ok_or_else(|| invalid("string out of bounds"))?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    };
+
+    let count = u32_at(8)?;
+    let originals = u32_at(12)?;
+    let translations = u32_at(16)?;
+    let mut catalog = HashMap::with_capacity(count);
+    for idx in 0..count {
+        let id = string_at(originals, idx)?;
+        // The entry with an empty ID is the catalog's own metadata
+        if id.is_empty() {
+            continue;
+        }
+        let translation = string_at(translations, idx)?;
+        // Plural forms are separated by NULs, of which only the singular is kept
+        let translation = translation.split('\0').next().unwrap_or_default();
+        catalog.insert(id, translation.to_string());
+    }
+    Ok(catalog)
+}
+
+/// The translation IDs referred to by a set of replays
+#[derive(Debug, Clone, Default)]
+pub struct TranslationCatalog {
+    ids: BTreeSet<String>,
+}
+
+impl TranslationCatalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the IDs of the replay's map, scenario and every player's ship name and class
+    pub fn add_replay<P: GameParamProvider>(&mut self, meta: &ReplayMeta, params: &P) {
+        self.add_id(format!("IDS_{}", meta.mapName.to_uppercase()));
+        self.add_id(format!("IDS_SCENARIO_{}", meta.scenario.to_uppercase()));
+        for vehicle in &meta.vehicles {
+            let ship = match params.game_param_by_id(vehicle.shipId as u32) {
+                Some(ship) => ship,
+                None => continue,
+            };
+            self.add_id(format!("IDS_{}", ship.index()));
+            if let Some(species) = ship.species() {
+                if !matches!(species, Species::Unknown(_)) {
+                    self.add_id(species.translation_id());
+                }
+            }
+        }
+    }
+
+    pub fn add_id(&mut self, id: String) {
+        self.ids.insert(id);
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.ids.iter().map(|id| id.as_str())
+    }
+
+    /// The strings of the collected IDs from one language's full translations. IDs
+    /// without a translation are left out.
+    pub fn extract(&self, translations: &HashMap<String, String>) -> BTreeMap<String, String> {
+        self.ids
+            .iter()
+            .filter_map(|id| Some((id.clone(), translations.get(id)?.clone())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a little-endian .mo file from (ID, translation) pairs
+    fn mo_file(entries: &[(&str, &str)]) -> Vec<u8> {
+        let count = entries.len() as u32;
+        let originals = 28;
+        let translations = originals + count * 8;
+        let mut strings_offset = translations + count * 8;
+        let mut header = vec![];
+        for value in [0x950412de, 0, count, originals, translations, 0, 0] {
+            header.extend_from_slice(&u32::to_le_bytes(value));
+        }
+        let mut tables = vec![];
+        let mut strings = vec![];
+        for column in 0..2 {
+            for entry in entries {
+                let string = if column == 0 { entry.0 } else { entry.1 };
+                tables.extend_from_slice(&(string.len() as u32).to_le_bytes());
+                tables.extend_from_slice(&strings_offset.to_le_bytes());
+                strings.extend_from_slice(string.as_bytes());
+                strings.push(0);
+                strings_offset += string.len() as u32 + 1;
+            }
+        }
+        [header, tables, strings].concat()
+    }
+
+    #[test]
+    fn test_read_mo() {
+        let data = mo_file(&[
+            ("", "Content-Type: text/plain; charset=UTF-8"),
+            ("IDS_PASB017", "Montana"),
+            ("IDS_SPACES/34_OC_ISLANDS", "Islands of Ice"),
+        ]);
+        let translations = read_mo(&data).unwrap();
+        assert_eq!(translations.len(), 2);
+        assert_eq!(translations["IDS_PASB017"], "Montana");
+        assert!(read_mo(b"nope").is_err());
+
+        let mut catalog = TranslationCatalog::new();
+        catalog.add_id("IDS_PASB017".to_string());
+        catalog.add_id("IDS_PJSD012".to_string());
+        let extracted = catalog.extract(&translations);
+        assert_eq!(extracted.len(), 1);
+        assert_eq!(extracted["IDS_PASB017"], "Montana");
+    }
+}
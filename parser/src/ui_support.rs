@@ -0,0 +1,359 @@
+//! Naming and ordering shared by front-ends which draw ships, such as renderers, HTML
+//! exports and GUIs, so they all refer to and list ships the same way.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analyzer::battle_controller::Relation,
+    game_params::{GameParamProvider, Param, Species},
+    ErrorKind,
+};
+
+/// The class of a player's ship. Classes are ordered as the in-game team panel lists
+/// them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ShipClass {
+    AirCarrier,
+    Battleship,
+    Cruiser,
+    Destroyer,
+    Submarine,
+    Auxiliary,
+}
+
+impl ShipClass {
+    /// The class of a ship with the given GameParams species, or `None` if the species
+    /// isn't a ship's
+    pub fn from_species(species: &Species) -> Option<Self> {
+        let class = match species {
+            Species::AirCarrier => ShipClass::AirCarrier,
+            Species::Battleship => ShipClass::Battleship,
+            Species::Cruiser => ShipClass::Cruiser,
+            Species::Destroyer => ShipClass::Destroyer,
+            Species::Submarine => ShipClass::Submarine,
+            Species::Auxiliary => ShipClass::Auxiliary,
+            _ => return None,
+        };
+        Some(class)
+    }
+
+    pub fn species(&self) -> Species {
+        match self {
+            ShipClass::AirCarrier => Species::AirCarrier,
+            ShipClass::Battleship => Species::Battleship,
+            ShipClass::Cruiser => Species::Cruiser,
+            ShipClass::Destroyer => Species::Destroyer,
+            ShipClass::Submarine => Species::Submarine,
+            ShipClass::Auxiliary => Species::Auxiliary,
+        }
+    }
+
+    /// The class's GameParams species name, e.g. `Destroyer`, which is also the key of
+    /// its translation as `IDS_Destroyer`
+    pub fn species_key(&self) -> &'static str {
+        self.species().into()
+    }
+
+    /// The common part of the class's icon names, e.g. `destroyer`
+    pub fn icon_base(&self) -> &'static str {
+        match self {
+            ShipClass::AirCarrier => "aircarrier",
+            ShipClass::Battleship => "battleship",
+            ShipClass::Cruiser => "cruiser",
+            ShipClass::Destroyer => "destroyer",
+            ShipClass::Submarine => "submarine",
+            ShipClass::Auxiliary => "auxiliary",
+        }
+    }
+
+    pub fn icon(&self, variant: IconVariant) -> IconKey {
+        IconKey {
+            class: *self,
+            variant,
+        }
+    }
+}
+
+/// How a ship's icon is tinted, from its relation to the viewer and whether it's alive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum IconVariant {
+    Own,
+    Ally,
+    Enemy,
+    /// Ships without a team relation, e.g. every ship in an observer replay
+    Neutral,
+    Dead,
+}
+
+impl IconVariant {
+    pub fn from_relation(relation: Relation, alive: bool) -> Self {
+        if !alive {
+            return IconVariant::Dead;
+        }
+        match relation {
+            Relation::Own => IconVariant::Own,
+            Relation::Friendly => IconVariant::Ally,
+            Relation::Enemy => IconVariant::Enemy,
+            Relation::Neutral => IconVariant::Neutral,
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            IconVariant::Own => "own",
+            IconVariant::Ally => "ally",
+            IconVariant::Enemy => "enemy",
+            IconVariant::Neutral => "neutral",
+            IconVariant::Dead => "dead",
+        }
+    }
+}
+
+/// Identifies the icon to draw a ship with. Its string form, e.g. `destroyer_enemy`, is
+/// what front-ends name their icon files or CSS classes after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IconKey {
+    class: ShipClass,
+    variant: IconVariant,
+}
+
+impl IconKey {
+    pub fn class(&self) -> ShipClass {
+        self.class
+    }
+
+    pub fn variant(&self) -> IconVariant {
+        self.variant
+    }
+}
+
+impl std::fmt::Display for IconKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}_{}", self.class.icon_base(), self.variant.suffix())
+    }
+}
+
+/// Whether ships are drawn with the icon of their class or, where one is available, a
+/// silhouette of the ship itself
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum IconStyle {
+    #[default]
+    Class,
+    Silhouette,
+}
+
+/// The icon to draw a ship with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShipIcon {
+    /// The silhouette of the ship with this GameParams ID, see [Silhouettes]
+    Silhouette(u32),
+    Class(IconKey),
+}
+
+/// Per-ship silhouettes, e.g. extracted from the game's assets, keyed by the ship's
+/// GameParams ID
+#[derive(Debug, Clone, Default)]
+pub struct Silhouettes {
+    paths: HashMap<u32, PathBuf>,
+}
+
+impl Silhouettes {
+    /// Finds the silhouettes in `dir`. Like the game's own, they're PNGs named after the
+    /// ship's GameParams index, e.g. `PASB017.png`. Files which aren't named after a
+    /// known ship are ignored.
+    pub fn from_dir<P: GameParamProvider>(dir: &Path, params: &P) -> Result<Self, ErrorKind> {
+        let mut silhouettes = Silhouettes::default();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .map_or(true, |extension| extension != "png")
+            {
+                continue;
+            }
+            let ship = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|index| params.game_param_by_index(index));
+            if let Some(ship) = ship {
+                silhouettes.insert(ship.id(), path);
+            }
+        }
+        Ok(silhouettes)
+    }
+
+    pub fn insert(&mut self, ship_id: u32, path: PathBuf) {
+        self.paths.insert(ship_id, path);
+    }
+
+    pub fn path(&self, ship_id: u32) -> Option<&Path> {
+        self.paths.get(&ship_id).map(|path| path.as_path())
+    }
+
+    /// The icon to draw the ship with in the given style. Ships without a silhouette fall
+    /// back to their class's icon, as do dead ships since they're drawn greyed out.
+    pub fn icon(
+        &self,
+        ship_id: u32,
+        class: ShipClass,
+        variant: IconVariant,
+        style: IconStyle,
+    ) -> ShipIcon {
+        match style {
+            IconStyle::Silhouette
+                if variant != IconVariant::Dead && self.paths.contains_key(&ship_id) =>
+            {
+                ShipIcon::Silhouette(ship_id)
+            }
+            _ => ShipIcon::Class(class.icon(variant)),
+        }
+    }
+}
+
+/// Orders ships as the in-game team panel does: by class (carriers, battleships,
+/// cruisers, destroyers, then submarines), then from the highest tier down, then
+/// alphabetically by ship name. Ships of unknown class come last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScoreboardKey {
+    class: Option<ShipClass>,
+    tier: u32,
+    ship_name: String,
+}
+
+impl ScoreboardKey {
+    /// `ship_name` is the name shown to users, which may be localized
+    pub fn new(ship: &Param, ship_name: &str) -> Self {
+        ScoreboardKey {
+            class: ship.species().as_ref().and_then(ShipClass::from_species),
+            tier: ship
+                .data()
+                .vehicle_ref()
+                .map(|vehicle| vehicle.level())
+                .unwrap_or_default(),
+            ship_name: ship_name.to_string(),
+        }
+    }
+
+    fn sort_key(&self) -> (bool, Option<ShipClass>, Reverse<u32>, &str) {
+        (
+            self.class.is_none(),
+            self.class,
+            Reverse(self.tier),
+            self.ship_name.as_str(),
+        )
+    }
+}
+
+impl PartialOrd for ScoreboardKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScoreboardKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+/// Sorts a team's players, or anything else with a ship, into scoreboard order. See
+/// [ScoreboardKey].
+pub fn sort_scoreboard<T, F>(items: &mut [T], key: F)
+where
+    F: Fn(&T) -> ScoreboardKey,
+{
+    items.sort_by_cached_key(key);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_icon_keys() {
+        let class = ShipClass::from_species(&Species::Destroyer).unwrap();
+        assert_eq!(class.species_key(), "Destroyer");
+        assert_eq!(
+            class
+                .icon(IconVariant::from_relation(Relation::Enemy, true))
+                .to_string(),
+            "destroyer_enemy"
+        );
+        assert_eq!(
+            ShipClass::AirCarrier
+                .icon(IconVariant::from_relation(Relation::Own, false))
+                .to_string(),
+            "aircarrier_dead"
+        );
+        assert_eq!(ShipClass::from_species(&Species::Torpedo), None);
+    }
+
+    #[test]
+    fn test_silhouette_fallback() {
+        let mut silhouettes = Silhouettes::default();
+        silhouettes.insert(1, PathBuf::from("PASB017.png"));
+        let variant = IconVariant::Enemy;
+        assert_eq!(
+            silhouettes.icon(1, ShipClass::Battleship, variant, IconStyle::Silhouette),
+            ShipIcon::Silhouette(1)
+        );
+        assert_eq!(
+            silhouettes.icon(2, ShipClass::Destroyer, variant, IconStyle::Silhouette),
+            ShipIcon::Class(ShipClass::Destroyer.icon(variant))
+        );
+        assert_eq!(
+            silhouettes.icon(1, ShipClass::Battleship, variant, IconStyle::Class),
+            ShipIcon::Class(ShipClass::Battleship.icon(variant))
+        );
+    }
+
+    #[test]
+    fn test_scoreboard_order() {
+        use crate::game_params::{ParamBuilder, ParamData, VehicleBuilder};
+
+        let ship = |species, level, name: &str| {
+            ParamBuilder::default()
+                .id(0)
+                .index(name.to_string())
+                .name(name.to_string())
+                .species(Some(species))
+                .nation(String::new())
+                .data(ParamData::Vehicle(
+                    VehicleBuilder::default()
+                        .level(level)
+                        .group("upgradeable".to_string())
+                        .abilities(vec![])
+                        .build()
+                        .unwrap(),
+                ))
+                .build()
+                .unwrap()
+        };
+        let mut ships = vec![
+            ship(Species::Destroyer, 10, "Shimakaze"),
+            ship(Species::Submarine, 10, "Balao"),
+            ship(Species::Cruiser, 9, "Seattle"),
+            ship(Species::Destroyer, 10, "Gearing"),
+            ship(Species::Battleship, 8, "Bismarck"),
+            ship(Species::Cruiser, 10, "Moskva"),
+            ship(Species::AirCarrier, 8, "Enterprise"),
+        ];
+        sort_scoreboard(&mut ships, |ship| ScoreboardKey::new(ship, ship.name()));
+        assert_eq!(
+            ships.iter().map(|ship| ship.name()).collect::<Vec<_>>(),
+            vec![
+                "Enterprise",
+                "Bismarck",
+                "Moskva",
+                "Seattle",
+                "Gearing",
+                "Shimakaze",
+                "Balao",
+            ]
+        );
+    }
+}
@@ -1,10 +1,11 @@
 use crate::error::ErrorKind;
 use rust_embed::RustEmbed;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use tracing::warn;
 
-#[derive(Debug, Serialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub struct Version {
     pub major: u32,
     pub minor: u32,
@@ -28,6 +29,16 @@ impl Version {
         format!("{}.{}.{}", self.major, self.minor, self.patch)
     }
 
+    /// Parses a version from its path form, e.g. `0.10.3`
+    pub fn from_path(path: &str) -> Option<Version> {
+        let mut parts = path.split('.').map(|part| part.parse::<u32>().ok());
+        let version = Version::new(parts.next()??, parts.next()??, parts.next()??);
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(version)
+    }
+
     pub fn is_at_least(&self, other: &Version) -> bool {
         if self.major > other.major {
             true
@@ -45,6 +56,100 @@ impl Version {
     }
 }
 
+impl Version {
+    const fn new(major: u32, minor: u32, patch: u32) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+            build: 0,
+        }
+    }
+
+    /// The parsing and decoding behavior that applies to this game version
+    pub fn capabilities(&self) -> &'static VersionCapabilities {
+        VERSION_CAPABILITIES
+            .iter()
+            .rev()
+            .find(|(since, _)| self.is_at_least(since))
+            .map(|(_, capabilities)| capabilities)
+            .unwrap_or(&VERSION_CAPABILITIES[0].1)
+    }
+}
+
+/// Which indices the keys of the player dictionaries in `onArenaStateReceived` are
+/// stored at. Named after the first version using each layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ArenaPlayerKeyMap {
+    V0_10_0,
+    V0_10_7,
+    V0_10_9,
+    V0_12_8,
+}
+
+/// Describes how the data in replays of a range of game versions is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct VersionCapabilities {
+    pub arena_player_keys: ArenaPlayerKeyMap,
+    /// `receive_CommonCMD` packs the voice line and audience into a blob argument
+    pub common_cmd_blob: bool,
+    /// `onBattleEnd` is sent with the winning team
+    pub battle_end_has_winner: bool,
+    /// The ship config blob has an extra u32 after the list of modules
+    pub ship_config_extra_unit_field: bool,
+}
+
+/// The capabilities of each range of game versions, in ascending order. Each entry
+/// applies from its version until the next entry's version. Supporting a new version is
+/// usually a matter of adding an entry here.
+pub static VERSION_CAPABILITIES: &[(Version, VersionCapabilities)] = &[
+    (
+        Version::new(0, 10, 0),
+        VersionCapabilities {
+            arena_player_keys: ArenaPlayerKeyMap::V0_10_0,
+            common_cmd_blob: false,
+            battle_end_has_winner: true,
+            ship_config_extra_unit_field: false,
+        },
+    ),
+    (
+        Version::new(0, 10, 7),
+        VersionCapabilities {
+            arena_player_keys: ArenaPlayerKeyMap::V0_10_7,
+            common_cmd_blob: false,
+            battle_end_has_winner: true,
+            ship_config_extra_unit_field: false,
+        },
+    ),
+    (
+        Version::new(0, 10, 9),
+        VersionCapabilities {
+            arena_player_keys: ArenaPlayerKeyMap::V0_10_9,
+            common_cmd_blob: false,
+            battle_end_has_winner: true,
+            ship_config_extra_unit_field: false,
+        },
+    ),
+    (
+        Version::new(0, 12, 8),
+        VersionCapabilities {
+            arena_player_keys: ArenaPlayerKeyMap::V0_12_8,
+            common_cmd_blob: true,
+            battle_end_has_winner: false,
+            ship_config_extra_unit_field: false,
+        },
+    ),
+    (
+        Version::new(13, 2, 0),
+        VersionCapabilities {
+            arena_player_keys: ArenaPlayerKeyMap::V0_12_8,
+            common_cmd_blob: true,
+            battle_end_has_winner: false,
+            ship_config_extra_unit_field: true,
+        },
+    ),
+];
+
 #[derive(RustEmbed)]
 #[folder = "../versions/"]
 struct Embedded;
@@ -78,6 +183,7 @@ where
 pub struct EmbeddedDataFiles {
     base_path: PathBuf,
     version: Version,
+    bridged: bool,
 }
 
 impl EmbeddedDataFiles {
@@ -91,9 +197,72 @@ impl EmbeddedDataFiles {
         Ok(EmbeddedDataFiles {
             base_path: base,
             version,
+            bridged: false,
         })
         //}
     }
+
+    /// Like [EmbeddedDataFiles::new], but if there are no data files for `version`, the
+    /// files of the nearest available version are used instead: the newest older version,
+    /// or the oldest newer one if there are no older versions. Entity definitions rarely
+    /// change much between patches, so this often parses fine. Packets should be parsed
+    /// with [crate::packet2::Parser::set_bridged] when [EmbeddedDataFiles::is_bridged].
+    pub fn nearest(base: PathBuf, version: Version) -> Result<EmbeddedDataFiles, ErrorKind> {
+        let available = EmbeddedDataFiles::available_versions(&base);
+        if available
+            .iter()
+            .any(|available| available.to_path() == version.to_path())
+        {
+            return EmbeddedDataFiles::new(base, version);
+        }
+
+        let nearest = available
+            .iter()
+            .rev()
+            .find(|available| version.is_at_least(available))
+            .or_else(|| available.first())
+            .copied()
+            .ok_or_else(|| ErrorKind::UnsupportedReplayVersion(version.to_path()))?;
+        warn!(
+            "no data files for version {}, using version {} instead",
+            version.to_path(),
+            nearest.to_path()
+        );
+
+        Ok(EmbeddedDataFiles {
+            base_path: base,
+            version: nearest,
+            bridged: true,
+        })
+    }
+
+    /// Every version with data files, either in `base` or embedded, in ascending order
+    pub fn available_versions(base: &Path) -> Vec<Version> {
+        let on_disk = std::fs::read_dir(base)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok());
+        let embedded =
+            Embedded::iter().filter_map(|path| Some(path.split('/').next()?.to_string()));
+
+        let mut versions: Vec<Version> = on_disk
+            .chain(embedded)
+            .filter_map(|name| Version::from_path(&name))
+            .collect();
+        versions.sort_by_key(|version| (version.major, version.minor, version.patch));
+        versions.dedup_by_key(|version| version.to_path());
+        versions
+    }
+
+    /// The version the data files are loaded from
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Whether the data files are from a different version than requested
+    pub fn is_bridged(&self) -> bool {
+        self.bridged
+    }
 }
 
 impl DataFileLoader for EmbeddedDataFiles {
@@ -125,6 +294,26 @@ mod test {
         assert!(!older.is_at_least(&newer));
     }
 
+    #[test]
+    fn version_from_path() {
+        let version = Version::from_path("0.10.3").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (0, 10, 3));
+        assert!(Version::from_path("0.10").is_none());
+        assert!(Version::from_path("0.10.3.1").is_none());
+        assert!(Version::from_path("README.md").is_none());
+    }
+
+    #[test]
+    fn nearest_data_files() {
+        let exact = EmbeddedDataFiles::nearest("versions".into(), Version::new(0, 10, 3)).unwrap();
+        assert!(!exact.is_bridged());
+
+        let bridged =
+            EmbeddedDataFiles::nearest("versions".into(), Version::new(0, 10, 12)).unwrap();
+        assert!(bridged.is_bridged());
+        assert_eq!(bridged.version().to_path(), "0.10.11");
+    }
+
     #[test]
     fn different_patch() {
         let older = Version::from_client_exe("0,10,9,0");
@@ -145,4 +334,22 @@ mod test {
         let newer = Version::from_client_exe("1,0,0,0");
         assert_older_newer(older, newer);
     }
+
+    #[test]
+    fn capabilities() {
+        let caps = Version::from_client_exe("0,10,3,3747819").capabilities();
+        assert_eq!(caps.arena_player_keys, ArenaPlayerKeyMap::V0_10_0);
+        assert!(caps.battle_end_has_winner);
+
+        let caps = Version::from_client_exe("0,12,8,0").capabilities();
+        assert_eq!(caps.arena_player_keys, ArenaPlayerKeyMap::V0_12_8);
+        assert!(!caps.ship_config_extra_unit_field);
+
+        let caps = Version::from_client_exe("13,5,1,0").capabilities();
+        assert!(caps.ship_config_extra_unit_field);
+
+        // Versions older than the table use its first entry
+        let caps = Version::from_client_exe("0,9,10,0").capabilities();
+        assert_eq!(caps.arena_player_keys, ArenaPlayerKeyMap::V0_10_0);
+    }
 }
@@ -1,12 +1,16 @@
-use crypto::symmetriccipher::BlockDecryptor;
+use crypto::symmetriccipher::{BlockDecryptor, BlockEncryptor};
 use nom::bytes::complete::take;
 use nom::multi::count;
 use nom::number::complete::le_u32;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::Read;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use tracing::{debug, info_span};
 
 use crate::error::*;
+#[cfg(feature = "analyzer")]
+use crate::resource_loader::ResourceLoader;
 
 #[allow(non_snake_case)]
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -28,7 +32,7 @@ pub struct ReplayMeta {
     pub mapDisplayName: String,
     pub mapId: u32,
     pub clientVersionFromXml: String,
-    pub weatherParams: HashMap<String, Vec<String>>,
+    pub weatherParams: BTreeMap<String, Vec<String>>,
     //mapBorder: Option<...>,
     pub duration: u32,
     pub gameLogic: Option<String>,
@@ -45,6 +49,64 @@ pub struct ReplayMeta {
     pub logic: Option<String>,
     pub playerVehicle: String,
     pub battleDuration: u32,
+    /// Every key which doesn't have a field above, so that data added by newer game
+    /// versions isn't lost
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl ReplayMeta {
+    /// Reads the `tempArenaInfo.json` which the game writes to its replays directory
+    /// while a battle is in progress. It holds the same metadata as a replay's header.
+    pub fn from_temp_arena_info(path: &std::path::Path) -> Result<ReplayMeta, ErrorKind> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Deserializes a key which doesn't have a dedicated field
+    pub fn extra_field<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.extra
+            .get(key)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
+
+    /// The weather presets for each stage of the battle, ordered by stage. A stage may
+    /// have several presets, e.g. a storm moving in.
+    pub fn weather(&self) -> Vec<(u32, &[String])> {
+        let mut weather: Vec<_> = self
+            .weatherParams
+            .iter()
+            .filter_map(|(stage, presets)| Some((stage.parse().ok()?, presets.as_slice())))
+            .collect();
+        weather.sort_by_key(|(stage, _)| *stage);
+        weather
+    }
+
+    /// The map border, if the map has one. Null in most replays.
+    pub fn map_border(&self) -> Option<&Value> {
+        self.extra.get("mapBorder").filter(|value| !value.is_null())
+    }
+
+    /// Name of the scripted battle logic module, present in newer replays of scenario
+    /// based modes
+    pub fn battle_logic_module(&self) -> Option<&str> {
+        self.extra
+            .get("battleLogicModule")
+            .and_then(|value| value.as_str())
+    }
+
+    /// Whether this replay was recorded by an observer (spectator) rather than a player.
+    /// Observer replays have no vehicle belonging to the recording player.
+    pub fn is_observer(&self) -> bool {
+        !self.vehicles.iter().any(|vehicle| vehicle.relation == 0)
+    }
+
+    /// Names of the ships which could not be used in this battle, present in newer replays
+    pub fn disabled_ships(&self) -> Vec<String> {
+        self.extra_field("disabledShipsNames")
+            .or_else(|| self.extra_field("disabledShips"))
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Debug)]
@@ -84,7 +146,16 @@ fn replay_format(i: &[u8]) -> IResult<&[u8], Replay> {
     let (i, block_count) = le_u32(i)?;
     let (i, (raw_meta, meta)) = parse_meta(i)?;
 
-    let (i, blocks) = count(block, (block_count as usize) - 1)(i)?;
+    // Every block has at least its 4 byte size
+    let block_count = (block_count as usize).saturating_sub(1);
+    if block_count.saturating_mul(4) > i.len() {
+        return Err(parsing_failure(format!(
+            "replay claims {} blocks but only {} bytes remain",
+            block_count,
+            i.len()
+        )));
+    }
+    let (i, blocks) = count(block, block_count)(i)?;
     let (i, decompressed_size) = le_u32(i)?;
     let (i, compressed_size) = le_u32(i)?;
     Ok((
@@ -104,6 +175,7 @@ pub struct ReplayFile {
     pub meta: ReplayMeta,
     pub raw_meta: String,
     pub packet_data: Vec<u8>,
+    pub(crate) extra_data: Vec<Vec<u8>>,
 }
 
 impl ReplayFile {
@@ -116,48 +188,557 @@ impl ReplayFile {
             meta: parsed_meta,
             raw_meta,
             packet_data,
+            extra_data: vec![],
         })
     }
 
+    /// The blocks stored after the metadata, other than the packet stream. Replays of
+    /// finished battles hold the battle results here as JSON.
+    pub fn extra_data(&self) -> &[Vec<u8>] {
+        &self.extra_data
+    }
+
+    /// Reads a replay from disk. The path may also point into an archive, like
+    /// `replays.zip!some.wowsreplay` (see [crate::archive]).
     pub fn from_file(replay: &std::path::Path) -> Result<ReplayFile, ErrorKind> {
-        let mut f = std::fs::File::open(replay).unwrap();
-        let mut contents = vec![];
-        f.read_to_end(&mut contents).unwrap();
-
-        let (remaining, result) = replay_format(&contents)?;
-
-        // Decrypt
-        let key = [
-            0x29, 0xB7, 0xC9, 0x09, 0x38, 0x3F, 0x84, 0x88, 0xFA, 0x98, 0xEC, 0x4E, 0x13, 0x19,
-            0x79, 0xFB,
-        ];
-        let blowfish = crypto::blowfish::Blowfish::new(&key);
-        assert!(blowfish.block_size() == 8);
-        let encrypted = remaining; //result.compressed_stream
-        let mut decrypted = vec![];
-        decrypted.resize(encrypted.len(), 0u8);
-        let num_blocks = encrypted.len() / blowfish.block_size();
-        let mut previous = [0; 8]; // 8 == block size
-        for i in 0..num_blocks {
-            let offset = i * blowfish.block_size();
-            blowfish.decrypt_block(
-                &encrypted[offset..offset + blowfish.block_size()],
-                &mut decrypted[offset..offset + blowfish.block_size()],
-            );
-            for j in 0..8 {
-                decrypted[offset + j] = decrypted[offset + j] ^ previous[j];
-                previous[j] = decrypted[offset + j];
+        let _span = info_span!("replay_file", path = %replay.display()).entered();
+        let contents = match crate::archive::split_archive_path(replay) {
+            Some((archive, inner)) if !replay.exists() => {
+                crate::archive::read_archived_file(&archive, &inner)?
+            }
+            _ => std::fs::read(replay)?,
+        };
+        Self::from_bytes(&contents)
+    }
+
+    /// Parses a replay from the contents of a `.wowsreplay` file. The encryption and
+    /// compression of the packet stream are detected from [ReplayCodec::known].
+    pub fn from_bytes(contents: &[u8]) -> Result<ReplayFile, ErrorKind> {
+        Self::from_bytes_detect(contents, &ReplayCodec::known())
+    }
+
+    /// Parses a replay whose packet stream is encoded with `codec`
+    pub fn from_bytes_with_codec(
+        contents: &[u8],
+        codec: &ReplayCodec,
+    ) -> Result<ReplayFile, ErrorKind> {
+        Self::from_bytes_detect(contents, std::slice::from_ref(codec))
+    }
+
+    /// Parses a replay using the first of `codecs` which can decode its packet stream.
+    /// Codecs are only fully tried if the start of the stream looks like their output.
+    pub fn from_bytes_detect(
+        contents: &[u8],
+        codecs: &[ReplayCodec],
+    ) -> Result<ReplayFile, ErrorKind> {
+        let (stream, result) = replay_format(contents)?;
+
+        let mut error = None;
+        for codec in codecs
+            .iter()
+            .filter(|codec| codec.looks_valid(stream, &result))
+        {
+            match codec.decode(stream, result.decompressed_size as usize) {
+                Ok(contents) => {
+                    debug!(
+                        version = %result.meta.clientVersionFromExe,
+                        map = %result.meta.mapName,
+                        packet_bytes = contents.len(),
+                        ?codec,
+                        "decrypted replay"
+                    );
+
+                    return Ok(ReplayFile {
+                        meta: result.meta,
+                        raw_meta: result.raw_meta.to_string(),
+                        packet_data: contents,
+                        extra_data: result
+                            .extra_data
+                            .iter()
+                            .map(|block| block.to_vec())
+                            .collect(),
+                    });
+                }
+                Err(e) => error = Some(e),
             }
         }
 
-        let mut deflater = flate2::read::ZlibDecoder::new(decrypted.as_slice());
-        let mut contents = vec![];
-        deflater.read_to_end(&mut contents).unwrap();
+        Err(error.unwrap_or_else(|| {
+            ErrorKind::ParsingFailure(
+                "No replay codec matches the replay's packet stream".to_string(),
+            )
+        }))
+    }
+}
 
-        Ok(ReplayFile {
-            meta: result.meta,
-            raw_meta: result.raw_meta.to_string(),
-            packet_data: contents,
-        })
+/// The Blowfish key used by the standard game client
+pub const DEFAULT_REPLAY_KEY: [u8; 16] = [
+    0x29, 0xB7, 0xC9, 0x09, 0x38, 0x3F, 0x84, 0x88, 0xFA, 0x98, 0xEC, 0x4E, 0x13, 0x19, 0x79, 0xFB,
+];
+
+/// How the packet stream of a replay is encrypted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayEncryption {
+    /// Blowfish in ECB mode with the given key, where each decrypted block is additionally
+    /// XORed with the previous decrypted block
+    Blowfish(Vec<u8>),
+    None,
+}
+
+/// How the packet stream of a replay is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayCompression {
+    Zlib,
+    None,
+}
+
+/// The encryption and compression applied to a replay's packet stream. Client variants
+/// differ in these, e.g. by using a different Blowfish key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayCodec {
+    pub encryption: ReplayEncryption,
+    pub compression: ReplayCompression,
+}
+
+impl Default for ReplayCodec {
+    fn default() -> Self {
+        ReplayCodec {
+            encryption: ReplayEncryption::Blowfish(DEFAULT_REPLAY_KEY.to_vec()),
+            compression: ReplayCompression::Zlib,
+        }
+    }
+}
+
+impl ReplayCodec {
+    /// The codecs used by known client variants, most common first
+    pub fn known() -> Vec<ReplayCodec> {
+        vec![
+            ReplayCodec::default(),
+            ReplayCodec {
+                encryption: ReplayEncryption::None,
+                compression: ReplayCompression::Zlib,
+            },
+            ReplayCodec {
+                encryption: ReplayEncryption::None,
+                compression: ReplayCompression::None,
+            },
+        ]
+    }
+
+    /// Checks whether the start of `stream` looks like it was produced by this codec,
+    /// without decoding all of it
+    fn looks_valid(&self, stream: &[u8], replay: &Replay<'_>) -> bool {
+        let head = match &self.encryption {
+            ReplayEncryption::Blowfish(key) => match blowfish(key) {
+                Ok(blowfish) => {
+                    let mut head = [0u8; 8];
+                    if stream.len() < head.len() {
+                        return false;
+                    }
+                    blowfish.decrypt_block(&stream[..8], &mut head);
+                    head.to_vec()
+                }
+                Err(_) => return false,
+            },
+            ReplayEncryption::None => stream.iter().take(8).cloned().collect(),
+        };
+
+        match self.compression {
+            // The zlib header's check bits make a false positive unlikely
+            ReplayCompression::Zlib => {
+                head.len() >= 2
+                    && head[0] & 0x0f == 8
+                    && (u16::from(head[0]) << 8 | u16::from(head[1])) % 31 == 0
+            }
+            ReplayCompression::None => {
+                replay.decompressed_size == replay.compressed_size
+                    && stream.len() >= replay.decompressed_size as usize
+            }
+        }
+    }
+
+    /// Decrypts and decompresses a replay's packet stream. `decompressed_size` is only
+    /// used to strip padding from uncompressed streams.
+    pub fn decode(&self, stream: &[u8], decompressed_size: usize) -> Result<Vec<u8>, ErrorKind> {
+        let decrypted = match &self.encryption {
+            ReplayEncryption::Blowfish(key) => {
+                let blowfish = blowfish(key)?;
+                let mut decrypted = vec![0u8; stream.len()];
+                let mut previous = [0; 8]; // 8 == block size
+                for (encrypted, decrypted) in stream.chunks_exact(8).zip(decrypted.chunks_mut(8)) {
+                    blowfish.decrypt_block(encrypted, decrypted);
+                    for (byte, previous) in decrypted.iter_mut().zip(previous.iter()) {
+                        *byte ^= previous;
+                    }
+                    previous.copy_from_slice(decrypted);
+                }
+                decrypted
+            }
+            ReplayEncryption::None => stream.to_vec(),
+        };
+
+        match self.compression {
+            ReplayCompression::Zlib => {
+                let mut deflater = flate2::read::ZlibDecoder::new(decrypted.as_slice());
+                let mut contents = vec![];
+                deflater.read_to_end(&mut contents)?;
+                Ok(contents)
+            }
+            ReplayCompression::None => {
+                let mut contents = decrypted;
+                contents.truncate(decompressed_size);
+                Ok(contents)
+            }
+        }
+    }
+
+    /// The inverse of [ReplayCodec::decode]. Returns the encoded stream along with its
+    /// size before encryption, as stored in the replay header.
+    pub fn encode(&self, packet_data: &[u8]) -> Result<(Vec<u8>, u32), ErrorKind> {
+        let compressed = match self.compression {
+            ReplayCompression::Zlib => {
+                let mut compressor =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                compressor.write_all(packet_data)?;
+                compressor.finish()?
+            }
+            ReplayCompression::None => packet_data.to_vec(),
+        };
+        let compressed_size = compressed.len() as u32;
+
+        let encrypted = match &self.encryption {
+            ReplayEncryption::Blowfish(key) => {
+                let blowfish = blowfish(key)?;
+                let mut padded = compressed;
+                padded.resize((padded.len() + 7) / 8 * 8, 0);
+
+                let mut encrypted = vec![0u8; padded.len()];
+                let mut previous = [0u8; 8];
+                for (plain, out) in padded.chunks(8).zip(encrypted.chunks_mut(8)) {
+                    let mut block = [0u8; 8];
+                    for j in 0..8 {
+                        block[j] = plain[j] ^ previous[j];
+                    }
+                    blowfish.encrypt_block(&block, out);
+                    previous.copy_from_slice(plain);
+                }
+                encrypted
+            }
+            ReplayEncryption::None => compressed,
+        };
+
+        Ok((encrypted, compressed_size))
+    }
+}
+
+fn blowfish(key: &[u8]) -> Result<crypto::blowfish::Blowfish, ErrorKind> {
+    if !(4..=56).contains(&key.len()) {
+        return Err(ErrorKind::ParsingFailure(format!(
+            "Blowfish keys must be 4 to 56 bytes, not {}",
+            key.len()
+        )));
+    }
+    Ok(crypto::blowfish::Blowfish::new(key))
+}
+
+/// Loads the replay at `replay` along with the entity specs for its game version
+#[cfg(feature = "analyzer")]
+fn load_replay(
+    replay: &std::path::Path,
+) -> Result<(ReplayFile, Vec<crate::rpc::entitydefs::EntitySpec>), ErrorKind> {
+    let replay_file = ReplayFile::from_file(replay)?;
+    debug!(version = %replay_file.meta.clientVersionFromExe, "loaded replay");
+    let datafiles = crate::version::EmbeddedDataFiles::new(
+        std::path::PathBuf::from("versions"),
+        crate::version::Version::from_client_exe(&replay_file.meta.clientVersionFromExe),
+    )?;
+    let specs = crate::parse_scripts(&datafiles)?;
+    Ok((replay_file, specs))
+}
+
+/// Loads the replay at `replay` and calls `f` with its packet data and a parser and
+/// [crate::analyzer::battle_controller::BattleController] set up for it
+#[cfg(feature = "analyzer")]
+fn with_controller<T, F>(
+    replay: &std::path::Path,
+    game_data: &crate::resource_loader::GameDataSource,
+    f: F,
+) -> Result<T, ErrorKind>
+where
+    F: for<'a> FnOnce(
+        &'a [u8],
+        crate::packet2::Parser<'a>,
+        crate::analyzer::battle_controller::BattleController<
+            'a,
+            'a,
+            crate::resource_loader::VersionedGameData<'a>,
+        >,
+    ) -> Result<T, ErrorKind>,
+{
+    let (replay_file, specs) = load_replay(replay)?;
+    let game_data = game_data.with_specs(specs);
+
+    let controller =
+        crate::analyzer::battle_controller::BattleController::new(&replay_file.meta, &game_data);
+    let mut parser = crate::packet2::Parser::new(game_data.entity_specs());
+    parser.set_game_mode(crate::game_mode::GameModeKind::from_meta(&replay_file.meta));
+    f(&replay_file.packet_data, parser, controller)
+}
+
+/// Parses the replay at `replay` with the [crate::analyzer::battle_controller::BattleController]
+/// and returns its report. The entity specs for the replay's game version are loaded
+/// automatically; `game_data` only needs to provide the version-independent game data.
+#[cfg(feature = "analyzer")]
+pub fn analyze_replay(
+    replay: &std::path::Path,
+    game_data: &crate::resource_loader::GameDataSource,
+) -> Result<crate::analyzer::battle_controller::BattleReport, ErrorKind> {
+    let _span = info_span!("analyze_replay", replay = %replay.display()).entered();
+    with_controller(
+        replay,
+        game_data,
+        |packet_data, mut parser, mut controller| {
+            parser.parse_packets_mut(packet_data, &mut controller)?;
+            Ok(controller.build_report())
+        },
+    )
+}
+
+/// How a battle ended, as found by [scan_results]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BattleEndScan {
+    /// The team which won, if the replay's game version records it
+    pub winning_team: Option<i8>,
+    /// The game clock when the battle ended, or `None` if the replay stops before the end
+    pub ended_at: Option<crate::clock::GameClock>,
+    /// The battle results JSON, which only some replays include
+    pub results: Option<String>,
+}
+
+/// Collects the [BattleEndScan] from the packets [scan_results] parses
+#[cfg(feature = "analyzer")]
+struct OutcomeScanner {
+    version: crate::version::Version,
+    outcome: BattleEndScan,
+}
+
+#[cfg(feature = "analyzer")]
+impl crate::packet2::PacketProcessorMut for OutcomeScanner {
+    fn process_mut(&mut self, packet: crate::packet2::Packet<'_, '_>) {
+        use crate::analyzer::decoder::{DecodedPacket, DecodedPacketPayload};
+
+        let decoded = DecodedPacket::from(&self.version, false, &packet);
+        match decoded.payload {
+            DecodedPacketPayload::BattleEnd { winning_team, .. } => {
+                self.outcome.winning_team = winning_team;
+                self.outcome.ended_at = Some(decoded.game_clock());
+            }
+            DecodedPacketPayload::BattleResults(results) => {
+                self.outcome.results = Some(results.to_string());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Finds how the battle in the replay at `replay` ended without analyzing the rest of
+/// it, see [crate::packet2::Parser::parse_results_mut]. This is much faster than
+/// [analyze_replay] and needs no game data.
+#[cfg(feature = "analyzer")]
+pub fn scan_results(replay: &std::path::Path) -> Result<BattleEndScan, ErrorKind> {
+    let _span = info_span!("scan_results", replay = %replay.display()).entered();
+    let (replay_file, specs) = load_replay(replay)?;
+
+    let mut parser = crate::packet2::Parser::new(&specs);
+    let mut scanner = OutcomeScanner {
+        version: crate::version::Version::from_client_exe(&replay_file.meta.clientVersionFromExe),
+        outcome: BattleEndScan::default(),
+    };
+    parser.parse_results_mut(&replay_file.packet_data, &mut scanner)?;
+
+    Ok(scanner.outcome)
+}
+
+/// The first multiple of `interval` after `clock`
+#[cfg(feature = "analyzer")]
+fn next_multiple(clock: std::time::Duration, interval: std::time::Duration) -> std::time::Duration {
+    use std::convert::TryFrom;
+
+    let nanos = (clock.as_nanos() / interval.as_nanos() + 1) * interval.as_nanos();
+    u64::try_from(nanos).map_or(std::time::Duration::MAX, std::time::Duration::from_nanos)
+}
+
+/// Parses `packet_data` with `processor`, calling `on_interval` with the processor and the
+/// packet's clock after the first packet at or after each multiple of `interval` of game
+/// time, or after every packet if `interval` is zero. Packets whose clock isn't a valid
+/// time never trigger it, since the controller drops them.
+#[cfg(feature = "analyzer")]
+fn parse_at_intervals<P, F>(
+    parser: &mut crate::packet2::Parser<'_>,
+    packet_data: &[u8],
+    processor: &mut P,
+    interval: std::time::Duration,
+    mut on_interval: F,
+) -> Result<(), ErrorKind>
+where
+    P: crate::packet2::PacketProcessorMut,
+    F: FnMut(&P, std::time::Duration),
+{
+    let mut next = std::time::Duration::ZERO;
+    let mut i = packet_data;
+    while !i.is_empty() {
+        let (remaining, packet) = parser.parse_packet_bytes(i)?;
+        i = remaining;
+        let clock = std::time::Duration::try_from_secs_f32(packet.clock);
+        processor.process_mut(packet);
+        let clock = match clock {
+            Ok(clock) if clock >= next => clock,
+            _ => continue,
+        };
+
+        on_interval(processor, clock);
+        if !interval.is_zero() {
+            next = next_multiple(clock, interval);
+        }
+    }
+    Ok(())
+}
+
+/// Parses the replay like [analyze_replay], calling `on_tick` with a snapshot of the
+/// battle every `tick` of game time, e.g. to stream the battle to a live viewer. A zero
+/// `tick` sends a snapshot after every packet.
+///
+/// With a `speed` the replay is played back in real time, sped up by that factor, rather
+/// than parsed as fast as possible.
+#[cfg(feature = "analyzer")]
+pub fn play_replay<F: FnMut(&crate::analyzer::battle_controller::BattleSnapshot)>(
+    replay: &std::path::Path,
+    game_data: &crate::resource_loader::GameDataSource,
+    tick: std::time::Duration,
+    speed: Option<f32>,
+    mut on_tick: F,
+) -> Result<crate::analyzer::battle_controller::BattleReport, ErrorKind> {
+    use crate::analyzer::battle_controller::BattleSnapshot;
+    use std::time::{Duration, Instant};
+
+    let _span = info_span!("play_replay", replay = %replay.display()).entered();
+    with_controller(
+        replay,
+        game_data,
+        |packet_data, mut parser, mut controller| {
+            let started = Instant::now();
+            parse_at_intervals(
+                &mut parser,
+                packet_data,
+                &mut controller,
+                tick,
+                |controller, clock| {
+                    if let Some(speed) = speed {
+                        let due = Duration::try_from_secs_f32(clock.as_secs_f32() / speed)
+                            .ok()
+                            .and_then(|elapsed| started.checked_add(elapsed));
+                        if let Some(wait) =
+                            due.and_then(|due| due.checked_duration_since(Instant::now()))
+                        {
+                            std::thread::sleep(wait);
+                        }
+                    }
+                    on_tick(&BattleSnapshot::from_state(controller));
+                },
+            )?;
+            Ok(controller.build_report())
+        },
+    )
+}
+
+/// Parses the replay at `replay` once into a [ReplaySession] which can then be seeked to
+/// any time, recording a keyframe every `interval` of game time (see
+/// [crate::analyzer::battle_controller::KEYFRAME_INTERVAL]). Property history is tracked
+/// so that ships can be placed between keyframes.
+#[cfg(feature = "analyzer")]
+pub fn open_replay_session(
+    replay: &std::path::Path,
+    game_data: &crate::resource_loader::GameDataSource,
+    interval: std::time::Duration,
+) -> Result<crate::analyzer::battle_controller::ReplaySession, ErrorKind> {
+    use crate::analyzer::battle_controller::{BattleSnapshot, ReplaySession, TrackedProperty};
+
+    let _span = info_span!("open_replay_session", replay = %replay.display()).entered();
+    with_controller(
+        replay,
+        game_data,
+        |packet_data, mut parser, mut controller| {
+            controller.track_property_history(&TrackedProperty::ALL);
+            let mut keyframes = Vec::new();
+            parse_at_intervals(
+                &mut parser,
+                packet_data,
+                &mut controller,
+                interval,
+                |controller, _| keyframes.push(BattleSnapshot::from_state(controller)),
+            )?;
+            // So that seeking to the very end sees every packet
+            keyframes.push(BattleSnapshot::from_state(&controller));
+            debug!(keyframes = keyframes.len(), "recorded keyframes");
+
+            Ok(ReplaySession::new(keyframes, controller.build_report()))
+        },
+    )
+}
+
+#[cfg(all(test, feature = "analyzer"))]
+mod test {
+    use super::*;
+    use crate::packet2::{Packet, PacketProcessorMut, PacketType, Parser};
+    use crate::version::{EmbeddedDataFiles, Version};
+    use std::time::Duration;
+
+    struct PacketCounter(usize);
+
+    impl PacketProcessorMut for PacketCounter {
+        fn process_mut(&mut self, _packet: Packet<'_, '_>) {
+            self.0 += 1;
+        }
+    }
+
+    #[test]
+    fn test_parse_at_intervals() {
+        let version = "0,11,7,0";
+        let datafiles =
+            EmbeddedDataFiles::new("versions".into(), Version::from_client_exe(version)).unwrap();
+        let specs = crate::parse_scripts(&datafiles).unwrap();
+
+        let mut builder =
+            crate::testkit::ReplayBuilder::new(&specs, crate::testkit::minimal_meta(version));
+        for clock in &[0.0, 0.5, 1.0, 1.2, f32::NAN, -1.0, 3.5, 3.9] {
+            builder.packet(*clock, &PacketType::CameraMode(0)).unwrap();
+        }
+
+        let intervals = |interval| {
+            let mut parser = Parser::new(&specs);
+            let mut counter = PacketCounter(0);
+            let mut calls = vec![];
+            parse_at_intervals(
+                &mut parser,
+                builder.packet_data(),
+                &mut counter,
+                interval,
+                |counter, clock| calls.push((counter.0, clock.as_secs_f32())),
+            )
+            .unwrap();
+            assert_eq!(counter.0, 8);
+            calls
+        };
+
+        assert_eq!(
+            intervals(Duration::from_secs(1)),
+            vec![(1, 0.0), (3, 1.0), (7, 3.5)]
+        );
+        assert_eq!(intervals(Duration::from_secs(2)), vec![(1, 0.0), (7, 3.5)]);
+        // Every packet with a valid clock
+        assert_eq!(
+            intervals(Duration::ZERO),
+            vec![(1, 0.0), (2, 0.5), (3, 1.0), (4, 1.2), (7, 3.5), (8, 3.9)]
+        );
     }
 }
@@ -10,6 +10,7 @@ use std::sync::Mutex;
 use tera::{Context, Tera};
 use wows_replays::analyzer::decoder::DecodedPacketPayload;
 use wows_replays::analyzer::AnalyzerBuilder;
+use wows_replays::notes::ReplayNotes;
 use wows_replays::packet2::Packet;
 use wows_replays::parse_scripts;
 use wows_replays::ReplayFile;
@@ -36,6 +37,9 @@ struct ReplayInfo {
     victory: Option<bool>,
     num_packets: usize,
     player_team: i64,
+    /// From the replay's notes sidecar, as of when the replay was parsed
+    tags: Vec<String>,
+    notes: Vec<String>,
 }
 
 impl wows_replays::packet2::PacketProcessor for ReplayInfo {
@@ -79,6 +83,8 @@ impl ReplayInfo {
             victory: None,
             num_packets: 0,
             player_team: -1,
+            tags: vec![],
+            notes: vec![],
         }
     }
 
@@ -102,6 +108,10 @@ impl ReplayInfo {
             &replay_file.meta,
         );
         p.parse_packets(&replay_file.packet_data, &mut processor);
+
+        let notes = ReplayNotes::load(replay).unwrap_or_default();
+        processor.tags = notes.tags().map(|tag| tag.to_string()).collect();
+        processor.notes = notes.notes().to_vec();
         Ok(processor)
     }
 }
@@ -141,7 +151,7 @@ fn file_watcher(path: String, file_sink: Sender<std::path::PathBuf>) {
         for entry in walkdir::WalkDir::new(&path)
             .into_iter()
             .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
+            .filter(|e| e.file_type().is_file() && !ReplayNotes::is_sidecar(e.path()))
         {
             let hash = format!("{}", entry.path().display());
             if seen.insert(hash.clone()) {
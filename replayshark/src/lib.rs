@@ -0,0 +1,2050 @@
+//! The replayshark command line tool, as a library so that other crates can build their
+//! own replayshark binary with extra subcommands rather than upstreaming every niche tool.
+//! Implement [Subcommand] for each one and hand them to [run]:
+//!
+//! ```ignore
+//! struct Hello;
+//!
+//! impl replayshark::Subcommand for Hello {
+//!     fn name(&self) -> &'static str {
+//!         "hello"
+//!     }
+//!
+//!     fn app(&self) -> clap::App<'static, 'static> {
+//!         clap::SubCommand::with_name("hello").arg(clap::Arg::with_name("REPLAY").required(true))
+//!     }
+//!
+//!     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<(), wows_replays::ErrorKind> {
+//!         let replay = wows_replays::ReplayFile::from_file(std::path::Path::new(
+//!             matches.value_of("REPLAY").unwrap(),
+//!         ))?;
+//!         println!("Hello, {}", replay.meta.playerName);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! fn main() {
+//!     let mut registry = replayshark::SubcommandRegistry::new();
+//!     registry.register(Hello);
+//!     replayshark::run(registry);
+//! }
+//! ```
+
+use clap::{App, AppSettings, Arg, SubCommand};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use wows_replays::analyzer::survey::PacketCoverage;
+use wows_replays::game_mode::GameModeKind;
+use wows_replays::notes::ReplayNotes;
+
+use wows_replays::archive::{self, ArchiveKind};
+use wows_replays::{parse_scripts, ErrorKind, ReplayFile};
+
+mod overlay;
+mod registry;
+
+pub use registry::*;
+
+mod built_info {
+    // The file has been placed there by the build script.
+    include!(concat!(env!("OUT_DIR"), "/built.rs"));
+}
+
+struct InvestigativePrinter {
+    filter_packet: Option<u32>,
+    filter_method: Option<String>,
+    timestamp: Option<f32>,
+    entity_id: Option<u32>,
+    meta: bool,
+    version: wows_replays::version::Version,
+}
+
+impl wows_replays::analyzer::AnalyzerMut for InvestigativePrinter {
+    fn finish(&mut self) {}
+
+    fn process_mut(&mut self, packet: &wows_replays::packet2::Packet<'_, '_>) {
+        let decoded =
+            wows_replays::analyzer::decoder::DecodedPacket::from(&self.version, true, packet);
+
+        if self.meta {
+            match &decoded.payload {
+                wows_replays::analyzer::decoder::DecodedPacketPayload::OnArenaStateReceived {
+                    players,
+                    ..
+                } => {
+                    for player in players.iter() {
+                        println!(
+                            "{} {}/{} ({:x?}/{:x?})",
+                            player.username,
+                            player.entity_id,
+                            player.avatar_id,
+                            (player.entity_id as u32).to_le_bytes(),
+                            (player.avatar_id as u32).to_le_bytes()
+                        );
+                    }
+                }
+                _ => {
+                    // Nop
+                }
+            }
+        }
+
+        if let Some(n) = self.filter_packet {
+            if n != decoded.packet_type {
+                return;
+            }
+        }
+        if let Some(s) = self.filter_method.as_ref() {
+            match &packet.payload {
+                wows_replays::packet2::PacketType::EntityMethod(method) => {
+                    if method.method != s {
+                        return;
+                    }
+                    if let Some(eid) = self.entity_id {
+                        if method.entity_id != eid {
+                            return;
+                        }
+                    }
+                }
+                _ => {
+                    return;
+                }
+            }
+        }
+        if let Some(t) = self.timestamp {
+            let clock = wows_replays::clock::GameClock(decoded.clock + t);
+            let encoded = if self.filter_method.is_some() {
+                match &packet.payload {
+                    wows_replays::packet2::PacketType::EntityMethod(method) => {
+                        serde_json::to_string(&method).unwrap()
+                    }
+                    _ => panic!(),
+                }
+            } else if self.filter_packet.is_some() {
+                match &packet.payload {
+                    wows_replays::packet2::PacketType::Unknown(x) => {
+                        let v: Vec<_> = x.iter().map(|n| format!("{:02x}", n)).collect();
+                        format!("0x[{}]", v.join(","))
+                    }
+                    _ => serde_json::to_string(&packet).unwrap(),
+                }
+            } else {
+                serde_json::to_string(&decoded).unwrap()
+            };
+            println!("{}: {}", clock.to_hhmmss(), encoded);
+        } else {
+            let encoded = serde_json::to_string(&decoded).unwrap();
+            println!("{}", &encoded);
+        }
+    }
+}
+
+pub struct InvestigativeBuilder {
+    no_meta: bool,
+    filter_packet: Option<String>,
+    filter_method: Option<String>,
+    timestamp: Option<String>,
+    entity_id: Option<String>,
+}
+
+impl wows_replays::analyzer::AnalyzerMutBuilder for InvestigativeBuilder {
+    fn build(
+        &self,
+        meta: &wows_replays::ReplayMeta,
+    ) -> Box<dyn wows_replays::analyzer::AnalyzerMut> {
+        let version = wows_replays::version::Version::from_client_exe(&meta.clientVersionFromExe);
+        let decoder = InvestigativePrinter {
+            version: version,
+            filter_packet: self
+                .filter_packet
+                .as_ref()
+                .map(|s| parse_int::parse::<u32>(s).unwrap()),
+            filter_method: self.filter_method.clone(),
+            timestamp: self.timestamp.as_ref().map(|s| {
+                let ts_parts: Vec<_> = s.split("+").collect();
+                let offset = ts_parts[1].parse::<u32>().unwrap();
+                let parts: Vec<_> = ts_parts[0].split(":").collect();
+                if parts.len() == 3 {
+                    let h = parts[0].parse::<u32>().unwrap();
+                    let m = parts[1].parse::<u32>().unwrap();
+                    let s = parts[2].parse::<u32>().unwrap();
+                    (h * 3600 + m * 60 + s) as f32 - offset as f32
+                } else {
+                    panic!("Expected hh:mm:ss+offset as timestamp");
+                }
+            }),
+            entity_id: self
+                .entity_id
+                .as_ref()
+                .map(|s| parse_int::parse(s).unwrap()),
+            meta: !self.no_meta,
+        };
+        if !self.no_meta {
+            println!("{}", &serde_json::to_string(&meta).unwrap());
+        }
+        Box::new(decoder)
+    }
+}
+
+/// Set by `--bridge-versions`
+static BRIDGE_VERSIONS: AtomicBool = AtomicBool::new(false);
+
+fn parse_replay<P: wows_replays::analyzer::AnalyzerMutBuilder>(
+    replay: &std::path::PathBuf,
+    processor: P,
+) -> Result<(), wows_replays::ErrorKind> {
+    let replay_file = ReplayFile::from_file(replay)?;
+
+    //let mut file = std::fs::File::create("foo.bin").unwrap();
+    //file.write_all(&replay_file.packet_data).unwrap();
+
+    let version =
+        wows_replays::version::Version::from_client_exe(&replay_file.meta.clientVersionFromExe);
+    let datafiles = if BRIDGE_VERSIONS.load(Ordering::Relaxed) {
+        wows_replays::version::EmbeddedDataFiles::nearest(
+            std::path::PathBuf::from("versions"),
+            version,
+        )?
+    } else {
+        wows_replays::version::EmbeddedDataFiles::new(
+            std::path::PathBuf::from("versions"),
+            version,
+        )?
+    };
+    let specs = parse_scripts(&datafiles)?;
+
+    let version_parts: Vec<_> = replay_file.meta.clientVersionFromExe.split(",").collect();
+    assert!(version_parts.len() == 4);
+
+    let processor = processor.build(&replay_file.meta);
+
+    // Parse packets
+    let mut p = wows_replays::packet2::Parser::new(&specs);
+    p.set_bridged(datafiles.is_bridged());
+    p.set_game_mode(wows_replays::game_mode::GameModeKind::from_meta(
+        &replay_file.meta,
+    ));
+    let mut analyzer_set = wows_replays::analyzer::AnalyzerAdapter::new(vec![processor]);
+    match p.parse_packets_mut::<wows_replays::analyzer::AnalyzerAdapter>(
+        &replay_file.packet_data,
+        &mut analyzer_set,
+    ) {
+        Ok(()) => {
+            analyzer_set.finish();
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn validate_results(
+    replay: &std::path::Path,
+    game_params: &std::path::Path,
+    layout: &std::path::Path,
+) -> Result<(), wows_replays::ErrorKind> {
+    let layout: wows_replays::analyzer::battle_controller::ResultsLayout =
+        serde_json::from_reader(std::fs::File::open(layout).unwrap())?;
+    let game_data = wows_replays::resource_loader::GameDataSource::load(game_params, None)?;
+    let report = wows_replays::analyze_replay(replay, &game_data)?;
+
+    let discrepancies = match report.diff_battle_results(&layout) {
+        Some(discrepancies) => discrepancies?,
+        None => {
+            println!("Replay does not contain battle results");
+            return Ok(());
+        }
+    };
+
+    if discrepancies.is_empty() {
+        println!("Tracked values match the battle results");
+    }
+    for discrepancy in discrepancies {
+        println!(
+            "{} ({}): {:?} tracked {} but results report {}",
+            discrepancy.player_name,
+            discrepancy.db_id,
+            discrepancy.field,
+            discrepancy.tracked,
+            discrepancy.reported
+        );
+    }
+
+    Ok(())
+}
+
+type WebSocketClients =
+    std::sync::Arc<std::sync::Mutex<Vec<tungstenite::WebSocket<std::net::TcpStream>>>>;
+
+/// Accepts WebSocket connections on `address` in the background
+fn accept_websocket_clients(address: &str) -> Result<WebSocketClients, wows_replays::ErrorKind> {
+    let listener = std::net::TcpListener::bind(address)?;
+    println!("Streaming to ws://{}", address);
+
+    let clients = WebSocketClients::default();
+    {
+        let clients = clients.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+                match tungstenite::accept(stream) {
+                    Ok(socket) => clients.lock().unwrap().push(socket),
+                    Err(e) => println!("WebSocket handshake failed: {}", e),
+                }
+            }
+        });
+    }
+    Ok(clients)
+}
+
+/// Sends `message` to every connected client
+fn broadcast(clients: &WebSocketClients, message: &str) {
+    // Clients which fail to receive a message have disconnected
+    clients.lock().unwrap().retain_mut(|socket| {
+        socket
+            .send(tungstenite::Message::Text(message.to_string()))
+            .is_ok()
+    });
+}
+
+/// Plays the replay back in real time, sending a JSON snapshot of the battle to every
+/// connected WebSocket client each tick
+fn serve_playback(
+    replay: &std::path::Path,
+    game_params: &std::path::Path,
+    address: &str,
+    tick: std::time::Duration,
+    speed: Option<f32>,
+) -> Result<(), wows_replays::ErrorKind> {
+    let game_data = wows_replays::resource_loader::GameDataSource::load(game_params, None)?;
+    let clients = accept_websocket_clients(address)?;
+
+    wows_replays::play_replay(replay, &game_data, tick, speed, |snapshot| {
+        broadcast(&clients, &serde_json::to_string(snapshot).unwrap());
+    })?;
+
+    for mut socket in clients.lock().unwrap().drain(..) {
+        let _ = socket.close(None);
+    }
+    Ok(())
+}
+
+fn print_loadouts(
+    replay: &std::path::Path,
+    game_params: &std::path::Path,
+    translations: Option<&std::path::Path>,
+) -> Result<(), wows_replays::ErrorKind> {
+    let game_data = wows_replays::resource_loader::GameDataSource::load(game_params, translations)?;
+    let report = wows_replays::analyze_replay(replay, &game_data)?;
+
+    let param_name = |id: u32| -> String {
+        match game_data.game_param_by_id(id) {
+            Some(param) => game_data
+                .localized_name_from_param(&param)
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| param.name().to_string()),
+            None => format!("<unknown {}>", id),
+        }
+    };
+    let param_names = |ids: &[u32]| -> String {
+        ids.iter()
+            .filter(|id| **id != 0)
+            .map(|id| param_name(*id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    for vehicle in report.player_entities() {
+        let player = match vehicle.player() {
+            Some(player) => player,
+            None => continue,
+        };
+        let ship = player.vehicle();
+        println!(
+            "[{}] {} ({})",
+            player.clan(),
+            player.name(),
+            game_data
+                .localized_name_from_param(ship)
+                .unwrap_or_else(|| ship.name())
+        );
+
+        let config = vehicle.props().ship_config();
+        println!("  Hull: {}", param_name(config.hull()));
+        println!("  Modules: {}", param_names(config.units()));
+        println!("  Upgrades: {}", param_names(config.modernization()));
+        println!("  Signals: {}", param_names(config.signals()));
+        println!("  Consumables: {}", param_names(config.abilities()));
+
+        let skills = vehicle
+            .commander_skills()
+            .unwrap_or_default()
+            .iter()
+            .map(|skill| {
+                game_data
+                    .localized_name_from_id(&format!(
+                        "IDS_SKILL_{}",
+                        skill.name().to_ascii_uppercase()
+                    ))
+                    .unwrap_or_else(|| skill.name().to_string())
+            })
+            .collect::<Vec<_>>();
+        println!(
+            "  Captain: {}",
+            vehicle
+                .captain()
+                .map(|captain| param_name(captain.id()))
+                .unwrap_or_else(|| "<default>".to_string())
+        );
+        println!("  Captain skills: {}", skills.join(", "));
+        println!();
+    }
+
+    let self_entity = match report.self_entity() {
+        Some(self_entity) => self_entity,
+        // Observer replays have no recording player
+        None => return Ok(()),
+    };
+    println!("Skill efficacy ({}):", self_entity.player().unwrap().name());
+    for efficacy in report.skill_efficacy() {
+        let evidence = efficacy
+            .evidence()
+            .iter()
+            .map(|evidence| format!("{:?}: {}", evidence.kind(), evidence.count()))
+            .collect::<Vec<_>>();
+        println!(
+            "  {} {}{}",
+            if efficacy.mattered() { "+" } else { "-" },
+            efficacy.skill_name(),
+            if evidence.is_empty() {
+                " (no tracked effects)".to_string()
+            } else {
+                format!(" ({})", evidence.join(", "))
+            }
+        );
+    }
+
+    Ok(())
+}
+
+/// Compares each player's build in the replay against the most common build of their ship
+/// across the corpus of replays
+fn print_build_deviations(
+    replay: &std::path::Path,
+    corpus: &[&str],
+    game_params: &std::path::Path,
+    translations: Option<&std::path::Path>,
+) -> Result<(), wows_replays::ErrorKind> {
+    use wows_replays::analyzer::battle_controller::{BuildComponent, BuildCorpus, PlayerId};
+
+    let game_data = wows_replays::resource_loader::GameDataSource::load(game_params, translations)?;
+
+    let mut builds = BuildCorpus::new();
+    for path in corpus {
+        for entry in walkdir::WalkDir::new(path) {
+            let entry = entry.expect("Error unwrapping entry");
+            if !entry.path().is_file() {
+                continue;
+            }
+            let replays = if ArchiveKind::from_path(entry.path()).is_some() {
+                archive::archived_replays(entry.path()).expect("Error reading archive")
+            } else {
+                vec![entry.path().to_path_buf()]
+            };
+            for corpus_replay in replays {
+                match wows_replays::analyze_replay(&corpus_replay, &game_data) {
+                    Ok(report) => builds.add_report(&report),
+                    Err(e) => println!("Skipping {}: {:?}", corpus_replay.display(), e),
+                }
+            }
+        }
+    }
+
+    let report = wows_replays::analyze_replay(replay, &game_data)?;
+    let param_name = |id: u32| -> String {
+        match game_data.game_param_by_id(id) {
+            Some(param) => game_data
+                .localized_name_from_param(&param)
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| param.name().to_string()),
+            None => format!("<unknown {}>", id),
+        }
+    };
+    // Captain skills are raw skill types rather than GameParams IDs
+    let item_names = |component: BuildComponent, ids: &[u32]| -> String {
+        ids.iter()
+            .map(|id| match component {
+                BuildComponent::CaptainSkills => format!("skill {}", id),
+                _ => param_name(*id),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let resolver = report.id_resolver();
+    for annotation in builds.annotate(&report) {
+        let player = match resolver.player(PlayerId::Entity(annotation.entity_id())) {
+            Some(player) => player,
+            None => continue,
+        };
+        println!(
+            "{} ({}, {} builds in corpus)",
+            player.name(),
+            param_name(annotation.ship_id()),
+            annotation.samples()
+        );
+        if annotation.deviations().is_empty() {
+            println!("  Matches the common build");
+        }
+        for deviation in annotation.deviations() {
+            let mut changes = vec![];
+            if !deviation.missing().is_empty() {
+                changes.push(format!(
+                    "without {}",
+                    item_names(deviation.component(), deviation.missing())
+                ));
+            }
+            if !deviation.extra().is_empty() {
+                changes.push(format!(
+                    "with {}",
+                    item_names(deviation.component(), deviation.extra())
+                ));
+            }
+            println!(
+                "  {:?}: {} (common build used by {:.0}%)",
+                deviation.component(),
+                changes.join(", "),
+                100. * deviation.popularity()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Rates each player's performance against their ship's expected values
+/// Writes a catalog of the translations which the replays refer to, see
+/// [wows_replays::translations]
+fn extract_translations(
+    replays: &[&str],
+    game_params: &std::path::Path,
+    mo: &std::path::Path,
+    output: &std::path::Path,
+) -> Result<(), wows_replays::ErrorKind> {
+    let game_data = wows_replays::resource_loader::GameDataSource::load(game_params, None)?;
+    let translations = wows_replays::translations::read_mo(&std::fs::read(mo)?)?;
+
+    let mut catalog = wows_replays::translations::TranslationCatalog::new();
+    let mut replay_count = 0;
+    for path in replays {
+        for entry in walkdir::WalkDir::new(path) {
+            let entry = entry.expect("Error unwrapping entry");
+            if !entry.path().is_file() {
+                continue;
+            }
+            let files = if ArchiveKind::from_path(entry.path()).is_some() {
+                archive::archived_replays(entry.path()).expect("Error reading archive")
+            } else {
+                vec![entry.path().to_path_buf()]
+            };
+            for file in files {
+                match ReplayFile::from_file(&file) {
+                    Ok(replay) => {
+                        catalog.add_replay(&replay.meta, game_data.params());
+                        replay_count += 1;
+                    }
+                    Err(e) => println!("Skipping {}: {:?}", file.display(), e),
+                }
+            }
+        }
+    }
+
+    let extracted = catalog.extract(&translations);
+    serde_json::to_writer(std::fs::File::create(output)?, &extracted)?;
+    println!(
+        "Extracted {} of {} referenced strings from {} replays",
+        extracted.len(),
+        catalog.ids().count(),
+        replay_count
+    );
+    Ok(())
+}
+
+/// Prints how the battle ended and its results JSON, without analyzing the rest of the
+/// replay
+fn print_results(replay: &std::path::Path) -> Result<(), wows_replays::ErrorKind> {
+    let outcome = wows_replays::scan_results(replay)?;
+    println!("{}", serde_json::to_string_pretty(&outcome)?);
+    Ok(())
+}
+
+/// Fills in the options for drawing the replay's ships in `trace` which need game data:
+/// the classes of the ships in the replay, for drawing them with icons, and the recording
+/// player's detection range
+#[cfg(feature = "graphics")]
+fn add_ship_render_options(
+    options: &mut analysis::trails::RenderOptions,
+    replay: &std::path::Path,
+    game_data: &wows_replays::resource_loader::GameDataSource,
+    silhouettes: Option<&std::path::Path>,
+) -> Result<(), wows_replays::ErrorKind> {
+    use wows_replays::game_params::GameParamProvider;
+    use wows_replays::ui_support::{IconStyle, ShipClass, Silhouettes};
+
+    let replay = ReplayFile::from_file(replay)?;
+    for vehicle in &replay.meta.vehicles {
+        let ship = match game_data.params().game_param_by_id(vehicle.shipId as u32) {
+            Some(ship) => ship,
+            None => continue,
+        };
+        if let Some(class) = ship.species().as_ref().and_then(ShipClass::from_species) {
+            options.ship_classes.insert(vehicle.shipId as u32, class);
+        }
+        if vehicle.name == replay.meta.playerName {
+            options.view_range = ship
+                .data()
+                .vehicle_ref()
+                .and_then(|vehicle| vehicle.base_stats())
+                .and_then(|stats| stats.concealment_by_sea);
+        }
+    }
+    if let Some(silhouettes) = silhouettes {
+        options.icon_style = IconStyle::Silhouette;
+        options.silhouettes = Silhouettes::from_dir(silhouettes, game_data.params())?;
+    }
+    Ok(())
+}
+
+fn print_ratings(
+    replay: &std::path::Path,
+    game_params: &std::path::Path,
+    expected_values: &std::path::Path,
+) -> Result<(), wows_replays::ErrorKind> {
+    let game_data = wows_replays::resource_loader::GameDataSource::load(game_params, None)?;
+    let expected_values =
+        wows_replays::analyzer::battle_controller::ExpectedValuesTable::load(expected_values)?;
+    let report = wows_replays::analyze_replay(replay, &game_data)?;
+
+    let resolver = report.id_resolver();
+    for rating in report.personal_ratings(&expected_values) {
+        let player = match resolver.player(
+            wows_replays::analyzer::battle_controller::PlayerId::Entity(rating.entity_id()),
+        ) {
+            Some(player) => player,
+            None => continue,
+        };
+        println!(
+            "{:>5.0} {} ({}): damage x{:.2}, frags x{:.2}{}",
+            rating.rating(),
+            player.name(),
+            player.vehicle().name(),
+            rating.damage_ratio(),
+            rating.frags_ratio(),
+            rating
+                .wins_ratio()
+                .map(|wins| format!(", wins x{:.2}", wins))
+                .unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+fn print_torpedo_warnings(
+    replay: &std::path::Path,
+    game_params: &std::path::Path,
+) -> Result<(), wows_replays::ErrorKind> {
+    let game_data = wows_replays::resource_loader::GameDataSource::load(game_params, None)?;
+    let report = wows_replays::analyze_replay(replay, &game_data)?;
+
+    let resolver = report.id_resolver();
+    let mut warning_times = Vec::new();
+    for warning in report.torpedo_warnings() {
+        let owner = resolver
+            .player(wows_replays::analyzer::battle_controller::PlayerId::Entity(
+                warning.owner(),
+            ))
+            .map(|player| player.name().to_string())
+            .unwrap_or_else(|| warning.owner().to_string());
+        let spotted_at = wows_replays::clock::GameClock::from(warning.spotted_at()).to_mmss();
+        match warning.warning_time() {
+            Some(warning_time) => {
+                warning_times.push(warning_time.as_secs_f32());
+                println!(
+                    "{} {}: {:?} after {:.1}s",
+                    spotted_at,
+                    owner,
+                    warning.outcome(),
+                    warning_time.as_secs_f32()
+                );
+            }
+            None => println!("{} {}: {:?}", spotted_at, owner, warning.outcome()),
+        }
+    }
+
+    if !warning_times.is_empty() {
+        println!(
+            "Average warning time: {:.1}s over {} torpedoes",
+            warning_times.iter().sum::<f32>() / warning_times.len() as f32,
+            warning_times.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Runs an external analyzer, streaming it the replay's packets as described in
+/// [wows_replays::analyzer::pipe]. Its stdout goes to `output`, or ours if not given.
+fn run_pipe(
+    replay: &std::path::Path,
+    command: &[&str],
+    format: wows_replays::analyzer::pipe::PipeFormat,
+    output: Option<&std::path::Path>,
+) -> Result<(), wows_replays::ErrorKind> {
+    let stdout = match output {
+        Some(path) => std::process::Stdio::from(std::fs::File::create(path)?),
+        None => std::process::Stdio::inherit(),
+    };
+    let mut child = std::process::Command::new(command[0])
+        .args(&command[1..])
+        .stdin(std::process::Stdio::piped())
+        .stdout(stdout)
+        .spawn()?;
+
+    let stdin = child.stdin.take().unwrap();
+    let pipe = wows_replays::analyzer::pipe::PipeBuilder::new(stdin, format);
+    let result = parse_replay(&replay.to_path_buf(), pipe);
+
+    let status = child.wait()?;
+    if !status.success() {
+        println!("{} exited with {}", command[0], status);
+    }
+    result
+}
+
+fn truncate_string(s: &str, length: usize) -> &str {
+    match s.char_indices().nth(length) {
+        None => s,
+        Some((idx, _)) => &s[..idx],
+    }
+}
+
+fn printspecs(specs: &Vec<wows_replays::rpc::entitydefs::EntitySpec>) {
+    println!("Have {} entities", specs.len());
+    for entity in specs.iter() {
+        println!();
+        println!(
+            "{} has {} properties ({} internal) and {}/{}/{} base/cell/client methods",
+            entity.name,
+            entity.properties.len(),
+            entity.internal_properties.len(),
+            entity.base_methods.len(),
+            entity.cell_methods.len(),
+            entity.client_methods.len()
+        );
+
+        println!("Properties:");
+        for (i, property) in entity.properties.iter().enumerate() {
+            println!(
+                " - {}: {} flag={:?} type={:?}",
+                i, property.name, property.flags, property.prop_type
+            );
+        }
+        println!("Internal properties:");
+        for (i, property) in entity.internal_properties.iter().enumerate() {
+            println!(" - {}: {} type={:?}", i, property.name, property.prop_type);
+        }
+        println!("Client methods:");
+        for (i, method) in entity.client_methods.iter().enumerate() {
+            println!(" - {}: {}", i, method.name);
+            for arg in method.args.iter() {
+                println!("      - {:?}", arg);
+            }
+        }
+    }
+}
+
+enum SurveyResult {
+    /// npackets, ninvalid, partial decodes by method
+    Success(
+        (
+            String,
+            String,
+            usize,
+            usize,
+            Vec<String>,
+            BTreeMap<String, usize>,
+            GameModeKind,
+            String,
+            BTreeMap<String, PacketCoverage>,
+        ),
+    ),
+    UnsupportedVersion(String),
+    ParseFailure(String, GameModeKind),
+}
+
+/// How well replays of a game mode are understood
+#[derive(Default)]
+struct ModeCoverage {
+    replays: usize,
+    parse_failures: usize,
+    packets: usize,
+    invalid_packets: usize,
+    partial_decodes: usize,
+}
+
+struct SurveyResults {
+    version_failures: usize,
+    parse_failures: usize,
+    successes: usize,
+    successes_with_invalids: usize,
+    total: usize,
+    invalid_versions: HashMap<String, usize>,
+    audits: HashMap<String, (String, Vec<String>)>,
+    /// Method name -> (calls which failed to decode, replays they appeared in)
+    partial_decodes: HashMap<String, (usize, usize)>,
+    modes: BTreeMap<GameModeKind, ModeCoverage>,
+    /// Game version -> packet kind -> how well it was decoded
+    coverage: BTreeMap<String, BTreeMap<String, PacketCoverage>>,
+}
+
+impl SurveyResults {
+    fn empty() -> Self {
+        Self {
+            version_failures: 0,
+            parse_failures: 0,
+            successes: 0,
+            successes_with_invalids: 0,
+            total: 0,
+            invalid_versions: HashMap::new(),
+            audits: HashMap::new(),
+            partial_decodes: HashMap::new(),
+            modes: BTreeMap::new(),
+            coverage: BTreeMap::new(),
+        }
+    }
+
+    fn add(&mut self, result: SurveyResult) {
+        self.total += 1;
+        match result {
+            SurveyResult::Success((
+                hash,
+                datetime,
+                npacks,
+                ninvalid,
+                audits,
+                partial_decodes,
+                mode,
+                version,
+                coverage,
+            )) => {
+                self.successes += 1;
+                if ninvalid > 0 {
+                    self.successes_with_invalids += 1;
+                }
+                let mode_coverage = self.modes.entry(mode).or_default();
+                mode_coverage.replays += 1;
+                mode_coverage.packets += npacks;
+                mode_coverage.invalid_packets += ninvalid;
+                mode_coverage.partial_decodes += partial_decodes.values().sum::<usize>();
+                for (method, count) in partial_decodes {
+                    let entry = self.partial_decodes.entry(method).or_insert((0, 0));
+                    entry.0 += count;
+                    entry.1 += 1;
+                }
+                if audits.len() > 0 {
+                    self.audits.insert(hash, (datetime, audits));
+                }
+                let version_coverage = self.coverage.entry(version).or_default();
+                for (packet, counts) in coverage {
+                    version_coverage.entry(packet).or_default().add(&counts);
+                }
+            }
+            SurveyResult::UnsupportedVersion(version) => {
+                self.version_failures += 1;
+                if !self.invalid_versions.contains_key(&version) {
+                    self.invalid_versions.insert(version.clone(), 0);
+                }
+                *self.invalid_versions.get_mut(&version).unwrap() += 1;
+            }
+            SurveyResult::ParseFailure(_error, mode) => {
+                self.parse_failures += 1;
+                let mode_coverage = self.modes.entry(mode).or_default();
+                mode_coverage.replays += 1;
+                mode_coverage.parse_failures += 1;
+            }
+        }
+    }
+
+    fn print(&self) {
+        let mut audits: Vec<_> = self.audits.iter().collect();
+        audits.sort_by_key(|(_, (tm, _))| {
+            chrono::NaiveDateTime::parse_from_str(tm, "%d.%m.%Y %H:%M:%S").unwrap()
+        });
+        for (k, (tm, v)) in audits.iter() {
+            println!();
+            println!(
+                "{} ({}) has {} audits:",
+                truncate_string(k, 20),
+                tm,
+                v.len()
+            );
+            let mut cnt = 0;
+            for audit in v.iter() {
+                if cnt >= 10 {
+                    println!("...truncating");
+                    break;
+                }
+                println!(" - {}", audit);
+                cnt += 1;
+            }
+        }
+        println!();
+        println!("Found {} replay files", self.total);
+        println!(
+            "- {} ({:.0}%) were parsed",
+            self.successes,
+            100. * self.successes as f64 / self.total as f64
+        );
+        println!(
+            "  - Of which {} ({:.0}%) contained invalid packets",
+            self.successes_with_invalids,
+            100. * self.successes_with_invalids as f64 / self.successes as f64
+        );
+        println!(
+            "- {} ({:.0}%) had a parse error",
+            self.parse_failures,
+            100. * self.parse_failures as f64 / self.total as f64
+        );
+        println!(
+            "- {} ({:.0}%) are an unrecognized version",
+            self.version_failures,
+            100. * self.version_failures as f64 / self.total as f64
+        );
+        if self.invalid_versions.len() > 0 {
+            for (k, v) in self.invalid_versions.iter() {
+                println!("  - Version {} appeared {} times", k, v);
+            }
+        }
+        if self.partial_decodes.len() > 0 {
+            let mut partial_decodes: Vec<_> = self.partial_decodes.iter().collect();
+            partial_decodes.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then(a_name.cmp(b_name)));
+            println!("Methods whose arguments failed to decode:");
+            for (method, (calls, replays)) in partial_decodes {
+                println!("- {}: {} calls in {} replays", method, calls, replays);
+            }
+        }
+        if self.modes.len() > 0 {
+            println!("Coverage by game mode:");
+            for (mode, coverage) in self.modes.iter() {
+                let undecoded = coverage.invalid_packets + coverage.partial_decodes;
+                println!(
+                    "- {:?}: {} replays, {} had a parse error, {} of {} packets ({:.2}%) not decoded",
+                    mode,
+                    coverage.replays,
+                    coverage.parse_failures,
+                    undecoded,
+                    coverage.packets,
+                    100. * undecoded as f64 / coverage.packets.max(1) as f64
+                );
+            }
+        }
+        let mut totals: BTreeMap<&str, PacketCoverage> = BTreeMap::new();
+        for packets in self.coverage.values() {
+            for (packet, coverage) in packets {
+                totals.entry(packet).or_default().add(coverage);
+            }
+        }
+        let mut undecoded: Vec<_> = totals
+            .iter()
+            .map(|(packet, coverage)| (coverage.observed - coverage.decoded, *packet, coverage))
+            .filter(|(undecoded, _, _)| *undecoded > 0)
+            .collect();
+        if undecoded.len() > 0 {
+            undecoded.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+            println!("Most frequent packets which aren't decoded:");
+            for (count, packet, coverage) in undecoded.iter().take(20) {
+                println!(
+                    "- {}: {} of {} not decoded, {} unknown",
+                    packet, count, coverage.observed, coverage.unknown
+                );
+            }
+        }
+    }
+
+    /// Writes a CSV matrix with a row per packet kind and a column per game version,
+    /// each cell being `decoded/observed` and the number of unknown packets if any
+    fn write_coverage(&self, path: &std::path::Path) -> Result<(), wows_replays::ErrorKind> {
+        let packets: BTreeSet<&String> = self.coverage.values().flat_map(|c| c.keys()).collect();
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        write!(out, "packet")?;
+        for version in self.coverage.keys() {
+            write!(out, ",{}", version.replace(',', "."))?;
+        }
+        writeln!(out)?;
+        for packet in packets {
+            write!(out, "{}", packet)?;
+            for coverage in self.coverage.values() {
+                match coverage.get(packet) {
+                    Some(c) if c.unknown > 0 => {
+                        write!(out, ",{}/{} ({} unknown)", c.decoded, c.observed, c.unknown)?
+                    }
+                    Some(c) => write!(out, ",{}/{}", c.decoded, c.observed)?,
+                    None => write!(out, ",")?,
+                }
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+fn survey_file(skip_decode: bool, replay: std::path::PathBuf) -> SurveyResult {
+    let filename = replay.file_name().unwrap().to_str().unwrap();
+    let filename = filename.to_string();
+
+    print!("Parsing {}: ", truncate_string(&filename, 20));
+    std::io::stdout().flush().unwrap();
+
+    let survey_stats = std::rc::Rc::new(std::cell::RefCell::new(
+        wows_replays::analyzer::survey::SurveyStats::new(),
+    ));
+    let survey =
+        wows_replays::analyzer::survey::SurveyBuilder::new(survey_stats.clone(), skip_decode);
+    match parse_replay(&std::path::PathBuf::from(replay), survey) {
+        Ok(_) => {
+            let stats = survey_stats.borrow();
+            if stats.invalid_packets > 0 {
+                println!(
+                    "OK ({} packets, {} invalid)",
+                    stats.total_packets, stats.invalid_packets
+                );
+            } else {
+                println!("OK ({} packets)", stats.total_packets);
+            }
+            SurveyResult::Success((
+                filename.to_string(),
+                stats.date_time.clone(),
+                stats.total_packets,
+                stats.invalid_packets,
+                stats.audits.clone(),
+                stats.partial_decodes.clone(),
+                stats.game_mode,
+                stats.version.clone(),
+                stats.coverage.clone(),
+            ))
+        }
+        Err(ErrorKind::DatafileNotFound { version, .. }) => {
+            println!("Unsupported version {}", version.to_path());
+            SurveyResult::UnsupportedVersion(version.to_path())
+        }
+        Err(ErrorKind::UnsupportedReplayVersion(n)) => {
+            println!("Unsupported version {}", n);
+            SurveyResult::UnsupportedVersion(n)
+        }
+        Err(e) => {
+            println!("Parse error: {:?}", e);
+            SurveyResult::ParseFailure(format!("{:?}", e), survey_stats.borrow().game_mode)
+        }
+    }
+}
+
+/// Sends the parser's tracing output to stderr
+fn init_logging(level: Option<&str>, json: bool) {
+    let filter = match level {
+        Some(level) => tracing_subscriber::EnvFilter::new(level),
+        None => tracing_subscriber::EnvFilter::from_default_env(),
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    if json {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
+}
+
+/// Runs replayshark with the command line arguments of this process, offering the
+/// `extra` subcommands alongside the built-in ones
+pub fn run(extra: SubcommandRegistry) {
+    let replay_arg = Arg::with_name("REPLAY")
+        .help("The replay file to use")
+        .required(true)
+        .index(1);
+    let matches = App::new("World of Warships Replay Parser Utility")
+        .version(built_info::GIT_VERSION.unwrap_or("undefined"))
+        .author("Lane Kolbly <lane@rscheme.org>")
+        .about("Parses & processes World of Warships replay files")
+        .arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .help("Log filter, e.g. \"debug\" or \"wows_replays=trace\". Defaults to RUST_LOG")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("log-json")
+                .long("log-json")
+                .help("Write logs as JSON lines")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("bridge-versions")
+                .long("bridge-versions")
+                .help("Parse replays of unsupported versions using the nearest supported version")
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("survey")
+                .about("Runs the parser against a directory of replays to validate the parser")
+                .arg(
+                    Arg::with_name("skip-decode")
+                        .long("skip-decode")
+                        .help("Don't run the decoder"),
+                )
+                .arg(
+                    Arg::with_name("coverage")
+                        .long("coverage")
+                        .help("CSV file to write how many packets of each kind were decoded to, per game version")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("REPLAYS")
+                        .help("The replay files, directories, or .zip/.tar.zst archives to use")
+                        .required(true)
+                        .multiple(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("chat")
+                .about("Print the chat log of the given game")
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("summary")
+                .about("Generate summary statistics of the game")
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("targets")
+                .about("Print a timeline of the recording player's targets, flagging questionable choices")
+                .arg(
+                    Arg::with_name("questionable")
+                        .long("questionable")
+                        .help("Only print samples where a better target was available"),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("export-paths")
+                .about("Export ship paths to a JSON file for use in external 3D tools")
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .help("Output JSON file to write")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("playback")
+                .about("Play a replay back in real time, streaming battle snapshots as JSON over WebSocket")
+                .arg(
+                    Arg::with_name("game-params")
+                        .long("game-params")
+                        .help("JSON GameParams dump to resolve IDs with")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .help("Address to accept WebSocket connections on")
+                        .takes_value(true)
+                        .default_value("127.0.0.1:9001"),
+                )
+                .arg(
+                    Arg::with_name("tick")
+                        .long("tick")
+                        .help("Game seconds between snapshots")
+                        .takes_value(true)
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::with_name("speed")
+                        .long("speed")
+                        .help("Playback speed multiplier, e.g. 4 for 4x")
+                        .takes_value(true)
+                        .default_value("1"),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("overlay")
+                .about("Follow the live battle in the game's replays directory, streaming its team rosters and result as JSON over WebSocket for overlay.html, e.g. as an OBS browser source")
+                .arg(
+                    Arg::with_name("game-params")
+                        .long("game-params")
+                        .help("JSON GameParams dump to resolve ship names and classes with")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .help("Address to accept WebSocket connections on")
+                        .takes_value(true)
+                        .default_value("127.0.0.1:9002"),
+                )
+                .arg(
+                    Arg::with_name("REPLAYS")
+                        .help("The game's replays directory")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("loadouts")
+                .about("Print each player's ship build: modules, upgrades, captain skills, signals and consumables")
+                .arg(
+                    Arg::with_name("game-params")
+                        .long("game-params")
+                        .help("JSON GameParams dump to resolve IDs with")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("translations")
+                        .long("translations")
+                        .help("JSON object mapping translation IDs to localized names")
+                        .takes_value(true),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("builds")
+                .about("Compare each player's build against the most common build of their ship in a corpus of replays")
+                .arg(
+                    Arg::with_name("game-params")
+                        .long("game-params")
+                        .help("JSON GameParams dump to resolve IDs with")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("translations")
+                        .long("translations")
+                        .help("JSON object mapping translation IDs to localized names")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("corpus")
+                        .long("corpus")
+                        .help("The replay files, directories, or .zip/.tar.zst archives to find common builds in")
+                        .takes_value(true)
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("extract-translations")
+                .about("Extract the ship, map and scenario names the replays refer to from a language's global.mo into a small JSON catalog")
+                .arg(
+                    Arg::with_name("game-params")
+                        .long("game-params")
+                        .help("JSON GameParams dump to resolve ship IDs with")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("mo")
+                        .long("mo")
+                        .help("The language's global.mo, from the game's res/texts/<language>/LC_MESSAGES")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .help("JSON file to write the catalog to, usable as --translations")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("REPLAYS")
+                        .help("The replay files, directories, or .zip/.tar.zst archives to extract names for")
+                        .required(true)
+                        .multiple(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("ratings")
+                .about("Rate each player's performance against their ship's expected values, as personal rating (PR)")
+                .arg(
+                    Arg::with_name("game-params")
+                        .long("game-params")
+                        .help("JSON GameParams dump to resolve IDs with")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("expected-values")
+                        .long("expected-values")
+                        .help("Expected values JSON as published by wows-numbers.com")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("torpedo-warnings")
+                .about("List the enemy torpedoes headed for the recording player, and how long they had to dodge each")
+                .arg(
+                    Arg::with_name("game-params")
+                        .long("game-params")
+                        .help("JSON GameParams dump to resolve IDs with")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("results")
+                .about("Quickly print the battle's outcome and results JSON, skipping every other packet")
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("validate-results")
+                .about("Compare the damage, frags and captures tracked from the packets against the post-battle results")
+                .arg(
+                    Arg::with_name("game-params")
+                        .long("game-params")
+                        .help("JSON GameParams dump to resolve IDs with")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("layout")
+                        .long("layout")
+                        .help("JSON file with the indices of the compared fields in the results' playersPublicInfo")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("dump")
+                .about("Dump the packets to console")
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .help("Output filename to dump to")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("no-meta")
+                        .long("no-meta")
+                        .help("Don't output the metadata as first line"),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("battle-logic")
+                .about("Dump the BattleLogic entity's full state as JSON lines every time it changes")
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .help("Output filename to dump to")
+                        .takes_value(true),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("spec")
+                .about("Dump the scripts specifications to console")
+                .arg(
+                    Arg::with_name("version")
+                        .help("Version to dump. Must be comma-delimited: major,minor,patch,build")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tag")
+                .about("Add or remove tags and notes on a replay, stored in a sidecar file next to it")
+                .arg(
+                    Arg::with_name("add")
+                        .long("add")
+                        .help("Tag to add. May be given more than once")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("remove")
+                        .long("remove")
+                        .help("Tag to remove. May be given more than once")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("note")
+                        .long("note")
+                        .help("Note to add")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("clear-notes")
+                        .long("clear-notes")
+                        .help("Remove the replay's notes, before adding --note"),
+                )
+                .arg(replay_arg.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("pipe")
+                .about("Stream the decoded packets to an external analyzer's stdin as length-prefixed messages")
+                .setting(AppSettings::TrailingVarArg)
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .help("Message encoding")
+                        .takes_value(true)
+                        .possible_values(&["json", "msgpack"])
+                        .default_value("json"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .help("File to write the analyzer's output to, instead of stdout")
+                        .takes_value(true),
+                )
+                .arg(replay_arg.clone())
+                .arg(
+                    Arg::with_name("COMMAND")
+                        .help("The analyzer to run, followed by its arguments")
+                        .required(true)
+                        .multiple(true)
+                        .index(2),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("search")
+                .about("Search a directory full of replays")
+                .arg(
+                    Arg::with_name("tag")
+                        .long("tag")
+                        .help("Only list replays with this tag. May be given more than once")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("REPLAYS")
+                        .help("The replay files, directories, or .zip/.tar.zst archives to use")
+                        .required(true)
+                        .multiple(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("investigate")
+                .about("Tools designed for reverse-engineering packets")
+                .arg(
+                    Arg::with_name("meta")
+                        .long("meta")
+                        .help("Don't output the metadata as first line"),
+                )
+                .arg(
+                    Arg::with_name("timestamp")
+                        .long("timestamp")
+                        .takes_value(true)
+                        .help("hh:mm:ss offset to render clock values with"),
+                )
+                .arg(
+                    Arg::with_name("filter-packet")
+                        .long("filter-packet")
+                        .takes_value(true)
+                        .help("If specified, only return packets of the given packet_type"),
+                )
+                .arg(
+                    Arg::with_name("filter-method")
+                        .long("filter-method")
+                        .takes_value(true)
+                        .help("If specified, only return method calls for the given method"),
+                )
+                .arg(
+                    Arg::with_name("entity-id")
+                        .long("entity-id")
+                        .takes_value(true)
+                        .help("Entity ID to apply to other filters if applicable"),
+                )
+                .arg(replay_arg.clone()),
+        );
+
+    #[cfg(feature = "graphics")]
+    let matches = matches.subcommand(
+        SubCommand::with_name("trace")
+            .about("Renders an image showing the trails of ships over the course of the game")
+            .arg(
+                Arg::with_name("out")
+                    .long("output")
+                    .help("Output PNG file to write")
+                    .takes_value(true)
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("game-params")
+                    .long("game-params")
+                    .help("JSON GameParams dump, to mark where enemy radar and hydro came off cooldown and where each phase of the battle began, and to draw the player's detection range")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("annotations")
+                    .long("annotations")
+                    .help("JSON file of timed callouts, arrows and highlighted ships to overlay")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("icons")
+                    .long("icons")
+                    .help("Directory of ship class icons, e.g. destroyer_enemy.png, to mark each ship's final position with")
+                    .takes_value(true)
+                    .requires("game-params"),
+            )
+            .arg(
+                Arg::with_name("silhouettes")
+                    .long("silhouettes")
+                    .help("Directory of per-ship silhouettes named after the ship, e.g. PASB017.png, to use instead of class icons where available")
+                    .takes_value(true)
+                    .requires("icons"),
+            )
+            .arg(
+                Arg::with_name("ghosts")
+                    .long("ghosts")
+                    .help("Mark where enemy ships went undetected and how long they stayed hidden"),
+            )
+            .arg(
+                Arg::with_name("torpedoes")
+                    .long("torpedoes")
+                    .help("Draw the tracks of torpedoes from where they were launched to where they stopped"),
+            )
+            .arg(
+                Arg::with_name("spotted-torpedoes")
+                    .long("spotted-torpedoes")
+                    .help("Only draw enemy torpedoes from where the player's team would have spotted them")
+                    .requires("torpedoes"),
+            )
+            .arg(
+                Arg::with_name("pings")
+                    .long("pings")
+                    .help("Draw quick commands and map pings with who sent them"),
+            )
+            .arg(
+                Arg::with_name("labels")
+                    .long("labels")
+                    .help("How to set labels off from the map so they stay readable")
+                    .takes_value(true)
+                    .possible_values(&["plain", "outline", "shadow", "pill"]),
+            )
+            .arg(
+                Arg::with_name("layers")
+                    .long("layers")
+                    .help("Comma-separated layers to draw, bottom first, e.g. background,trails,pings,labels")
+                    .takes_value(true)
+                    .use_delimiter(true),
+            )
+            .arg(
+                Arg::with_name("profile")
+                    .long("profile")
+                    .help("Print how long each step of drawing the image took"),
+            )
+            .arg(
+                Arg::with_name("transparent")
+                    .long("transparent")
+                    .help("Leave out the map and draw everything else on a transparent background"),
+            )
+            .arg(
+                Arg::with_name("kill-feed")
+                    .long("kill-feed")
+                    .help("List the ships sunk, who sank them and how"),
+            )
+            .arg(
+                Arg::with_name("chat")
+                    .long("chat")
+                    .help("List the battle's chat messages"),
+            )
+            .arg(
+                Arg::with_name("hud")
+                    .long("hud")
+                    .help("Write the kill feed, chat and team graph to their own transparent image at this path")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("perspective")
+                    .long("perspective")
+                    .help("Whose information to draw: the player's team's, the enemy team's, or everything in the replay")
+                    .takes_value(true)
+                    .possible_values(&["ally", "enemy", "omniscient"])
+                    .default_value("omniscient"),
+            )
+            .arg(
+                Arg::with_name("positions")
+                    .long("positions")
+                    .help("Also write a JSON file of where each ship is on the image, e.g. for tooltips")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("background")
+                    .long("background")
+                    .help("How to draw the map: as in game, darkened, or only the outlines of the islands")
+                    .takes_value(true)
+                    .possible_values(&["composited", "darkened", "outlines"]),
+            )
+            .arg(
+                Arg::with_name("map-image")
+                    .long("map-image")
+                    .help("Image to draw as the map instead of the minimap")
+                    .takes_value(true)
+                    .conflicts_with("background"),
+            )
+            .arg(
+                Arg::with_name("asset-cache")
+                    .long("asset-cache")
+                    .help("Directory to keep map backgrounds in, so later renders don't rebuild them")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("team-graph")
+                    .long("team-graph")
+                    .help("Graph each team's health and score over the battle in the corner")
+                    .requires("game-params"),
+            )
+            .arg(replay_arg.clone()),
+    );
+
+    #[cfg(feature = "scripting")]
+    let matches = matches.subcommand(
+        SubCommand::with_name("script")
+            .about("Run a Rhai script's event handlers (on_kill, on_chat, on_position, ...) against the replay")
+            .arg(replay_arg.clone())
+            .arg(
+                Arg::with_name("SCRIPT")
+                    .help("The .rhai script to run")
+                    .required(true)
+                    .index(2),
+            ),
+    );
+
+    let matches = extra.add_to(matches).get_matches();
+
+    init_logging(
+        matches.value_of("log-level"),
+        matches.is_present("log-json"),
+    );
+    BRIDGE_VERSIONS.store(matches.is_present("bridge-versions"), Ordering::Relaxed);
+
+    if let Some(matches) = matches.subcommand_matches("dump") {
+        let input = matches.value_of("REPLAY").unwrap();
+        let dump = wows_replays::analyzer::decoder::DecoderBuilder::new(
+            false,
+            matches.is_present("no-meta"),
+            matches.value_of("output"),
+        );
+        parse_replay(&std::path::PathBuf::from(input), dump).unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("battle-logic") {
+        let input = matches.value_of("REPLAY").unwrap();
+        let dump = wows_replays::analyzer::battle_logic_dump::BattleLogicDumpBuilder::new(
+            matches.value_of("output"),
+        );
+        parse_replay(&std::path::PathBuf::from(input), dump).unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("investigate") {
+        let input = matches.value_of("REPLAY").unwrap();
+        let dump = InvestigativeBuilder {
+            no_meta: !matches.is_present("meta"),
+            filter_packet: matches.value_of("filter-packet").map(|s| s.to_string()),
+            filter_method: matches.value_of("filter-method").map(|s| s.to_string()),
+            entity_id: matches.value_of("entity-id").map(|s| s.to_string()),
+            timestamp: matches.value_of("timestamp").map(|s| s.to_string()),
+        };
+        parse_replay(&std::path::PathBuf::from(input), dump).unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("spec") {
+        let datafiles = wows_replays::version::EmbeddedDataFiles::new(
+            std::path::PathBuf::from("versions"),
+            wows_replays::version::Version::from_client_exe(matches.value_of("version").unwrap()),
+        )
+        .unwrap();
+        let specs = parse_scripts(&datafiles).unwrap();
+        printspecs(&specs);
+    }
+    if let Some(matches) = matches.subcommand_matches("summary") {
+        let input = matches.value_of("REPLAY").unwrap();
+        let dump = wows_replays::analyzer::summary::SummaryBuilder::new();
+        parse_replay(&std::path::PathBuf::from(input), dump).unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("targets") {
+        let input = matches.value_of("REPLAY").unwrap();
+        let timeline = std::rc::Rc::new(std::cell::RefCell::new(
+            wows_replays::analyzer::target_selection::TargetTimeline::new(),
+        ));
+        let targets =
+            wows_replays::analyzer::target_selection::TargetSelectionBuilder::new(timeline.clone());
+        parse_replay(&std::path::PathBuf::from(input), targets).unwrap();
+        let timeline = timeline.borrow();
+        for sample in timeline.samples.iter() {
+            if matches.is_present("questionable") && !sample.is_questionable() {
+                continue;
+            }
+            println!("{}", serde_json::to_string(sample).unwrap());
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("export-paths") {
+        let input = matches.value_of("REPLAY").unwrap();
+        let exporter = wows_replays::analyzer::path_export::PathExportBuilder::new(
+            matches.value_of("output").unwrap(),
+        );
+        parse_replay(&std::path::PathBuf::from(input), exporter).unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("playback") {
+        let input = matches.value_of("REPLAY").unwrap();
+        let tick: f32 = matches
+            .value_of("tick")
+            .unwrap()
+            .parse()
+            .expect("invalid tick");
+        let speed: f32 = matches
+            .value_of("speed")
+            .unwrap()
+            .parse()
+            .expect("invalid speed");
+        serve_playback(
+            std::path::Path::new(input),
+            std::path::Path::new(matches.value_of("game-params").unwrap()),
+            matches.value_of("listen").unwrap(),
+            std::time::Duration::from_secs_f32(tick),
+            Some(speed),
+        )
+        .unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("overlay") {
+        overlay::serve_overlay(
+            std::path::Path::new(matches.value_of("REPLAYS").unwrap()),
+            std::path::Path::new(matches.value_of("game-params").unwrap()),
+            matches.value_of("listen").unwrap(),
+        )
+        .unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("loadouts") {
+        let input = matches.value_of("REPLAY").unwrap();
+        print_loadouts(
+            std::path::Path::new(input),
+            std::path::Path::new(matches.value_of("game-params").unwrap()),
+            matches.value_of("translations").map(std::path::Path::new),
+        )
+        .unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("builds") {
+        let input = matches.value_of("REPLAY").unwrap();
+        let corpus: Vec<_> = matches.values_of("corpus").unwrap().collect();
+        print_build_deviations(
+            std::path::Path::new(input),
+            &corpus,
+            std::path::Path::new(matches.value_of("game-params").unwrap()),
+            matches.value_of("translations").map(std::path::Path::new),
+        )
+        .unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("extract-translations") {
+        let replays: Vec<_> = matches.values_of("REPLAYS").unwrap().collect();
+        extract_translations(
+            &replays,
+            std::path::Path::new(matches.value_of("game-params").unwrap()),
+            std::path::Path::new(matches.value_of("mo").unwrap()),
+            std::path::Path::new(matches.value_of("output").unwrap()),
+        )
+        .unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("ratings") {
+        let input = matches.value_of("REPLAY").unwrap();
+        print_ratings(
+            std::path::Path::new(input),
+            std::path::Path::new(matches.value_of("game-params").unwrap()),
+            std::path::Path::new(matches.value_of("expected-values").unwrap()),
+        )
+        .unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("torpedo-warnings") {
+        let input = matches.value_of("REPLAY").unwrap();
+        print_torpedo_warnings(
+            std::path::Path::new(input),
+            std::path::Path::new(matches.value_of("game-params").unwrap()),
+        )
+        .unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("results") {
+        let input = matches.value_of("REPLAY").unwrap();
+        print_results(std::path::Path::new(input)).unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("validate-results") {
+        let input = matches.value_of("REPLAY").unwrap();
+        validate_results(
+            std::path::Path::new(input),
+            std::path::Path::new(matches.value_of("game-params").unwrap()),
+            std::path::Path::new(matches.value_of("layout").unwrap()),
+        )
+        .unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("chat") {
+        let input = matches.value_of("REPLAY").unwrap();
+        let chatlogger = wows_replays::analyzer::chat::ChatLoggerBuilder::new();
+        parse_replay(&std::path::PathBuf::from(input), chatlogger).unwrap();
+    }
+    #[cfg(feature = "graphics")]
+    {
+        if let Some(matches) = matches.subcommand_matches("trace") {
+            let input = matches.value_of("REPLAY").unwrap();
+            let output = matches.value_of("out").unwrap();
+            let mut trailer = analysis::trails::TrailsBuilder::new(output);
+            let mut options = analysis::trails::RenderOptions {
+                class_icons: matches.value_of("icons").map(std::path::PathBuf::from),
+                ghosts: matches.is_present("ghosts"),
+                torpedoes: matches.is_present("torpedoes"),
+                spotted_torpedoes_only: matches.is_present("spotted-torpedoes"),
+                pings: matches.is_present("pings"),
+                profile: matches.is_present("profile"),
+                transparent: matches.is_present("transparent"),
+                kill_feed: matches.is_present("kill-feed"),
+                chat: matches.is_present("chat"),
+                hud_output: matches.value_of("hud").map(std::path::PathBuf::from),
+                positions_sidecar: matches.value_of("positions").map(std::path::PathBuf::from),
+                asset_cache: matches
+                    .value_of("asset-cache")
+                    .map(std::path::PathBuf::from),
+                ..Default::default()
+            };
+            if let Some(game_params) = matches.value_of("game-params") {
+                let game_data = wows_replays::resource_loader::GameDataSource::load(
+                    std::path::Path::new(game_params),
+                    None,
+                )
+                .unwrap();
+                let report =
+                    wows_replays::analyze_replay(std::path::Path::new(input), &game_data).unwrap();
+                trailer = trailer
+                    .with_consumables(report.consumables().to_vec())
+                    .with_phases(report.phases());
+                if matches.is_present("team-graph") {
+                    trailer = trailer.with_team_graph(report.timeline().to_vec());
+                }
+                add_ship_render_options(
+                    &mut options,
+                    std::path::Path::new(input),
+                    &game_data,
+                    matches.value_of("silhouettes").map(std::path::Path::new),
+                )
+                .unwrap();
+            }
+            match matches.value_of("perspective") {
+                Some("ally") => options.fog_of_war = true,
+                Some("enemy") => {
+                    options.fog_of_war = true;
+                    options.perspective = analysis::trails::Perspective::Enemy;
+                }
+                _ => {}
+            }
+            options.background = match (
+                matches.value_of("background"),
+                matches.value_of("map-image"),
+            ) {
+                (_, Some(path)) => analysis::assets::MapBackground::Custom(path.into()),
+                (Some("darkened"), _) => analysis::assets::MapBackground::Darkened,
+                (Some("outlines"), _) => analysis::assets::MapBackground::Outlines,
+                _ => analysis::assets::MapBackground::Composited,
+            };
+            options.label_style = match matches.value_of("labels") {
+                Some("outline") => analysis::trails::LabelStyle::Outline,
+                Some("shadow") => analysis::trails::LabelStyle::Shadow,
+                Some("pill") => analysis::trails::LabelStyle::Pill,
+                _ => analysis::trails::LabelStyle::Plain,
+            };
+            if let Some(layers) = matches.values_of("layers") {
+                options.layers = Some(
+                    layers
+                        .map(|layer| layer.parse())
+                        .collect::<Result<_, _>>()
+                        .unwrap(),
+                );
+            }
+            trailer = trailer.with_options(options);
+            if let Some(annotations) = matches.value_of("annotations") {
+                trailer = trailer.with_annotations(
+                    wows_replays::annotations::Annotations::load(std::path::Path::new(annotations))
+                        .unwrap(),
+                );
+            }
+            parse_replay(&std::path::PathBuf::from(input), trailer).unwrap();
+        }
+    }
+    #[cfg(feature = "scripting")]
+    {
+        if let Some(matches) = matches.subcommand_matches("script") {
+            let input = matches.value_of("REPLAY").unwrap();
+            let script = wows_replays::analyzer::scripting::ScriptBuilder::from_file(
+                std::path::Path::new(matches.value_of("SCRIPT").unwrap()),
+            )
+            .unwrap();
+            parse_replay(&std::path::PathBuf::from(input), script).unwrap();
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("survey") {
+        let mut survey_result = SurveyResults::empty();
+        for replay in matches.values_of("REPLAYS").unwrap() {
+            for entry in walkdir::WalkDir::new(replay) {
+                let entry = entry.expect("Error unwrapping entry");
+                if !entry.path().is_file() {
+                    continue;
+                }
+                let replays = if ArchiveKind::from_path(entry.path()).is_some() {
+                    archive::archived_replays(entry.path()).expect("Error reading archive")
+                } else {
+                    vec![entry.path().to_path_buf()]
+                };
+                for replay in replays {
+                    let result = survey_file(matches.is_present("skip-decode"), replay);
+                    survey_result.add(result);
+                }
+            }
+        }
+        survey_result.print();
+        if let Some(path) = matches.value_of("coverage") {
+            survey_result
+                .write_coverage(std::path::Path::new(path))
+                .unwrap();
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("pipe") {
+        let input = matches.value_of("REPLAY").unwrap();
+        let command: Vec<_> = matches.values_of("COMMAND").unwrap().collect();
+        let format = match matches.value_of("format").unwrap() {
+            #[cfg(feature = "msgpack")]
+            "msgpack" => wows_replays::analyzer::pipe::PipeFormat::MsgPack,
+            "json" => wows_replays::analyzer::pipe::PipeFormat::Json,
+            format => panic!("{} support requires the {} feature", format, format),
+        };
+        run_pipe(
+            std::path::Path::new(input),
+            &command,
+            format,
+            matches.value_of("output").map(std::path::Path::new),
+        )
+        .unwrap();
+    }
+    if let Some(matches) = matches.subcommand_matches("tag") {
+        let input = std::path::Path::new(matches.value_of("REPLAY").unwrap());
+        let mut notes = ReplayNotes::load(input).unwrap();
+        for tag in matches.values_of("add").into_iter().flatten() {
+            notes.add_tag(tag);
+        }
+        for tag in matches.values_of("remove").into_iter().flatten() {
+            notes.remove_tag(tag);
+        }
+        if matches.is_present("clear-notes") {
+            notes.clear_notes();
+        }
+        if let Some(note) = matches.value_of("note") {
+            notes.add_note(note);
+        }
+        notes.save(input).unwrap();
+
+        println!("Tags: {}", notes.tags().collect::<Vec<_>>().join(", "));
+        for note in notes.notes() {
+            println!("- {}", note);
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches("search") {
+        let tags: Vec<_> = matches.values_of("tag").into_iter().flatten().collect();
+        let mut replays = vec![];
+        for replay in matches.values_of("REPLAYS").unwrap() {
+            for entry in walkdir::WalkDir::new(replay) {
+                let entry = entry.expect("Error unwrapping entry");
+                if !entry.path().is_file() || ReplayNotes::is_sidecar(entry.path()) {
+                    continue;
+                }
+                let replay = entry.path().to_path_buf();
+                let replay_path = replay.clone();
+
+                if ArchiveKind::from_path(&replay).is_some() {
+                    // Replays inside of archives can't have sidecars, so they have no tags
+                    if !tags.is_empty() {
+                        continue;
+                    }
+                    // Read the archive in one pass rather than once per replay
+                    archive::for_each_archived_replay(&replay, |replay_path, contents| {
+                        if let Ok(replay) = ReplayFile::from_bytes(&contents) {
+                            replays.push((replay_path, replay.meta, ReplayNotes::default()));
+                            if replays.len() % 100 == 0 {
+                                println!("Parsed {} games...", replays.len());
+                            }
+                        }
+                    })
+                    .expect("Error reading archive");
+                    continue;
+                }
+
+                let notes = ReplayNotes::load(&replay_path).unwrap_or_default();
+                if !tags.iter().all(|tag| notes.has_tag(tag)) {
+                    continue;
+                }
+                let replay = match ReplayFile::from_file(&replay) {
+                    Ok(replay) => replay,
+                    Err(_) => {
+                        continue;
+                    }
+                };
+                replays.push((replay_path, replay.meta, notes));
+
+                if replays.len() % 100 == 0 {
+                    println!("Parsed {} games...", replays.len());
+                }
+
+                //let result = survey_file(matches.is_present("skip-decode"), replay);
+                //survey_result.add(result);
+            }
+        }
+        replays.sort_by_key(|replay| {
+            match chrono::NaiveDateTime::parse_from_str(&replay.1.dateTime, "%d.%m.%Y %H:%M:%S") {
+                Ok(x) => x,
+                Err(e) => {
+                    println!("Couldn't parse '{}' because {:?}", replay.1.dateTime, e);
+                    chrono::NaiveDateTime::parse_from_str(
+                        "05.05.1995 01:02:03",
+                        "%d.%m.%Y %H:%M:%S",
+                    )
+                    .unwrap()
+                }
+            }
+            //replay.1.dateTime.clone()
+        });
+        println!("Found {} games", replays.len());
+        for (path, meta, notes) in replays.iter().rev().take(10) {
+            println!(
+                "{:?} {} {} {} {} [{}]",
+                path,
+                meta.playerName,
+                meta.dateTime,
+                meta.mapDisplayName,
+                meta.playerVehicle,
+                notes.tags().collect::<Vec<_>>().join(", ")
+            );
+        }
+    }
+
+    extra.run(&matches).unwrap();
+}
@@ -0,0 +1,121 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use wows_replays::analyzer::battle_controller::BattleOutcome;
+use wows_replays::resource_loader::GameDataSource;
+use wows_replays::{ErrorKind, ReplayMeta};
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OverlayPlayer {
+    name: String,
+    ship: String,
+    /// Ship class, e.g. "Destroyer"
+    class: Option<String>,
+}
+
+/// The battle shown by the overlay: the live battle, or the last one played
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OverlayState {
+    date: String,
+    map: String,
+    game_type: String,
+    player: String,
+    allies: Vec<OverlayPlayer>,
+    enemies: Vec<OverlayPlayer>,
+    /// How the battle ended for the recording player, once its replay has been written.
+    /// `None` both while the battle is in progress and if the result is unknown.
+    outcome: Option<BattleOutcome>,
+    finished: bool,
+}
+
+impl OverlayState {
+    fn from_meta(meta: &ReplayMeta, game_data: &GameDataSource) -> Self {
+        let mut allies = vec![];
+        let mut enemies = vec![];
+        for vehicle in meta.vehicles.iter() {
+            let param = game_data.game_param_by_id(vehicle.shipId as u32);
+            let player = OverlayPlayer {
+                name: vehicle.name.clone(),
+                ship: param
+                    .as_ref()
+                    .map(|param| {
+                        game_data
+                            .localized_name_from_param(param)
+                            .unwrap_or_else(|| param.name())
+                            .to_string()
+                    })
+                    .unwrap_or_else(|| format!("{}", vehicle.shipId)),
+                class: param
+                    .and_then(|param| param.species())
+                    .map(|species| <&'static str>::from(species).to_string()),
+            };
+            // Relation 0 is the recording player and 1 their allies
+            if vehicle.relation == 2 {
+                enemies.push(player);
+            } else {
+                allies.push(player);
+            }
+        }
+
+        OverlayState {
+            date: meta.dateTime.clone(),
+            map: meta.mapDisplayName.clone(),
+            game_type: meta.gameType.clone(),
+            player: meta.playerName.clone(),
+            allies,
+            enemies,
+            outcome: None,
+            finished: false,
+        }
+    }
+}
+
+/// Finds the replay the game wrote for the battle which started at `date`. The game
+/// writes it once the battle ends, so it's the most recently modified one.
+fn find_replay(replays: &Path, date: &str) -> Option<PathBuf> {
+    let newest = walkdir::WalkDir::new(replays)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "wowsreplay"))
+        .max_by_key(|e| e.metadata().ok().and_then(|meta| meta.modified().ok()))?
+        .into_path();
+    let replay = wows_replays::ReplayFile::from_file(&newest).ok()?;
+    if replay.meta.dateTime == date {
+        Some(newest)
+    } else {
+        None
+    }
+}
+
+/// Follows the game's `tempArenaInfo.json` in `replays`, sending the [OverlayState] as
+/// JSON to every client connected to `address` every couple of seconds. The file exists
+/// for the duration of a battle, and once it's gone the battle's replay is analyzed for
+/// the result.
+pub fn serve_overlay(replays: &Path, game_params: &Path, address: &str) -> Result<(), ErrorKind> {
+    let game_data = GameDataSource::load(game_params, None)?;
+    let clients = crate::accept_websocket_clients(address)?;
+    let arena_info = replays.join("tempArenaInfo.json");
+
+    let mut state: Option<OverlayState> = None;
+    loop {
+        match ReplayMeta::from_temp_arena_info(&arena_info) {
+            Ok(meta) => {
+                if state.as_ref().map(|state| &state.date) != Some(&meta.dateTime) {
+                    state = Some(OverlayState::from_meta(&meta, &game_data));
+                }
+            }
+            Err(_) => {
+                if let Some(state) = state.as_mut().filter(|state| !state.finished) {
+                    if let Some(path) = find_replay(replays, &state.date) {
+                        state.outcome = wows_replays::analyze_replay(&path, &game_data)
+                            .ok()
+                            .and_then(|report| report.outcome());
+                        state.finished = true;
+                    }
+                }
+            }
+        }
+        crate::broadcast(&clients, &serde_json::to_string(&state).unwrap());
+        std::thread::sleep(std::time::Duration::from_secs(2));
+    }
+}
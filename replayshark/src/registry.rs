@@ -0,0 +1,54 @@
+use clap::{App, ArgMatches};
+use wows_replays::ErrorKind;
+
+/// A subcommand added to replayshark from outside of this crate, see [crate::run]
+pub trait Subcommand {
+    /// The name the subcommand is invoked with. This must match the name of the
+    /// [Subcommand::app] and not clash with any of the built-in subcommands.
+    fn name(&self) -> &'static str;
+
+    /// The subcommand's arguments and help text
+    fn app(&self) -> App<'static, 'static>;
+
+    fn run(&self, matches: &ArgMatches<'_>) -> Result<(), ErrorKind>;
+}
+
+/// The extra subcommands to offer alongside the built-in ones
+#[derive(Default)]
+pub struct SubcommandRegistry {
+    subcommands: Vec<Box<dyn Subcommand>>,
+}
+
+impl SubcommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<S: Subcommand + 'static>(&mut self, subcommand: S) -> &mut Self {
+        self.subcommands.push(Box::new(subcommand));
+        self
+    }
+
+    /// Adds the registered subcommands to `app`
+    pub(crate) fn add_to(&self, app: App<'static, 'static>) -> App<'static, 'static> {
+        self.subcommands
+            .iter()
+            .fold(app, |app, subcommand| app.subcommand(subcommand.app()))
+    }
+
+    /// Runs the registered subcommand that was invoked, if any
+    pub(crate) fn run(&self, matches: &ArgMatches<'_>) -> Result<(), ErrorKind> {
+        let (name, matches) = match matches.subcommand() {
+            (name, Some(matches)) => (name, matches),
+            _ => return Ok(()),
+        };
+        match self
+            .subcommands
+            .iter()
+            .find(|subcommand| subcommand.name() == name)
+        {
+            Some(subcommand) => subcommand.run(matches),
+            None => Ok(()),
+        }
+    }
+}